@@ -0,0 +1,123 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+/// The kind of access to the host an import gives a component
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Filesystem,
+    Network,
+    Environment,
+    Clocks,
+    Random,
+    /// Imports that are not part of the WASI host, like the component's own
+    /// interfaces or the ones wepl provides (e.g. `wasi:keyvalue`)
+    Custom,
+}
+
+impl Category {
+    pub const ALL: [Category; 6] = [
+        Category::Filesystem,
+        Category::Network,
+        Category::Environment,
+        Category::Clocks,
+        Category::Random,
+        Category::Custom,
+    ];
+
+    /// The category of the import named `import` (e.g. `wasi:sockets/tcp@0.2.0`)
+    pub fn of(import: &str) -> Self {
+        let package = unversioned(import)
+            .split_once('/')
+            .map_or(import, |(package, _)| package);
+        match package {
+            "wasi:filesystem" => Category::Filesystem,
+            "wasi:sockets" | "wasi:http" => Category::Network,
+            "wasi:cli" | "wasi:io" => Category::Environment,
+            "wasi:clocks" => Category::Clocks,
+            "wasi:random" => Category::Random,
+            _ => Category::Custom,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::Filesystem => "Filesystem",
+            Category::Network => "Network",
+            Category::Environment => "Environment and stdio",
+            Category::Clocks => "Clocks",
+            Category::Random => "Random",
+            Category::Custom => "Custom",
+        }
+    }
+}
+
+/// The WASI interfaces whose use wepl notices even though wasmtime implements
+/// them, through the clocks, random generators, sockets checks and output
+/// streams it hands to the guest
+pub const OBSERVED_WASI: &[&str] = &[
+    "wasi:clocks/wall-clock",
+    "wasi:clocks/monotonic-clock",
+    "wasi:random/random",
+    "wasi:random/insecure",
+    "wasi:sockets/tcp",
+    "wasi:sockets/udp",
+    "wasi:cli/stdout",
+    "wasi:cli/stderr",
+];
+
+/// The imported interfaces and functions the component used during the
+/// session, by their names without versions
+#[derive(Clone, Default)]
+pub struct Exercised(Arc<Mutex<BTreeSet<String>>>);
+
+impl Exercised {
+    /// Note that the component used the import `import`
+    pub fn record(&self, import: &str) {
+        let import = unversioned(import);
+        let mut exercised = self.0.lock().unwrap();
+        if !exercised.contains(import) {
+            exercised.insert(import.to_owned());
+        }
+    }
+
+    /// Whether the component used the import `import`
+    pub fn contains(&self, import: &str) -> bool {
+        self.0.lock().unwrap().contains(unversioned(import))
+    }
+}
+
+/// The name `name` of an interface without its version
+pub fn unversioned(name: &str) -> &str {
+    name.split_once('@').map_or(name, |(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories() {
+        assert_eq!(
+            Category::of("wasi:filesystem/types@0.2.0"),
+            Category::Filesystem
+        );
+        assert_eq!(
+            Category::of("wasi:http/outgoing-handler@0.2.0"),
+            Category::Network
+        );
+        assert_eq!(Category::of("wasi:io/streams@0.2.0"), Category::Environment);
+        assert_eq!(Category::of("wasi:keyvalue/store"), Category::Custom);
+        assert_eq!(Category::of("my:kv/store@1.0.0"), Category::Custom);
+        assert_eq!(Category::of("log"), Category::Custom);
+    }
+
+    #[test]
+    fn exercised_ignores_versions() {
+        let exercised = Exercised::default();
+        exercised.record("wasi:clocks/wall-clock@0.2.0");
+        assert!(exercised.contains("wasi:clocks/wall-clock@0.2.1"));
+        assert!(!exercised.contains("wasi:clocks/monotonic-clock@0.2.0"));
+    }
+}
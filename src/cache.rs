@@ -0,0 +1,181 @@
+use std::{
+    hash::Hash as _,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest as _, Sha256};
+use wasmtime::{component::Component, Engine};
+
+/// How many bytes of compiled components the cache keeps before evicting the
+/// least recently used ones
+const MAX_CACHE_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// A cache of compiled components on disk keyed by the hash of their bytes and
+/// of the settings of the engine compiling them
+#[derive(Clone)]
+pub struct ComponentCache {
+    dir: PathBuf,
+}
+
+impl ComponentCache {
+    /// The cache in `$XDG_CACHE_HOME/wepl` or `~/.cache/wepl`, if either can be found
+    pub fn new() -> Option<Self> {
+        let base = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => home::home_dir()?.join(".cache"),
+        };
+        Some(Self {
+            dir: base.join("wepl"),
+        })
+    }
+
+    /// Load the compiled component from the cache or compile it and add it
+    ///
+    /// Failing to read or write the cache is not an error since the component
+    /// can always be compiled.
+    pub fn load(&self, engine: &Engine, component_bytes: &[u8]) -> anyhow::Result<Component> {
+        let path = self
+            .dir
+            .join(format!("{}.cwasm", key(engine, component_bytes)));
+        if path.exists() {
+            // SAFETY: the file was written by `Component::serialize` below and
+            // `deserialize_file` rejects artifacts of incompatible engines.
+            match unsafe { Component::deserialize_file(engine, &path) } {
+                Ok(component) => {
+                    // Mark the artifact as recently used so it is evicted last
+                    if let Err(e) = touch(&path) {
+                        tracing::debug!("could not touch '{}': {e}", path.display());
+                    }
+                    return Ok(component);
+                }
+                Err(e) => tracing::debug!("ignoring cached component '{}': {e}", path.display()),
+            }
+        }
+        let component = crate::runtime::compile_component(engine, component_bytes)?;
+        if let Err(e) = self.store(&path, &component) {
+            tracing::debug!("could not cache component at '{}': {e}", path.display());
+        }
+        if let Err(e) = evict(&self.dir, MAX_CACHE_SIZE) {
+            tracing::debug!("could not evict cached components: {e}");
+        }
+        Ok(component)
+    }
+
+    fn store(&self, path: &Path, component: &Component) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so other sessions never see a partial artifact
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&tmp, component.serialize()?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Mark the file at `path` as used now
+fn touch(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Remove the least recently used compilations in `dir` until the rest take
+/// at most `max_size` bytes
+fn evict(dir: &Path, max_size: u64) -> std::io::Result<()> {
+    let mut artifacts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "cwasm") {
+            let metadata = entry.metadata()?;
+            artifacts.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    let mut size = artifacts.iter().map(|(_, len, _)| len).sum::<u64>();
+    artifacts.sort();
+    for (_, len, path) in artifacts {
+        if size <= max_size {
+            break;
+        }
+        std::fs::remove_file(&path)?;
+        size -= len;
+    }
+    Ok(())
+}
+
+/// The name of the compilation of `component_bytes` by `engine`, which differs
+/// between engines whose compilations are not compatible
+fn key(engine: &Engine, component_bytes: &[u8]) -> String {
+    let mut hasher = DigestHasher(Sha256::new());
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    hasher.0.update(component_bytes);
+    format!("{:x}", hasher.0.finalize())
+}
+
+/// Feeds what is hashed into a digest, whose value is the hash
+struct DigestHasher(Sha256);
+
+impl std::hash::Hasher for DigestHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        unreachable!("the hash is the value of the digest")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(configure: impl FnOnce(&mut wasmtime::Config)) -> Engine {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        configure(&mut config);
+        Engine::new(&config).unwrap()
+    }
+
+    #[test]
+    fn keys_depend_on_the_engine() {
+        let (speed, none) = (
+            engine(|config| {
+                config.cranelift_opt_level(wasmtime::OptLevel::Speed);
+            }),
+            engine(|config| {
+                config.cranelift_opt_level(wasmtime::OptLevel::None);
+            }),
+        );
+        assert_eq!(
+            key(&speed, b"component"),
+            key(&engine(|_| {}), b"component")
+        );
+        assert_ne!(key(&speed, b"component"), key(&none, b"component"));
+        assert_ne!(key(&speed, b"component"), key(&speed, b"other"));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = std::env::temp_dir().join(format!("wepl-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("old", 30), ("used", 20), ("new", 10)] {
+            let path = dir.join(format!("{name}.cwasm"));
+            std::fs::write(&path, [0; 100]).unwrap();
+            let file = std::fs::File::options().append(true).open(&path).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        touch(&dir.join("used.cwasm")).unwrap();
+        evict(&dir, 200).unwrap();
+        let exists = |name: &str| dir.join(format!("{name}.cwasm")).exists();
+        assert!(!exists("old"));
+        assert!(exists("used"));
+        assert!(exists("new"));
+        evict(&dir, 100).unwrap();
+        assert!(!exists("new"));
+        assert!(exists("used"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    os::{fd::AsRawFd as _, unix::fs::OpenOptionsExt as _},
+};
+
+use anyhow::Context as _;
+
+/// The file everything written to stdout and stderr goes to while a command
+/// runs, so it can be sent to whoever ran the command
+pub struct Capture {
+    file: File,
+}
+
+impl Capture {
+    pub fn new() -> anyhow::Result<Self> {
+        let file = anonymous_file()
+            .context("could not create the file capturing the output of commands")?;
+        Ok(Self { file })
+    }
+
+    /// Run `f` with everything written to stdout and stderr, including by the
+    /// component, going to the file instead and return what was written
+    ///
+    /// The streams are those of the whole process, so whatever other threads
+    /// print meanwhile is captured too and captures must not overlap.
+    pub fn run<R>(&self, f: impl FnOnce() -> R) -> anyhow::Result<(R, String)> {
+        let mut file = &self.file;
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        let redirect = Redirect::new(file)?;
+        let result = f();
+        drop(redirect);
+        let mut printed = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut printed)?;
+        Ok((result, String::from_utf8_lossy(&printed).into_owned()))
+    }
+}
+
+/// Stdout and stderr going to a file until this is dropped, which restores
+/// them even if redirecting them failed halfway or the code running
+/// meanwhile panicked
+struct Redirect {
+    /// The redirected streams along with duplicates of what they referred to
+    saved: Vec<(i32, i32)>,
+}
+
+impl Redirect {
+    fn new(file: &File) -> anyhow::Result<Self> {
+        std::io::stdout().flush()?;
+        std::io::stderr().flush()?;
+        let mut redirect = Self { saved: Vec::new() };
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            // SAFETY: `fd` is open for the whole life of the process and
+            // `dup2` only swaps what it refers to
+            unsafe {
+                let saved = libc::dup(fd);
+                if saved == -1 {
+                    return Err(std::io::Error::last_os_error())
+                        .context("could not capture the output of the command");
+                }
+                redirect.saved.push((fd, saved));
+                if libc::dup2(file.as_raw_fd(), fd) == -1 {
+                    return Err(std::io::Error::last_os_error())
+                        .context("could not capture the output of the command");
+                }
+            }
+        }
+        Ok(redirect)
+    }
+}
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        for (fd, saved) in self.saved.drain(..) {
+            // SAFETY: `saved` was duplicated from `fd` when redirecting and is
+            // closed only here
+            unsafe {
+                libc::dup2(saved, fd);
+                libc::close(saved);
+            }
+        }
+    }
+}
+
+/// A file only readable by this process that has no name, so that nobody
+/// else can read what is captured or have it written somewhere else
+///
+/// The file is created under a random name that must not exist yet, which
+/// refuses links planted at the name, and is removed right away.
+fn anonymous_file() -> std::io::Result<File> {
+    loop {
+        let path =
+            std::env::temp_dir().join(format!("wepl-capture-{:016x}", rand::random::<u64>()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path);
+        match file {
+            Ok(file) => {
+                std::fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The device and inode of the file stdout refers to
+    fn stdout_file() -> (u64, u64) {
+        // SAFETY: `stat` is plain data that `fstat` fills in
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        assert_eq!(unsafe { libc::fstat(libc::STDOUT_FILENO, &mut stat) }, 0);
+        (stat.st_dev as u64, stat.st_ino as u64)
+    }
+
+    #[test]
+    fn captures() {
+        let capture = Capture::new().unwrap();
+        let ((), printed) = capture
+            .run(|| std::io::stdout().write_all(b"captured\n").unwrap())
+            .unwrap();
+        assert!(printed.contains("captured\n"));
+    }
+
+    #[test]
+    fn leaves_no_file_behind() {
+        let capture = Capture::new().unwrap();
+        let path = format!("/proc/self/fd/{}", capture.file.as_raw_fd());
+        let target = std::fs::read_link(path).unwrap();
+        assert!(target.to_string_lossy().ends_with(" (deleted)"));
+    }
+
+    #[test]
+    fn restores_after_panics() {
+        let capture = Capture::new().unwrap();
+        let before = stdout_file();
+        let run = std::panic::AssertUnwindSafe(|| capture.run(|| panic!("boom")));
+        assert!(std::panic::catch_unwind(run).is_err());
+        assert_eq!(stdout_file(), before);
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    ffi::OsString,
+    io::IsTerminal as _,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use anyhow::Context as _;
+use clap::Parser;
+use colored::*;
+use rustyline::error::ReadlineError;
+
+use crate::{
+    command, diagnostics, diff, events::Events, exit_status, golden, is_stdin, main_component,
+    print_error, print_error_of_line, print_error_prefix, print_uncallable_exports, progress, rc,
+    read_component, runtime, start_session, Cli, Command, ErrorFormat, Session, JSON_ERRORS,
+};
+#[cfg(unix)]
+use crate::{jupyter, repl_server, tui};
+
+/// Run the `wepl` command line
+pub fn main() {
+    if let Err(e) = _main() {
+        print_error(&e);
+        std::process::exit(exit_status(&e));
+    }
+}
+
+fn _main() -> anyhow::Result<()> {
+    let mut cli = Cli::parse();
+    diagnostics::init(cli.log, cli.verbose, use_color(&cli));
+    JSON_ERRORS.store(cli.format == ErrorFormat::Json, Ordering::Relaxed);
+    colored::control::set_override(use_color(&cli));
+    // Frontends reading the output of lines would get the spinners too
+    let captured = cli.tui
+        || cli.jupyter_kernel.is_some()
+        || matches!(cli.command, Some(Command::ServeRepl { .. }));
+    if !cli.quiet && !cli.echo_commands && !captured {
+        progress::enable();
+    }
+    // They also show the output of calls while the calls run
+    cli.engine.stream_output = captured;
+    match &cli.command {
+        Some(Command::Compile { component, output }) => return compile(component, output, &cli),
+        Some(Command::Diff { old, new }) => {
+            let events = Events::default();
+            let (_, old) = read_component(old, &cli, &events)?;
+            let (_, new) = read_component(new, &cli, &events)?;
+            diff::print(&events, &old, &new);
+            return Ok(());
+        }
+        Some(Command::Test {
+            component,
+            dir,
+            bless,
+        }) => {
+            // The options before the subcommand apply to every script
+            if !golden::run(component, dir, *bless, &script_args(&cli))? {
+                std::process::exit(TEST_FAILED);
+            }
+            return Ok(());
+        }
+        Some(Command::ServeRepl {
+            component,
+            socket,
+            tcp,
+            allow_host_code,
+        }) => {
+            let (component, socket, tcp) = (component.clone(), socket.clone(), *tcp);
+            let allow_host_code = *allow_host_code;
+            return serve_repl(cli, component, socket, tcp, allow_host_code);
+        }
+        None => {}
+    }
+    let component = main_component(&cli)?;
+    let mut session = start_session(cli, component)?;
+    // Scripts run by `wepl test` do not depend on the user's rc file
+    let rc_file = rc::path().filter(|_| !session.cli.echo_commands);
+    if let Some(connection_file) = session.cli.jupyter_kernel.clone() {
+        if let Some(rc_file) = rc_file {
+            session.run_rc_file(rc_file);
+        }
+        return jupyter_kernel(&mut session, &connection_file);
+    }
+    if session.cli.tui {
+        if let Some(rc_file) = rc_file {
+            session.run_rc_file(rc_file);
+        }
+        return tui(&mut session);
+    }
+
+    // The component was piped in so stdin is not the terminal anymore
+    let behavior = if is_stdin(&session.instance.component) {
+        rustyline::Behavior::PreferTerm
+    } else {
+        rustyline::Behavior::Stdio
+    };
+    let config = rustyline::Config::builder().behavior(behavior).build();
+    let mut rl = rustyline::DefaultEditor::with_config(config)?;
+    // Lines can span several lines in the terminal, e.g. for long records
+    rl.bind_sequence(
+        rustyline::KeyEvent(rustyline::KeyCode::Enter, rustyline::Modifiers::ALT),
+        rustyline::Cmd::Newline,
+    );
+    // Scripts run by `wepl test` are not part of the history
+    let history = home::home_dir()
+        .filter(|_| !session.cli.echo_commands)
+        .map(|home| home.join(".weplhistory"));
+    if let Some(history) = &history {
+        let _ = rl.load_history(history);
+    }
+    if !session.cli.quiet {
+        let world = session.instance.resolver.world_name();
+        println!("{}: {world}", "World".blue().bold());
+        if session.instance.runtime.lock().unwrap().dry_run() {
+            println!("Only WIT is loaded, so calls are type-checked but not made");
+        }
+        print_uncallable_exports(&session.instance.resolver, &session.events());
+    }
+    if let Some(rc_file) = rc_file {
+        session.run_rc_file(rc_file);
+    }
+    // The line `.! $n --edit` has the user edit before running it
+    let mut initial: Option<String> = None;
+    // Scripts piped into the repl exit with the status of the first line that
+    // failed, unless they choose their own with `.exit`
+    let script = !is_stdin(&session.instance.component) && !std::io::stdin().is_terminal();
+    let mut status = 0;
+    loop {
+        set_keybindings(&mut rl, session.keybindings);
+        let prompt = session.prompt().blue().bold().to_string();
+        let readline = match initial.take() {
+            Some(line) => rl.readline_with_initial(&prompt, (line.as_str(), "")),
+            None => rl.readline(&prompt),
+        };
+        match readline {
+            Ok(line) => {
+                let _ = rl.add_history_entry(&line);
+                session.add_history(&line);
+                if session.cli.echo_commands {
+                    println!("> {line}");
+                }
+                match session.run_line(&line) {
+                    Ok(command::Outcome::ClearScreen) => {
+                        let _ = rl.clear_screen();
+                    }
+                    Ok(command::Outcome::Edit(line)) => initial = Some(line),
+                    Ok(command::Outcome::Exit(code)) => {
+                        status = code;
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        print_error_of_line(&e, Some(&line));
+                        if script && status == 0 {
+                            status = exit_status(&e);
+                        }
+                    }
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(ReadlineError::WindowResized) => continue,
+            Err(ReadlineError::Io(e)) => {
+                print_error_prefix();
+                eprintln!("reading from stdin failed: {e}");
+                break;
+            }
+            Err(e) => {
+                print_error_prefix();
+                eprintln!("reading from stdin failed: {e}");
+                break;
+            }
+        }
+    }
+    if let Some(history) = &history {
+        let _ = rl.save_history(history);
+    }
+    if status != 0 {
+        std::process::exit(status);
+    }
+    Ok(())
+}
+
+/// The status `wepl test` exits with when the output of a script differs
+/// from what was expected
+const TEST_FAILED: i32 = 6;
+
+/// Whether output is colored, which it is not when it is redirected to a file
+/// or another program unless `CLICOLOR_FORCE` says otherwise
+fn use_color(cli: &Cli) -> bool {
+    let env = |name| std::env::var_os(name).filter(|v| !v.is_empty());
+    if cli.no_color {
+        false
+    } else if env("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+        true
+    } else if env("NO_COLOR").is_some() {
+        false
+    } else {
+        std::io::stdout().is_terminal() && std::io::stderr().is_terminal()
+    }
+}
+
+/// Make `rl` edit lines with `keybindings`
+fn set_keybindings(rl: &mut rustyline::DefaultEditor, keybindings: command::Keybindings) {
+    use rustyline::config::Configurer as _;
+    let mode = match keybindings {
+        command::Keybindings::Emacs => rustyline::EditMode::Emacs,
+        command::Keybindings::Vi => rustyline::EditMode::Vi,
+    };
+    rl.set_edit_mode(mode);
+}
+
+/// The options of `cli` that `wepl test` starts the process running each
+/// script with, leaving out the component and the frontends
+fn script_args(cli: &Cli) -> Vec<OsString> {
+    fn value<T: clap::ValueEnum>(value: &T) -> String {
+        let value = value.to_possible_value().expect("no values are skipped");
+        value.get_name().to_owned()
+    }
+    let mut args = Vec::<OsString>::new();
+    let mut arg = |name: &str, value: Option<OsString>| {
+        args.push(name.into());
+        args.extend(value);
+    };
+    for (key, val) in &cli.env {
+        arg("--env", Some(format!("{key}={val}").into()));
+    }
+    for (key, val) in &cli.config {
+        arg("--config", Some(format!("{key}={val}").into()));
+    }
+    if let Some(stdin) = &cli.stdin {
+        arg("--stdin", Some(stdin.into()));
+    }
+    if let Some(seed) = cli.random_seed {
+        arg("--random-seed", Some(seed.to_string().into()));
+    }
+    if let Some(bundle) = &cli.world_bundle {
+        arg("--world-bundle", Some(bundle.into()));
+    }
+    match cli.allow_network.as_deref() {
+        Some([]) => arg("--allow-network", None),
+        Some(networks) => arg(&format!("--allow-network={}", networks.join(",")), None),
+        None => {}
+    }
+    for (flag, set) in [
+        ("--prompt-imports", cli.prompt_imports),
+        ("--no-cache", cli.no_cache),
+        ("--debug-info", cli.engine.debug_info),
+        ("--pooling", cli.engine.pooling),
+        ("--fuel", cli.engine.fuel),
+        ("--profiling", cli.engine.profiling),
+        ("--quiet", cli.quiet),
+        ("--no-color", cli.no_color),
+        ("--sandbox", cli.sandbox),
+    ] {
+        if set {
+            arg(flag, None);
+        }
+    }
+    if let Some(adapter) = &cli.adapter {
+        arg("--adapter", Some(value(adapter).into()));
+    }
+    if let Some(registry) = &cli.registry {
+        arg("--registry", Some(registry.into()));
+    }
+    if let Some(wit) = &cli.wit {
+        arg("--wit", Some(wit.into()));
+    }
+    if let Some(world) = &cli.world {
+        arg("--world", Some(world.into()));
+    }
+    for (feature, enable) in &cli.engine.features {
+        let enable = if *enable { "y" } else { "n" };
+        arg(
+            "--wasm",
+            Some(format!("{}={enable}", value(feature)).into()),
+        );
+    }
+    arg("--opt-level", Some(value(&cli.engine.opt_level).into()));
+    arg("--compiler", Some(value(&cli.engine.compiler).into()));
+    arg("--format", Some(value(&cli.format).into()));
+    arg("--log", Some(value(&cli.log).into()));
+    for _ in 0..cli.verbose {
+        arg("--verbose", None);
+    }
+    args
+}
+
+/// Serve a session of the repl for `component` to the tools connecting to the
+/// Unix `socket` or the `tcp` address instead of reading commands from the
+/// terminal
+///
+/// The tools can only run code on the host if `allow_host_code` is set.
+#[cfg(unix)]
+fn serve_repl(
+    cli: Cli,
+    component: PathBuf,
+    socket: Option<PathBuf>,
+    tcp: Option<std::net::SocketAddr>,
+    allow_host_code: bool,
+) -> anyhow::Result<()> {
+    let address = match (socket, tcp) {
+        (Some(path), _) => repl_server::Address::Unix(path),
+        (None, Some(addr)) => repl_server::Address::Tcp(addr),
+        (None, None) => unreachable!("clap requires an address"),
+    };
+    let mut session = start_session(cli, component)?;
+    session.host_code = allow_host_code;
+    repl_server::serve(&mut session, &address)
+}
+
+/// Run `session` as a Jupyter kernel started with the connection file at
+/// `connection_file` instead of reading commands from the terminal
+#[cfg(unix)]
+fn jupyter_kernel(session: &mut Session, connection_file: &Path) -> anyhow::Result<()> {
+    jupyter::run(session, connection_file)
+}
+
+#[cfg(not(unix))]
+fn jupyter_kernel(_: &mut Session, _: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("`wepl --jupyter-kernel` is only supported on Unix")
+}
+
+/// Run `session` in the full-screen terminal UI instead of line by line
+#[cfg(unix)]
+fn tui(session: &mut Session) -> anyhow::Result<()> {
+    tui::run(session)
+}
+
+#[cfg(not(unix))]
+fn tui(_: &mut Session) -> anyhow::Result<()> {
+    anyhow::bail!("`wepl --tui` is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+fn serve_repl(
+    _: Cli,
+    _: PathBuf,
+    _: Option<PathBuf>,
+    _: Option<std::net::SocketAddr>,
+    _: bool,
+) -> anyhow::Result<()> {
+    anyhow::bail!("`wepl serve-repl` is only supported on Unix")
+}
+
+/// Precompile the component at `path` for this version of wepl, writing it to `output`
+fn compile(path: &Path, output: &Path, cli: &Cli) -> anyhow::Result<()> {
+    let (bytes, _) = read_component(path, cli, &Events::default())?;
+    let compiled = runtime::precompile(&bytes, &cli.engine)?;
+    std::fs::write(output, compiled)
+        .with_context(|| format!("could not write '{}'", output.display()))?;
+    if !cli.quiet {
+        println!("{} {}", "Compiled:".green().bold(), output.display());
+    }
+    if output.with_extension("wasm") != path {
+        println!(
+            "Keep the component at '{}' to load it",
+            output.with_extension("wasm").display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_get_the_options_before_test() {
+        let cli = Cli::try_parse_from([
+            "wepl",
+            "--env",
+            "MODE=test",
+            "--allow-network=10.0.0.0/8,example.com",
+            "-W",
+            "simd=n",
+            "-vv",
+            "test",
+            "component.wasm",
+            "tests",
+        ])
+        .unwrap();
+        let args = script_args(&cli);
+        // The scripts are run with the same options
+        let script = Cli::try_parse_from(
+            std::iter::once(OsString::from("wepl"))
+                .chain(args.iter().cloned())
+                .chain([OsString::from("component.wasm")]),
+        )
+        .unwrap();
+        assert_eq!(script.env, [("MODE".to_owned(), "test".to_owned())]);
+        assert_eq!(
+            script.allow_network.as_deref(),
+            Some(&["10.0.0.0/8".to_owned(), "example.com".to_owned()][..])
+        );
+        assert_eq!(script.engine.features, cli.engine.features);
+        assert_eq!(script.verbose, 2);
+        assert!(script.command.is_none());
+        assert_eq!(script_args(&script), args);
+    }
+}
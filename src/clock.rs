@@ -0,0 +1,326 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context as _;
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock};
+
+/// A clock that either follows the host's clocks or is frozen at a point in
+/// time that only moves when explicitly advanced.
+///
+/// Clones share the same state so changes are visible to every store the
+/// clock was handed to.
+#[derive(Clone)]
+pub struct VirtualClock(Arc<Mutex<ClockState>>);
+
+struct ClockState {
+    /// The instant the monotonic clock counts from
+    base: Instant,
+    /// Nanoseconds the monotonic clock is ahead of `base`, which keeps it
+    /// from going backwards when it is unfrozen after being advanced
+    offset: u64,
+    frozen: Option<FrozenTime>,
+}
+
+#[derive(Clone, Copy)]
+struct FrozenTime {
+    /// Time since the unix epoch
+    wall: Duration,
+    /// Nanoseconds since `ClockState::base`
+    monotonic: u64,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(ClockState {
+            base: Instant::now(),
+            offset: 0,
+            frozen: None,
+        })))
+    }
+}
+
+impl VirtualClock {
+    /// Freeze the clock at `wall` time since the unix epoch.
+    pub fn freeze(&self, wall: Duration) {
+        let mut state = self.0.lock().unwrap();
+        let monotonic = state.monotonic_now();
+        state.frozen = Some(FrozenTime { wall, monotonic });
+    }
+
+    /// Move the clock forward by `by`, freezing it at the current time first
+    /// if it is following the host's clocks.
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.0.lock().unwrap();
+        let mut frozen = match state.frozen {
+            Some(frozen) => frozen,
+            None => FrozenTime {
+                wall: real_wall_now(),
+                monotonic: state.monotonic_now(),
+            },
+        };
+        frozen.wall = frozen.wall.saturating_add(by);
+        frozen.monotonic = frozen
+            .monotonic
+            .saturating_add(by.as_nanos().try_into().unwrap_or(u64::MAX));
+        state.frozen = Some(frozen);
+    }
+
+    /// Go back to following the host's clocks.
+    pub fn unfreeze(&self) {
+        let mut state = self.0.lock().unwrap();
+        if let Some(frozen) = state.frozen.take() {
+            let lead = frozen.monotonic.saturating_sub(state.monotonic_now());
+            state.offset = state.offset.saturating_add(lead);
+        }
+    }
+
+    /// The frozen wall clock time since the unix epoch, if the clock is frozen.
+    pub fn frozen_at(&self) -> Option<Duration> {
+        self.0.lock().unwrap().frozen.map(|f| f.wall)
+    }
+
+    /// The wall clock to hand to a WASI context
+    pub fn wall_clock(&self) -> impl HostWallClock {
+        WallClock(self.clone())
+    }
+
+    /// The monotonic clock to hand to a WASI context
+    pub fn monotonic_clock(&self) -> impl HostMonotonicClock {
+        MonotonicClock(self.clone())
+    }
+}
+
+impl ClockState {
+    fn monotonic_now(&self) -> u64 {
+        match self.frozen {
+            Some(frozen) => frozen.monotonic,
+            None => u64::try_from(self.base.elapsed().as_nanos())
+                .unwrap_or(u64::MAX)
+                .saturating_add(self.offset),
+        }
+    }
+}
+
+fn real_wall_now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+struct WallClock(VirtualClock);
+
+impl HostWallClock for WallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.0.frozen_at().unwrap_or_else(real_wall_now)
+    }
+}
+
+struct MonotonicClock(VirtualClock);
+
+impl HostMonotonicClock for MonotonicClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.0 .0.lock().unwrap().monotonic_now()
+    }
+}
+
+/// Parse a UTC timestamp of the form `2024-01-01T00:00:00Z` into the time
+/// since the unix epoch.
+pub fn parse_timestamp(s: &str) -> anyhow::Result<Duration> {
+    let err = || format!("'{s}' is not a timestamp of the form 2024-01-01T00:00:00Z");
+    let rest = s.strip_suffix('Z').with_context(err)?;
+    let (date, time) = rest.split_once('T').with_context(err)?;
+    let parse_parts = |s: &str, sep: char| -> anyhow::Result<Vec<u64>> {
+        s.split(sep)
+            .map(|p| p.parse::<u64>().with_context(err))
+            .collect()
+    };
+    let (&[year, month, day], &[hour, minute, second]) = (
+        parse_parts(date, '-')?.as_slice(),
+        parse_parts(time, ':')?.as_slice(),
+    ) else {
+        anyhow::bail!(err())
+    };
+    if !(1970..=9999).contains(&year) || !(1..=12).contains(&month) {
+        anyhow::bail!(err())
+    }
+    if !(1..=days_in_month(year, month)).contains(&day) {
+        anyhow::bail!("'{s}' is not a timestamp: {year:04}-{month:02} has no day {day}")
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        anyhow::bail!(err())
+    }
+    let secs = days_from_civil(year, month, day)
+        .checked_mul(86400)
+        .and_then(|secs| secs.checked_add(hour * 3600 + minute * 60 + second))
+        .with_context(err)?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Format the time since the unix epoch as a UTC timestamp like `2024-01-01T00:00:00Z`
+pub fn format_timestamp(since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let secs = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Parse a duration like `5s`, `250ms`, `2m`, `1h` or `3d`.
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("'{s}' is not a duration like 5s"))?;
+    let secs = |per_unit: u64| {
+        amount
+            .checked_mul(per_unit)
+            .map(Duration::from_secs)
+            .with_context(|| format!("'{s}' is too long a duration"))
+    };
+    match unit {
+        "ns" => Ok(Duration::from_nanos(amount)),
+        "us" => Ok(Duration::from_micros(amount)),
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" | "" => secs(1),
+        "m" => secs(60),
+        "h" => secs(60 * 60),
+        "d" => secs(60 * 60 * 24),
+        _ => anyhow::bail!(
+            "unrecognized duration unit '{unit}' (expected one of ns, us, ms, s, m, h, d)"
+        ),
+    }
+}
+
+/// The number of days in `month` of `year` in the proleptic Gregorian calendar
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since the unix epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = (month + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date in the proleptic Gregorian calendar of days since the unix epoch
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_round_trip() {
+        let epoch = parse_timestamp("1970-01-01T00:00:00Z").unwrap();
+        assert_eq!(epoch, Duration::ZERO);
+
+        let leap_day = parse_timestamp("2024-02-29T12:34:56Z").unwrap();
+        assert_eq!(leap_day, Duration::from_secs(1709210096));
+        assert_eq!(format_timestamp(leap_day), "2024-02-29T12:34:56Z");
+
+        assert!(parse_timestamp("2024-01-01").is_err());
+        assert!(parse_timestamp("2024-13-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn timestamps_past_the_end_of_the_month_are_rejected() {
+        assert!(parse_timestamp("2024-02-31T00:00:00Z").is_err());
+        assert!(parse_timestamp("2023-02-29T00:00:00Z").is_err());
+        assert!(parse_timestamp("2024-04-31T00:00:00Z").is_err());
+        assert!(parse_timestamp("2000-02-29T00:00:00Z").is_ok());
+        assert!(parse_timestamp("1900000000000000000-01-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("5 fortnights").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn overlong_durations_are_errors() {
+        assert!(parse_duration("999999999999999999d").is_err());
+        assert!(parse_duration("999999999999999999h").is_err());
+        assert!(parse_duration("18446744073709551615m").is_err());
+        assert_eq!(
+            parse_duration("18446744073709551615s").unwrap(),
+            Duration::from_secs(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn frozen_clock_only_moves_when_advanced() {
+        let clock = VirtualClock::default();
+        let wall = clock.wall_clock();
+        let monotonic = clock.monotonic_clock();
+        clock.freeze(Duration::from_secs(100));
+        let start = monotonic.now();
+        assert_eq!(wall.now(), Duration::from_secs(100));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(wall.now(), Duration::from_secs(105));
+        assert_eq!(monotonic.now() - start, 5_000_000_000);
+        clock.unfreeze();
+        assert!(wall.now() > Duration::from_secs(105));
+    }
+
+    #[test]
+    fn monotonic_clock_never_goes_back_when_unfrozen() {
+        let clock = VirtualClock::default();
+        let monotonic = clock.monotonic_clock();
+        clock.freeze(Duration::from_secs(100));
+        clock.advance(Duration::from_secs(3600));
+        let advanced = monotonic.now();
+        clock.unfreeze();
+        let unfrozen = monotonic.now();
+        assert!(unfrozen >= advanced);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(monotonic.now() > unfrozen);
+
+        clock.advance(Duration::from_secs(60));
+        assert!(monotonic.now() >= unfrozen + 60_000_000_000);
+        clock.unfreeze();
+        assert!(monotonic.now() >= unfrozen + 60_000_000_000);
+    }
+}
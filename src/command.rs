@@ -1,6 +1,9 @@
 pub mod parser;
 mod tokenizer;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context as _};
 use colored::Colorize;
@@ -9,11 +12,35 @@ use wasmtime::component::Val;
 use self::parser::Ident;
 use self::tokenizer::TokenKind;
 
-use super::runtime::Runtime;
+use super::runtime::{GuestStdin, Runtime, StateMode, StubComponent, StubConfig};
 use super::wit::WorldResolver;
-use crate::evaluator::Evaluator;
+use crate::audit::Category;
+use crate::clock::format_timestamp;
+use crate::engine::{EngineOptions, WasmFeature};
+use crate::evaluator::{type_check, Evaluator};
+use crate::events::{Event, Events, OutputSource};
+use crate::files::FileFormat;
+use crate::logging::{Level, LogRecord};
+use crate::streams;
 use crate::wit::Expansion;
 
+/// A line that is not a command, with where in the line parsing failed
+#[derive(Debug)]
+pub struct ParseError {
+    message: String,
+    /// The byte range of the line that could not be parsed, which is empty at
+    /// the end of the line when more input was expected
+    pub span: std::ops::Range<usize>,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 pub enum Cmd<'a> {
     BuiltIn {
         name: &'a str,
@@ -24,13 +51,56 @@ pub enum Cmd<'a> {
         ident: &'a str,
         value: parser::Expr<'a>,
     },
+    /// A command for the host's shell, given after a `!`
+    Shell(&'a str),
+    /// Run the line numbered `entry` in the session's history again, or
+    /// edit it first
+    Rerun {
+        entry: usize,
+        edit: bool,
+    },
 }
 
 impl<'a> Cmd<'a> {
     pub fn parse(input: &'a str) -> anyhow::Result<Option<Cmd<'a>>> {
-        let tokens = tokenizer::Token::tokenize(input)?;
-        let line = parser::Line::parse(tokens).map_err(|e| anyhow::anyhow!("{e}"))?;
-        log::debug!("Parsed line: {line:?}");
+        if let Some(command) = input.trim_start().strip_prefix('!') {
+            return Ok(Some(Cmd::Shell(command.trim())));
+        }
+        if let Some(rest) = input.trim_start().strip_prefix(".!") {
+            let (entry, edit) = match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [entry] => (entry.parse().ok(), false),
+                [entry, "--edit"] => (entry.parse().ok(), true),
+                _ => (None, false),
+            };
+            let Some(entry) = entry else {
+                bail!("usage: .! $n [--edit]");
+            };
+            return Ok(Some(Cmd::Rerun { entry, edit }));
+        }
+        let tokens = match raw_arg(input) {
+            Some(raw) => tokenizer::Token::tokenize_around(input, raw),
+            None => tokenizer::Token::tokenize(input),
+        };
+        let tokens = tokens.map_err(|e| {
+            let tokenizer::TokenizeError::UnexpectedChar(char, offset) = e;
+            ParseError {
+                message: e.to_string(),
+                span: offset..offset + char.len_utf8(),
+            }
+        })?;
+        let line = parser::Line::parse(tokens).map_err(|e| {
+            let span = match &e {
+                parser::ParserError::UnexpectedToken(token) => {
+                    token.input.offset..token.input.offset + token.input.str.len()
+                }
+                _ => input.len()..input.len(),
+            };
+            ParseError {
+                message: e.to_string(),
+                span,
+            }
+        })?;
+        tracing::trace!("Parsed line: {line:?}");
         match line {
             parser::Line::Expr(expr) => Ok(Some(Cmd::Eval(expr))),
             parser::Line::Assignment(ident, value) => Ok(Some(Cmd::Assign { ident, value })),
@@ -41,46 +111,177 @@ impl<'a> Cmd<'a> {
         }
     }
 
+    /// Turn a call like `backend.foo(1)` into a call of `foo` when `is_instance`
+    /// says that the receiver names a loaded instance, returning the instance
+    pub fn route(&mut self, is_instance: impl Fn(&str) -> bool) -> Option<&'a str> {
+        let (Cmd::Eval(expr) | Cmd::Assign { value: expr, .. }) = self else {
+            return None;
+        };
+        let parser::Expr::MethodCall(call) = expr else {
+            return None;
+        };
+        let receiver = call.receiver;
+        if receiver.interface.is_some() || !is_instance(receiver.item) {
+            return None;
+        }
+        *expr = parser::Expr::FunctionCall(parser::FunctionCall {
+            ident: parser::ItemIdent {
+                interface: None,
+                item: call.method,
+            },
+            args: std::mem::take(&mut call.args),
+        });
+        Some(receiver.item)
+    }
+
+    /// Whether the command runs code on the host outside of the component or
+    /// uses the host's files, which `--sandbox` forbids
+    ///
+    /// Components can still be loaded from files, since they are sandboxed
+    /// too, except for precompiled ones, which loading them refuses. `scope`
+    /// tells whether `.write` writes to a stream or to a file.
+    pub fn escapes_sandbox(&self, scope: &HashMap<String, Val>) -> bool {
+        if self.runs_host_code() {
+            return true;
+        }
+        let Cmd::BuiltIn { name, args } = self else {
+            return false;
+        };
+        let first = args.first().map(|t| t.token());
+        match *name {
+            "read-file" | "import-csv" | "export-csv" | "scaffold" | "profile"
+            | "replay-imports" => true,
+            "write" => match first {
+                Some(TokenKind::Ident(var)) => !matches!(scope.get(var), Some(Val::Resource(_))),
+                _ => false,
+            },
+            "wit" => first == Some(TokenKind::Flag("out")),
+            "stdin" => first == Some(TokenKind::Flag("file")),
+            "record-imports" | "freeze-world" => {
+                first.is_some() && first != Some(TokenKind::Ident("off"))
+            }
+            "coredump" => first == Some(TokenKind::Ident("on")),
+            _ => false,
+        }
+    }
+
+    /// Whether the command runs code on the host outside of the component,
+    /// which served sessions only allow with `--allow-host-code`
+    pub fn runs_host_code(&self) -> bool {
+        match self {
+            Cmd::Shell(_) => true,
+            Cmd::BuiltIn { name, .. } => matches!(*name, "stub-proc" | "plugin"),
+            _ => false,
+        }
+    }
+
+    /// Whether the command configures the runtime and so has to be run again
+    /// when the component is reloaded
+    pub fn is_replayed_on_reload(&self) -> bool {
+        const REPLAYED: &[&str] = &[
+            "compose",
+            "link",
+            "stub",
+            "stub-proc",
+            "plugin",
+            "mock",
+            "unstub",
+            "spy",
+            "env",
+            "stdin",
+            "stub-config",
+            "capture",
+            "output",
+            "format",
+            "stats",
+            "clock",
+            "random",
+            "state",
+            "trace",
+            "coredump",
+            "log-level",
+            "provide",
+            "replay-imports",
+        ];
+        match self {
+            // The session keeps the engine settings, so only setting values of
+            // `wasi:config` has to be replayed
+            Cmd::BuiltIn {
+                name: "config",
+                args,
+            } => args.first().map(|t| t.token()) == Some(TokenKind::Ident("set")),
+            // Listing the limits does not configure anything
+            Cmd::BuiltIn {
+                name: "limit",
+                args,
+            } => !args.is_empty(),
+            // Only choosing a world changes how the component is seen
+            Cmd::BuiltIn {
+                name: "world",
+                args,
+            } => !args.is_empty(),
+            Cmd::BuiltIn { name, .. } => REPLAYED.contains(name),
+            _ => false,
+        }
+    }
+
     /// Run the command
     ///
-    /// Returns `Ok(true)` if the screen should be cleared
+    /// Returns what the repl has to do next that the command cannot do itself
     pub fn run(
         self,
         runtime: &mut Runtime,
         resolver: &mut WorldResolver,
         scope: &mut HashMap<String, Val>,
-    ) -> anyhow::Result<bool> {
+    ) -> anyhow::Result<Outcome> {
+        let events = runtime.events().clone();
         let mut eval = Evaluator::new(runtime, resolver, scope);
         match self {
             Cmd::Eval(expr) => match expr {
                 parser::Expr::Literal(l) => {
                     let val = eval.eval_literal(l, None)?;
-                    println!("{}: {}", format_val(&val), val_as_type(&val));
+                    let table = events.tables();
+                    runtime.events().emit(Event::Value { val, table });
                 }
                 parser::Expr::Ident(ident) => match scope.get(ident) {
-                    Some(val) => {
-                        println!("{}: {}", format_val(val), val_as_type(val))
-                    }
+                    Some(val) => runtime.events().emit(Event::Value {
+                        val: val.clone(),
+                        table: events.tables(),
+                    }),
                     None => {
                         anyhow::bail!("no identifier '{ident}' in scope")
                     }
                 },
                 parser::Expr::FunctionCall(func) => {
-                    let results = eval.call_func(func.ident, func.args)?;
-                    println!(
-                        "{}",
-                        results
-                            .into_iter()
-                            .map(|v| format_val(&v))
-                            .collect::<Vec<_>>()
-                            .join("\n")
-                    )
+                    let values = eval.call_func(func.ident, func.args)?;
+                    let table = events.tables();
+                    runtime.events().emit(Event::Results { values, table });
+                    bind_captured_output(runtime, scope);
+                }
+                parser::Expr::MethodCall(call) => {
+                    let values = eval.call_method(call)?;
+                    let table = events.tables();
+                    runtime.events().emit(Event::Results { values, table });
+                    bind_captured_output(runtime, scope);
                 }
             },
+            Cmd::Shell(command) => {
+                if command.is_empty() {
+                    bail!("usage: !$command");
+                }
+                let status = shell_command(command)
+                    .status()
+                    .with_context(|| format!("could not run '{command}'"))?;
+                if !status.success() {
+                    bail!("'{command}' failed with {status}");
+                }
+            }
+            Cmd::Rerun { entry, edit } => return Ok(Outcome::Rerun { entry, edit }),
             Cmd::Assign { ident, value } => {
                 let val = eval.eval(value, None)?;
-                println!("{}: {}", ident, val_as_type(&val));
+                events.print(format!("{}: {}", ident, val_as_type(&val)));
                 scope.insert(ident.into(), val);
+                bind_captured_output(runtime, scope);
             }
             Cmd::BuiltIn {
                 name: "exports",
@@ -95,7 +296,7 @@ impl<'a> Cmd<'a> {
                 for (export_name, export) in resolver.world().exports.iter() {
                     let export_name = resolver.world_item_name(export_name);
                     if let Some(ty) = format_world_item(export, resolver) {
-                        println!("{}: {ty}", export_name.bold());
+                        events.print(format!("{}: {ty}", export_name.bold()));
                     }
                 }
             }
@@ -122,118 +323,1906 @@ impl<'a> Cmd<'a> {
                 for (import_name, import) in resolver.imports(include_wasi) {
                     let import_name = resolver.world_item_name(import_name);
                     if let Some(ty) = format_world_item(import, resolver) {
-                        println!("{}: {ty}", import_name.bold());
+                        events.print(format!("{}: {ty}", import_name.bold()));
+                    }
+                }
+            }
+            Cmd::BuiltIn {
+                name: "audit",
+                args,
+            } => {
+                if !args.is_empty() {
+                    bail!("usage: .audit");
+                }
+                print_audit(&events, resolver, runtime);
+            }
+            Cmd::BuiltIn { name: "type", args } => {
+                match args.as_slice() {
+                    &[token] => {
+                        let TokenKind::Ident(name) = token.token() else {
+                            bail!("unrecognized token")
+                        };
+                        let types = resolver.types_by_name(name);
+                        for (interface, ty) in &types {
+                            let typ = resolver.display_wit_type_def(ty, Expansion::Expanded(1));
+                            let name = &ty.name;
+                            let interface = interface.and_then(|i| resolver.interface_name(i));
+                            let ident = match (interface, name) {
+                                (Some(i), Some(n)) => format!("{i}#{n}: "),
+                                (None, Some(n)) => format!("{n}: "),
+                                _ => todo!(),
+                            };
+                            events.print(format!("{ident}{typ}"));
+                        }
+                    }
+                    _ => bail!(
+                        "wrong number of arguments to inspect function. Expected 1 got {}",
+                        args.len()
+                    ),
+                };
+            }
+            Cmd::BuiltIn {
+                name: "compose",
+                args,
+            } => {
+                runtime.compose(compose_config(args)?)?;
+                *resolver = WorldResolver::from_bytes(runtime.component_bytes())?;
+            }
+            Cmd::BuiltIn { name: "link", args } => {
+                let lenient = args.iter().any(|t| t.token() == TokenKind::Flag("lenient"));
+                let mut args: VecDeque<_> = args
+                    .into_iter()
+                    .filter(|t| t.token() != TokenKind::Flag("lenient"))
+                    .collect();
+                if args.get(1).map(|t| t.token()) == Some(TokenKind::Ident("needs")) {
+                    if lenient {
+                        bail!("`--lenient` is not supported when linking instances");
+                    }
+                    return link_instances(args);
+                }
+                let Ok(Some(import_ident)) = Ident::try_parse(&mut args) else {
+                    bail!("import_ident is not a proper item identifier");
+                };
+                let Ok(Some(export_ident)) = Ident::try_parse(&mut args) else {
+                    bail!("export_ident is not a proper item identifier");
+                };
+
+                let Some(TokenKind::String(component)) = args.pop_front().map(|t| t.token()) else {
+                    bail!("component path is not a string");
+                };
+                let component_bytes = std::fs::read(component)
+                    .with_context(|| format!("could not read component '{component}'"))?;
+                let stub = StubComponent::new(component.as_ref(), component_bytes);
+                runtime.stub(resolver, import_ident, export_ident, &stub, lenient)?;
+            }
+            Cmd::BuiltIn { name: "stub", args } => {
+                let mut args = args.into_iter().collect();
+                let import_ident = match Ident::try_parse(&mut args) {
+                    Ok(Some(Ident::Interface(import_ident))) => {
+                        let (Some(TokenKind::Flag("wat")), Some(TokenKind::String(path)), None) = (
+                            args.pop_front().map(|t| t.token()),
+                            args.pop_front().map(|t| t.token()),
+                            args.pop_front(),
+                        ) else {
+                            bail!("usage: .stub $interface --wat $path");
+                        };
+                        runtime.stub_with_wat(resolver, import_ident, path.as_ref())?;
+                        return Ok(Outcome::Done);
+                    }
+                    Ok(Some(Ident::Item(import_ident))) => import_ident,
+                    _ => bail!("import_ident is not a proper identifier"),
+                };
+                if args.pop_front().map(|t| t.token()) != Some(TokenKind::FatArrow) {
+                    bail!("usage: .stub $import => $value");
+                }
+                let parser::Line::Expr(expr) =
+                    parser::Line::parse(args).map_err(|e| anyhow::anyhow!("{e}"))?
+                else {
+                    bail!("expected a value to return from '{import_ident}'");
+                };
+                if resolver.imported_function(import_ident).is_none() {
+                    bail!("no import with name '{import_ident}'");
+                }
+                let results = runtime.imported_function_results(import_ident)?;
+                let [ty] = results.as_slice() else {
+                    bail!(
+                        "'{import_ident}' returns {} values but only functions returning one value can be stubbed with a constant",
+                        results.len()
+                    );
+                };
+                let val = Evaluator::without_runtime(resolver, scope).eval(expr, Some(ty))?;
+                let val = crate::runtime::compat::coerce_val(val.clone(), ty).unwrap_or(val);
+                type_check(&val, ty)?;
+                runtime.stub_constant(import_ident, vec![val])?;
+            }
+            Cmd::BuiltIn { name: "mock", args } => {
+                let mut args = args.into_iter().collect();
+                let Ok(Some(Ident::Interface(import_ident))) = Ident::try_parse(&mut args) else {
+                    bail!("import_ident is not a proper interface identifier");
+                };
+                if !args.is_empty() {
+                    bail!("usage: .mock $interface");
+                }
+                let count = runtime.mock(resolver, import_ident)?;
+                events.print(format!(
+                    "{} {count} functions of '{import_ident}'",
+                    "Mocked".blue().bold()
+                ));
+            }
+            Cmd::BuiltIn {
+                name: "provide",
+                args,
+            } => {
+                const USAGE: &str = "usage: .provide wasi:keyvalue memory";
+                let [package @ .., implementation] = args.as_slice() else {
+                    bail!(USAGE);
+                };
+                if joined_str(package) != "wasi:keyvalue"
+                    || implementation.token() != TokenKind::Ident("memory")
+                {
+                    bail!(USAGE);
+                }
+                let count = runtime.provide_keyvalue()?;
+                events.print(format!(
+                    "{} {count} functions of 'wasi:keyvalue' with in-memory stores",
+                    "Provided".blue().bold()
+                ));
+            }
+            Cmd::BuiltIn { name: "kv", args } => {
+                const USAGE: &str =
+                    "usage: .kv dump [$store] | get $store $key | set $store $key $value";
+                let stores = runtime.keyvalue();
+                match args.as_slice() {
+                    [sub, store @ ..]
+                        if sub.token() == TokenKind::Ident("dump") && store.len() < 2 =>
+                    {
+                        let store = store.first().map(token_str);
+                        for (name, entries) in stores.dump() {
+                            if store.is_some_and(|store| store != name) {
+                                continue;
+                            }
+                            events.print(format!("{}: {} keys", name.bold(), entries.len()));
+                            for (key, value) in entries {
+                                events.print(format!("  {key} = {}", format_bytes(&value)));
+                            }
+                        }
+                    }
+                    [sub, store, key] if sub.token() == TokenKind::Ident("get") => {
+                        let (store, key) = (token_str(store), token_str(key));
+                        let value = stores
+                            .get(store, key)
+                            .with_context(|| format!("no key '{key}' in the store '{store}'"))?;
+                        events.print(format_bytes(&value));
+                    }
+                    [sub, store, key, value] if sub.token() == TokenKind::Ident("set") => {
+                        stores.set(
+                            token_str(store),
+                            token_str(key),
+                            token_str(value).as_bytes().to_vec(),
+                        );
+                    }
+                    _ => bail!(USAGE),
+                }
+            }
+            Cmd::BuiltIn {
+                name: "await",
+                args,
+            } => {
+                const USAGE: &str = "usage: .await $handle [--timeout $duration]";
+                let [handle, rest @ ..] = args.as_slice() else {
+                    bail!(USAGE);
+                };
+                let TokenKind::Ident(name) = handle.token() else {
+                    bail!(USAGE);
+                };
+                let timeout = match rest {
+                    [] => None,
+                    [flag, duration @ ..]
+                        if flag.token() == TokenKind::Flag("timeout") && !duration.is_empty() =>
+                    {
+                        Some(crate::clock::parse_duration(&joined_str(duration))?)
+                    }
+                    _ => bail!(USAGE),
+                };
+                let Some(Val::Resource(resource)) = scope.get(name) else {
+                    bail!("'{name}' is not a resource handle");
+                };
+                let start = std::time::Instant::now();
+                let (ready, resource) = runtime.await_ready(*resource, timeout)?;
+                let elapsed = start.elapsed();
+                scope.insert(name.to_owned(), Val::Resource(resource));
+                if ready {
+                    events.print(format!("{} after {elapsed:.2?}", "Ready".green().bold()));
+                } else {
+                    events.print(format!(
+                        "{} after {elapsed:.2?}",
+                        "Not ready".yellow().bold()
+                    ));
+                }
+            }
+            Cmd::BuiltIn { name: "read", args } => {
+                const USAGE: &str = "usage: .read $handle [$len]";
+                // The most `wasi:io/streams` reads at a time by default
+                const DEFAULT_LEN: usize = 4096;
+                let (name, len) = match args.as_slice() {
+                    [handle] => (handle.token(), DEFAULT_LEN),
+                    [handle, len] => match len.token() {
+                        TokenKind::Number(len) => (handle.token(), len),
+                        _ => bail!(USAGE),
+                    },
+                    _ => bail!(USAGE),
+                };
+                let TokenKind::Ident(name) = name else {
+                    bail!(USAGE);
+                };
+                let Some(Val::Resource(resource)) = scope.get(name) else {
+                    bail!("'{name}' is not a resource handle");
+                };
+                let (bytes, resource) = runtime.read_stream(*resource, len as u64)?;
+                scope.insert(name.to_owned(), Val::Resource(resource));
+                match bytes {
+                    Some(bytes) => events.print(format_bytes(&bytes)),
+                    None => events.print("closed".dimmed().to_string()),
+                }
+            }
+            Cmd::BuiltIn {
+                name: "write",
+                args,
+            } => {
+                const USAGE: &str =
+                    "usage: .write $handle $string | .write $var $path [--as json | bytes | text]";
+                let mut args: VecDeque<_> = args.into_iter().collect();
+                let Some(TokenKind::Ident(name)) = args.pop_front().map(|t| t.token()) else {
+                    bail!(USAGE);
+                };
+                match scope.get(name) {
+                    Some(Val::Resource(_)) => {}
+                    Some(val) => {
+                        let (path, format) = path_and_format(&Vec::from(args)).context(USAGE)?;
+                        let format = format.unwrap_or_else(|| FileFormat::of(val));
+                        let contents = crate::files::to_bytes(val, format)?;
+                        std::fs::write(&path, &contents)
+                            .with_context(|| format!("could not write '{}'", path.display()))?;
+                        events.print(format!(
+                            "{} {} bytes to '{}'",
+                            "Wrote".blue().bold(),
+                            contents.len(),
+                            path.display()
+                        ));
+                        return Ok(Outcome::Done);
+                    }
+                    None => bail!("no identifier '{name}' in scope"),
+                }
+                // What is written to a stream was taken as a raw path
+                let args = match (args.len(), args.front().map(|t| t.token())) {
+                    (1, Some(TokenKind::Rest(data))) => {
+                        tokenizer::Token::tokenize(data).map_err(|e| anyhow::anyhow!("{e}"))?
+                    }
+                    _ => args,
+                };
+                let parser::Line::Expr(expr) =
+                    parser::Line::parse(args).map_err(|e| anyhow::anyhow!("{e}"))?
+                else {
+                    bail!(USAGE);
+                };
+                let Val::String(data) = Evaluator::without_runtime(resolver, scope)
+                    .eval(expr, Some(&wasmtime::component::Type::String))?
+                else {
+                    bail!("only strings can be written to streams");
+                };
+                let Some(Val::Resource(resource)) = scope.get(name) else {
+                    bail!("'{name}' is not a resource handle");
+                };
+                let resource = runtime.write_stream(*resource, data.as_bytes())?;
+                scope.insert(name.to_owned(), Val::Resource(resource));
+            }
+            Cmd::BuiltIn {
+                name: "read-file",
+                args,
+            } => {
+                const USAGE: &str = "usage: .read-file $var $path [--as list<u8> | string]";
+                let Some((var, args)) = args.split_first() else {
+                    bail!(USAGE);
+                };
+                let TokenKind::Ident(var) = var.token() else {
+                    bail!(USAGE);
+                };
+                let (path, format) = path_and_format(args).context(USAGE)?;
+                let contents = std::fs::read(&path)
+                    .with_context(|| format!("could not read '{}'", path.display()))?;
+                let val = crate::files::from_bytes(contents, format.unwrap_or(FileFormat::Bytes))
+                    .with_context(|| format!("could not read '{}'", path.display()))?;
+                events.print(format!("{}: {}", var, val_as_type(&val)));
+                scope.insert(var.to_owned(), val);
+            }
+            Cmd::BuiltIn {
+                name: "import-csv",
+                args,
+            } => {
+                const USAGE: &str = "usage: .import-csv $var $path --type list<$record>";
+                let Some((var, args)) = args.split_first() else {
+                    bail!(USAGE);
+                };
+                let TokenKind::Ident(var) = var.token() else {
+                    bail!(USAGE);
+                };
+                let Some(flag) = args
+                    .iter()
+                    .position(|t| t.token() == TokenKind::Flag("type"))
+                else {
+                    bail!(USAGE);
+                };
+                let (path, ty) = (&args[..flag], joined_str(&args[flag + 1..]));
+                if path.is_empty() || ty.is_empty() {
+                    bail!(USAGE);
+                }
+                let path = PathBuf::from(joined_str(path));
+                let name = ty
+                    .strip_prefix("list<")
+                    .and_then(|ty| ty.strip_suffix('>'))
+                    .unwrap_or(&ty);
+                let Some((_, record)) = resolver
+                    .types_by_name(name)
+                    .into_iter()
+                    .find(|(_, ty)| matches!(ty.kind, wit_parser::TypeDefKind::Record(_)))
+                else {
+                    bail!("no record type named '{name}' found");
+                };
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("could not read '{}'", path.display()))?;
+                let val = crate::csv::to_records(&text, record, resolver)
+                    .with_context(|| format!("could not read '{}'", path.display()))?;
+                let Val::List(records) = &val else {
+                    unreachable!("CSV is always read as a list");
+                };
+                events.print(format!("{}: list<{name}> ({} rows)", var, records.len()));
+                scope.insert(var.to_owned(), val);
+            }
+            Cmd::BuiltIn {
+                name: "export-csv",
+                args,
+            } => {
+                let [var, path @ ..] = args.as_slice() else {
+                    bail!("usage: .export-csv $var $path");
+                };
+                let (TokenKind::Ident(var), false) = (var.token(), path.is_empty()) else {
+                    bail!("usage: .export-csv $var $path");
+                };
+                let Some(val) = scope.get(var) else {
+                    bail!("no identifier '{var}' in scope");
+                };
+                let path = PathBuf::from(joined_str(path));
+                std::fs::write(&path, crate::csv::from_records(val)?)
+                    .with_context(|| format!("could not write '{}'", path.display()))?;
+                events.print(format!(
+                    "{} '{var}' to '{}'",
+                    "Exported".blue().bold(),
+                    path.display()
+                ));
+            }
+            Cmd::BuiltIn {
+                name: "http-request",
+                args,
+            } => {
+                const USAGE: &str =
+                    "usage: .http-request $var $method $url [--header $name:$value]...";
+                let [var, method, rest @ ..] = args.as_slice() else {
+                    bail!(USAGE);
+                };
+                let (TokenKind::Ident(var), TokenKind::Ident(method)) =
+                    (var.token(), method.token())
+                else {
+                    bail!(USAGE);
+                };
+                let mut parts = rest.split(|t| t.token() == TokenKind::Flag("header"));
+                let url = joined_str(parts.next().unwrap_or_default());
+                if url.is_empty() {
+                    bail!(USAGE);
+                }
+                let headers = parts
+                    .map(|header| http_header(&joined_str(header)))
+                    .collect::<anyhow::Result<_>>()?;
+                let request = crate::http::Request::new(method, &url, headers)?;
+                let val = runtime.new_http_request(request)?;
+                events.print(format!("{var}: outgoing-request"));
+                scope.insert(var.to_owned(), val);
+            }
+            Cmd::BuiltIn {
+                name: "handle",
+                args,
+            } => {
+                const USAGE: &str =
+                    "usage: .handle $method $path [--header $name:$value]... [--body $body]";
+                if runtime.wasi_config().sandbox {
+                    bail!("handling requests is not allowed with --sandbox since the component may make requests of its own");
+                }
+                if !resolver.exports_wasi_http_handler() {
+                    bail!("component does not export the 'wasi:http/incoming-handler' interface")
+                }
+                let [method, rest @ ..] = args.as_slice() else {
+                    bail!(USAGE);
+                };
+                let TokenKind::Ident(method) = method.token() else {
+                    bail!(USAGE);
+                };
+                let flags = rest.iter().filter_map(|t| match t.token() {
+                    TokenKind::Flag(flag) => Some(flag),
+                    _ => None,
+                });
+                let mut values = rest.split(|t| matches!(t.token(), TokenKind::Flag(_)));
+                let path = joined_str(values.next().unwrap_or_default());
+                if !path.starts_with('/') {
+                    bail!("expected a path like /items, got '{path}'");
+                }
+                let (mut headers, mut body) = (Vec::new(), Vec::new());
+                for (flag, value) in flags.zip(values) {
+                    match flag {
+                        "header" => headers.push(http_header(&joined_str(value))?),
+                        "body" => body = joined_str(value).into_bytes(),
+                        _ => bail!(USAGE),
+                    }
+                }
+                let response = crate::serve::handle(runtime, method, &path, &headers, body)?;
+                let status = format!("{} {}", response.status, response.reason.unwrap_or(""));
+                events.print(format!("{} {}", "Status:".blue().bold(), status.trim_end()));
+                for (name, value) in &response.headers {
+                    events.print(format!("{name}: {}", String::from_utf8_lossy(value)));
+                }
+                if !response.body.is_empty() {
+                    events.print(format!("\n{}", String::from_utf8_lossy(&response.body)));
+                }
+            }
+            Cmd::BuiltIn {
+                name: "stream",
+                args,
+            } => {
+                const USAGE: &str =
+                    "usage: .stream [push $handle $value | read $handle | close $handle]";
+                let streams = runtime.streams().clone();
+                let mut args: VecDeque<_> = args.into_iter().collect();
+                let (Some(sub), Some(handle)) = (args.pop_front(), args.pop_front()) else {
+                    if !args.is_empty() {
+                        bail!(USAGE);
+                    }
+                    for (handle, queue) in streams.dump() {
+                        let closed = if queue.closed { " (closed)" } else { "" };
+                        events.print(format!(
+                            "{}: {} chunks{closed}",
+                            handle.bold(),
+                            queue.chunks.len()
+                        ));
+                        for chunk in &queue.chunks {
+                            events.print(format!("  {}", format_val(chunk)));
+                        }
+                    }
+                    return Ok(Outcome::Done);
+                };
+                let TokenKind::Ident(handle) = handle.token() else {
+                    bail!(USAGE);
+                };
+                match sub.token() {
+                    TokenKind::Ident("push") => {
+                        let parser::Line::Expr(expr) =
+                            parser::Line::parse(args).map_err(|e| anyhow::anyhow!("{e}"))?
+                        else {
+                            bail!("expected a value to push to '{handle}'");
+                        };
+                        let val = Evaluator::without_runtime(resolver, scope).eval(expr, None)?;
+                        streams.push(handle, val)?;
+                    }
+                    TokenKind::Ident("read") if args.is_empty() => match streams.read(handle)? {
+                        streams::Read::Chunk(chunk) => events.print(format_val(&chunk)),
+                        streams::Read::Pending => events.print("pending".dimmed().to_string()),
+                        streams::Read::Closed => events.print("closed".dimmed().to_string()),
+                    },
+                    TokenKind::Ident("close") if args.is_empty() => streams.close(handle),
+                    _ => bail!(USAGE),
+                }
+            }
+            Cmd::BuiltIn {
+                name: "stub-proc",
+                args,
+            } => {
+                let mut args = args.into_iter().collect();
+                let Ok(Some(Ident::Interface(import_ident))) = Ident::try_parse(&mut args) else {
+                    bail!("import_ident is not a proper interface identifier");
+                };
+                let (Some(TokenKind::Rest(command)), None) =
+                    (args.pop_front().map(|t| t.token()), args.pop_front())
+                else {
+                    bail!("usage: .stub-proc $interface -- $command");
+                };
+                runtime.stub_process(resolver, import_ident, command)?;
+            }
+            Cmd::BuiltIn {
+                name: "plugin",
+                args,
+            } => {
+                let [token] = args.as_slice() else {
+                    bail!("usage: .plugin $path");
+                };
+                let TokenKind::String(path) = token.token() else {
+                    bail!("unrecognized token {}", token.input.str);
+                };
+                let names = runtime.load_plugin(resolver, path.as_ref())?;
+                events.print(format!(
+                    "{} {} with '{path}'",
+                    "Stubbed".blue().bold(),
+                    names.join(", ")
+                ));
+            }
+            Cmd::BuiltIn { name: "spy", args } => {
+                let mut args = args.into_iter().collect();
+                let Ok(Some(Ident::Item(import_ident))) = Ident::try_parse(&mut args) else {
+                    bail!("import_ident is not a proper item identifier");
+                };
+                if !args.is_empty() {
+                    bail!("usage: .spy $import");
+                }
+                if resolver.imported_function(import_ident).is_none() {
+                    bail!("no import with name '{import_ident}'");
+                }
+                runtime.spy(import_ident)?;
+            }
+            Cmd::BuiltIn {
+                name: "limit",
+                args,
+            } => {
+                if args.is_empty() {
+                    print_limits(&events, runtime);
+                    return Ok(Outcome::Done);
+                }
+                let mut args = args.into_iter().collect();
+                let import = match Ident::try_parse(&mut args) {
+                    Ok(Some(Ident::Interface(ident))) => ident.to_string(),
+                    Ok(Some(Ident::Item(ident))) => ident.to_string(),
+                    _ => bail!("usage: .limit [$import ($n | off)]"),
+                };
+                let max = match (args.pop_front().map(|t| t.token()), args.pop_front()) {
+                    (Some(TokenKind::Number(n)), None) => Some(n),
+                    (Some(TokenKind::Ident("off")), None) => None,
+                    _ => bail!("usage: .limit [$import ($n | off)]"),
+                };
+                runtime.set_limit(&import, max)?;
+            }
+            Cmd::BuiltIn {
+                name: "record-imports",
+                args,
+            } => {
+                let path = match args.as_slice() {
+                    [] => bail!("usage: .record-imports $path | off"),
+                    [t] if t.token() == TokenKind::Ident("off") => None,
+                    path => Some(PathBuf::from(joined_str(path))),
+                };
+                let recording = path.is_some();
+                if let Some(stopped) = runtime.record_imports(path)? {
+                    events.print(format!(
+                        "{} {} calls to '{}'",
+                        "Recorded".blue().bold(),
+                        stopped.call_count(),
+                        stopped.path().display()
+                    ));
+                }
+                let mode = if recording { "on" } else { "off" };
+                events.print(format!("{}: {mode}", "Recording imports".blue().bold()));
+            }
+            Cmd::BuiltIn {
+                name: "freeze-world",
+                args,
+            } => {
+                let path = match args.as_slice() {
+                    [] => bail!("usage: .freeze-world $path | off"),
+                    [t] if t.token() == TokenKind::Ident("off") => None,
+                    path => Some(PathBuf::from(joined_str(path))),
+                };
+                let frozen = path.clone();
+                if let Some(stopped) = runtime.freeze_world(path)? {
+                    events.print(format!(
+                        "{} {} calls to '{}'",
+                        "Recorded".blue().bold(),
+                        stopped.call_count(),
+                        stopped.path().display()
+                    ));
+                }
+                if let Some(path) = frozen {
+                    let clock = runtime.wasi_config().clock.frozen_at().unwrap_or_default();
+                    events.print(format!(
+                        "{}: clock at {}, random seed {}, recording imports into '{}' (restore with `--world-bundle`)",
+                        "World frozen".blue().bold(),
+                        crate::clock::format_timestamp(clock),
+                        runtime.wasi_config().random_seed.unwrap_or_default(),
+                        path.display()
+                    ));
+                }
+            }
+            Cmd::BuiltIn {
+                name: "replay-imports",
+                args,
+            } => {
+                if args.is_empty() {
+                    bail!("usage: .replay-imports $path");
+                }
+                for (import, count) in runtime.replay_imports(Path::new(&joined_str(&args)))? {
+                    events.print(format!(
+                        "{} {count} calls to '{import}'",
+                        "Replaying".blue().bold()
+                    ));
+                }
+            }
+            Cmd::BuiltIn {
+                name: "unstub",
+                args,
+            } => match args.as_slice() {
+                [t] if t.token() == TokenKind::Ident("all") => runtime.unstub_all()?,
+                _ => {
+                    let mut args = args.into_iter().collect();
+                    let Ok(Some(Ident::Item(import_ident))) = Ident::try_parse(&mut args) else {
+                        bail!("usage: .unstub [$import | all]");
+                    };
+                    runtime.unstub(import_ident)?;
+                }
+            },
+            Cmd::BuiltIn {
+                name: "calls",
+                args,
+            } => match args.as_slice() {
+                [t] if t.token() == TokenKind::Ident("clear") => runtime.clear_calls(),
+                [] => {
+                    let mut calls = runtime.calls().into_iter().collect::<Vec<_>>();
+                    calls.sort_by(|(n1, _), (n2, _)| n1.cmp(n2));
+                    for (name, calls) in calls {
+                        events.print(format!("{}: {} calls", name.bold(), calls.len()));
+                    }
+                }
+                _ => {
+                    let mut args = args.into_iter().collect();
+                    let Ok(Some(Ident::Item(import_ident))) = Ident::try_parse(&mut args) else {
+                        bail!("usage: .calls [$import | clear]");
+                    };
+                    let name = import_ident.to_string();
+                    let calls = runtime.calls();
+                    let calls = calls
+                        .get(&name)
+                        .with_context(|| format!("not spying on '{name}'"))?;
+                    events.print(format!("{}: {} calls", name.bold(), calls.len()));
+                    for call in calls {
+                        let at = call
+                            .at
+                            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                            .unwrap_or_default();
+                        let args = call.args.iter().map(format_val).collect::<Vec<_>>();
+                        let results = call.results.iter().map(format_val).collect::<Vec<_>>();
+                        let results = match results.as_slice() {
+                            [] => String::new(),
+                            results => format!(" -> {}", results.join(", ")),
+                        };
+                        events.print(format!(
+                            "  {} {}({}){results}",
+                            format!("[{}]", format_timestamp(at)).dimmed(),
+                            import_ident.item,
+                            args.join(", "),
+                        ));
+                    }
+                }
+            },
+            Cmd::BuiltIn {
+                name: "unset",
+                args,
+            } => {
+                let [var] = args.as_slice() else {
+                    bail!("usage: .unset $variable");
+                };
+                let name = token_str(var);
+                let val = scope
+                    .remove(name)
+                    .with_context(|| format!("no identifier '{name}' in scope"))?;
+                // Handles to resources owned by the repl are dropped with their variable
+                if let Val::Resource(handle) = val {
+                    if handle.owned() && runtime.resource(handle).is_some() {
+                        runtime.drop_resource(handle)?;
+                    }
+                }
+            }
+            Cmd::BuiltIn {
+                name: "reset",
+                args,
+            } => {
+                let [what] = args.as_slice() else {
+                    bail!("usage: .reset instance | vars | all");
+                };
+                match what.token() {
+                    TokenKind::Ident("instance") => {
+                        let stale = remove_resource_vars(runtime, scope);
+                        runtime.refresh()?;
+                        events.print(format!("{} instance", "Reset:".green().bold()));
+                        print_removed_handles(&events, &stale);
+                    }
+                    TokenKind::Ident("vars") => {
+                        let count = scope.len();
+                        // Handles to resources owned by the repl are dropped with their variable
+                        for (_, val) in scope.drain() {
+                            if let Val::Resource(handle) = val {
+                                if handle.owned() && runtime.resource(handle).is_some() {
+                                    runtime.drop_resource(handle)?;
+                                }
+                            }
+                        }
+                        events.print(format!("{} {count} variables", "Reset:".green().bold()));
+                    }
+                    TokenKind::Ident("all") => {
+                        scope.clear();
+                        runtime.refresh()?;
+                        events.print(format!(
+                            "{} instance and variables",
+                            "Reset:".green().bold()
+                        ));
+                    }
+                    _ => bail!("usage: .reset instance | vars | all"),
+                }
+            }
+            Cmd::BuiltIn {
+                name: "snapshot",
+                args,
+            } => match args.as_slice() {
+                [] => {
+                    for (name, calls) in runtime.snapshots() {
+                        events.print(format!("{}: {calls} calls", name.bold()));
+                    }
+                }
+                [name] => {
+                    let name = token_str(name);
+                    let calls = runtime.snapshot(name)?;
+                    events.print(format!(
+                        "{} '{name}' ({calls} calls)",
+                        "Saved:".green().bold()
+                    ));
+                }
+                _ => bail!("usage: .snapshot [$name]"),
+            },
+            Cmd::BuiltIn {
+                name: "restore",
+                args,
+            } => {
+                let [name] = args.as_slice() else {
+                    bail!("usage: .restore $name");
+                };
+                let name = token_str(name);
+                if !runtime.snapshots().any(|(snapshot, _)| snapshot == name) {
+                    bail!("no snapshot named '{name}'");
+                }
+                let stale = remove_resource_vars(runtime, scope);
+                let calls = runtime.restore(name)?;
+                events.print(format!(
+                    "{} '{name}' ({calls} calls made again)",
+                    "Restored:".green().bold()
+                ));
+                print_removed_handles(&events, &stale);
+            }
+            Cmd::BuiltIn { name: "drop", args } => match args.as_slice() {
+                [flag] if flag.token() == TokenKind::Flag("unnamed") => {
+                    let unnamed = runtime
+                        .resources()
+                        .iter()
+                        .map(|(handle, _)| *handle)
+                        .filter(|handle| {
+                            !scope
+                                .values()
+                                .any(|val| matches!(val, Val::Resource(h) if h == handle))
+                        })
+                        .collect::<Vec<_>>();
+                    for handle in unnamed {
+                        runtime.drop_resource(handle)?;
+                    }
+                }
+                [var] => {
+                    let name = token_str(var);
+                    let val = scope
+                        .get(name)
+                        .with_context(|| format!("no identifier '{name}' in scope"))?;
+                    let Val::Resource(handle) = *val else {
+                        bail!("'{name}' is not a resource handle")
+                    };
+                    if !handle.owned() || runtime.resource(handle).is_none() {
+                        bail!("the resource handle '{name}' is no longer valid")
+                    }
+                    runtime.drop_resource(handle)?;
+                    scope.remove(name);
+                }
+                _ => bail!("usage: .drop $variable | .drop --unnamed"),
+            },
+            Cmd::BuiltIn {
+                name: "resources",
+                args,
+            } => {
+                let &[] = args.as_slice() else {
+                    bail!("usage: .resources");
+                };
+                for (handle, resource) in runtime.resources() {
+                    let mut names = scope
+                        .iter()
+                        .filter(|(_, val)| matches!(val, Val::Resource(h) if h == handle))
+                        .map(|(name, _)| name.as_str())
+                        .collect::<Vec<_>>();
+                    names.sort();
+                    let held_by = if names.is_empty() {
+                        "<unnamed>".dimmed()
+                    } else {
+                        names.join(", ").bold()
+                    };
+                    events.print(format!("{held_by}: {resource}"));
+                }
+            }
+            Cmd::BuiltIn {
+                name: "inspect",
+                args,
+            } => {
+                // Variables are inspected along the path into their value
+                let path = joined_str(&args);
+                if let Ok((root, segments)) = crate::inspect::parse_path(&path) {
+                    if let Some(val) = scope.get(root) {
+                        crate::inspect::print(
+                            &events,
+                            &path,
+                            crate::inspect::lookup(val, &segments)?,
+                        );
+                        return Ok(Outcome::Done);
+                    }
+                }
+                let mut args = args.into_iter().collect();
+                let Ok(Some(ident)) = Ident::try_parse(&mut args) else {
+                    bail!("ident is not a proper item identifier");
+                };
+                match ident {
+                    Ident::Item(ident) => {
+                        let f = resolver
+                            .exported_function(ident)
+                            .or_else(|| resolver.imported_function(ident));
+                        match f {
+                            Some(f) => events.print(format_function(f, resolver)),
+                            None => bail!("Could not find imported or exported function '{ident}'"),
+                        }
+                    }
+                    Ident::Interface(ident) => {
+                        let i = resolver
+                            .exported_interface(ident)
+                            .or_else(|| resolver.imported_interface(ident));
+                        match i {
+                            Some(f) => events.print(format_interface(f, resolver)),
+                            None => {
+                                bail!("Could not find imported or exported interface '{ident}'")
+                            }
+                        }
+                    }
+                }
+            }
+            Cmd::BuiltIn { name: "info", args } => {
+                let &[] = args.as_slice() else {
+                    bail!("usage: .info");
+                };
+                crate::info::print(&events, runtime.component_bytes(), resolver)?;
+            }
+            Cmd::BuiltIn {
+                name: "validate",
+                args,
+            } => {
+                let &[] = args.as_slice() else {
+                    bail!("usage: .validate");
+                };
+                let warnings = crate::validate::validate(runtime.component_bytes())?;
+                for warning in &warnings {
+                    events.print(format!("{}: {warning}", "Warning".yellow().bold()));
+                }
+                events.print(format!(
+                    "{} the component validates with every WebAssembly feature ({} warnings)",
+                    "Valid:".green().bold(),
+                    warnings.len()
+                ));
+            }
+            Cmd::BuiltIn {
+                name: "world",
+                args,
+            } => match args.as_slice() {
+                [] => {
+                    let selected = resolver.world_name();
+                    for name in resolver.world_names() {
+                        match name == selected {
+                            true => events.print(format!("{} {}", "*".green().bold(), name.bold())),
+                            false => events.print(format!("  {name}")),
+                        }
+                    }
+                }
+                name => {
+                    resolver.select_world(&joined_str(name))?;
+                    events.print(format!(
+                        "{}: {}",
+                        "World".blue().bold(),
+                        resolver.world_name()
+                    ));
+                }
+            },
+            Cmd::BuiltIn { name: "wit", args } => match args.as_slice() {
+                [] => events.print(resolver.world_wit()?),
+                [flag, path @ ..] if flag.token() == TokenKind::Flag("out") && !path.is_empty() => {
+                    let dir = joined_str(path);
+                    for path in resolver.write_wit(Path::new(&dir))? {
+                        events.print(format!("{} {}", "Wrote:".green().bold(), path.display()));
+                    }
+                }
+                _ => bail!("usage: .wit [--out $dir]"),
+            },
+            Cmd::BuiltIn {
+                name: "scaffold",
+                args,
+            } => {
+                const USAGE: &str = "usage: .scaffold $interface [--lang rust] --out $dir";
+                // The interface and the value of each flag can be split into
+                // several tokens, which run until the next flag
+                let mut groups = args.split(|t| matches!(t.token(), TokenKind::Flag(_)));
+                let interface = joined_str(groups.next().unwrap_or_default());
+                let flags = args.iter().filter_map(|t| match t.token() {
+                    TokenKind::Flag(flag) => Some(flag),
+                    _ => None,
+                });
+                let (mut lang, mut out) = ("rust".to_owned(), None);
+                for (flag, value) in flags.zip(groups) {
+                    match flag {
+                        _ if value.is_empty() => bail!(USAGE),
+                        "lang" => lang = joined_str(value),
+                        "out" => out = Some(PathBuf::from(joined_str(value))),
+                        _ => bail!(USAGE),
+                    }
+                }
+                let (false, Some(out)) = (interface.is_empty(), out) else {
+                    bail!(USAGE);
+                };
+                if lang != "rust" {
+                    bail!("cannot scaffold components in '{lang}', only in 'rust'");
+                }
+                for path in crate::scaffold::rust(resolver, &interface, &out)? {
+                    events.print(format!("{} {}", "Wrote:".green().bold(), path.display()));
+                }
+                events.print(format!(
+                    "Build it with `cargo component build` in '{}'",
+                    out.display()
+                ));
+            }
+            Cmd::BuiltIn { name: "diff", args } => {
+                let [path] = args.as_slice() else {
+                    bail!("usage: .diff $path");
+                };
+                let path = token_str(path);
+                let bytes = std::fs::read(path)
+                    .with_context(|| format!("could not read the component at '{path}'"))?;
+                crate::diff::print(&events, resolver, &WorldResolver::from_bytes(&bytes)?);
+            }
+            Cmd::BuiltIn {
+                name: "search",
+                args,
+            } => {
+                /// The most matches shown
+                const MAX_RESULTS: usize = 20;
+                if args.is_empty() {
+                    bail!("usage: .search $query");
+                }
+                let query = joined_str(&args);
+                let mut matches = search_items(resolver)
+                    .into_iter()
+                    .filter_map(|(name, key, description)| {
+                        let score = crate::fuzzy::score(&query, &key)?;
+                        Some((score, name, description))
+                    })
+                    .collect::<Vec<_>>();
+                matches.sort_by(|(s1, n1, _), (s2, n2, _)| s2.cmp(s1).then(n1.cmp(n2)));
+                if matches.is_empty() {
+                    events.print(format!("No matches for '{query}'"));
+                }
+                for (_, name, description) in matches.into_iter().take(MAX_RESULTS) {
+                    events.print(format!("{}: {description}", name.bold()));
+                }
+            }
+            Cmd::BuiltIn {
+                name: "example",
+                args,
+            } => {
+                let mut args = args.into_iter().collect();
+                let Ok(Some(Ident::Item(ident))) = Ident::try_parse(&mut args) else {
+                    bail!("usage: .example $func");
+                };
+                let f = resolver.find_exported_function(ident)?;
+                let args = f
+                    .params
+                    .iter()
+                    .map(|(_, ty)| resolver.example_value(ty))
+                    .collect::<Vec<_>>();
+                events.print(format!("{ident}({})", args.join(", ")));
+            }
+            Cmd::BuiltIn {
+                name: "serve",
+                args,
+            } => {
+                if runtime.wasi_config().sandbox {
+                    bail!("serving over the network is not allowed with --sandbox");
+                }
+                if !resolver.exports_wasi_http_handler() {
+                    bail!("component does not export the 'wasi:http/incoming-handler' interface")
+                }
+                let addr = match args.as_slice() {
+                    [] => "127.0.0.1:8080".into(),
+                    tokens => joined_str(tokens),
+                };
+                let addr: std::net::SocketAddr = addr
+                    .parse()
+                    .with_context(|| format!("'{addr}' is not a valid socket address"))?;
+                crate::serve::serve(runtime, addr)?;
+            }
+            Cmd::BuiltIn { name: "run", args } => {
+                let run_interface = resolver
+                    .exported_wasi_cli_run()
+                    .context("component does not export the 'wasi:cli/run' interface")?;
+                // The first argument is conventionally the program name
+                let args = std::iter::once(resolver.world().name.clone())
+                    .chain(args.iter().map(|t| token_str(t).to_owned()))
+                    .collect::<Vec<_>>();
+                let code = runtime.run(&run_interface, &args)?;
+                events.print(format!("{}: {code}", "Exit status".blue().bold()));
+            }
+            Cmd::BuiltIn { name: "env", args } => match args.as_slice() {
+                [] => print_env(&events, runtime),
+                [sub] if sub.token() == TokenKind::Ident("list") => print_env(&events, runtime),
+                [sub, key, value @ ..]
+                    if sub.token() == TokenKind::Ident("set") && !value.is_empty() =>
+                {
+                    runtime.set_env(token_str(key), &joined_str(value))?;
+                }
+                [sub, key] if sub.token() == TokenKind::Ident("unset") => {
+                    runtime.unset_env(token_str(key))?;
+                }
+                _ => bail!("usage: .env [list | set $key $value | unset $key]"),
+            },
+            Cmd::BuiltIn {
+                name: "stdin",
+                args,
+            } => {
+                let stdin = match args.as_slice() {
+                    [] => GuestStdin::Empty,
+                    [token] => match token.token() {
+                        TokenKind::String(s) => GuestStdin::Bytes(s.to_owned().into()),
+                        TokenKind::Ident("inherit") => GuestStdin::Inherit,
+                        _ => bail!("unrecognized token {}", token.input.str),
+                    },
+                    [flag, path] if flag.token() == TokenKind::Flag("file") => {
+                        let path = token_str(path);
+                        let contents = std::fs::read(path)
+                            .with_context(|| format!("could not read file '{path}'"))?;
+                        GuestStdin::Bytes(contents.into())
+                    }
+                    _ => bail!("usage: .stdin [$text | --file $path | inherit]"),
+                };
+                runtime.set_stdin(stdin)?;
+            }
+            Cmd::BuiltIn {
+                name: "stub-config",
+                args,
+            } => match args.as_slice() {
+                [] => {
+                    for (name, config) in runtime.stub_configs() {
+                        print_stub_config(&events, name, config);
+                    }
+                }
+                [name] => print_stub_config(
+                    &events,
+                    token_str(name),
+                    &runtime.stub_config(token_str(name)),
+                ),
+                [name, setting, rest @ ..] => {
+                    let name = token_str(name);
+                    let mut config = runtime.stub_config(name);
+                    match (setting.token(), rest) {
+                        (TokenKind::Ident("env"), [key, value]) => {
+                            let key = token_str(key);
+                            config.env.retain(|(k, _)| k != key);
+                            config.env.push((key.to_owned(), token_str(value).to_owned()));
+                        }
+                        (TokenKind::Ident("dir"), [host, guest]) => {
+                            config
+                                .dirs
+                                .push((token_str(host).to_owned(), token_str(guest).to_owned()));
+                        }
+                        (TokenKind::Ident("stdin"), []) => config.stdin = GuestStdin::Empty,
+                        (TokenKind::Ident("stdin"), [t]) if t.token() == TokenKind::Ident("inherit") => {
+                            config.stdin = GuestStdin::Inherit
+                        }
+                        (TokenKind::Ident("stdin"), [text]) => {
+                            config.stdin = GuestStdin::Bytes(token_str(text).to_owned().into())
+                        }
+                        _ => bail!("usage: .stub-config $name [env $key $value | dir $host $guest | stdin [$text | inherit]]"),
+                    }
+                    runtime.set_stub_config(name, config)?;
+                }
+            },
+            Cmd::BuiltIn {
+                name: "stub-reset",
+                args,
+            } => {
+                let [name] = args.as_slice() else {
+                    bail!("usage: .stub-reset $name");
+                };
+                runtime.reset_stub(token_str(name))?;
+            }
+            Cmd::BuiltIn {
+                name: "capture",
+                args,
+            } => match args.as_slice() {
+                [t] if t.token() == TokenKind::Ident("on") => runtime.set_capture(true),
+                [t] if t.token() == TokenKind::Ident("off") => runtime.set_capture(false),
+                [] => bail!("usage: .capture [on | off | $expr]"),
+                _ => {
+                    let line = parser::Line::parse(args.into_iter().collect())
+                        .map_err(|e| anyhow::anyhow!("{e}"))?;
+                    let parser::Line::Expr(expr) = line else {
+                        bail!("only expressions can be captured")
+                    };
+                    let capture_enabled = runtime.capture_enabled();
+                    // Discard anything left over from earlier failed calls
+                    let _ = runtime.take_captured();
+                    let _ = runtime.take_captured_logs();
+                    runtime.set_capture(true);
+                    let result = Cmd::Eval(expr).run(runtime, resolver, scope);
+                    runtime.set_capture(capture_enabled);
+                    result?;
+                }
+            },
+            Cmd::BuiltIn {
+                name: "profile",
+                mut args,
+            } => {
+                const USAGE: &str = "usage: .profile $expr --out $path";
+                let out = args
+                    .iter()
+                    .position(|t| t.token() == TokenKind::Flag("out"))
+                    .context(USAGE)?;
+                let path = match args.get(out + 1).map(|t| t.token()) {
+                    Some(TokenKind::String(path)) => PathBuf::from(path),
+                    _ => bail!(USAGE),
+                };
+                args.drain(out..out + 2);
+                let line = parser::Line::parse(args.into_iter().collect())
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+                let parser::Line::Expr(
+                    expr @ parser::Expr::FunctionCall(_) | expr @ parser::Expr::MethodCall(_),
+                ) = line
+                else {
+                    bail!("only calls can be profiled")
+                };
+                let (result, profile) =
+                    runtime.profile(|runtime| Cmd::Eval(expr).run(runtime, resolver, scope))?;
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                let file = std::fs::File::create(&path)
+                    .with_context(|| format!("could not create '{}'", path.display()))?;
+                profile.write(&name, std::io::BufWriter::new(file))?;
+                events.print(format!(
+                    "{} {} samples written to '{}'",
+                    "Profiled:".green().bold(),
+                    profile.sample_count(),
+                    path.display()
+                ));
+                result?;
+            }
+            Cmd::BuiltIn { name: "fuzz", args } => {
+                const USAGE: &str = "usage: .fuzz $func [--runs $n] [--seed $n]";
+                let mut args = args.into_iter().collect::<VecDeque<_>>();
+                let Ok(Some(Ident::Item(ident))) = Ident::try_parse(&mut args) else {
+                    bail!(USAGE);
+                };
+                let (mut runs, mut seed) = (100, None);
+                while let Some(flag) = args.pop_front() {
+                    match (flag.token(), args.pop_front().map(|t| t.token())) {
+                        (TokenKind::Flag("runs"), Some(TokenKind::Number(n))) => runs = n,
+                        (TokenKind::Flag("seed"), Some(TokenKind::Number(n))) => {
+                            seed = Some(n as u64)
+                        }
+                        _ => bail!(USAGE),
+                    }
+                }
+                let seed = seed.unwrap_or_else(rand::random);
+                resolver.find_exported_function(ident)?;
+                // Every failing input would otherwise leave a core dump behind
+                let coredump = runtime.coredump();
+                runtime.set_coredump(false);
+                let result = fuzz(runtime, ident, runs, seed);
+                runtime.set_coredump(coredump);
+                let format_call = |args: &[Val]| {
+                    let args = args.iter().map(format_val).collect::<Vec<_>>();
+                    format!("{ident}({})", args.join(", "))
+                };
+                match result? {
+                    None => events.print(format!(
+                        "{} {runs} runs of '{ident}' passed (seed {seed})",
+                        "Fuzzed:".green().bold()
+                    )),
+                    Some(failure) => {
+                        events.print(format!(
+                            "{} on run {} (seed {seed})",
+                            "Fuzzing failed".red().bold(),
+                            failure.run
+                        ));
+                        events.print(format!(
+                            "  {}  {}",
+                            "input:".bold(),
+                            format_call(&failure.input)
+                        ));
+                        events.print(format!(
+                            "  {} {}",
+                            "shrunk:".bold(),
+                            format_call(&failure.shrunk)
+                        ));
+                        return Err(failure.error);
+                    }
+                }
+            }
+            Cmd::BuiltIn { name: "map", args } => {
+                const USAGE: &str = "usage: .map $func over $var [--parallel $n] [--into $var]";
+                let mut args = args.into_iter().collect::<VecDeque<_>>();
+                let Ok(Some(Ident::Item(ident))) = Ident::try_parse(&mut args) else {
+                    bail!(USAGE);
+                };
+                let (Some(TokenKind::Ident("over")), Some(TokenKind::Ident(var))) = (
+                    args.pop_front().map(|t| t.token()),
+                    args.pop_front().map(|t| t.token()),
+                ) else {
+                    bail!(USAGE);
+                };
+                let (mut parallel, mut into) = (1, None);
+                while let Some(flag) = args.pop_front() {
+                    match (flag.token(), args.pop_front().map(|t| t.token())) {
+                        (TokenKind::Flag("parallel"), Some(TokenKind::Number(n))) => parallel = n,
+                        (TokenKind::Flag("into"), Some(TokenKind::Ident(name))) => {
+                            into = Some(name)
+                        }
+                        _ => bail!(USAGE),
+                    }
+                }
+                let Some(Val::List(items)) = scope.get(var) else {
+                    bail!("'{var}' is not a list in scope");
+                };
+                resolver.find_exported_function(ident)?;
+                let func = runtime.get_func(ident)?;
+                let params = func.params(&runtime.store).len();
+                // Functions taking several arguments are mapped over tuples of them
+                let inputs = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| match item {
+                        _ if params == 1 => Ok(vec![item.clone()]),
+                        Val::Tuple(args) if args.len() == params => Ok(args.clone()),
+                        _ => bail!(
+                            "{var}[{i}] is not a tuple of the {params} arguments of '{ident}'"
+                        ),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let start = std::time::Instant::now();
+                let outcomes = runtime.map(ident, &inputs, parallel)?;
+                let elapsed = start.elapsed();
+                let mut results = Vec::new();
+                let mut failures = Vec::new();
+                for (i, outcome) in outcomes.into_iter().enumerate() {
+                    match outcome {
+                        Ok(mut vals) if vals.len() == 1 => results.push(vals.remove(0)),
+                        Ok(vals) => results.push(Val::Tuple(vals)),
+                        Err(e) => failures.push((i, e)),
+                    }
+                }
+                events.print(format!(
+                    "{} '{ident}' over {} elements in {elapsed:?} ({} succeeded, {} failed)",
+                    "Mapped:".green().bold(),
+                    inputs.len(),
+                    results.len(),
+                    failures.len()
+                ));
+                for (i, e) in &failures {
+                    let messages = crate::error_messages(e);
+                    events.print(format!(
+                        "  {} {}",
+                        format!("{var}[{i}]:").red().bold(),
+                        messages.join(": ")
+                    ));
+                }
+                // Functions without results have nothing to collect
+                if !func.results(&runtime.store).is_empty() {
+                    let results = Val::List(results);
+                    if let Some(into) = into {
+                        scope.insert(into.to_owned(), results.clone());
+                    }
+                    runtime.events().emit(Event::Results {
+                        values: vec![results],
+                        table: events.tables(),
+                    });
+                }
+            }
+            Cmd::BuiltIn {
+                name: "format",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    [t] if t.token() == TokenKind::Ident("table") => events.set_tables(true),
+                    [t] if t.token() == TokenKind::Ident("plain") => events.set_tables(false),
+                    _ => bail!("usage: .format [plain | table]"),
+                }
+                let format = if events.tables() { "table" } else { "plain" };
+                events.print(format!("{}: {format}", "Format".blue().bold()));
+            }
+            Cmd::BuiltIn {
+                name: "stats",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    [t] if t.token() == TokenKind::Ident("on") => runtime.set_stats(true),
+                    [t] if t.token() == TokenKind::Ident("off") => runtime.set_stats(false),
+                    _ => bail!("usage: .stats [on | off]"),
+                }
+                let stats = if runtime.stats() { "on" } else { "off" };
+                events.print(format!("{}: {stats}", "Stats".blue().bold()));
+                if runtime.stats() && !runtime.engine_options().fuel {
+                    events.print("  fuel is only counted with `.config fuel on` or `--fuel`");
+                }
+            }
+            Cmd::BuiltIn {
+                name: "output",
+                args,
+            } => match args.as_slice() {
+                [] => {
+                    for (name, source) in [
+                        ("guest", OutputSource::Guest),
+                        ("import", OutputSource::Import),
+                    ] {
+                        let style = runtime.output_style(source);
+                        let prefix = match &style.prefix {
+                            Some(prefix) => format!(
+                                "\"{}\" ({:?})",
+                                prefix.color(style.color).bold(),
+                                style.color
+                            ),
+                            None => "off".into(),
+                        };
+                        events.print(format!("{}: prefix {prefix}", name.bold()));
+                    }
+                }
+                [setting, source, value] => {
+                    let source = match source.token() {
+                        TokenKind::Ident("guest") => OutputSource::Guest,
+                        TokenKind::Ident("import") => OutputSource::Import,
+                        _ => bail!(
+                            "unrecognized output source '{}', expected 'guest' or 'import'",
+                            source.input.str
+                        ),
+                    };
+                    let mut style = runtime.output_style(source);
+                    match setting.token() {
+                        TokenKind::Ident("prefix") => {
+                            style.prefix = match value.token() {
+                                TokenKind::Ident("off") => None,
+                                _ => Some(token_str(value).to_owned()),
+                            };
+                        }
+                        TokenKind::Ident("color") => {
+                            let color = token_str(value);
+                            style.color = color
+                                .parse()
+                                .map_err(|_| anyhow::anyhow!("unrecognized color '{color}'"))?;
+                        }
+                        _ => bail!(
+                            "unrecognized output setting '{}', expected 'prefix' or 'color'",
+                            setting.input.str
+                        ),
+                    }
+                    runtime.set_output_style(source, style);
+                }
+                _ => bail!("usage: .output [prefix | color] [guest | import] $value"),
+            },
+            Cmd::BuiltIn {
+                name: "clock",
+                args,
+            } => {
+                let clock = &runtime.wasi_config().clock;
+                match args.as_slice() {
+                    [] => {}
+                    [sub] if sub.token() == TokenKind::Ident("real") => clock.unfreeze(),
+                    [sub] if sub.token() == TokenKind::Ident("freeze") => clock.freeze(
+                        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?,
+                    ),
+                    [sub, rest @ ..] if sub.token() == TokenKind::Ident("freeze") => {
+                        clock.freeze(crate::clock::parse_timestamp(&joined_str(rest))?)
+                    }
+                    [sub, rest @ ..]
+                        if sub.token() == TokenKind::Ident("advance") && !rest.is_empty() =>
+                    {
+                        clock.advance(crate::clock::parse_duration(&joined_str(rest))?)
+                    }
+                    _ => bail!("usage: .clock [freeze [$timestamp] | advance $duration | real]"),
+                }
+                match clock.frozen_at() {
+                    Some(time) => events.print(format!(
+                        "{}: frozen at {}",
+                        "Clock".blue().bold(),
+                        crate::clock::format_timestamp(time)
+                    )),
+                    None => events.print(format!("{}: real", "Clock".blue().bold())),
+                }
+            }
+            Cmd::BuiltIn {
+                name: "random",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    [sub, seed] if sub.token() == TokenKind::Ident("seed") => {
+                        let TokenKind::Number(seed) = seed.token() else {
+                            bail!("random seed must be a number")
+                        };
+                        runtime.set_random_seed(Some(seed.try_into()?))?;
+                    }
+                    [sub] if sub.token() == TokenKind::Ident("real") => {
+                        runtime.set_random_seed(None)?
+                    }
+                    _ => bail!("usage: .random [seed $n | real]"),
+                }
+                match runtime.wasi_config().random_seed {
+                    Some(seed) => {
+                        events.print(format!("{}: seeded with {seed}", "Random".blue().bold()))
+                    }
+                    None => events.print(format!("{}: real", "Random".blue().bold())),
+                }
+            }
+            Cmd::BuiltIn {
+                name: "unstubbed",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    [t] if t.token() == TokenKind::Ident("prompt") => {
+                        runtime.set_prompt_imports(true)
+                    }
+                    [t] if t.token() == TokenKind::Ident("warn") => {
+                        runtime.set_prompt_imports(false)
+                    }
+                    _ => bail!("usage: .unstubbed [prompt | warn]"),
+                }
+                let mode = if runtime.prompt_imports() {
+                    "prompt"
+                } else {
+                    "warn"
+                };
+                events.print(format!("{}: {mode}", "Unstubbed imports".blue().bold()));
+            }
+            Cmd::BuiltIn {
+                name: "trace",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    [t] if t.token() == TokenKind::Ident("on") => runtime.set_tracing(true),
+                    [t] if t.token() == TokenKind::Ident("off") => runtime.set_tracing(false),
+                    _ => bail!("usage: .trace [on | off]"),
+                }
+                let mode = if runtime.tracing() { "on" } else { "off" };
+                events.print(format!("{}: {mode}", "Tracing".blue().bold()));
+            }
+            Cmd::BuiltIn {
+                name: "config",
+                args,
+            } => {
+                const USAGE: &str = "usage: .config [$feature on | off | default] [opt-level $level] [compiler $compiler] [debug-info on | off] [pooling on | off] [fuel on | off] [profiling on | off] [set $key $value]";
+                let on_off = |value| match value {
+                    "on" => Ok(true),
+                    "off" => Ok(false),
+                    _ => bail!(USAGE),
+                };
+                let (setting, value) = match args.as_slice() {
+                    [] => {
+                        events.print(runtime.engine_options().to_string());
+                        if let Some(values) = runtime.config_values() {
+                            events.print(format!("{}:", "wasi:config".blue().bold()));
+                            for (key, value) in values {
+                                events.print(format!(
+                                    "  {key} = {}",
+                                    format_val(&Val::String(value))
+                                ));
+                            }
+                        }
+                        return Ok(Outcome::Done);
+                    }
+                    [sub, key, value] if sub.token() == TokenKind::Ident("set") => {
+                        runtime.set_config_value(token_str(key), token_str(value))?;
+                        return Ok(Outcome::Done);
+                    }
+                    [setting, value] => match (setting.token(), value.token()) {
+                        (TokenKind::Ident(setting), TokenKind::Ident(value)) => (setting, value),
+                        _ => bail!(USAGE),
+                    },
+                    _ => bail!(USAGE),
+                };
+                let mut options = runtime.engine_options().clone();
+                match setting {
+                    "opt-level" => {
+                        options.opt_level = clap::ValueEnum::from_str(value, false)
+                            .map_err(|_| anyhow::anyhow!("unknown opt-level '{value}' (expected none, speed or speed-and-size)"))?
+                    }
+                    "compiler" => {
+                        options.compiler = clap::ValueEnum::from_str(value, false)
+                            .map_err(|_| anyhow::anyhow!("unknown compiler '{value}' (expected cranelift or winch)"))?
+                    }
+                    "debug-info" => options.debug_info = on_off(value)?,
+                    "pooling" => options.pooling = on_off(value)?,
+                    "fuel" => options.fuel = on_off(value)?,
+                    "profiling" => options.profiling = on_off(value)?,
+                    feature => {
+                        let feature = <WasmFeature as clap::ValueEnum>::from_str(feature, false)
+                            .map_err(|_| anyhow::anyhow!("unknown setting '{feature}'\n{USAGE}"))?;
+                        let enable = match value {
+                            "default" => None,
+                            value => Some(on_off(value)?),
+                        };
+                        options.set_feature(feature, enable);
+                    }
+                }
+                return Ok(Outcome::Configure(options));
+            }
+            Cmd::BuiltIn {
+                name: "log-level",
+                args,
+            } => {
+                const USAGE: &str =
+                    "usage: .log-level [trace | debug | info | warn | error | critical | off] | .log-level wepl [$level | $filter]";
+                if let [t, filter @ ..] = args.as_slice() {
+                    if t.token() == TokenKind::Ident("wepl") {
+                        match filter {
+                            [] => {}
+                            [filter] => crate::diagnostics::set_filter(token_str(filter))?,
+                            _ => bail!(USAGE),
+                        }
+                        let filter =
+                            crate::diagnostics::filter().unwrap_or_else(|| "off".to_owned());
+                        events.print(format!("{}: {filter}", "wepl log filter".blue().bold()));
+                        return Ok(Outcome::Done);
+                    }
+                }
+                let logger = runtime
+                    .logger()
+                    .context("the component does not import wasi:logging")?;
+                match args.as_slice() {
+                    [] => {}
+                    [t] if t.token() == TokenKind::Ident("off") => logger.set_level(None),
+                    [t] => match t.token() {
+                        TokenKind::Ident(level) => {
+                            logger.set_level(Some(Level::parse(level).context(USAGE)?))
+                        }
+                        _ => bail!(USAGE),
+                    },
+                    _ => bail!(USAGE),
+                }
+                let level = logger.level().map_or("off", Level::name);
+                events.print(format!("{}: {level}", "Log level".blue().bold()));
+            }
+            Cmd::BuiltIn {
+                name: "coredump",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    // Core dumps are captured by the engine, which has to be
+                    // set up to capture them first
+                    [t] if t.token() == TokenKind::Ident("on")
+                        && !runtime.engine_options().coredumps =>
+                    {
+                        let mut options = runtime.engine_options().clone();
+                        options.coredumps = true;
+                        return Ok(Outcome::Configure(options));
+                    }
+                    [t] if t.token() == TokenKind::Ident("on") => runtime.set_coredump(true),
+                    [t] if t.token() == TokenKind::Ident("off") => runtime.set_coredump(false),
+                    _ => bail!("usage: .coredump [on | off]"),
+                }
+                let mode = if runtime.coredump() { "on" } else { "off" };
+                events.print(format!("{}: {mode}", "Core dumps".blue().bold()));
+            }
+            Cmd::BuiltIn {
+                name: "state",
+                args,
+            } => {
+                match args.as_slice() {
+                    [] => {}
+                    [t] if t.token() == TokenKind::Ident("keep") => {
+                        runtime.set_state_mode(StateMode::Keep)
+                    }
+                    [t] if t.token() == TokenKind::Ident("fresh") => {
+                        runtime.set_state_mode(StateMode::Fresh)
                     }
+                    _ => bail!("usage: .state [keep | fresh]"),
                 }
+                let mode = match runtime.state_mode() {
+                    StateMode::Keep => "keep",
+                    StateMode::Fresh => "fresh",
+                };
+                events.print(format!("{}: {mode}", "State".blue().bold()));
             }
-            Cmd::BuiltIn { name: "type", args } => {
-                match args.as_slice() {
-                    &[token] => {
-                        let TokenKind::Ident(name) = token.token() else {
-                            bail!("unrecognized token")
-                        };
-                        let types = resolver.types_by_name(name);
-                        for (interface, ty) in &types {
-                            let typ = resolver.display_wit_type_def(ty, Expansion::Expanded(1));
-                            let name = &ty.name;
-                            let interface = interface.and_then(|i| resolver.interface_name(i));
-                            let ident = match (interface, name) {
-                                (Some(i), Some(n)) => format!("{i}#{n}: "),
-                                (None, Some(n)) => format!("{n}: "),
-                                _ => todo!(),
-                            };
-                            println!("{ident}{typ}");
-                        }
+            Cmd::BuiltIn {
+                name: "help",
+                args: _,
+            } => print_help(&events),
+            Cmd::BuiltIn {
+                name: "clear",
+                args: _,
+            } => return Ok(Outcome::ClearScreen),
+            Cmd::BuiltIn {
+                name: "reload",
+                args,
+            } => {
+                let &[] = args.as_slice() else {
+                    bail!("usage: .reload");
+                };
+                return Ok(Outcome::Load {
+                    path: None,
+                    name: None,
+                });
+            }
+            Cmd::BuiltIn { name: "load", args } => {
+                let (path, name) = match args.as_slice() {
+                    [path] => (path, None),
+                    [flag, name, path] if flag.token() == TokenKind::Flag("as") => {
+                        (path, Some(token_str(name).to_owned()))
                     }
-                    _ => bail!(
-                        "wrong number of arguments to inspect function. Expected 1 got {}",
-                        args.len()
-                    ),
+                    _ => bail!("usage: .load [--as $name] $path"),
                 };
+                return Ok(Outcome::Load {
+                    path: Some(token_str(path).into()),
+                    name,
+                });
             }
             Cmd::BuiltIn {
-                name: "compose",
+                name: "instances",
                 args,
             } => {
-                let &[token] = args.as_slice() else {
-                    bail!(
-                        "wrong number of arguments to compose function. Expected 1 got {}",
-                        args.len()
-                    )
-                };
-                let TokenKind::String(path) = token.token() else {
-                    bail!("unrecognized token {}", token.input.str);
+                let &[] = args.as_slice() else {
+                    bail!("usage: .instances");
                 };
-                let adapter =
-                    std::fs::read(path).context("could not read path to adapter module")?;
-                runtime.compose(&adapter)?;
-                *resolver = WorldResolver::from_bytes(runtime.component_bytes())?;
+                return Ok(Outcome::Instances);
             }
-            Cmd::BuiltIn { name: "link", args } => {
-                let mut args = args.into_iter().collect();
-                let Ok(Some(import_ident)) = Ident::try_parse(&mut args) else {
-                    bail!("import_ident is not a proper item identifier");
+            Cmd::BuiltIn { name: "use", args } => {
+                let [name] = args.as_slice() else {
+                    bail!("usage: .use $name");
                 };
-                let Ok(Some(export_ident)) = Ident::try_parse(&mut args) else {
-                    bail!("export_ident is not a proper item identifier");
+                return Ok(Outcome::Use(token_str(name).to_owned()));
+            }
+            Cmd::BuiltIn {
+                name: "alias",
+                args,
+            } => {
+                let [name, command @ ..] = args.as_slice() else {
+                    return Ok(Outcome::Aliases);
                 };
-
-                let Some(TokenKind::String(component)) = args.pop_front().map(|t| t.token()) else {
-                    bail!("component path is not a string");
+                let (TokenKind::Ident(name), false) = (name.token(), command.is_empty()) else {
+                    bail!("usage: .alias $name $command");
                 };
-                let component_bytes = std::fs::read(component)
-                    .with_context(|| format!("could not read component '{component}'"))?;
-                runtime.stub(resolver, import_ident, export_ident, &component_bytes)?;
+                return Ok(Outcome::Alias {
+                    name: name.to_owned(),
+                    command: source_str(command),
+                });
             }
             Cmd::BuiltIn {
-                name: "inspect",
+                name: "unalias",
                 args,
             } => {
-                let mut args = args.into_iter().collect();
-                let Ok(Some(ident)) = Ident::try_parse(&mut args) else {
-                    bail!("ident is not a proper item identifier");
+                let [name] = args.as_slice() else {
+                    bail!("usage: .unalias $name");
                 };
-                match ident {
-                    Ident::Item(ident) => {
-                        let f = resolver
-                            .exported_function(ident)
-                            .or_else(|| resolver.imported_function(ident));
-                        match f {
-                            Some(f) => println!("{}", format_function(f, resolver)),
-                            None => bail!("Could not find imported or exported function '{ident}'"),
-                        }
-                    }
-                    Ident::Interface(ident) => {
-                        let i = resolver
-                            .exported_interface(ident)
-                            .or_else(|| resolver.imported_interface(ident));
-                        match i {
-                            Some(f) => println!("{}", format_interface(f, resolver)),
-                            None => {
-                                bail!("Could not find imported or exported interface '{ident}'")
-                            }
-                        }
+                return Ok(Outcome::Unalias(token_str(name).to_owned()));
+            }
+            Cmd::BuiltIn {
+                name: "prompt",
+                args,
+            } => {
+                return match args.as_slice() {
+                    [] => Ok(Outcome::ShowPrompt),
+                    [t] if t.token() == TokenKind::Ident("default") => Ok(Outcome::Prompt(None)),
+                    [t] if matches!(t.token(), TokenKind::String(_)) => {
+                        let template = token_str(t);
+                        crate::prompt::check(template)?;
+                        Ok(Outcome::Prompt(Some(template.to_owned())))
                     }
-                }
+                    _ => bail!("usage: .prompt [$template | default]"),
+                };
+            }
+            Cmd::BuiltIn { name: "exit", args } => {
+                let status = match args.as_slice() {
+                    [] => 0,
+                    [t] => match t.token() {
+                        TokenKind::Number(n) if n <= 255 => n as i32,
+                        _ => bail!("the exit status has to be a number from 0 to 255"),
+                    },
+                    _ => bail!("usage: .exit [$status]"),
+                };
+                return Ok(Outcome::Exit(status));
             }
             Cmd::BuiltIn {
-                name: "help",
-                args: _,
-            } => print_help(),
+                name: "history",
+                args,
+            } => {
+                let count = match args.as_slice() {
+                    [] => 20,
+                    [t] => match t.token() {
+                        TokenKind::Number(n) => n,
+                        _ => bail!("usage: .history [$n]"),
+                    },
+                    _ => bail!("usage: .history [$n]"),
+                };
+                return Ok(Outcome::History(count));
+            }
             Cmd::BuiltIn {
-                name: "clear",
-                args: _,
-            } => return Ok(true),
+                name: "keybindings",
+                args,
+            } => {
+                let keybindings = match args.as_slice() {
+                    [] => None,
+                    [t] if t.token() == TokenKind::Ident("emacs") => Some(Keybindings::Emacs),
+                    [t] if t.token() == TokenKind::Ident("vi") => Some(Keybindings::Vi),
+                    _ => bail!("usage: .keybindings [emacs | vi]"),
+                };
+                return Ok(Outcome::Keybindings(keybindings));
+            }
             Cmd::BuiltIn { name, args: _ } => {
                 bail!("Unrecognized built-in function '{name}'")
             }
         }
-        Ok(false)
+        Ok(Outcome::Done)
     }
 }
 
-fn print_help() {
-    println!("Calling imports can be done like so:
+/// What the repl has to do after a command ran
+pub enum Outcome {
+    Done,
+    ClearScreen,
+    /// Load the component at `path`, or the instance's component again from
+    /// disk, into the instance `name` (the current instance by default)
+    Load {
+        path: Option<PathBuf>,
+        name: Option<String>,
+    },
+    /// Satisfy the import `import` of the instance `importer` with the
+    /// exports of the live instance `exporter`
+    Link {
+        importer: String,
+        import: String,
+        exporter: String,
+    },
+    /// List the loaded instances
+    Instances,
+    /// Run commands against the instance with the given name
+    Use(String),
+    /// Define the alias `name`, which expands to `command` at the start of
+    /// a line
+    Alias {
+        name: String,
+        command: String,
+    },
+    /// Remove the alias with the given name
+    Unalias(String),
+    /// List the aliases
+    Aliases,
+    /// Stop the repl, exiting with the given status
+    Exit(i32),
+    /// List the given number of the most recent lines of the history
+    History(usize),
+    /// Run the line numbered `entry` in the history again, or let the user
+    /// edit it first
+    Rerun {
+        entry: usize,
+        edit: bool,
+    },
+    /// Let the user edit the given line before it runs
+    Edit(String),
+    /// Render the prompt from the given template, or show the default prompt
+    /// without one
+    Prompt(Option<String>),
+    /// Show the template of the prompt
+    ShowPrompt,
+    /// Edit lines with the given keybindings, or show the ones in use
+    Keybindings(Option<Keybindings>),
+    /// Compile every instance again with the given options
+    Configure(EngineOptions),
+}
+
+/// The keybindings lines are edited with in the terminal
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Keybindings {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+/// The names of the built-in functions, without their leading `.`
+pub const BUILTINS: &[&str] = &[
+    "alias",
+    "audit",
+    "await",
+    "calls",
+    "capture",
+    "clear",
+    "clock",
+    "compose",
+    "config",
+    "coredump",
+    "diff",
+    "drop",
+    "env",
+    "example",
+    "exit",
+    "export-csv",
+    "exports",
+    "format",
+    "freeze-world",
+    "fuzz",
+    "handle",
+    "help",
+    "history",
+    "http-request",
+    "import-csv",
+    "imports",
+    "info",
+    "inspect",
+    "instances",
+    "keybindings",
+    "kv",
+    "limit",
+    "link",
+    "load",
+    "log-level",
+    "map",
+    "mock",
+    "output",
+    "plugin",
+    "profile",
+    "prompt",
+    "provide",
+    "random",
+    "read",
+    "record-imports",
+    "read-file",
+    "reload",
+    "replay-imports",
+    "reset",
+    "resources",
+    "restore",
+    "run",
+    "scaffold",
+    "search",
+    "serve",
+    "snapshot",
+    "spy",
+    "state",
+    "stats",
+    "stdin",
+    "stream",
+    "stub",
+    "stub-config",
+    "stub-proc",
+    "stub-reset",
+    "trace",
+    "type",
+    "unalias",
+    "unset",
+    "unstub",
+    "unstubbed",
+    "use",
+    "validate",
+    "wit",
+    "world",
+    "write",
+];
+
+fn print_help(events: &Events) {
+    events.print("Calling imports can be done like so:
 
 > my-func(my-arg)
 
@@ -243,12 +2232,451 @@ Variables can be saved as well:
 
 There are also builtin functions that can be called with a preceding '.'. Supported functions include:
   .imports                  print a list of all the component's imports
+  .audit                    list the imports by the access to the host they give (filesystem, network, ...) and whether the component used them
   .exports                  print a list of all the component's exports
-  .link $function $wasm     satisfy the imported function `$func` with an export from the wasm component `$wasm`
-  .compose $adapter         satisfy imports with the supplied adapter module (e.g., to compose with WASI-Virt adapter)
+  .link $function $wasm     satisfy the imported function `$func` with an export from the wasm component `$wasm` (`--lenient` accepts compatible instead of equal types)
+  .link $a needs $i from $b satisfy the import `$i` of the instance `$a` with the exports of the loaded instance `$b`
+  .stub $import => $value   make the imported function `$import` return `$value` every time it is called
+  .stub $iface --wat $path  satisfy the imported interface `$iface` with the component or core module in the WAT file `$path`
+  .stub-proc $iface -- $cmd satisfy the imported interface `$iface` by sending each call as JSON-RPC to a process running `$cmd`
+  .plugin $path             satisfy the imports the native plugin library at `$path` implements
+  .mock $interface          stub every function of the imported interface `$interface` with one returning default values (new instances for imported resources, of which a component can import at most 32)
+  .provide wasi:keyvalue memory implement the imported wasi:keyvalue interfaces with in-memory stores
+  .kv set $store $key $val  set `$key` in the in-memory wasi:keyvalue store `$store` (`.kv get $store $key` and `.kv dump [$store]` inspect the stores)
+  .await $handle            wait until the pollable or stream `$handle` is ready (`--timeout $duration` gives up after `$duration`)
+  .read $handle [$len]      read up to `$len` bytes from the input stream `$handle` returned by an export
+  .write $handle $string    write `$string` to the output stream `$handle` returned by an export
+  .write $var $path         write the value of `$var` to the file `$path` as raw bytes, text or JSON (`--as bytes|text|json` picks)
+  .import-csv $var $path    read the CSV file `$path` into `$var` as a list of records, with `--type list<$record>` naming the record type
+  .export-csv $var $path    write the list of records `$var` to the CSV file `$path` with a header row of field names
+  .http-request $var $method $url bind `$var` to a `wasi:http/types#outgoing-request` for `$url` (`--header $name:$value` adds headers)
+  .read-file $var $path     read the file `$path` into `$var` as a `list<u8>` (`--as string` reads it as text)
+  .stream push $h $value    push a chunk to the host-side queue `$h` standing in for a stream (`.stream read $h`, `.stream close $h` and `.stream` use the queues)
+  .record-imports $path     record every call to an import with its results into the JSON file `$path` (`.record-imports off` stops)
+  .freeze-world $path       freeze the clocks and randomness and record them, the environment and the calls to imports into the bundle `$path` for `--world-bundle` (`.freeze-world off` stops)
+  .replay-imports $path     stub the imports called in the recording `$path` with ones returning the recorded results in order
+  .trace on                 print every call the component makes to its imports with its arguments, results and duration (`.trace off` stops)
+  .config $setting $value  change how components are compiled and reload them (e.g. `.config simd off`, `.config opt-level none`; `.config set $key $value` sets a value of wasi:config and no arguments lists the settings)
+  .coredump on              write a core dump of the component to a file when a call traps, for debuggers like wasmgdb (the first time reloads the instances to capture them; `.coredump off` stops)
+  .spy $import              record every call to the imported function `$import` (see `.calls`)
+  .limit $import $n         let the component call the import `$import` (an interface or function) `$n` more times before calls to it fail (`off` lifts the limit, `.limit` lists them)
+  .calls $import            show the calls recorded for `$import` by `.spy` (`.calls clear` forgets them)
+  .stub-config $name $cfg   configure the WASI context of the stub component `$name` (`env $key $value`, `dir $host $guest` or `stdin $text`)
+  .stub-reset $name         instantiate the stub component `$name` again, dropping its state
+  .unstub $import           restore the imported function `$import` to its definition on startup (`.unstub all` restores every import)
+  .compose $adapter...      satisfy imports with the supplied adapter modules (e.g., to compose with WASI-Virt adapter; `--config $path` reads a wasm-compose configuration)
+  .run $args...             invoke the component's `wasi:cli/run` export with the arguments `$args`
+  .env set $key $value      set the environment variable `$key` seen by the component (`.env list` to list them)
+  .stdin $text              feed `$text` to the component's stdin (also `--file $path`, `inherit`, or nothing to clear)
+  .capture $expr            evaluate `$expr`, binding the guest's output to the `stdout` and `stderr` variables and its wasi:logging records to `logs` (`.capture on|off` toggles this for every call)
+  .log-level $level         only print the records the guest logs through wasi:logging at `$level` or above (`off` prints none)
+  .log-level wepl $filter   log wepl's own spans and events at a level (e.g. `debug`) or with `RUST_LOG` directives (e.g. \"wepl=trace,wasmtime=debug\")
+  .fuzz $func               call the exported function `$func` with random arguments, shrinking the first that makes it fail (`--runs $n` and `--seed $n`)
+  .map $func over $var      call the exported function `$func` once per element of the list `$var`, each in a fresh instance, and print the results (`--parallel $n` makes `$n` calls at once and `--into $var` binds the results)
+  .profile $expr --out $p   profile the call `$expr`, writing the samples of the guest's stack to `$p` in speedscope's format (needs `.config profiling on`)
+  .format table             print lists of records as tables with a column for each field (`.format plain` goes back)
+  .stats on                 print how much memory, table space and resources every call adds and the fuel it consumes (`.stats off` stops)
+  .output prefix $src $pfx  prefix each line of output from `$src` (`guest` or `import`) with `$pfx` (`off` to disable; `.output color $src $color` sets its color)
+  .clock freeze $time       freeze the component's clocks at `$time` (e.g. \"2024-01-01T00:00:00Z\"; `.clock advance 5s` moves them, `.clock real` unfreezes)
+  .random seed $n           make the component's randomness deterministic using seed `$n` (`.random real` undoes this)
+  .unstubbed prompt         prompt for the return values when an import nothing satisfies is called (`.unstubbed warn` only warns)
+  .state keep               keep the guest's state when stubbing imports if possible, warning when it is reset (`.state fresh` gets a new instance every time)
+  .serve $addr              serve the component's `wasi:http/incoming-handler` export over HTTP on `$addr`
+  .handle $method $path     call the `wasi:http/incoming-handler` export once with a request for `$path` and print the response (`--header $name:$value` and `--body $body` fill in the request)
+  .reload                   load the component again from disk, reapplying the stubs and settings of the session
+  .load $path               switch to the component at `$path`, keeping the variables that do not hold resources
+  .load --as $name $path    load the component at `$path` alongside the current one as the instance `$name`
+  .instances                list the loaded instances
+  .use $name                run commands against the instance `$name` (`$name.func(...)` calls a function of another instance)
+  .exit $status             stop the repl, exiting with `$status` (0 by default) so scripts can signal their own failures
+  .history $n               list the last `$n` lines typed in the session (20 by default) along with their numbers
+  .! $n                     run the line numbered `$n` in the history again (`.! $n --edit` edits it first)
+  .alias $name $command     make `$name` at the start of a line stand for `$command`, saving the alias in ~/.weplrc (no arguments lists the aliases; `.unalias $name` removes one)
+  .prompt $template         render the prompt from `$template`, e.g. \"{component}:{world}{dirty}> \" where `{dirty}` is `*` when the component changed on disk (`.prompt default` goes back to the default)
+  .keybindings vi           edit lines with vi keybindings, e.g. from ~/.weplrc (`.keybindings emacs` goes back to the default)
+  .unset $var               remove the variable `$var` from scope, dropping the resource it holds if any
+  .reset instance           get a new instance of the component, dropping its state (`vars` removes all variables and `all` does both)
+  .snapshot $name           save the calls made to the exports since the instance was created as `$name` (no argument lists the snapshots)
+  .restore $name            get a new instance and make the calls saved as `$name` again to get back to its state
+  .resources                list the live resource handles returned by the component
+  .drop $var                drop the resource handle in `$var`, running the component's destructor (`--unnamed` drops the handles no variable holds)
+  .info                     show the component's size by section and nested module, its WIT packages, producers and registry metadata
+  .validate                 validate the component with every WebAssembly feature and warn about unknown custom sections, missing producers and unusual names
+  .world                    list the worlds of the component's WIT, marking the one the repl uses (`.world $name` picks another)
+  .wit                      print the WIT of the component's world (`--out $dir` writes it with its dependencies to `$dir` for `wit-bindgen`)
+  .scaffold $iface --out $dir write a `cargo component` project to `$dir` implementing the imported interface `$iface` with `todo!()` bodies
+  .diff $path               compare the component's world with that of the component at `$path`, showing what changed and how compatible it is
+  .search $query            list the exports, interfaces and types whose names best match `$query`
+  .example $func            print a call of the exported function `$func` with placeholder arguments to fill in
+  .inspect $var.$path       show the type, size and contents of the value at `$path` in the variable `$var` (e.g. `.inspect x.items[3].meta`)
+  !$command                 run `$command` in the host's shell, e.g. `!cargo component build` before `.reload` (not allowed with `--sandbox`)
   .inspect $item            inspect an item `$item` in scope (`?` is alias for this built-in)")
 }
 
+/// An input `.fuzz` found to make a function fail
+struct FuzzFailure {
+    /// The number of the run that found `input`, counting from one
+    run: usize,
+    input: Vec<Val>,
+    /// The smallest input found by shrinking `input` that still fails
+    shrunk: Vec<Val>,
+    /// The error of calling the function with `shrunk`
+    error: anyhow::Error,
+}
+
+/// Call the exported function `ident` with up to `runs` inputs generated from
+/// `seed`, returning the first one that fails
+fn fuzz(
+    runtime: &mut Runtime,
+    ident: parser::ItemIdent<'_>,
+    runs: usize,
+    seed: u64,
+) -> anyhow::Result<Option<FuzzFailure>> {
+    /// The most calls made while shrinking a failing input
+    const MAX_SHRINK_CALLS: usize = 1000;
+    /// The most elements in generated lists and characters in strings
+    const MAX_LEN: usize = 16;
+    let func = runtime.get_func(ident)?;
+    let types = func.params(&runtime.store);
+    let mut generator = crate::fuzz::ValGenerator::new(seed, MAX_LEN);
+    // Failed calls poison the instance so each gets a new one afterwards
+    let call = |runtime: &mut Runtime, args: &[Val]| -> anyhow::Result<Option<anyhow::Error>> {
+        match runtime.call(ident, args) {
+            Ok(_) => Ok(None),
+            Err(e) => {
+                runtime.refresh()?;
+                Ok(Some(e))
+            }
+        }
+    };
+    for run in 1..=runs {
+        let input = types
+            .iter()
+            .map(|ty| generator.generate(ty))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("could not generate arguments for '{ident}'"))?;
+        let Some(mut error) = call(runtime, &input)? else {
+            continue;
+        };
+        let mut call_error = None;
+        let shrunk = crate::fuzz::shrink_args(input.clone(), MAX_SHRINK_CALLS, |args| {
+            match call(runtime, args) {
+                Ok(Some(e)) => {
+                    error = e;
+                    true
+                }
+                Ok(None) => false,
+                Err(e) => {
+                    call_error.get_or_insert(e);
+                    false
+                }
+            }
+        });
+        if let Some(e) = call_error {
+            return Err(e);
+        }
+        return Ok(Some(FuzzFailure {
+            run,
+            input,
+            shrunk,
+            error,
+        }));
+    }
+    Ok(None)
+}
+
+/// Parse the arguments of `.link $importer needs $import from $exporter`
+fn link_instances(mut args: VecDeque<tokenizer::Token<'_>>) -> anyhow::Result<Outcome> {
+    const USAGE: &str = "usage: .link $instance needs $import from $instance";
+    let Some(TokenKind::Ident(importer)) = args.pop_front().map(|t| t.token()) else {
+        bail!(USAGE);
+    };
+    args.pop_front();
+    let Ok(Some(import)) = Ident::try_parse(&mut args) else {
+        bail!("import is not a proper identifier\n{USAGE}");
+    };
+    let (Some(TokenKind::Ident("from")), Some(exporter), None) = (
+        args.pop_front().map(|t| t.token()),
+        args.pop_front(),
+        args.pop_front(),
+    ) else {
+        bail!(USAGE);
+    };
+    Ok(Outcome::Link {
+        importer: importer.to_owned(),
+        import: import.to_string(),
+        exporter: token_str(&exporter).to_owned(),
+    })
+}
+
+/// Build the wasm-compose configuration for the arguments of `.compose`
+///
+/// Paths given as arguments are relative to the current directory while paths
+/// in a `--config` file are relative to the file's directory.
+fn compose_config(args: Vec<tokenizer::Token<'_>>) -> anyhow::Result<wasm_compose::config::Config> {
+    const USAGE: &str = "usage: .compose $adapter... [--config $path] [--definition $path] [--search-path $path] [--dependency $name $path] [--instantiate $name $dependency] [--skip-validation]";
+    let cwd = std::env::current_dir()?;
+    let mut args = args.into_iter().map(|t| t.token());
+    let string = |args: &mut dyn Iterator<Item = TokenKind<'_>>| match args.next() {
+        Some(TokenKind::String(s)) => Ok(s.to_owned()),
+        _ => bail!(USAGE),
+    };
+    let mut config = wasm_compose::config::Config::default();
+    let mut definitions = Vec::new();
+    let mut search_paths = Vec::new();
+    let mut dependencies = Vec::new();
+    let mut instantiations = Vec::new();
+    let mut skip_validation = false;
+    while let Some(token) = args.next() {
+        match token {
+            TokenKind::String(path) => definitions.push(cwd.join(path)),
+            TokenKind::Flag("config") => {
+                config = wasm_compose::config::Config::from_file(cwd.join(string(&mut args)?))?;
+            }
+            TokenKind::Flag("definition") => definitions.push(cwd.join(string(&mut args)?)),
+            TokenKind::Flag("search-path") => search_paths.push(cwd.join(string(&mut args)?)),
+            TokenKind::Flag("dependency") => {
+                dependencies.push((string(&mut args)?, cwd.join(string(&mut args)?)))
+            }
+            TokenKind::Flag("instantiate") => {
+                instantiations.push((string(&mut args)?, string(&mut args)?))
+            }
+            TokenKind::Flag("skip-validation") => skip_validation = true,
+            _ => bail!(USAGE),
+        }
+    }
+    config.definitions.extend(definitions);
+    config.search_paths.extend(search_paths);
+    config.skip_validation |= skip_validation;
+    for (name, path) in dependencies {
+        config
+            .dependencies
+            .insert(name, wasm_compose::config::Dependency { path });
+    }
+    for (name, dependency) in instantiations {
+        config.instantiations.insert(
+            name,
+            wasm_compose::config::Instantiation {
+                dependency: Some(dependency),
+                ..Default::default()
+            },
+        );
+    }
+    if config.definitions.is_empty() && config.dependencies.is_empty() {
+        bail!("nothing to compose with\n{USAGE}");
+    }
+    Ok(config)
+}
+
+/// Bind the guest output captured during the last command to the `stdout`
+/// and `stderr` variables if capturing is enabled.
+fn bind_captured_output(runtime: &mut Runtime, scope: &mut HashMap<String, Val>) {
+    if !runtime.capture_enabled() {
+        return;
+    }
+    let (stdout, stderr) = runtime.take_captured();
+    scope.insert("stdout".into(), Val::String(stdout));
+    scope.insert("stderr".into(), Val::String(stderr));
+    if runtime.logger().is_some() {
+        let logs = runtime.take_captured_logs();
+        let logs = logs.iter().map(LogRecord::to_val).collect();
+        scope.insert("logs".into(), Val::List(logs));
+    }
+}
+
+fn print_env(events: &Events, runtime: &Runtime) {
+    for (key, value) in &runtime.wasi_config().env {
+        events.print(format!("{}={value}", key.bold()));
+    }
+}
+
+fn print_audit(events: &Events, resolver: &WorldResolver, runtime: &Runtime) {
+    let imports = resolver
+        .imports(true)
+        .filter(|(_, item)| !matches!(item, wit_parser::WorldItem::Type(_)))
+        .map(|(name, _)| resolver.world_item_name(name))
+        .collect::<Vec<_>>();
+    for category in Category::ALL {
+        events.print(format!("{}:", category.label().bold()));
+        let mut imports = imports
+            .iter()
+            .filter(|import| Category::of(import) == category)
+            .peekable();
+        if imports.peek().is_none() {
+            events.print(format!("  {}", "none".dimmed()));
+        }
+        for import in imports {
+            let exercised = match runtime.exercised(import) {
+                Some(true) => "used".yellow(),
+                Some(false) => "unused".normal(),
+                None => "not observable".dimmed(),
+            };
+            events.print(format!("  {import}: {exercised}"));
+        }
+    }
+}
+
+fn print_limits(events: &Events, runtime: &Runtime) {
+    for (import, budget) in runtime.limits() {
+        events.print(format!(
+            "{}: {} of {} calls",
+            import.bold(),
+            budget.calls,
+            budget.max
+        ));
+    }
+}
+
+fn print_stub_config(events: &Events, name: &str, config: &StubConfig) {
+    events.print(format!("{}:", name.bold()));
+    for (key, value) in &config.env {
+        events.print(format!("  env {key}={value}"));
+    }
+    for (host, guest) in &config.dirs {
+        events.print(format!("  dir {host} -> {guest}"));
+    }
+    match &config.stdin {
+        GuestStdin::Empty => {}
+        GuestStdin::Bytes(bytes) => {
+            events.print(format!("  stdin {:?}", String::from_utf8_lossy(bytes)))
+        }
+        GuestStdin::Inherit => events.print("  stdin inherited"),
+    }
+}
+
+/// Builtins taking a path or a URL from the raw rest of the line like
+/// `!$command` does, since the tokenizer would reject or split it: how many
+/// arguments come before it and the flags that may follow it
+const RAW_ARGS: &[(&str, usize, &[&str])] = &[
+    ("read-file", 1, &["as"]),
+    ("write", 1, &["as"]),
+    ("import-csv", 1, &["type"]),
+    ("export-csv", 1, &[]),
+    ("http-request", 2, &["header"]),
+];
+
+/// The range of `input` holding the raw path or URL of the builtin it runs,
+/// unless it is quoted or the builtin takes none
+fn raw_arg(input: &str) -> Option<std::ops::Range<usize>> {
+    let line = input.trim_start().strip_prefix('.')?;
+    let name = &line[..line.find(char::is_whitespace)?];
+    let (_, before, flags) = RAW_ARGS.iter().find(|(builtin, ..)| *builtin == name)?;
+    let mut start = input.len() - line.len() + name.len();
+    for _ in 0..*before {
+        let arg = input[start..].trim_start();
+        start = input.len() - arg.len() + arg.find(char::is_whitespace)?;
+    }
+    start = input.len() - input[start..].trim_start().len();
+    let is_flag = |after: &str| {
+        flags.iter().any(|flag| {
+            after
+                .strip_prefix("--")
+                .and_then(|after| after.strip_prefix(flag))
+                .is_some_and(|after| after.is_empty() || after.starts_with(char::is_whitespace))
+        })
+    };
+    let end = input[start..]
+        .match_indices(char::is_whitespace)
+        .map(|(i, _)| start + i)
+        .find(|&i| is_flag(input[i..].trim_start()))
+        .unwrap_or(input.len());
+    let path = input[start..end].trim_end();
+    (!path.is_empty() && !path.starts_with('"')).then(|| start..start + path.len())
+}
+
+/// The path and the format after `--as`, if any, that the arguments `args`
+/// of `.write` and `.read-file` give
+fn path_and_format(args: &[tokenizer::Token<'_>]) -> anyhow::Result<(PathBuf, Option<FileFormat>)> {
+    let (path, format) = match args.iter().position(|t| t.token() == TokenKind::Flag("as")) {
+        Some(i) => (&args[..i], Some(&args[i + 1..])),
+        None => (args, None),
+    };
+    if path.is_empty() || format.is_some_and(<[_]>::is_empty) {
+        bail!("expected a path and optionally a format");
+    }
+    let format = format
+        .map(|format| FileFormat::parse(&joined_str(format)))
+        .transpose()?;
+    Ok((PathBuf::from(joined_str(path)), format))
+}
+
+/// The name and value of the header `header` written as `$name:$value`
+fn http_header(header: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let (name, value) = header
+        .split_once(':')
+        .with_context(|| format!("expected a header like $name:$value, got '{header}'"))?;
+    Ok((name.trim().to_owned(), value.trim().as_bytes().to_vec()))
+}
+
+/// Remove the variables holding resource handles, which belong to the current
+/// instance, returning their names in order
+fn remove_resource_vars(runtime: &Runtime, scope: &mut HashMap<String, Val>) -> Vec<String> {
+    let mut stale = scope
+        .iter()
+        .filter(|(_, val)| runtime.holds_resource(val))
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    stale.sort();
+    for name in &stale {
+        scope.remove(name);
+    }
+    stale
+}
+
+fn print_removed_handles(events: &Events, removed: &[String]) {
+    if !removed.is_empty() {
+        events.print(format!(
+            "{} {}",
+            "Removed resource handles:".yellow().bold(),
+            removed.join(", ")
+        ));
+    }
+}
+
+/// The string a token represents, with quotes removed from string literals
+fn token_str<'a>(token: &tokenizer::Token<'a>) -> &'a str {
+    match token.token() {
+        TokenKind::String(s) => s,
+        _ => token.input.str,
+    }
+}
+
+/// The string represented by a sequence of tokens
+///
+/// Unquoted arguments like `127.0.0.1:8080` or `5s` are split into several
+/// tokens by the tokenizer so they are stitched back together.
+fn joined_str(tokens: &[tokenizer::Token<'_>]) -> String {
+    match tokens {
+        [token] => token_str(token).to_owned(),
+        tokens => tokens.iter().map(|t| t.input.str).collect(),
+    }
+}
+
+/// A process running `command` in the host's shell
+pub(crate) fn shell_command(command: &str) -> std::process::Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut process = std::process::Command::new(shell);
+    process.arg(flag).arg(command);
+    process
+}
+
+/// The input the tokens `tokens` were read from, with the whitespace between
+/// them as spaces
+fn source_str(tokens: &[tokenizer::Token<'_>]) -> String {
+    let mut source = String::new();
+    let mut end = tokens.first().map_or(0, |t| t.input.offset);
+    for token in tokens {
+        source.push_str(&" ".repeat(token.input.offset - end));
+        source.push_str(token.input.str);
+        end = token.input.offset + token.input.str.len();
+    }
+    source
+}
+
 fn format_world_item(item: &wit_parser::WorldItem, resolver: &WorldResolver) -> Option<String> {
     match item {
         wit_parser::WorldItem::Function(f) => Some(format_function(f, resolver)),
@@ -264,6 +2692,62 @@ fn format_world_item(item: &wit_parser::WorldItem, resolver: &WorldResolver) ->
     }
 }
 
+/// The exports and types `.search` looks through, with the name they are
+/// shown with, the name matched against the query and a description
+fn search_items(resolver: &WorldResolver) -> Vec<(String, String, String)> {
+    let mut items = Vec::new();
+    for (key, item) in &resolver.world().exports {
+        let name = resolver.world_item_name(key);
+        match item {
+            wit_parser::WorldItem::Function(f) => {
+                items.push((name.clone(), name, format_function(f, resolver)));
+            }
+            wit_parser::WorldItem::Interface { id, .. } => {
+                let Some(interface) = resolver.interface_by_id(*id) else {
+                    continue;
+                };
+                for (func_name, f) in &interface.functions {
+                    items.push((
+                        format!("{name}#{func_name}"),
+                        func_name.clone(),
+                        format_function(f, resolver),
+                    ));
+                }
+                items.push((name.clone(), name, "interface".into()));
+            }
+            wit_parser::WorldItem::Type(_) => {}
+        }
+    }
+    for (interface, ty) in resolver.named_types() {
+        let Some(name) = ty.name.clone() else {
+            continue;
+        };
+        // Types used from other interfaces are found where they are defined
+        if let wit_parser::TypeDefKind::Type(wit_parser::Type::Id(id)) = ty.kind {
+            if resolver.type_by_id(id).is_some_and(|t| t.name.is_some()) {
+                continue;
+            }
+        }
+        let description = match &ty.kind {
+            wit_parser::TypeDefKind::Record(_) => "record".into(),
+            wit_parser::TypeDefKind::Variant(_) => "variant".into(),
+            wit_parser::TypeDefKind::Enum(_) => "enum".into(),
+            wit_parser::TypeDefKind::Flags(_) => "flags".into(),
+            wit_parser::TypeDefKind::Resource => "resource".into(),
+            _ => format!(
+                "type {}",
+                resolver.display_wit_type_def(ty, Expansion::Collapsed)
+            ),
+        };
+        let shown = match interface {
+            Some(interface) => format!("{interface}#{name}"),
+            None => name.clone(),
+        };
+        items.push((shown, name, description));
+    }
+    items
+}
+
 fn format_interface(interface: &wit_parser::Interface, resolver: &WorldResolver) -> String {
     use std::fmt::Write;
     let mut output = String::from("{\n");
@@ -280,7 +2764,7 @@ fn format_interface(interface: &wit_parser::Interface, resolver: &WorldResolver)
     output
 }
 
-fn format_function(f: &wit_parser::Function, resolver: &WorldResolver) -> String {
+pub fn format_function(f: &wit_parser::Function, resolver: &WorldResolver) -> String {
     let mut params = Vec::new();
     for (param_name, param_type) in &f.params {
         let ty = resolver.display_wit_type(param_type, Expansion::Collapsed);
@@ -308,7 +2792,16 @@ fn format_function(f: &wit_parser::Function, resolver: &WorldResolver) -> String
     format!("func({params}){rets}")
 }
 
-fn format_val(val: &Val) -> String {
+/// Bytes stored by the component, as a string if they are printable UTF-8
+/// and as a list of bytes otherwise
+fn format_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.contains(char::is_control) => format_val(&Val::String(s.to_owned())),
+        _ => format_val(&Val::List(bytes.iter().copied().map(Val::U8).collect())),
+    }
+}
+
+pub fn format_val(val: &Val) -> String {
     match val {
         Val::String(s) => format!(r#""{s}""#),
         Val::Bool(b) => b.to_string(),
@@ -345,15 +2838,21 @@ fn format_val(val: &Val) -> String {
                 .join(", ");
             format!("{{ {fields} }}")
         }
-        Val::Tuple(_) => todo!(),
-        Val::Variant(_, _) => todo!(),
-        Val::Enum(_) => todo!(),
-        Val::Flags(_) => todo!(),
-        Val::Resource(_) => todo!(),
+        Val::Tuple(t) => {
+            let items = t.iter().map(format_val).collect::<Vec<_>>().join(", ");
+            format!("({items})")
+        }
+        Val::Variant(case, payload) => match payload {
+            Some(payload) => format!("{case}({})", format_val(payload)),
+            None => case.clone(),
+        },
+        Val::Enum(case) => case.clone(),
+        Val::Flags(flags) => format!("{{{}}}", flags.join(", ")),
+        Val::Resource(_) => "<resource>".into(),
     }
 }
 
-fn val_as_type(val: &Val) -> &'static str {
+pub fn val_as_type(val: &Val) -> &'static str {
     match val {
         Val::String(_) => "string",
         Val::Bool(_) => "bool",
@@ -372,10 +2871,194 @@ fn val_as_type(val: &Val) -> &'static str {
         Val::Result(_) => "result",
         Val::List(_) => "list",
         Val::Record(_) => "record",
-        Val::Tuple(_) => todo!(),
-        Val::Variant(_, _) => todo!(),
-        Val::Enum(_) => todo!(),
-        Val::Flags(_) => todo!(),
-        Val::Resource(_) => todo!(),
+        Val::Tuple(_) => "tuple",
+        Val::Variant(_, _) => "variant",
+        Val::Enum(_) => "enum",
+        Val::Flags(_) => "flags",
+        Val::Resource(_) => "resource",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escapes_sandbox(line: &str) -> bool {
+        let scope = HashMap::from([("rows".to_owned(), Val::List(Vec::new()))]);
+        Cmd::parse(line).unwrap().unwrap().escapes_sandbox(&scope)
+    }
+
+    fn args(line: &str) -> Vec<TokenKind<'_>> {
+        let Some(Cmd::BuiltIn { args, .. }) = Cmd::parse(line).unwrap() else {
+            panic!("'{line}' is not a builtin");
+        };
+        args.iter().map(|t| t.token()).collect()
+    }
+
+    #[test]
+    fn raw_paths_to_read() {
+        assert_eq!(
+            args(".read-file data ~/my_data?.bin --as string"),
+            [
+                TokenKind::Ident("data"),
+                TokenKind::Rest("~/my_data?.bin"),
+                TokenKind::Flag("as"),
+                TokenKind::Ident("string"),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_paths_to_write() {
+        assert_eq!(
+            args(".write rows 50%_off&more@home.json"),
+            [
+                TokenKind::Ident("rows"),
+                TokenKind::Rest("50%_off&more@home.json")
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_csv_paths() {
+        assert_eq!(
+            args(".import-csv rows ~/rows_2024.csv --type list<row>"),
+            [
+                TokenKind::Ident("rows"),
+                TokenKind::Rest("~/rows_2024.csv"),
+                TokenKind::Flag("type"),
+                TokenKind::Ident("list"),
+                TokenKind::OpenAngle,
+                TokenKind::Ident("row"),
+                TokenKind::ClosedAngle,
+            ]
+        );
+        assert_eq!(
+            args(".export-csv rows out --as-of-today.csv"),
+            [
+                TokenKind::Ident("rows"),
+                TokenKind::Rest("out --as-of-today.csv")
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_urls() {
+        assert_eq!(
+            args(".http-request req GET http://localhost:8080/items?page=2&sort=name_asc --header accept:text/csv"),
+            [
+                TokenKind::Ident("req"),
+                TokenKind::Ident("GET"),
+                TokenKind::Rest("http://localhost:8080/items?page=2&sort=name_asc"),
+                TokenKind::Flag("header"),
+                TokenKind::Ident("accept"),
+                TokenKind::Colon,
+                TokenKind::Ident("text"),
+                TokenKind::Slash,
+                TokenKind::Ident("csv"),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_paths() {
+        assert_eq!(
+            args(r#".write rows "my rows.json" --as json"#),
+            [
+                TokenKind::Ident("rows"),
+                TokenKind::String("my rows.json"),
+                TokenKind::Flag("as"),
+                TokenKind::Ident("json"),
+            ]
+        );
+    }
+
+    #[test]
+    fn sandbox_refuses_host_code() {
+        assert!(escapes_sandbox("!ls"));
+        assert!(escapes_sandbox(".stub-proc my:pkg/iface#get ./stub.sh"));
+        assert!(escapes_sandbox(".plugin \"./libmock.so\""));
+    }
+
+    #[test]
+    fn host_code() {
+        let runs_host_code = |line| Cmd::parse(line).unwrap().unwrap().runs_host_code();
+        assert!(runs_host_code("!ls"));
+        assert!(runs_host_code(".stub-proc my:pkg/iface#get ./stub.sh"));
+        assert!(!runs_host_code(".write rows rows.json"));
+    }
+
+    #[test]
+    fn sandbox_refuses_writing_variables_to_files() {
+        assert!(escapes_sandbox(".write rows rows.json"));
+    }
+
+    #[test]
+    fn sandbox_refuses_reading_files() {
+        assert!(escapes_sandbox(".read-file data data.bin"));
+    }
+
+    #[test]
+    fn sandbox_refuses_importing_csv() {
+        assert!(escapes_sandbox(
+            ".import-csv rows rows.csv --type list<row>"
+        ));
+    }
+
+    #[test]
+    fn sandbox_refuses_exporting_csv() {
+        assert!(escapes_sandbox(".export-csv rows rows.csv"));
+    }
+
+    #[test]
+    fn sandbox_refuses_scaffolding() {
+        assert!(escapes_sandbox(".scaffold my:pkg/iface --out stub"));
+    }
+
+    #[test]
+    fn sandbox_refuses_writing_wit() {
+        assert!(escapes_sandbox(".wit --out wit"));
+        assert!(!escapes_sandbox(".wit"));
+    }
+
+    #[test]
+    fn sandbox_refuses_profiling() {
+        assert!(escapes_sandbox(".profile add(1, 2) --out \"add.json\""));
+    }
+
+    #[test]
+    fn sandbox_refuses_stdin_files() {
+        assert!(escapes_sandbox(".stdin --file input.txt"));
+        assert!(!escapes_sandbox(".stdin \"input\""));
+    }
+
+    #[test]
+    fn sandbox_refuses_recording_imports() {
+        assert!(escapes_sandbox(".record-imports calls.json"));
+        assert!(!escapes_sandbox(".record-imports off"));
+    }
+
+    #[test]
+    fn sandbox_refuses_freezing_the_world() {
+        assert!(escapes_sandbox(".freeze-world world.json"));
+        assert!(!escapes_sandbox(".freeze-world off"));
+    }
+
+    #[test]
+    fn sandbox_refuses_replaying_imports() {
+        assert!(escapes_sandbox(".replay-imports calls.json"));
+    }
+
+    #[test]
+    fn sandbox_refuses_core_dumps() {
+        assert!(escapes_sandbox(".coredump on"));
+        assert!(!escapes_sandbox(".coredump off"));
+    }
+
+    #[test]
+    fn sandbox_allows_loading_components() {
+        assert!(!escapes_sandbox(".load other.wasm"));
+        assert!(!escapes_sandbox(".reload"));
+        assert!(!escapes_sandbox(".exports"));
     }
 }
@@ -101,6 +101,7 @@ impl std::fmt::Display for ParserError<'_> {
 #[derive(Debug, PartialEq)]
 pub enum Expr<'a> {
     FunctionCall(FunctionCall<'a>),
+    MethodCall(MethodCall<'a>),
     Ident(&'a str),
     Literal(Literal<'a>),
 }
@@ -194,6 +195,9 @@ impl<'a> Expr<'a> {
                 Err(ParserError::UnexpectedEndOfInput)
             }
             TokenKind::Ident(_) => {
+                if let Some(call) = MethodCall::try_parse(input)? {
+                    return Ok(Some(Expr::MethodCall(call)));
+                }
                 let func = FunctionCall::try_parse(input)?;
                 match func {
                     Some(f) => Ok(Some(Expr::FunctionCall(f))),
@@ -226,26 +230,60 @@ impl<'a> FunctionCall<'a> {
             *input = original;
             return Ok(None);
         }
-        expect_token(input, |t| t == TokenKind::OpenParen)?;
-        let mut args = Vec::new();
-        loop {
-            let Some(expr) = Expr::try_parse(input)? else {
-                break;
-            };
-            args.push(expr);
-            if input.front().map(|t| t.token()) != Some(TokenKind::Comma) {
-                break;
-            }
-            input.pop_front();
-        }
-        expect_token(input, |t| t == TokenKind::ClosedParen)?;
         Ok(Some(FunctionCall {
             ident: function_ident,
-            args,
+            args: parse_args(input)?,
         }))
     }
 }
 
+/// A call of a method on a resource handle or of a constructor (`new`) or
+/// static function of a resource
+#[derive(Debug, PartialEq)]
+pub struct MethodCall<'a> {
+    /// The resource or the variable holding the handle
+    pub receiver: ItemIdent<'a>,
+    pub method: &'a str,
+    pub args: Vec<Expr<'a>>,
+}
+
+impl<'a> MethodCall<'a> {
+    fn try_parse(input: &mut VecDeque<Token<'a>>) -> Result<Option<Self>, ParserError<'a>> {
+        let original = input.clone();
+        let receiver = match ItemIdent::try_parse(input) {
+            Ok(Some(receiver)) if input.front().map(|t| t.token()) == Some(TokenKind::Period) => {
+                receiver
+            }
+            _ => {
+                *input = original;
+                return Ok(None);
+            }
+        };
+        input.pop_front();
+        let method = Literal::parse_ident(input)?;
+        Ok(Some(MethodCall {
+            receiver,
+            method,
+            args: parse_args(input)?,
+        }))
+    }
+}
+
+/// Parse a parenthesized list of arguments
+fn parse_args<'a>(input: &mut VecDeque<Token<'a>>) -> Result<Vec<Expr<'a>>, ParserError<'a>> {
+    expect_token(input, |t| t == TokenKind::OpenParen)?;
+    let mut args = Vec::new();
+    while let Some(expr) = Expr::try_parse(input)? {
+        args.push(expr);
+        if input.front().map(|t| t.token()) != Some(TokenKind::Comma) {
+            break;
+        }
+        input.pop_front();
+    }
+    expect_token(input, |t| t == TokenKind::ClosedParen)?;
+    Ok(args)
+}
+
 fn expect_token<'a>(
     input: &mut VecDeque<Token<'a>>,
     pred: impl FnOnce(TokenKind<'a>) -> bool,
@@ -266,13 +304,27 @@ pub enum Ident<'a> {
 }
 
 impl<'a> Ident<'a> {
+    /// Parse an interface or item identifier on its own
+    pub fn parse_str(input: &'a str) -> Option<Self> {
+        let mut tokens = Token::tokenize(input).ok()?;
+        let ident = Ident::try_parse(&mut tokens).ok()??;
+        tokens.is_empty().then_some(ident)
+    }
+
     pub(crate) fn try_parse(
         input: &mut VecDeque<Token<'a>>,
     ) -> Result<Option<Ident<'a>>, ParserError<'a>> {
-        match ItemIdent::try_parse(input)? {
-            Some(i) => Ok(Some(Self::Item(i))),
-            None => Ok(InterfaceIdent::try_parse(input)?.map(Self::Interface)),
+        // An interface with a package not followed by `#` cannot be an item
+        let original = input.clone();
+        if let Some(interface) = InterfaceIdent::try_parse(input)? {
+            if interface.package.is_some()
+                && input.front().map(|t| t.token()) != Some(TokenKind::Hash)
+            {
+                return Ok(Some(Self::Interface(interface)));
+            }
         }
+        *input = original;
+        Ok(ItemIdent::try_parse(input)?.map(Self::Item))
     }
 }
 
@@ -283,6 +335,13 @@ pub struct ItemIdent<'a> {
 }
 
 impl<'a> ItemIdent<'a> {
+    /// Parse an item identifier like `my:pkg/iface#func` on its own
+    pub fn parse_str(input: &'a str) -> Option<Self> {
+        let mut tokens = Token::tokenize(input).ok()?;
+        let ident = ItemIdent::try_parse(&mut tokens).ok()??;
+        tokens.is_empty().then_some(ident)
+    }
+
     fn try_parse(input: &mut VecDeque<Token<'a>>) -> Result<Option<Self>, ParserError<'a>> {
         let interface = InterfaceIdent::try_parse(input)?;
         match interface {
@@ -324,6 +383,15 @@ impl<'a> ItemIdent<'a> {
     }
 }
 
+impl std::fmt::Display for Ident<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ident::Item(item) => item.fmt(f),
+            Ident::Interface(interface) => interface.fmt(f),
+        }
+    }
+}
+
 impl std::fmt::Display for ItemIdent<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(interface) = self.interface {
@@ -340,6 +408,13 @@ pub struct InterfaceIdent<'a> {
 }
 
 impl<'a> InterfaceIdent<'a> {
+    /// Parse an interface identifier like `my:pkg/iface` on its own
+    pub fn parse_str(input: &'a str) -> Option<Self> {
+        let mut tokens = Token::tokenize(input).ok()?;
+        let ident = InterfaceIdent::try_parse(&mut tokens).ok()??;
+        tokens.is_empty().then_some(ident)
+    }
+
     fn try_parse<'b>(input: &'b mut VecDeque<Token<'a>>) -> Result<Option<Self>, ParserError<'a>> {
         #[derive(Debug)]
         #[allow(clippy::enum_variant_names)]
@@ -553,6 +628,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_method_calls() {
+        let method = Line::Expr(Expr::MethodCall(MethodCall {
+            receiver: ItemIdent {
+                interface: None,
+                item: "counter",
+            },
+            method: "add",
+            args: vec![Expr::Literal(Literal::Number(1))],
+        }));
+        let line = parse([
+            TokenKind::Ident("counter"),
+            TokenKind::Period,
+            TokenKind::Ident("add"),
+            TokenKind::OpenParen,
+            TokenKind::Number(1),
+            TokenKind::ClosedParen,
+        ])
+        .unwrap();
+        assert_eq!(line, method);
+
+        let err = parse([
+            TokenKind::Ident("counter"),
+            TokenKind::Period,
+            TokenKind::Ident("add"),
+        ])
+        .unwrap_err();
+        assert_eq!(err, ParserError::UnexpectedEndOfInput);
+    }
+
     #[test]
     fn parse_function_calls() {
         let function = Line::Expr(Expr::FunctionCall(FunctionCall {
@@ -644,6 +749,57 @@ mod tests {
         assert_eq!(err, ParserError::UnexpectedToken(token(TokenKind::Hash)));
     }
 
+    #[test]
+    fn parse_idents() {
+        let mut input = tokens([
+            TokenKind::Ident("foo"),
+            TokenKind::Colon,
+            TokenKind::Ident("bar"),
+            TokenKind::Slash,
+            TokenKind::Ident("baz"),
+        ]);
+        let ident = Ident::try_parse(&mut input).unwrap().unwrap();
+        assert_eq!(
+            ident,
+            Ident::Interface(InterfaceIdent {
+                package: Some(("foo", "bar")),
+                interface: "baz"
+            })
+        );
+        assert!(input.is_empty());
+
+        let mut input = tokens([
+            TokenKind::Ident("foo"),
+            TokenKind::Colon,
+            TokenKind::Ident("bar"),
+            TokenKind::Slash,
+            TokenKind::Ident("baz"),
+            TokenKind::Hash,
+            TokenKind::Ident("qux"),
+        ]);
+        let ident = Ident::try_parse(&mut input).unwrap().unwrap();
+        assert_eq!(
+            ident,
+            Ident::Item(ItemIdent {
+                interface: Some(InterfaceIdent {
+                    package: Some(("foo", "bar")),
+                    interface: "baz"
+                }),
+                item: "qux"
+            })
+        );
+
+        let mut input = tokens([TokenKind::Ident("foo")]);
+        let ident = Ident::try_parse(&mut input).unwrap().unwrap();
+        assert_eq!(
+            ident,
+            Ident::Item(ItemIdent {
+                interface: None,
+                item: "foo"
+            })
+        );
+    }
+
     #[test]
     fn parse_ident_expr() {
         let line = parse([TokenKind::Ident("foo")]).unwrap();
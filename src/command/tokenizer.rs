@@ -1,4 +1,8 @@
-use std::{collections::VecDeque, fmt::Write, ops::Deref};
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    ops::{Deref, Range},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Token<'a> {
@@ -18,8 +22,11 @@ pub enum TokenKind<'a> {
     Ident(&'a str),
     Builtin(&'a str),
     Flag(&'a str),
+    /// Everything after a standalone `--`, left uninterpreted
+    Rest(&'a str),
     Number(usize),
     Equal,
+    FatArrow,
     OpenParen,
     ClosedParen,
     Slash,
@@ -29,13 +36,16 @@ pub enum TokenKind<'a> {
     ClosedBracket,
     OpenBrace,
     ClosedBrace,
+    /// `<` and `>`, which only appear in WIT types like `list<u8>`
+    OpenAngle,
+    ClosedAngle,
     Comma,
     Period,
 }
 
 impl<'a> Token<'a> {
     pub fn tokenize(input: &'a str) -> Result<VecDeque<Token<'a>>, TokenizeError> {
-        let mut tokens = VecDeque::new();
+        let mut tokens: VecDeque<Token<'a>> = VecDeque::new();
         let mut rest = SpannedStr {
             str: input,
             offset: 0,
@@ -43,13 +53,57 @@ impl<'a> Token<'a> {
         while !rest.is_empty() {
             let (new_rest, token) = Token::next(rest)?;
             if let Some(token) = token {
-                tokens.push_back(token);
+                match (tokens.back(), token.token) {
+                    // A builtin directly following an ident or a call is a method (e.g. `x.len()`)
+                    (Some(prev), TokenKind::Builtin(name))
+                        if matches!(prev.token, TokenKind::Ident(_) | TokenKind::ClosedParen)
+                            && prev.input.offset + prev.input.len() == token.input.offset =>
+                    {
+                        let (period, ident) = token.input.split_at(1);
+                        tokens.push_back(Token {
+                            input: period,
+                            token: TokenKind::Period,
+                        });
+                        tokens.push_back(Token {
+                            input: ident,
+                            token: TokenKind::Ident(name),
+                        });
+                    }
+                    _ => tokens.push_back(token),
+                }
             }
             rest = new_rest;
         }
         Ok(tokens)
     }
 
+    /// Tokenize `input` like [`Token::tokenize`], except for the range `raw`,
+    /// which becomes a single [`TokenKind::Rest`] token of what it holds
+    pub fn tokenize_around(
+        input: &'a str,
+        raw: Range<usize>,
+    ) -> Result<VecDeque<Token<'a>>, TokenizeError> {
+        let mut tokens = Token::tokenize(&input[..raw.start])?;
+        let str = &input[raw.clone()];
+        tokens.push_back(Token {
+            input: SpannedStr {
+                str,
+                offset: raw.start,
+            },
+            token: TokenKind::Rest(str),
+        });
+        let after = Token::tokenize(&input[raw.end..]).map_err(
+            |TokenizeError::UnexpectedChar(char, offset)| {
+                TokenizeError::UnexpectedChar(char, raw.end + offset)
+            },
+        )?;
+        for mut token in after {
+            token.input.offset += raw.end;
+            tokens.push_back(token);
+        }
+        Ok(tokens)
+    }
+
     fn next(rest: SpannedStr<'a>) -> Result<(SpannedStr<'a>, Option<Token<'a>>), TokenizeError> {
         let mut chars = rest.chars().peekable();
         let original_offset = rest.offset;
@@ -90,6 +144,7 @@ impl<'a> Token<'a> {
                 (offset, Some(TokenKind::Number(num)))
             }
             c if c.is_whitespace() => (c.len_utf8(), None),
+            '=' if chars.peek() == Some(&'>') => ("=>".len(), Some(TokenKind::FatArrow)),
             '=' => ('='.len_utf8(), Some(TokenKind::Equal)),
             '(' => ('('.len_utf8(), Some(TokenKind::OpenParen)),
             ')' => (')'.len_utf8(), Some(TokenKind::ClosedParen)),
@@ -99,10 +154,12 @@ impl<'a> Token<'a> {
             '[' => ('['.len_utf8(), Some(TokenKind::OpenBracket)),
             ']' => (']'.len_utf8(), Some(TokenKind::ClosedBracket)),
             ',' => (','.len_utf8(), Some(TokenKind::Comma)),
+            '<' => ('<'.len_utf8(), Some(TokenKind::OpenAngle)),
+            '>' => ('>'.len_utf8(), Some(TokenKind::ClosedAngle)),
             '.' => {
                 if matches!(chars.peek(), Some(c) if c.is_alphabetic()) {
                     let len: usize = chars
-                        .take_while(|c| c.is_ascii_alphabetic() || *c == '_')
+                        .take_while(|c| c.is_ascii_alphabetic() || *c == '_' || *c == '-')
                         .map(|c| c.len_utf8())
                         .sum();
                     let offset = '.'.len_utf8() + len;
@@ -114,6 +171,11 @@ impl<'a> Token<'a> {
             }
             '{' => ('.'.len_utf8(), Some(TokenKind::OpenBrace)),
             '}' => ('.'.len_utf8(), Some(TokenKind::ClosedBrace)),
+            '-' if rest.str[1..].starts_with('-')
+                && !rest.str[2..].starts_with(|c: char| !c.is_whitespace()) =>
+            {
+                (rest.str.len(), Some(TokenKind::Rest(rest.str[2..].trim())))
+            }
             '-' if chars.peek() == Some(&'-') => {
                 let len: usize = chars
                     .skip(1)
@@ -171,6 +233,16 @@ impl<'a> SpannedStr<'a> {
             offset: self.offset + offset,
         }
     }
+
+    fn split_at(self, mid: usize) -> (SpannedStr<'a>, SpannedStr<'a>) {
+        (
+            Self {
+                str: &self.str[..mid],
+                offset: self.offset,
+            },
+            self.offset(mid),
+        )
+    }
 }
 
 impl Deref for SpannedStr<'_> {
@@ -240,6 +312,24 @@ mod tests {
         )
     }
 
+    #[test]
+    fn tokenize_fat_arrow() {
+        let input = "get-config => 5";
+        let tokens = Token::tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Ident("get-config"),
+                TokenKind::FatArrow,
+                TokenKind::Number(5),
+            ]
+        )
+    }
+
     #[test]
     fn tokenize_builtin() {
         let input = ".foo hello";
@@ -253,4 +343,63 @@ mod tests {
             vec![TokenKind::Builtin("foo"), TokenKind::Ident("hello"),]
         )
     }
+
+    #[test]
+    fn tokenize_method_call() {
+        let input = "counter.get() .foo";
+        let tokens = Token::tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Ident("counter"),
+                TokenKind::Period,
+                TokenKind::Ident("get"),
+                TokenKind::OpenParen,
+                TokenKind::ClosedParen,
+                TokenKind::Builtin("foo"),
+            ]
+        )
+    }
+
+    #[test]
+    fn tokenize_rest() {
+        let input = ".stub-proc my-iface -- python3 ./my_handler.py --verbose";
+        let tokens = Token::tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::Builtin("stub-proc"),
+                TokenKind::Ident("my-iface"),
+                TokenKind::Rest("python3 ./my_handler.py --verbose"),
+            ]
+        )
+    }
+
+    #[test]
+    fn tokenize_around() {
+        let input = ".read-file x ~/my_data?.bin --as string";
+        let start = input.find('~').unwrap();
+        let end = input.find(" --").unwrap();
+        let tokens = Token::tokenize_around(input, start..end).unwrap();
+        assert_eq!(
+            tokens.iter().map(|t| t.token).collect::<Vec<_>>(),
+            vec![
+                TokenKind::Builtin("read-file"),
+                TokenKind::Ident("x"),
+                TokenKind::Rest("~/my_data?.bin"),
+                TokenKind::Flag("as"),
+                TokenKind::Ident("string"),
+            ]
+        );
+        assert_eq!(tokens[2].input.offset, start);
+        assert_eq!(tokens[4].input.offset, input.find("string").unwrap());
+    }
 }
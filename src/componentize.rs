@@ -0,0 +1,83 @@
+use anyhow::Context as _;
+
+/// The WASI preview1 adapter that turns a core module into a component
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Adapter {
+    /// For modules with a `_start` function, exporting `wasi:cli/run`
+    Command,
+    /// For modules whose exports are called directly
+    Reactor,
+}
+
+impl Adapter {
+    fn bytes(self) -> &'static [u8] {
+        match self {
+            Adapter::Command => {
+                wasi_preview1_component_adapter_provider::WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER
+            }
+            Adapter::Reactor => {
+                wasi_preview1_component_adapter_provider::WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Adapter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Adapter::Command => f.write_str("command"),
+            Adapter::Reactor => f.write_str("reactor"),
+        }
+    }
+}
+
+/// Whether `bytes` is a binary component rather than a core module
+pub fn is_component(bytes: &[u8]) -> bool {
+    // The layer field of the preamble distinguishes core modules (0) from components (1)
+    bytes.get(6..8) == Some(&[1, 0])
+}
+
+/// Wrap `bytes` in a component with a WASI preview1 adapter if it is a core
+/// module, returning the adapter it was wrapped with if it was
+///
+/// Without an explicit `adapter` the command adapter is used for modules
+/// exporting `_start` and the reactor adapter for all others. Anything that
+/// is not a binary core module is returned as is.
+pub fn componentize(
+    bytes: Vec<u8>,
+    adapter: Option<Adapter>,
+) -> anyhow::Result<(Vec<u8>, Option<Adapter>)> {
+    if !bytes.starts_with(b"\0asm") || is_component(&bytes) {
+        return Ok((bytes, None));
+    }
+    let adapter = match adapter {
+        Some(adapter) => adapter,
+        None if exports_start(&bytes)? => Adapter::Command,
+        None => Adapter::Reactor,
+    };
+    let encode = || {
+        wit_component::ComponentEncoder::default()
+            .module(&bytes)?
+            .adapter("wasi_snapshot_preview1", adapter.bytes())?
+            .validate(true)
+            .encode()
+    };
+    let component = encode().with_context(|| {
+        format!("could not turn the core module into a component with the {adapter} adapter")
+    })?;
+    Ok((component, Some(adapter)))
+}
+
+/// Whether the core module exports a `_start` function
+fn exports_start(module: &[u8]) -> anyhow::Result<bool> {
+    for payload in wasmparser::Parser::new(0).parse_all(module) {
+        if let wasmparser::Payload::ExportSection(exports) = payload? {
+            for export in exports {
+                if export?.name == "_start" {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,324 @@
+use std::borrow::Cow;
+
+use anyhow::Context as _;
+use wasmtime::component::Val;
+use wit_parser::{Type, TypeDef, TypeDefKind};
+
+use crate::command::format_val;
+use crate::wit::{Expansion, WorldResolver};
+
+/// The rows of the CSV text `text` along with the line each starts on, with
+/// quoted fields unquoted and empty lines skipped
+pub fn parse(text: &str) -> anyhow::Result<Vec<(usize, Vec<String>)>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    // Whether the row has a quoted field, which makes it a row even if empty
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut row_line = line;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                quoted = true;
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => {
+                        line += usize::from(c == '\n');
+                        field.push(c);
+                    }
+                    None => anyhow::bail!("unterminated quoted field on line {line}"),
+                }
+            },
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if field.is_empty() && row.is_empty() && !quoted {
+                    line += 1;
+                    row_line = line;
+                    continue;
+                }
+                row.push(std::mem::take(&mut field));
+                rows.push((row_line, std::mem::take(&mut row)));
+                quoted = false;
+                line += 1;
+                row_line = line;
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() || quoted {
+        row.push(field);
+        rows.push((row_line, row));
+    }
+    Ok(rows)
+}
+
+/// The list of `record` values that the CSV text `text` describes, with a
+/// column for each field named in its header row
+///
+/// Optional fields may be left out of the header and empty cells of optional
+/// fields are `none`.
+pub fn to_records(text: &str, record: &TypeDef, resolver: &WorldResolver) -> anyhow::Result<Val> {
+    let TypeDefKind::Record(record) = &record.kind else {
+        anyhow::bail!("CSV rows can only be read as records");
+    };
+    let mut rows = parse(text)?.into_iter();
+    let (_, header) = rows.next().context("the CSV has no header row")?;
+    if let Some(column) = header
+        .iter()
+        .find(|column| !record.fields.iter().any(|f| &f.name == *column))
+    {
+        anyhow::bail!("the record has no field named '{column}'");
+    }
+    if let Some((i, column)) = header
+        .iter()
+        .enumerate()
+        .find(|(i, column)| header[..*i].contains(column))
+    {
+        anyhow::bail!(
+            "the header names the field '{column}' twice (column {})",
+            i + 1
+        );
+    }
+    let mut records = Vec::new();
+    for (line, row) in rows {
+        if row.len() != header.len() {
+            anyhow::bail!(
+                "line {line} has {} cells but the header has {}",
+                row.len(),
+                header.len()
+            );
+        }
+        let fields = record
+            .fields
+            .iter()
+            .map(|field| {
+                let val = match header.iter().position(|column| *column == field.name) {
+                    Some(column) => cell_val(&row[column], &field.ty, resolver)
+                        .with_context(|| format!("invalid '{}' on line {line}", field.name))?,
+                    None => cell_val("", &field.ty, resolver).with_context(|| {
+                        format!("the CSV has no column for the field '{}'", field.name)
+                    })?,
+                };
+                Ok((field.name.clone(), val))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        records.push(Val::Record(fields));
+    }
+    Ok(Val::List(records))
+}
+
+/// The value of type `ty` that the cell `cell` holds
+fn cell_val(cell: &str, ty: &Type, resolver: &WorldResolver) -> anyhow::Result<Val> {
+    fn number<T: std::str::FromStr>(cell: &str, ty: &str) -> anyhow::Result<T> {
+        cell.trim()
+            .parse()
+            .ok()
+            .with_context(|| format!("'{cell}' is not a {ty}"))
+    }
+    Ok(match ty {
+        Type::Bool => Val::Bool(number(cell, "bool")?),
+        Type::U8 => Val::U8(number(cell, "u8")?),
+        Type::U16 => Val::U16(number(cell, "u16")?),
+        Type::U32 => Val::U32(number(cell, "u32")?),
+        Type::U64 => Val::U64(number(cell, "u64")?),
+        Type::S8 => Val::S8(number(cell, "s8")?),
+        Type::S16 => Val::S16(number(cell, "s16")?),
+        Type::S32 => Val::S32(number(cell, "s32")?),
+        Type::S64 => Val::S64(number(cell, "s64")?),
+        Type::F32 => Val::Float32(number(cell, "float32")?),
+        Type::F64 => Val::Float64(number(cell, "float64")?),
+        Type::Char => {
+            let mut chars = cell.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Val::Char(c),
+                _ => anyhow::bail!("'{cell}' is not a single char"),
+            }
+        }
+        Type::String => Val::String(cell.to_owned()),
+        Type::Id(id) => {
+            let typ = resolver
+                .type_by_id(*id)
+                .expect("found type id for type not present in resolver");
+            match &typ.kind {
+                TypeDefKind::Type(ty) => cell_val(cell, ty, resolver)?,
+                TypeDefKind::Option(_) if cell.is_empty() => Val::Option(None),
+                TypeDefKind::Option(ty) => {
+                    Val::Option(Some(Box::new(cell_val(cell, ty, resolver)?)))
+                }
+                TypeDefKind::Enum(e) => match e.cases.iter().find(|c| c.name == cell.trim()) {
+                    Some(case) => Val::Enum(case.name.clone()),
+                    None => anyhow::bail!("'{cell}' is not a case of the enum"),
+                },
+                _ => anyhow::bail!(
+                    "fields of type {} cannot be read from CSV",
+                    resolver.display_wit_type(ty, Expansion::Collapsed)
+                ),
+            }
+        }
+    })
+}
+
+/// The list of records `val` as CSV text, with a header row naming the
+/// fields of the records
+pub fn from_records(val: &Val) -> anyhow::Result<String> {
+    const EXPECTED: &str = "only a list of records can be written as CSV";
+    let Val::List(records) = val else {
+        anyhow::bail!(EXPECTED);
+    };
+    let Some(Val::Record(first)) = records.first() else {
+        anyhow::bail!("{EXPECTED}, and an empty list has no columns");
+    };
+    let header = first
+        .iter()
+        .map(|(name, _)| quoted(name))
+        .collect::<Vec<_>>();
+    let mut csv = header.join(",") + "\n";
+    for record in records {
+        let Val::Record(fields) = record else {
+            anyhow::bail!(EXPECTED);
+        };
+        let cells = fields
+            .iter()
+            .map(|(_, val)| cell_text(val))
+            .collect::<Vec<_>>();
+        csv.push_str(&cells.join(","));
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+/// The cell for the field value `val`: strings and chars as they are, `none`
+/// as an empty cell and anything else like the repl prints it
+fn cell_text(val: &Val) -> String {
+    let text = match val {
+        Val::String(s) => Cow::Borrowed(s.as_str()),
+        Val::Char(c) => Cow::Owned(c.to_string()),
+        Val::Enum(case) => Cow::Borrowed(case.as_str()),
+        Val::Option(None) => Cow::Borrowed(""),
+        Val::Option(Some(val)) => return cell_text(val),
+        val => Cow::Owned(format_val(val)),
+    };
+    quoted(&text).into_owned()
+}
+
+/// `text` quoted for a CSV cell if it has to be
+fn quoted(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver() -> WorldResolver {
+        WorldResolver::from_wit_str(
+            "package my:test;
+            world w {
+                enum color { red, green }
+                record row { name: string, count: u32, color: color, note: option<string> }
+            }",
+        )
+    }
+
+    const TEXT: &str = "count,name,color\r\n3,\"a, \"\"b\"\"\",green\n4,c,red\n";
+
+    #[test]
+    fn reads_records() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        let rows = to_records(TEXT, row, &resolver).unwrap();
+        let Val::List(records) = &rows else {
+            panic!("expected a list, got {rows:?}");
+        };
+        assert_eq!(
+            records[0],
+            Val::Record(vec![
+                ("name".to_owned(), Val::String("a, \"b\"".to_owned())),
+                ("count".to_owned(), Val::U32(3)),
+                ("color".to_owned(), Val::Enum("green".to_owned())),
+                ("note".to_owned(), Val::Option(None)),
+            ])
+        );
+    }
+
+    #[test]
+    fn writes_records() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        let rows = to_records(TEXT, row, &resolver).unwrap();
+        assert_eq!(
+            from_records(&rows).unwrap(),
+            "name,count,color,note\n\"a, \"\"b\"\"\",3,green,\nc,4,red,\n"
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        assert!(to_records("count\nx\n", row, &resolver).is_err());
+    }
+
+    #[test]
+    fn unknown_columns() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        assert!(to_records("count,size\n1,2\n", row, &resolver).is_err());
+    }
+
+    #[test]
+    fn short_rows() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        assert!(to_records("count,name\n1\n", row, &resolver).is_err());
+    }
+
+    #[test]
+    fn empty_lines_are_skipped() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        let text = "\ncount,name,color\n\n1,a,red\r\n\r\n2,b,green\n\n";
+        let Val::List(records) = to_records(text, row, &resolver).unwrap() else {
+            panic!("expected a list");
+        };
+        assert_eq!(records.len(), 2);
+        let error = to_records("count,name,color\n\n1\n", row, &resolver).unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{error}");
+    }
+
+    #[test]
+    fn quoted_empty_cells_are_rows() {
+        assert_eq!(
+            parse("name\n\"\"\n\n\"\"").unwrap(),
+            [
+                (1, vec!["name".to_owned()]),
+                (2, vec![String::new()]),
+                (4, vec![String::new()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_columns() {
+        let resolver = resolver();
+        let (_, row) = resolver.types_by_name("row")[0];
+        let error = to_records("count,name,count\n1,a,2\n", row, &resolver).unwrap_err();
+        assert!(error.to_string().contains("'count' twice"), "{error}");
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert!(parse("\"open").is_err());
+    }
+}
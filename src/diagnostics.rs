@@ -0,0 +1,82 @@
+use std::sync::OnceLock;
+
+use anyhow::Context as _;
+use tracing_subscriber::{
+    fmt::format::FmtSpan, layer::SubscriberExt as _, reload, util::SubscriberInitExt as _,
+    EnvFilter, Layer as _, Registry,
+};
+
+/// How wepl writes its own logs to stderr
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Lines for people, naming the spans (e.g. `call{func=add}`) the event
+    /// happened in
+    #[default]
+    Pretty,
+    /// One JSON object per event, with the spans it happened in
+    Json,
+    /// Nothing, not even what `RUST_LOG` enables
+    Off,
+}
+
+/// Replaces the filter of the subscriber installed by [`init`], unless
+/// logging is off
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Log in `format` what `-v` (debug) or `-vv` (trace) asks for on top of what
+/// `RUST_LOG` enables
+///
+/// Spans log how long they took when they close, which is what makes
+/// instantiating, linking, stubbing, composing and calling diagnosable.
+pub fn init(format: LogFormat, verbose: u8, color: bool) {
+    if format == LogFormat::Off {
+        return;
+    }
+    let mut directives = std::env::var("RUST_LOG").unwrap_or_else(|_| "error".to_owned());
+    match verbose {
+        0 => {}
+        1 => directives.push_str(",wepl=debug"),
+        _ => directives.push_str(",wepl=trace"),
+    }
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(directives));
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+    let layer = match format {
+        LogFormat::Json => layer.json().boxed(),
+        _ => layer.with_ansi(color).boxed(),
+    };
+    if tracing_subscriber::registry()
+        .with(filter)
+        .with(layer)
+        .try_init()
+        .is_ok()
+    {
+        let _ = FILTER.set(handle);
+    }
+}
+
+/// Log what `filter` enables from now on: with a level (e.g. `debug`) wepl's
+/// own logs at that level and errors of everything else, or with directives
+/// like `RUST_LOG`'s (e.g. `wepl=trace,wasmtime=debug`) exactly what they
+/// enable
+pub fn set_filter(filter: &str) -> anyhow::Result<()> {
+    let handle = FILTER
+        .get()
+        .context("wepl's logs are off, which `--log pretty` or `--log json` turns on")?;
+    let directives = match filter {
+        "off" => filter.to_owned(),
+        level if level.parse::<tracing::Level>().is_ok() => format!("error,wepl={level}"),
+        directives => directives.to_owned(),
+    };
+    // The parse error's source repeats its message
+    let filter = EnvFilter::try_new(&directives)
+        .map_err(|e| anyhow::anyhow!("'{directives}' is not a valid log filter: {e}"))?;
+    handle.reload(filter)?;
+    Ok(())
+}
+
+/// The directives of the filter wepl logs with, unless logging is off
+pub fn filter() -> Option<String> {
+    FILTER.get()?.with_current(ToString::to_string).ok()
+}
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use wit_parser::{TypeDefKind, WorldItem, WorldKey};
+
+use crate::command::format_function;
+use crate::events::Events;
+use crate::wit::{Expansion, WorldResolver};
+
+/// How the world of a component changed, by what it means for the
+/// components and hosts using it
+#[derive(Debug, PartialEq, PartialOrd)]
+pub enum Compatibility {
+    /// Nothing the worlds are made of changed
+    Patch,
+    /// Only exports were added or imports removed, so everything that worked
+    /// with the old component works with the new one
+    Minor,
+    /// Exports were removed or changed, imports were added or changed or
+    /// types changed, so users of the old component may break
+    Major,
+}
+
+/// A change between the items of two worlds
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added(String),
+    Removed(String),
+    Changed { old: String, new: String },
+}
+
+/// The changes between the items of the same kind (e.g. exports) of two
+/// worlds by the name of the item
+type Changes = BTreeMap<String, Change>;
+
+/// Compare the worlds of `old` and `new`, emitting what was added, removed or
+/// changed to `events`, and return how compatible `new` is with `old`
+pub fn print(events: &Events, old: &WorldResolver, new: &WorldResolver) -> Compatibility {
+    let imports = compare(
+        items(old, &old.world().imports),
+        items(new, &new.world().imports),
+    );
+    let exports = compare(
+        items(old, &old.world().exports),
+        items(new, &new.world().exports),
+    );
+    let types = compare(types(old), types(new));
+    for (title, changes) in [
+        ("Imports", &imports),
+        ("Exports", &exports),
+        ("Types", &types),
+    ] {
+        if changes.is_empty() {
+            continue;
+        }
+        events.print(format!("{}:", title.blue().bold()));
+        for (name, change) in changes {
+            match change {
+                Change::Added(new) => {
+                    events.print(format!("  {} {}: {new}", "+".green().bold(), name.bold()))
+                }
+                Change::Removed(old) => {
+                    events.print(format!("  {} {}: {old}", "-".red().bold(), name.bold()))
+                }
+                Change::Changed { old, new } => {
+                    events.print(format!("  {} {}", "~".yellow().bold(), name.bold()));
+                    events.print(format!("      {} {old}", "was".dimmed()));
+                    events.print(format!("      {} {new}", "now".dimmed()));
+                }
+            }
+        }
+    }
+    let compatibility = compatibility(&imports, &exports, &types);
+    let verdict = match compatibility {
+        Compatibility::Patch => "patch (the worlds are the same)".green(),
+        Compatibility::Minor => "minor (only exports were added or imports removed)".yellow(),
+        Compatibility::Major => "major (users of the old component may break)".red(),
+    };
+    events.print(format!("{}: {verdict}", "Compatibility".blue().bold()));
+    compatibility
+}
+
+fn compatibility(imports: &Changes, exports: &Changes, types: &Changes) -> Compatibility {
+    let breaks = |changes: &Changes, breaking: fn(&Change) -> bool| changes.values().any(breaking);
+    if breaks(imports, |c| !matches!(c, Change::Removed(_)))
+        || breaks(exports, |c| !matches!(c, Change::Added(_)))
+        || breaks(types, |c| !matches!(c, Change::Added(_)))
+    {
+        Compatibility::Major
+    } else if imports.is_empty() && exports.is_empty() && types.is_empty() {
+        Compatibility::Patch
+    } else {
+        Compatibility::Minor
+    }
+}
+
+fn compare(old: BTreeMap<String, String>, mut new: BTreeMap<String, String>) -> Changes {
+    let mut changes = Changes::new();
+    for (name, old) in old {
+        match new.remove(&name) {
+            None => {
+                changes.insert(name, Change::Removed(old));
+            }
+            Some(new) if new != old => {
+                changes.insert(name, Change::Changed { old, new });
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, new) in new {
+        changes.insert(name, Change::Added(new));
+    }
+    changes
+}
+
+/// The signature of every function among `items`, including those of
+/// interfaces by `$interface#$function`
+fn items<'a>(
+    resolver: &WorldResolver,
+    items: impl IntoIterator<Item = (&'a WorldKey, &'a WorldItem)>,
+) -> BTreeMap<String, String> {
+    let mut signatures = BTreeMap::new();
+    for (key, item) in items {
+        let name = resolver.world_item_name(key);
+        match item {
+            WorldItem::Function(f) => {
+                signatures.insert(name, format_function(f, resolver));
+            }
+            WorldItem::Interface { id, .. } => {
+                let Some(interface) = resolver.interface_by_id(*id) else {
+                    continue;
+                };
+                if interface.functions.is_empty() {
+                    signatures.insert(name.clone(), "interface".into());
+                }
+                for (func_name, f) in &interface.functions {
+                    signatures.insert(format!("{name}#{func_name}"), format_function(f, resolver));
+                }
+            }
+            WorldItem::Type(_) => {}
+        }
+    }
+    signatures
+}
+
+/// The definition of every named type by `$interface#$type`
+fn types(resolver: &WorldResolver) -> BTreeMap<String, String> {
+    resolver
+        .named_types()
+        .filter_map(|(interface, ty)| {
+            let name = ty.name.as_ref()?;
+            // Types used from other interfaces are compared where they are defined
+            if let TypeDefKind::Type(wit_parser::Type::Id(id)) = ty.kind {
+                if resolver.type_by_id(id).is_some_and(|t| t.name.is_some()) {
+                    return None;
+                }
+            }
+            let definition = match &ty.kind {
+                TypeDefKind::Flags(flags) => {
+                    let names = flags
+                        .flags
+                        .iter()
+                        .map(|f| f.name.as_str())
+                        .collect::<Vec<_>>();
+                    format!("flags {{ {} }}", names.join(", "))
+                }
+                TypeDefKind::Resource => "resource".into(),
+                _ => resolver
+                    .display_wit_type_def(ty, Expansion::Expanded(1))
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            let name = match interface {
+                Some(interface) => format!("{interface}#{name}"),
+                None => name.clone(),
+            };
+            Some((name, definition))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(items: &[(&str, &str)]) -> BTreeMap<String, String> {
+        items
+            .iter()
+            .map(|(name, sig)| (name.to_string(), sig.to_string()))
+            .collect()
+    }
+
+    fn old() -> BTreeMap<String, String> {
+        map(&[("get", "func() -> u32"), ("put", "func(v: u32)")])
+    }
+
+    #[test]
+    fn same_items() {
+        assert!(compare(old(), old()).is_empty());
+    }
+
+    #[test]
+    fn added_items() {
+        let mut new = old();
+        new.insert("del".to_owned(), "func()".to_owned());
+        let added = compare(old(), new);
+        assert_eq!(added["del"], Change::Added("func()".into()));
+    }
+
+    #[test]
+    fn changed_and_removed_items() {
+        let changed = compare(old(), map(&[("get", "func() -> u64")]));
+        assert_eq!(changed.len(), 2);
+    }
+
+    #[test]
+    fn verdicts() {
+        let mut new = old();
+        new.insert("del".to_owned(), "func()".to_owned());
+        let added = compare(old(), new);
+        let changed = compare(old(), map(&[("get", "func() -> u64")]));
+        let none = Changes::new();
+        assert_eq!(compatibility(&none, &none, &none), Compatibility::Patch);
+        assert_eq!(compatibility(&none, &added, &none), Compatibility::Minor);
+        assert_eq!(compatibility(&added, &none, &none), Compatibility::Major);
+        assert_eq!(compatibility(&none, &changed, &none), Compatibility::Major);
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+
+use colored::Colorize;
+use wasmtime::{Config, InstanceAllocationStrategy, OptLevel, PoolingAllocationConfig, Strategy};
+
+/// How components are compiled, set on the command line and with `.config`
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct EngineOptions {
+    /// Enable or disable a WebAssembly proposal (e.g. `-W simd=n`), leaving
+    /// the others at wasmtime's defaults
+    #[arg(short = 'W', long = "wasm", value_name = "FEATURE[=y|n]", value_parser = parse_feature_setting)]
+    pub features: Vec<(WasmFeature, bool)>,
+    /// How much the compiler optimizes the component's code
+    #[arg(long, value_enum, default_value_t)]
+    pub opt_level: Optimization,
+    /// The compiler turning the component's code into machine code
+    #[arg(long, value_enum, default_value_t)]
+    pub compiler: Compiler,
+    /// Emit debug info so native debuggers (e.g. gdb or lldb) can debug the
+    /// compiled code
+    #[arg(long)]
+    pub debug_info: bool,
+    /// Allocate instances from pools reserved up front, which makes getting
+    /// new instances faster
+    #[arg(long)]
+    pub pooling: bool,
+    /// Count the fuel every call consumes, which `.stats on` shows, at the
+    /// cost of slower code
+    #[arg(long)]
+    pub fuel: bool,
+    /// Let `.profile` sample the stack of calls, which interrupts them
+    /// periodically at the cost of slower code
+    #[arg(long)]
+    pub profiling: bool,
+    /// Interrupt calls periodically so that frontends collecting output can
+    /// show it before calls return
+    #[arg(skip)]
+    pub stream_output: bool,
+    /// Capture a core dump of the component when a call traps, which
+    /// `.coredump on` asks for
+    #[arg(skip)]
+    pub coredumps: bool,
+}
+
+impl EngineOptions {
+    /// Whether calls are interrupted periodically, for profiling or to
+    /// stream their output
+    pub fn interrupts(&self) -> bool {
+        self.profiling || self.stream_output
+    }
+
+    /// Apply the options to the configuration of an engine
+    pub fn configure(&self, config: &mut Config) {
+        for &(feature, enable) in &self.features {
+            match feature {
+                WasmFeature::Simd => config.wasm_simd(enable),
+                WasmFeature::RelaxedSimd => config.wasm_relaxed_simd(enable),
+                WasmFeature::Threads => config.wasm_threads(enable),
+                WasmFeature::TailCall => config.wasm_tail_call(enable),
+                WasmFeature::Memory64 => config.wasm_memory64(enable),
+            };
+        }
+        config.cranelift_opt_level(match self.opt_level {
+            Optimization::None => OptLevel::None,
+            Optimization::Speed => OptLevel::Speed,
+            Optimization::SpeedAndSize => OptLevel::SpeedAndSize,
+        });
+        config.strategy(match self.compiler {
+            Compiler::Cranelift => Strategy::Cranelift,
+            Compiler::Winch => Strategy::Winch,
+        });
+        config.debug_info(self.debug_info);
+        config.epoch_interruption(self.interrupts());
+        config.coredump_on_trap(self.coredumps);
+        if self.pooling {
+            config.allocation_strategy(InstanceAllocationStrategy::Pooling(
+                PoolingAllocationConfig::default(),
+            ));
+        }
+    }
+
+    /// Enable or disable `feature`, or leave it at wasmtime's default if
+    /// `enable` is `None`
+    pub fn set_feature(&mut self, feature: WasmFeature, enable: Option<bool>) {
+        self.features.retain(|(f, _)| *f != feature);
+        if let Some(enable) = enable {
+            self.features.push((feature, enable));
+        }
+    }
+
+    /// Whether `feature` was enabled or disabled, if it was set at all
+    pub fn feature(&self, feature: WasmFeature) -> Option<bool> {
+        self.features
+            .iter()
+            .rev()
+            .find_map(|(f, enable)| (*f == feature).then_some(*enable))
+    }
+}
+
+impl fmt::Display for EngineOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {}",
+            "compiler".blue().bold(),
+            value_name(self.compiler)
+        )?;
+        writeln!(
+            f,
+            "{}: {}",
+            "opt-level".blue().bold(),
+            value_name(self.opt_level)
+        )?;
+        let debug_info = if self.debug_info { "on" } else { "off" };
+        writeln!(f, "{}: {debug_info}", "debug-info".blue().bold())?;
+        let pooling = if self.pooling { "on" } else { "off" };
+        writeln!(f, "{}: {pooling}", "pooling".blue().bold())?;
+        let fuel = if self.fuel { "on" } else { "off" };
+        writeln!(f, "{}: {fuel}", "fuel".blue().bold())?;
+        let profiling = if self.profiling { "on" } else { "off" };
+        write!(f, "{}: {profiling}", "profiling".blue().bold())?;
+        for feature in <WasmFeature as clap::ValueEnum>::value_variants() {
+            let setting = match self.feature(*feature) {
+                Some(true) => "on",
+                Some(false) => "off",
+                None => "default",
+            };
+            write!(f, "\n{}: {setting}", value_name(*feature).blue().bold())?;
+        }
+        Ok(())
+    }
+}
+
+/// A WebAssembly proposal whose support can be configured
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum WasmFeature {
+    Simd,
+    RelaxedSimd,
+    Threads,
+    TailCall,
+    Memory64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Optimization {
+    None,
+    #[default]
+    Speed,
+    SpeedAndSize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compiler {
+    /// The optimizing compiler
+    #[default]
+    Cranelift,
+    /// The baseline compiler, which compiles faster but generates slower
+    /// code (only available if wasmtime was built with it)
+    Winch,
+}
+
+/// The name `value` has on the command line
+pub fn value_name(value: impl clap::ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+        .unwrap_or_default()
+}
+
+/// Parse a `FEATURE[=y|n]` setting
+fn parse_feature_setting(s: &str) -> Result<(WasmFeature, bool), String> {
+    let (name, enable) = match s.split_once('=') {
+        Some((name, "y" | "yes" | "true")) => (name, true),
+        Some((name, "n" | "no" | "false")) => (name, false),
+        Some((_, value)) => return Err(format!("expected 'y' or 'n' but found '{value}'")),
+        None => (s, true),
+    };
+    let feature = <WasmFeature as clap::ValueEnum>::from_str(name, false)?;
+    Ok((feature, enable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_feature_settings() {
+        assert_eq!(
+            parse_feature_setting("simd=n"),
+            Ok((WasmFeature::Simd, false))
+        );
+        assert_eq!(
+            parse_feature_setting("tail-call"),
+            Ok((WasmFeature::TailCall, true))
+        );
+        assert_eq!(
+            parse_feature_setting("memory64=yes"),
+            Ok((WasmFeature::Memory64, true))
+        );
+        assert!(parse_feature_setting("simd=maybe").is_err());
+        assert!(parse_feature_setting("gc").is_err());
+    }
+
+    #[test]
+    fn interrupts_only_when_asked() {
+        let mut options = EngineOptions::default();
+        assert!(!options.interrupts());
+        options.stream_output = true;
+        assert!(options.interrupts());
+        options = EngineOptions {
+            profiling: true,
+            ..Default::default()
+        };
+        assert!(options.interrupts());
+    }
+}
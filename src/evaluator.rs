@@ -1,12 +1,24 @@
 use std::collections::HashMap;
 
 use anyhow::{bail, Context};
-use wasmtime::component::{self, Val};
+use wasmtime::component::{self, ResourceAny, Val};
 
-use crate::{command::parser, runtime::Runtime, wit::WorldResolver};
+use crate::{
+    command::parser,
+    fuzzy, progress,
+    runtime::{compat::coerce_val, InterfaceResource, Runtime},
+    wit::WorldResolver,
+};
+
+/// A [`TypeError`] with a message formatted like `format!`
+macro_rules! type_error {
+    ($($arg:tt)*) => {
+        anyhow::Error::new(TypeError(format!($($arg)*)))
+    };
+}
 
 pub struct Evaluator<'a> {
-    runtime: &'a mut Runtime,
+    runtime: Option<&'a mut Runtime>,
     resolver: &'a WorldResolver,
     scope: &'a HashMap<String, Val>,
 }
@@ -19,7 +31,18 @@ impl<'a> Evaluator<'a> {
         scope: &'a HashMap<String, Val>,
     ) -> Self {
         Self {
-            runtime,
+            runtime: Some(runtime),
+            resolver,
+            scope,
+        }
+    }
+
+    /// Create a new evaluator that cannot call into the component
+    ///
+    /// Evaluating function calls with this evaluator fails.
+    pub fn without_runtime(resolver: &'a WorldResolver, scope: &'a HashMap<String, Val>) -> Self {
+        Self {
+            runtime: None,
             resolver,
             scope,
         }
@@ -37,7 +60,7 @@ impl<'a> Evaluator<'a> {
             parser::Expr::FunctionCall(func) => {
                 let ident = func.ident;
                 let mut args = func.args;
-                log::debug!(
+                tracing::trace!(
                     "Checking for type constructor for {ident} #args={} type_hint={type_hint:?}",
                     args.len()
                 );
@@ -49,6 +72,29 @@ impl<'a> Evaluator<'a> {
                         let val = self.eval(args.remove(0), Some(&o.ty()))?;
                         return Ok(val);
                     }
+                    Some(component::Type::Variant(v))
+                        if ident.interface.is_none() && args.len() == 1 =>
+                    {
+                        let case = v.cases().find(|c| c.name == ident.item);
+                        match case.and_then(|c| c.ty) {
+                            Some(ty) => {
+                                let payload = self.eval(args.remove(0), Some(&ty))?;
+                                return Ok(Val::Variant(
+                                    ident.item.to_owned(),
+                                    Some(Box::new(payload)),
+                                ));
+                            }
+                            // Only calls of exported functions can stand for a case
+                            None if self.resolver.exported_function(ident).is_none() => {
+                                return Err(unknown_name(
+                                    "variant case",
+                                    ident.item,
+                                    v.cases().map(|c| c.name),
+                                ));
+                            }
+                            None => {}
+                        }
+                    }
                     Some(component::Type::Result(r)) if args.len() == 1 => {
                         if let Some(ok) = r.ok() {
                             if ident.interface.is_none() && ident.item == "ok" {
@@ -75,6 +121,17 @@ impl<'a> Evaluator<'a> {
                 }
                 Ok(results.remove(0))
             }
+            parser::Expr::MethodCall(call) => {
+                let name = format!("{}.{}", call.receiver, call.method);
+                let mut results = self.call_method(call)?;
+                if results.len() != 1 {
+                    bail!(
+                        "Expected method '{name}' to return one result but got {}",
+                        results.len()
+                    )
+                }
+                Ok(results.remove(0))
+            }
         }
     }
 
@@ -84,34 +141,149 @@ impl<'a> Evaluator<'a> {
         ident: parser::ItemIdent,
         args: Vec<parser::Expr<'_>>,
     ) -> anyhow::Result<Vec<Val>> {
-        log::debug!("Calling function: {ident} with args: {args:?}");
-        let func_def = self
-            .resolver
-            .exported_function(ident)
-            .with_context(|| format!("no function with name '{ident}'"))?;
-        let mut evaled_args = Vec::with_capacity(func_def.params.len());
-        if func_def.params.len() != args.len() {
+        self.call_func_with_receiver(ident, None, args)
+    }
+
+    /// Call a method on the resource handle in the variable `call.receiver`,
+    /// or the constructor (`new`) or a static function of the exported
+    /// resource `call.receiver`
+    pub fn call_method(&mut self, call: parser::MethodCall<'_>) -> anyhow::Result<Vec<Val>> {
+        let receiver = call.receiver;
+        let handle = match self.scope.get(receiver.item) {
+            Some(val) if receiver.interface.is_none() => {
+                let Val::Resource(handle) = val else {
+                    bail!("'{receiver}' is not a resource handle")
+                };
+                Some(*handle)
+            }
+            _ => None,
+        };
+        let (interface, item) = match handle {
+            Some(handle) => {
+                let resource = self.runtime()?.resource(handle).with_context(|| {
+                    format!("the resource handle '{receiver}' is no longer valid")
+                })?;
+                (
+                    resource.interface.clone(),
+                    format!("[method]{}.{}", resource.name, call.method),
+                )
+            }
+            None => {
+                let interface = self.resolver.exported_resource(receiver).with_context(|| {
+                    format!("no exported resource or resource handle named '{receiver}'")
+                })?;
+                let item = match call.method {
+                    "new" => format!("[constructor]{}", receiver.item),
+                    method => format!("[static]{}.{method}", receiver.item),
+                };
+                (interface, item)
+            }
+        };
+        let ident = parser::ItemIdent {
+            interface: Some(
+                parser::InterfaceIdent::parse_str(&interface)
+                    .with_context(|| format!("cannot call into the interface '{interface}'"))?,
+            ),
+            item: &item,
+        };
+        if self.resolver.exported_function(ident).is_none() {
+            bail!("'{receiver}' has no method named '{}'", call.method)
+        }
+        self.call_func_with_receiver(ident, handle.map(Val::Resource), call.args)
+    }
+
+    /// Call the function with `receiver` as the first argument if there is one
+    #[tracing::instrument(name = "call", level = "debug", skip_all, fields(func = %ident))]
+    fn call_func_with_receiver(
+        &mut self,
+        ident: parser::ItemIdent,
+        receiver: Option<Val>,
+        args: Vec<parser::Expr<'_>>,
+    ) -> anyhow::Result<Vec<Val>> {
+        tracing::debug!("Calling function: {ident} with args: {args:?}");
+        let func_def = self.resolver.find_exported_function(ident)?;
+        if self.resolver.uses_async_types(func_def) {
+            bail!("'{ident}' takes or returns a `future` or `stream`, which cannot be passed to or from components yet")
+        }
+        let receiver_count = usize::from(receiver.is_some());
+        if func_def.params.len() != args.len() + receiver_count {
             bail!(
                 "tried to call a function that has {} params with {} args",
-                func_def.params.len(),
+                func_def.params.len() - receiver_count,
                 args.len()
             )
         }
-        let func = self.runtime.get_func(ident)?;
-        let names = func_def.params.iter().map(|(n, _)| n);
-        let types = func.params(&mut self.runtime.store);
-        for (param_name, (param_type, arg)) in names.zip(types.iter().zip(args)) {
+        let mut evaled_args = Vec::with_capacity(func_def.params.len());
+        evaled_args.extend(receiver);
+        let func = self.runtime()?.get_func(ident)?;
+        let names = func_def.params.iter().map(|(n, _)| n).skip(receiver_count);
+        let types = func.params(&mut self.runtime()?.store);
+        for (param_name, (param_type, arg)) in
+            names.zip(types.iter().skip(receiver_count).zip(args))
+        {
+            // Check the type here since a failed call poisons the instance
             let evaled_arg = self
                 .eval(arg, Some(param_type))
-                .map_err(|e| anyhow::anyhow!("argument '{param_name}': {e}"))?;
+                // Values from other components (e.g. the results of a stub)
+                // can have structurally equal types that differ in the order
+                // of record fields
+                .map(|val| coerce_val(val.clone(), param_type).unwrap_or(val))
+                .and_then(|val| type_check(&val, param_type).map(|()| val))
+                .map_err(|error| ArgumentError {
+                    param: param_name.clone(),
+                    error,
+                })?;
             evaled_args.push(evaled_arg);
         }
-        let results = self
-            .runtime
-            .call_func(func, &evaled_args, func_def.results.len())?;
+        let spinner = progress::Spinner::start(format!("Calling {ident}"));
+        let outcome = self.runtime()?.call(ident, &evaled_args);
+        drop(spinner);
+        let outcome = outcome?;
+        tracing::debug!(
+            "Call of {ident} returned after {:?}, printing {} bytes to stdout",
+            outcome.duration,
+            outcome.stdout.len()
+        );
+        let results = outcome.results;
+        // Owned handles passed as arguments now belong to the component
+        let mut moved = Vec::new();
+        for ((_, ty), val) in func_def.params.iter().zip(&evaled_args) {
+            visit_handles(self.resolver, ty, val, &mut |_, kind, handle| {
+                if let wit_parser::Handle::Own(_) = kind {
+                    moved.push(handle)
+                }
+            });
+        }
+        for handle in moved {
+            self.runtime()?.forget_resource(handle);
+        }
+        for (ty, val) in func_def.results.iter_types().zip(&results) {
+            self.track_resources(ty, val)?;
+        }
         Ok(results)
     }
 
+    /// Remember the exported resource types of the handles in `val` of type `ty`
+    fn track_resources(&mut self, ty: &wit_parser::Type, val: &Val) -> anyhow::Result<()> {
+        let mut handles = Vec::new();
+        visit_handles(self.resolver, ty, val, &mut |id, _, handle| {
+            handles.push((id, handle))
+        });
+        for (id, handle) in handles {
+            if let Some((interface, name)) = self.resolver.exported_resource_name(id) {
+                let resource = InterfaceResource { interface, name };
+                self.runtime()?.track_resource(handle, resource);
+            }
+        }
+        Ok(())
+    }
+
+    fn runtime(&mut self) -> anyhow::Result<&mut Runtime> {
+        self.runtime
+            .as_deref_mut()
+            .context("functions cannot be called here")
+    }
+
     /// Evaluate a literal using the provided type hint
     pub fn eval_literal(
         &mut self,
@@ -136,15 +308,15 @@ impl<'a> Evaluator<'a> {
                             }
                             Ok(Val::Option(Some(Box::new(Val::List(values)))))
                         }
-                        t => bail!(
-                            "type error - required option<{}> found = list",
+                        t => Err(type_error!(
+                            "required option<{}> found = list",
                             display_component_type(&t)
-                        ),
+                        )),
                     },
-                    Some(t) => bail!(
-                        "type error - required = {} found = list",
+                    Some(t) => Err(type_error!(
+                        "required = {} found = list",
                         display_component_type(t)
-                    ),
+                    )),
                     None => {
                         // TODO: try to find a list type that fits the shape of the literal
                         bail!("cannot determine type of list")
@@ -154,10 +326,12 @@ impl<'a> Evaluator<'a> {
             parser::Literal::Record(mut r) => {
                 let ty = match type_hint {
                     Some(component::Type::Record(r)) => r,
-                    Some(t) => bail!(
-                        "type error - required = {} found = record",
-                        display_component_type(t)
-                    ),
+                    Some(t) => {
+                        return Err(type_error!(
+                            "required = {} found = record",
+                            display_component_type(t)
+                        ))
+                    }
                     None => {
                         // TODO: try to find a record type that fits the shape of the literal
                         bail!("cannot determine type of record")
@@ -169,6 +343,13 @@ impl<'a> Evaluator<'a> {
                     .enumerate()
                     .map(|(index, field)| (field.name, index))
                     .collect::<HashMap<_, _>>();
+                if let Some((name, _)) = r.fields.iter().find(|(f, _)| !types.contains_key(f)) {
+                    return Err(unknown_name(
+                        "record field",
+                        name,
+                        ty.fields().map(|f| f.name),
+                    ));
+                }
                 // Sort the fields since wasmtime expects the fields to be in the defined order
                 r.fields
                     .sort_by(|(f1, _), (f2, _)| types.get(f1).unwrap().cmp(types.get(f2).unwrap()));
@@ -194,6 +375,12 @@ impl<'a> Evaluator<'a> {
             }
             parser::Literal::Number(n) => match type_hint {
                 Some(component::Type::U8) => Ok(Val::U8(n.try_into()?)),
+                Some(component::Type::U16) => Ok(Val::U16(n.try_into()?)),
+                Some(component::Type::U32) => Ok(Val::U32(n.try_into()?)),
+                Some(component::Type::U64) => Ok(Val::U64(n.try_into()?)),
+                Some(component::Type::S8) => Ok(Val::S8(n.try_into()?)),
+                Some(component::Type::S16) => Ok(Val::S16(n.try_into()?)),
+                Some(component::Type::S64) => Ok(Val::S64(n.try_into()?)),
                 _ => Ok(Val::S32(n.try_into()?)),
             },
         }
@@ -204,15 +391,30 @@ impl<'a> Evaluator<'a> {
         ident: &str,
         type_hint: Option<&component::Type>,
     ) -> Result<Val, anyhow::Error> {
-        log::debug!("Resolving ident {ident} with type hint {type_hint:?}");
+        tracing::trace!("Resolving ident {ident} with type hint {type_hint:?}");
         match type_hint {
             Some(t) => match t {
                 component::Type::Bool if ident == "true" => Ok(Val::Bool(true)),
                 component::Type::Bool if ident == "false" => Ok(Val::Bool(false)),
-                component::Type::Enum(_) => Ok(Val::Enum(ident.to_owned())),
-                component::Type::Variant(_) => match self.lookup_in_scope(ident) {
+                component::Type::Enum(e) if e.names().any(|n| n == ident) => {
+                    Ok(Val::Enum(ident.to_owned()))
+                }
+                component::Type::Enum(e) => self
+                    .lookup_in_scope(ident)
+                    .map_err(|_| unknown_name("enum case", ident, e.names())),
+                component::Type::Variant(v) => match self.lookup_in_scope(ident) {
                     Ok(v) => Ok(v),
-                    Err(_) => Ok(Val::Option(None)),
+                    Err(_) if v.cases().any(|c| c.name == ident && c.ty.is_none()) => {
+                        Ok(Val::Variant(ident.to_owned(), None))
+                    }
+                    Err(_) if v.cases().any(|c| c.name == ident) => {
+                        Err(type_error!("variant case '{ident}' requires a payload"))
+                    }
+                    Err(_) => Err(unknown_name(
+                        "variant case",
+                        ident,
+                        v.cases().map(|c| c.name),
+                    )),
                 },
                 component::Type::Option(_) if ident == "none" => Ok(Val::Option(None)),
                 component::Type::Option(o) => Ok(Val::Option(Some(Box::new(
@@ -236,7 +438,17 @@ impl<'a> Evaluator<'a> {
                 | component::Type::S32
                 | component::Type::S64
                 | component::Type::String => self.lookup_in_scope(ident),
-                t => todo!("handle ident '{ident}' with type {t:?}"),
+                component::Type::Own(_) | component::Type::Borrow(_) => {
+                    let val = self.lookup_in_scope(ident)?;
+                    if let (Val::Resource(handle), Some(runtime)) = (&val, &self.runtime) {
+                        if runtime.resource(*handle).is_none() {
+                            bail!("the resource handle '{ident}' is no longer valid")
+                        }
+                    }
+                    Ok(val)
+                }
+                // Values of every other type can only come from variables
+                _ => self.lookup_in_scope(ident),
             },
             None => self.lookup_in_scope(ident),
         }
@@ -250,7 +462,235 @@ impl<'a> Evaluator<'a> {
     }
 }
 
-fn display_component_type(ty: &component::Type) -> &'static str {
+/// Call `f` with the handle type, the kind of handle and the handle for
+/// every resource handle in `val` of type `ty`
+fn visit_handles(
+    resolver: &WorldResolver,
+    ty: &wit_parser::Type,
+    val: &Val,
+    f: &mut impl FnMut(wit_parser::TypeId, &wit_parser::Handle, ResourceAny),
+) {
+    let wit_parser::Type::Id(id) = ty else {
+        return;
+    };
+    let Some(def) = resolver.type_by_id(*id) else {
+        return;
+    };
+    match (&def.kind, val) {
+        (wit_parser::TypeDefKind::Handle(kind), Val::Resource(handle)) => f(*id, kind, *handle),
+        (wit_parser::TypeDefKind::Type(ty), val) => visit_handles(resolver, ty, val, f),
+        (wit_parser::TypeDefKind::Option(ty), Val::Option(Some(val))) => {
+            visit_handles(resolver, ty, val, f)
+        }
+        (wit_parser::TypeDefKind::Result(r), Val::Result(result)) => {
+            let (ty, val) = match result {
+                Ok(val) => (r.ok.as_ref(), val),
+                Err(val) => (r.err.as_ref(), val),
+            };
+            if let (Some(ty), Some(val)) = (ty, val) {
+                visit_handles(resolver, ty, val, f)
+            }
+        }
+        (wit_parser::TypeDefKind::List(ty), Val::List(vals)) => {
+            for val in vals {
+                visit_handles(resolver, ty, val, f)
+            }
+        }
+        (wit_parser::TypeDefKind::Tuple(t), Val::Tuple(vals)) => {
+            for (ty, val) in t.types.iter().zip(vals) {
+                visit_handles(resolver, ty, val, f)
+            }
+        }
+        (wit_parser::TypeDefKind::Record(r), Val::Record(fields)) => {
+            for (field, (_, val)) in r.fields.iter().zip(fields) {
+                visit_handles(resolver, &field.ty, val, f)
+            }
+        }
+        (wit_parser::TypeDefKind::Variant(v), Val::Variant(case, Some(val))) => {
+            if let Some(ty) = v.cases.iter().find(|c| c.name == *case).and_then(|c| c.ty) {
+                visit_handles(resolver, &ty, val, f)
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check that `val` is a value of type `ty`
+pub fn type_check(val: &Val, ty: &component::Type) -> anyhow::Result<()> {
+    let mismatch = || {
+        type_error!(
+            "required = {} found = {}",
+            display_component_type(ty),
+            display_val_type(val)
+        )
+    };
+    match (val, ty) {
+        (Val::Bool(_), component::Type::Bool)
+        | (Val::S8(_), component::Type::S8)
+        | (Val::U8(_), component::Type::U8)
+        | (Val::S16(_), component::Type::S16)
+        | (Val::U16(_), component::Type::U16)
+        | (Val::S32(_), component::Type::S32)
+        | (Val::U32(_), component::Type::U32)
+        | (Val::S64(_), component::Type::S64)
+        | (Val::U64(_), component::Type::U64)
+        | (Val::Float32(_), component::Type::Float32)
+        | (Val::Float64(_), component::Type::Float64)
+        | (Val::Char(_), component::Type::Char)
+        | (Val::String(_), component::Type::String) => Ok(()),
+        (Val::List(items), component::Type::List(l)) => {
+            items.iter().try_for_each(|item| type_check(item, &l.ty()))
+        }
+        (Val::Record(fields), component::Type::Record(r)) => {
+            if fields.len() != r.fields().len() {
+                return Err(type_error!(
+                    "record has {} fields but {} are required",
+                    fields.len(),
+                    r.fields().len()
+                ));
+            }
+            for ((name, val), field) in fields.iter().zip(r.fields()) {
+                if name != field.name {
+                    return Err(type_error!("expected record field '{}'", field.name));
+                }
+                type_check(val, &field.ty).with_context(|| format!("record field '{name}'"))?;
+            }
+            Ok(())
+        }
+        (Val::Tuple(items), component::Type::Tuple(t)) => {
+            if items.len() != t.types().len() {
+                return Err(type_error!(
+                    "tuple has {} items but {} are required",
+                    items.len(),
+                    t.types().len()
+                ));
+            }
+            items
+                .iter()
+                .zip(t.types())
+                .try_for_each(|(item, ty)| type_check(item, &ty))
+        }
+        (Val::Variant(name, payload), component::Type::Variant(v)) => {
+            let case = v
+                .cases()
+                .find(|c| c.name == name)
+                .ok_or_else(|| unknown_name("variant case", name, v.cases().map(|c| c.name)))?;
+            match (payload, case.ty) {
+                (None, None) => Ok(()),
+                (Some(payload), Some(ty)) => type_check(payload, &ty),
+                _ => Err(type_error!("wrong payload for variant case '{name}'")),
+            }
+        }
+        (Val::Enum(name), component::Type::Enum(e)) => {
+            if !e.names().any(|n| n == name) {
+                return Err(unknown_name("enum case", name, e.names()));
+            }
+            Ok(())
+        }
+        (Val::Option(val), component::Type::Option(o)) => match val {
+            Some(val) => type_check(val, &o.ty()),
+            None => Ok(()),
+        },
+        (Val::Result(val), component::Type::Result(r)) => match (val, r.ok(), r.err()) {
+            (Ok(None), None, _) | (Err(None), _, None) => Ok(()),
+            (Ok(Some(val)), Some(ty), _) | (Err(Some(val)), _, Some(ty)) => type_check(val, &ty),
+            _ => Err(type_error!("wrong payload for result")),
+        },
+        (Val::Flags(flags), component::Type::Flags(f)) => {
+            if let Some(flag) = flags.iter().find(|flag| !f.names().any(|n| n == *flag)) {
+                return Err(unknown_name("flag", flag, f.names()));
+            }
+            Ok(())
+        }
+        (Val::Resource(handle), component::Type::Own(r) | component::Type::Borrow(r)) => {
+            if handle.ty() != *r {
+                return Err(type_error!(
+                    "the resource handle is for a different resource type"
+                ));
+            }
+            if matches!(ty, component::Type::Own(_)) && !handle.owned() {
+                return Err(type_error!("required an owned handle found a borrowed one"));
+            }
+            Ok(())
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+/// A value that does not have the type it needs to have
+#[derive(Debug)]
+pub struct TypeError(String);
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "type error - {}", self.0)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// An argument of a call that could not be evaluated to a value of the type of
+/// its parameter
+#[derive(Debug)]
+pub struct ArgumentError {
+    pub param: String,
+    pub error: anyhow::Error,
+}
+
+impl std::fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "argument '{}': {:#}", self.param, self.error)
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+/// The error for `name` not being one of the `names` of a `kind` (e.g.
+/// "record field"), listing them and suggesting the closest
+fn unknown_name<'a>(
+    kind: &str,
+    name: &str,
+    names: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Error {
+    let names = names.into_iter().collect::<Vec<_>>();
+    let suggestion = match fuzzy::suggest(name, names.iter().copied()) {
+        Some(suggestion) => format!(", did you mean '{suggestion}'?"),
+        None => String::new(),
+    };
+    type_error!(
+        "no {kind} named '{name}'{suggestion} (expected one of: {})",
+        names.join(", ")
+    )
+}
+
+fn display_val_type(val: &Val) -> &'static str {
+    match val {
+        Val::Bool(_) => "bool",
+        Val::S8(_) => "s8",
+        Val::U8(_) => "u8",
+        Val::S16(_) => "s16",
+        Val::U16(_) => "u16",
+        Val::S32(_) => "s32",
+        Val::U32(_) => "u32",
+        Val::S64(_) => "s64",
+        Val::U64(_) => "u64",
+        Val::Float32(_) => "float32",
+        Val::Float64(_) => "float64",
+        Val::Char(_) => "char",
+        Val::String(_) => "string",
+        Val::List(_) => "list",
+        Val::Record(_) => "record",
+        Val::Tuple(_) => "tuple",
+        Val::Variant(..) => "variant",
+        Val::Enum(_) => "enum",
+        Val::Option(_) => "option",
+        Val::Result(_) => "result",
+        Val::Flags(_) => "flags",
+        Val::Resource(_) => "resource",
+    }
+}
+
+pub fn display_component_type(ty: &component::Type) -> &'static str {
     match ty {
         component::Type::Bool => "bool",
         component::Type::S8 => "s8",
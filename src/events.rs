@@ -0,0 +1,525 @@
+use std::{
+    collections::HashSet,
+    io::Write as _,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use colored::Colorize;
+use serde_json::json;
+use wasmtime::component::Val;
+
+use crate::{
+    command::{format_val, val_as_type},
+    json::val_to_json,
+    logging::LogRecord,
+    stats::CallStats,
+    table,
+};
+
+/// Something that happened while a line was evaluated, which frontends render
+/// however suits them
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The value of an expression that is a literal or a variable
+    Value {
+        val: Val,
+        /// Whether a list of records is rendered as a table, as
+        /// `.format table` asks
+        table: bool,
+    },
+    /// The results of a call into the component
+    Results {
+        values: Vec<Val>,
+        /// Whether lists of records are rendered as tables, as
+        /// `.format table` asks
+        table: bool,
+    },
+    /// A chunk of what the guest or a stub wrote to stdout, or to stderr
+    /// under `--sandbox`
+    Output {
+        source: OutputSource,
+        /// The name of the stub that wrote the chunk, if a stub did
+        stub: Option<String>,
+        bytes: bytes::Bytes,
+    },
+    /// A call across the component's boundary shown by `.trace`
+    Trace {
+        /// The function called along with its arguments, e.g. `get(1)`
+        call: String,
+        /// The results of the call or the error it failed with
+        results: Result<Vec<Val>, String>,
+        elapsed: Duration,
+        /// What implements the function, e.g. `stubbed`
+        source: &'static str,
+    },
+    /// How much the instance grew during a call, shown by `.stats`
+    Stats(CallStats),
+    /// Something that went wrong without failing the line
+    Warning(String),
+    /// The guest was denied an address by the network policy
+    Denied(String),
+    /// A record the guest logged through `wasi:logging` at or above the
+    /// level `.log-level` shows
+    Log(LogRecord),
+    /// A line of what a builtin shows, like the exports `.exports` lists
+    Message(String),
+}
+
+impl Event {
+    /// The event as the repl prints it, except for output which is printed
+    /// as it is (with a prefix at the start of each line)
+    pub fn render(&self) -> String {
+        match self {
+            Event::Value { val, table } => match table::format(val, *table) {
+                Some(table) => table,
+                None => format!("{}: {}", format_val(val), val_as_type(val)),
+            },
+            Event::Results { values, table } => values
+                .iter()
+                .map(|val| table::format(val, *table).unwrap_or_else(|| format_val(val)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Event::Output { bytes, .. } => String::from_utf8_lossy(bytes).into_owned(),
+            Event::Trace {
+                call,
+                results,
+                elapsed,
+                source,
+            } => {
+                let results = match results {
+                    Ok(results) if results.is_empty() => String::new(),
+                    Ok(results) => {
+                        let results = results.iter().map(format_val).collect::<Vec<_>>();
+                        format!(" -> {}", results.join(", "))
+                    }
+                    Err(e) => format!(" -> error: {e}"),
+                };
+                format!(
+                    "{} {call}{results} ({elapsed:?}, {source})",
+                    "Trace:".magenta().bold()
+                )
+            }
+            Event::Stats(stats) => format!("{} {stats}", "Stats:".blue().bold()),
+            Event::Warning(warning) => format!("{} {warning}", "Warning:".yellow().bold()),
+            Event::Denied(denied) => format!("{} {denied}", "Denied:".red().bold()),
+            Event::Log(record) => record.render(),
+            Event::Message(text) => text.clone(),
+        }
+    }
+
+    /// The values the event shows, which only results and values have
+    pub fn values(&self) -> &[Val] {
+        match self {
+            Event::Value { val, .. } => std::slice::from_ref(val),
+            Event::Results { values, .. } => values,
+            _ => &[],
+        }
+    }
+
+    /// The event as a JSON object, whose `event` tells its kind and whose
+    /// `text` is how it is rendered
+    ///
+    /// Values that cannot be represented in JSON, like resources, are `null`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let rendered = self.render();
+        let values = |vals: &[Val]| {
+            vals.iter()
+                .map(|val| val_to_json(val).unwrap_or_default())
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Event::Value { val, .. } => json!({
+                "event": "value",
+                "value": val_to_json(val).unwrap_or_default(),
+                "type": val_as_type(val),
+                "text": rendered,
+            }),
+            Event::Results {
+                values: results, ..
+            } => json!({
+                "event": "results",
+                "values": values(results),
+                "text": rendered,
+            }),
+            Event::Output { source, stub, .. } => json!({
+                "event": "output",
+                "source": source.name(),
+                "stub": stub,
+                "text": rendered,
+            }),
+            Event::Trace {
+                call,
+                results,
+                elapsed,
+                source,
+            } => {
+                let (results, error) = match results {
+                    Ok(results) => (json!(values(results)), None),
+                    Err(e) => (serde_json::Value::Null, Some(e)),
+                };
+                json!({
+                    "event": "trace",
+                    "call": call,
+                    "results": results,
+                    "error": error,
+                    "elapsed_ms": elapsed.as_secs_f64() * 1000.0,
+                    "source": source,
+                    "text": rendered,
+                })
+            }
+            Event::Stats(stats) => json!({
+                "event": "stats",
+                "memory": stats.memory,
+                "table_elements": stats.table_elements,
+                "resources_created": stats.resources_created,
+                "resources_dropped": stats.resources_dropped,
+                "fuel": stats.fuel,
+                "text": rendered,
+            }),
+            Event::Warning(_) => json!({"event": "warning", "text": rendered}),
+            Event::Denied(_) => json!({"event": "denied", "text": rendered}),
+            Event::Log(record) => json!({
+                "event": "log",
+                "level": record.level.name(),
+                "context": record.context,
+                "message": record.message,
+                "text": rendered,
+            }),
+            Event::Message(_) => json!({"event": "message", "text": rendered}),
+        }
+    }
+}
+
+/// Where output printed to the terminal originates from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputSource {
+    /// The main component
+    Guest,
+    /// Components satisfying the main component's imports
+    Import,
+}
+
+impl OutputSource {
+    fn name(self) -> &'static str {
+        match self {
+            OutputSource::Guest => "guest",
+            OutputSource::Import => "import",
+        }
+    }
+}
+
+/// How output from an [`OutputSource`] is decorated
+#[derive(Clone, Debug)]
+pub struct OutputStyle {
+    /// The prefix printed at the start of every line, if any
+    pub prefix: Option<String>,
+    /// The color of the prefix
+    pub color: colored::Color,
+}
+
+impl OutputStyle {
+    fn new(prefix: &str, color: colored::Color) -> Self {
+        Self {
+            prefix: Some(prefix.to_owned()),
+            color,
+        }
+    }
+}
+
+/// The output styles of all output sources
+#[derive(Clone)]
+struct OutputStyles {
+    guest: OutputStyle,
+    import: OutputStyle,
+}
+
+impl Default for OutputStyles {
+    fn default() -> Self {
+        Self {
+            guest: OutputStyle::new("<guest>", colored::Color::Cyan),
+            import: OutputStyle::new("<{stub}>", colored::Color::Green),
+        }
+    }
+}
+
+impl OutputStyles {
+    fn get_mut(&mut self, source: OutputSource) -> &mut OutputStyle {
+        match source {
+            OutputSource::Guest => &mut self.guest,
+            OutputSource::Import => &mut self.import,
+        }
+    }
+}
+
+/// How often the output collected while a call runs is passed on to the
+/// frontend collecting it
+pub const OUTPUT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the output collected while a call runs is passed to, so frontends
+/// that collect events can show it before the call returns
+pub type OutputHandler = Arc<dyn Fn(Vec<Event>) + Send + Sync>;
+
+/// Where a runtime emits its [`Event`]s, shared with the output streams and
+/// import definitions that emit them
+///
+/// Events are printed to the terminal as they are emitted unless they are
+/// being collected for a frontend that renders them itself.
+#[derive(Clone, Default)]
+pub struct Events {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    styles: OutputStyles,
+    /// Whether lists of records are rendered as tables, which `.format table`
+    /// turns on
+    tables: bool,
+    collected: Option<Vec<Event>>,
+    /// What collected output is passed to while calls run, if anything
+    output_handler: Option<OutputHandler>,
+    /// The outputs whose last chunk did not end a line, so that the next one
+    /// does not start with a prefix
+    mid_line: HashSet<(OutputSource, Option<String>)>,
+}
+
+impl Events {
+    pub fn emit(&self, event: Event) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(collected) = &mut inner.collected {
+            collected.push(event);
+            return;
+        }
+        crate::progress::interrupt();
+        match event {
+            Event::Output {
+                source,
+                stub,
+                bytes,
+            } => {
+                let output = inner.prefixed(source, stub, &bytes);
+                let mut stdout = std::io::stdout().lock();
+                let _ = stdout.write_all(&output);
+                let _ = stdout.flush();
+            }
+            Event::Warning(_) | Event::Denied(_) | Event::Log(_) => {
+                eprintln!("{}", event.render())
+            }
+            _ => println!("{}", event.render()),
+        }
+    }
+
+    /// Emit `text` as a line a builtin shows
+    pub fn print(&self, text: impl Into<String>) {
+        self.emit(Event::Message(text.into()));
+    }
+
+    /// Collect the events emitted from now on instead of printing them
+    pub fn collect(&self) {
+        self.inner
+            .lock()
+            .unwrap()
+            .collected
+            .get_or_insert_with(Vec::new);
+    }
+
+    /// Collect the events emitted from now on like [`Events::collect`],
+    /// except for output, which is passed to `handler` every time
+    /// [`Events::flush_output`] is called
+    pub fn collect_streaming(&self, handler: OutputHandler) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.collected.get_or_insert_with(Vec::new);
+        inner.output_handler = Some(handler);
+    }
+
+    /// Collect events like `other` does, if it does, taking over the ones it
+    /// collected so far
+    ///
+    /// Runtimes replacing others while a line runs collect like them this way.
+    pub fn collect_like(&self, other: &Events) {
+        let (collected, handler) = {
+            let mut other = other.inner.lock().unwrap();
+            let Some(collected) = &mut other.collected else {
+                return;
+            };
+            (std::mem::take(collected), other.output_handler.clone())
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .collected
+            .get_or_insert_with(Vec::new)
+            .splice(0..0, collected);
+        inner.output_handler = handler;
+    }
+
+    /// Whether events are collected rather than printed to the terminal
+    pub fn collects(&self) -> bool {
+        self.inner.lock().unwrap().collected.is_some()
+    }
+
+    /// Whether collected output is passed to a handler while calls run
+    pub fn streams_output(&self) -> bool {
+        self.inner.lock().unwrap().output_handler.is_some()
+    }
+
+    /// Pass the output collected so far to the handler given to
+    /// [`Events::collect_streaming`], leaving the other events collected
+    pub fn flush_output(&self) {
+        let (handler, output) = {
+            let mut inner = self.inner.lock().unwrap();
+            let Some(handler) = inner.output_handler.clone() else {
+                return;
+            };
+            let Some(collected) = &mut inner.collected else {
+                return;
+            };
+            let (output, rest) = std::mem::take(collected)
+                .into_iter()
+                .partition::<Vec<_>, _>(|event| matches!(event, Event::Output { .. }));
+            *collected = rest;
+            (handler, output)
+        };
+        // The handler may take a while, so events are not held up meanwhile
+        if !output.is_empty() {
+            handler(output);
+        }
+    }
+
+    /// Stop collecting events, returning the ones collected
+    pub fn take(&self) -> Vec<Event> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.output_handler = None;
+        inner.collected.take().unwrap_or_default()
+    }
+
+    /// Whether values and results are to be rendered with lists of records as
+    /// tables
+    pub fn tables(&self) -> bool {
+        self.inner.lock().unwrap().tables
+    }
+
+    /// Render lists of records as tables from now on, or go back to rendering
+    /// them like other values
+    pub fn set_tables(&self, tables: bool) {
+        self.inner.lock().unwrap().tables = tables;
+    }
+
+    /// The style of the output of `source`
+    pub fn style(&self, source: OutputSource) -> OutputStyle {
+        self.inner.lock().unwrap().styles.get_mut(source).clone()
+    }
+
+    /// Change the style of the output of `source`, which takes effect
+    /// immediately
+    pub fn set_style(&self, source: OutputSource, style: OutputStyle) {
+        *self.inner.lock().unwrap().styles.get_mut(source) = style;
+    }
+}
+
+impl Inner {
+    /// `bytes` with the prefix of `source` at the start of every line
+    fn prefixed(&mut self, source: OutputSource, stub: Option<String>, bytes: &[u8]) -> Vec<u8> {
+        let style = self.styles.get_mut(source).clone();
+        let Some(mut prefix) = style.prefix else {
+            return bytes.to_vec();
+        };
+        if let Some(stub) = &stub {
+            prefix = prefix.replace("{stub}", stub);
+        }
+        let prefix = format!("{} ", prefix.color(style.color).bold());
+        let key = (source, stub);
+        let mut at_line_start = !self.mid_line.contains(&key);
+        let mut output = Vec::with_capacity(bytes.len());
+        for line in bytes.split_inclusive(|b| *b == b'\n') {
+            if at_line_start {
+                output.extend_from_slice(prefix.as_bytes());
+            }
+            output.extend_from_slice(line);
+            at_line_start = line.ends_with(b"\n");
+        }
+        if at_line_start {
+            self.mid_line.remove(&key);
+        } else {
+            self.mid_line.insert(key);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        colored::control::set_override(false);
+        let events = Events::default();
+        let mut inner = events.inner.lock().unwrap();
+        let guest = |inner: &mut Inner, bytes: &[u8]| {
+            String::from_utf8(inner.prefixed(OutputSource::Guest, None, bytes)).unwrap()
+        };
+        assert_eq!(guest(&mut inner, b"a\nb"), "<guest> a\n<guest> b");
+        let stub = inner.prefixed(OutputSource::Import, Some("kv".to_owned()), b"c\n");
+        assert_eq!(String::from_utf8(stub).unwrap(), "<kv> c\n");
+        assert_eq!(guest(&mut inner, b"c\nd\n"), "c\n<guest> d\n");
+        assert_eq!(guest(&mut inner, b"e"), "<guest> e");
+    }
+
+    #[test]
+    fn collecting() {
+        let events = Events::default();
+        assert!(!events.collects());
+        events.collect();
+        assert!(events.collects());
+        events.emit(Event::Warning("careful".to_owned()));
+        events.emit(Event::Results {
+            values: vec![Val::U32(1), Val::Bool(true)],
+            table: events.tables(),
+        });
+        let collected = events.take();
+        assert_eq!(collected.len(), 2);
+        assert_eq!(collected[1].values(), [Val::U32(1), Val::Bool(true)]);
+        assert_eq!(collected[1].to_json()["values"], json!([1, true]));
+        assert!(events.take().is_empty());
+        assert!(!events.collects());
+    }
+
+    #[test]
+    fn tables_are_rendered_per_events() {
+        let (tables, plain) = (Events::default(), Events::default());
+        tables.set_tables(true);
+        assert!(!plain.tables());
+        let record = Val::Record(vec![("a".to_owned(), Val::U32(1))]);
+        let results = |events: &Events| Event::Results {
+            values: vec![Val::List(vec![record.clone()])],
+            table: events.tables(),
+        };
+        assert_eq!(results(&plain).render(), "[{ a: 1 }]");
+        assert_ne!(results(&tables).render(), "[{ a: 1 }]");
+    }
+
+    #[test]
+    fn streaming_output() {
+        let streamed = Arc::new(Mutex::new(Vec::new()));
+        let events = Events::default();
+        events.collect_streaming(Arc::new({
+            let streamed = streamed.clone();
+            move |output| streamed.lock().unwrap().extend(output)
+        }));
+        let output = |text: &'static str| Event::Output {
+            source: OutputSource::Guest,
+            stub: None,
+            bytes: bytes::Bytes::from_static(text.as_bytes()),
+        };
+        events.emit(output("a"));
+        events.emit(Event::Warning("careful".to_owned()));
+        events.flush_output();
+        events.emit(output("b"));
+        assert_eq!(streamed.lock().unwrap().len(), 1);
+        let collected = events.take();
+        assert_eq!(collected.len(), 2);
+        assert!(matches!(collected[0], Event::Warning(_)));
+        assert!(!events.streams_output());
+    }
+}
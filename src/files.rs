@@ -0,0 +1,127 @@
+use anyhow::Context as _;
+use wasmtime::component::Val;
+
+use crate::json;
+
+/// How a value is stored in a file by `.write` and `.read-file`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    /// The JSON form of [`json::val_to_json`]
+    Json,
+    /// The raw bytes of a `list<u8>`
+    Bytes,
+    /// The UTF-8 text of a `string`
+    Text,
+}
+
+impl FileFormat {
+    /// The format named `name` after `--as`, which is either the name of the
+    /// format or the WIT type it stores
+    pub fn parse(name: &str) -> anyhow::Result<Self> {
+        Ok(match name {
+            "json" => FileFormat::Json,
+            "bytes" | "list<u8>" => FileFormat::Bytes,
+            "text" | "string" => FileFormat::Text,
+            _ => anyhow::bail!(
+                "unknown file format '{name}', expected json, bytes (or list<u8>) or text (or string)"
+            ),
+        })
+    }
+
+    /// The format `val` is written in unless another one is asked for: raw
+    /// bytes for lists of bytes, text for strings and JSON for anything else
+    pub fn of(val: &Val) -> Self {
+        match val {
+            Val::String(_) => FileFormat::Text,
+            Val::List(items)
+                if !items.is_empty() && items.iter().all(|i| matches!(i, Val::U8(_))) =>
+            {
+                FileFormat::Bytes
+            }
+            _ => FileFormat::Json,
+        }
+    }
+}
+
+/// The contents of a file storing `val` in `format`
+pub fn to_bytes(val: &Val, format: FileFormat) -> anyhow::Result<Vec<u8>> {
+    match format {
+        FileFormat::Json => {
+            let json = json::val_to_json(val)?;
+            let mut contents = serde_json::to_string_pretty(&json)?;
+            contents.push('\n');
+            Ok(contents.into_bytes())
+        }
+        FileFormat::Bytes => match val {
+            Val::List(items) => items
+                .iter()
+                .map(|item| match item {
+                    Val::U8(byte) => Ok(*byte),
+                    _ => anyhow::bail!("only a list<u8> can be written as bytes"),
+                })
+                .collect(),
+            _ => anyhow::bail!("only a list<u8> can be written as bytes"),
+        },
+        FileFormat::Text => match val {
+            Val::String(s) => Ok(s.as_bytes().to_vec()),
+            _ => anyhow::bail!("only a string can be written as text"),
+        },
+    }
+}
+
+/// The value stored in a file with the contents `bytes` in `format`
+///
+/// JSON cannot be read since the type of the value it stores is unknown.
+pub fn from_bytes(bytes: Vec<u8>, format: FileFormat) -> anyhow::Result<Val> {
+    match format {
+        FileFormat::Bytes => Ok(Val::List(bytes.into_iter().map(Val::U8).collect())),
+        FileFormat::Text => Ok(Val::String(
+            String::from_utf8(bytes).context("the file is not valid UTF-8")?,
+        )),
+        FileFormat::Json => {
+            anyhow::bail!("files can only be read as list<u8> or string")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        let bytes = Val::List(vec![Val::U8(0), Val::U8(255)]);
+        assert_eq!(FileFormat::of(&bytes), FileFormat::Bytes);
+        assert_eq!(to_bytes(&bytes, FileFormat::Bytes).unwrap(), [0, 255]);
+        assert_eq!(from_bytes(vec![0, 255], FileFormat::Bytes).unwrap(), bytes);
+        assert!(to_bytes(&bytes, FileFormat::Text).is_err());
+    }
+
+    #[test]
+    fn text() {
+        let text = Val::String("héllo".to_owned());
+        assert_eq!(FileFormat::of(&text), FileFormat::Text);
+        assert_eq!(
+            from_bytes(to_bytes(&text, FileFormat::Text).unwrap(), FileFormat::Text).unwrap(),
+            text
+        );
+        assert!(from_bytes(vec![0xff], FileFormat::Text).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let record = Val::Record(vec![("n".to_owned(), Val::U32(1))]);
+        assert_eq!(FileFormat::of(&record), FileFormat::Json);
+        assert_eq!(
+            to_bytes(&record, FileFormat::Json).unwrap(),
+            b"{\n  \"n\": 1\n}\n"
+        );
+        assert_eq!(FileFormat::of(&Val::List(Vec::new())), FileFormat::Json);
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(FileFormat::parse("list<u8>").unwrap(), FileFormat::Bytes);
+        assert!(FileFormat::parse("u32").is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use rand::{rngs::StdRng, seq::SliceRandom as _, Rng as _, SeedableRng as _};
+use wasmtime::component::{Type, Val};
+
+/// How deeply values nest before lists are left empty and options `none`
+const MAX_DEPTH: usize = 4;
+
+/// Generates random values of component types
+///
+/// Numbers are often picked among the edge cases of their type (e.g. `0`,
+/// `-1` or the largest value) since those are where bugs tend to be.
+pub struct ValGenerator {
+    rng: StdRng,
+    /// The most elements in generated lists and characters in strings
+    max_len: usize,
+}
+
+impl ValGenerator {
+    pub fn new(seed: u64, max_len: usize) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            max_len,
+        }
+    }
+
+    /// A random value of type `ty`
+    pub fn generate(&mut self, ty: &Type) -> anyhow::Result<Val> {
+        self.generate_at(ty, 0)
+    }
+
+    fn generate_at(&mut self, ty: &Type, depth: usize) -> anyhow::Result<Val> {
+        macro_rules! int {
+            ($val:ident, $int:ty) => {{
+                let edges = [0, 1, <$int>::MIN, <$int>::MAX, <$int>::MAX / 2 + 1];
+                Val::$val(match self.rng.gen_bool(0.25) {
+                    true => *edges.choose(&mut self.rng).unwrap(),
+                    false => self.rng.gen(),
+                })
+            }};
+        }
+        macro_rules! float {
+            ($val:ident, $float:ty) => {{
+                let edges = [
+                    0.0,
+                    -0.0,
+                    1.0,
+                    <$float>::NAN,
+                    <$float>::INFINITY,
+                    <$float>::NEG_INFINITY,
+                    <$float>::MAX,
+                    <$float>::MIN_POSITIVE,
+                ];
+                Val::$val(match self.rng.gen_bool(0.25) {
+                    true => *edges.choose(&mut self.rng).unwrap(),
+                    false => self.rng.gen_range(-1e6..1e6),
+                })
+            }};
+        }
+        let nested = depth + 1;
+        Ok(match ty {
+            Type::Bool => Val::Bool(self.rng.gen()),
+            Type::S8 => int!(S8, i8),
+            Type::U8 => int!(U8, u8),
+            Type::S16 => int!(S16, i16),
+            Type::U16 => int!(U16, u16),
+            Type::S32 => int!(S32, i32),
+            Type::U32 => int!(U32, u32),
+            Type::S64 => int!(S64, i64),
+            Type::U64 => int!(U64, u64),
+            Type::Float32 => float!(Float32, f32),
+            Type::Float64 => float!(Float64, f64),
+            Type::Char => Val::Char(self.char()),
+            Type::String => {
+                let len = self.len(depth);
+                Val::String((0..len).map(|_| self.char()).collect())
+            }
+            Type::List(list) => {
+                let len = self.len(depth);
+                Val::List(
+                    (0..len)
+                        .map(|_| self.generate_at(&list.ty(), nested))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            Type::Record(record) => Val::Record(
+                record
+                    .fields()
+                    .map(|field| Ok((field.name.to_owned(), self.generate_at(&field.ty, nested)?)))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            Type::Tuple(tuple) => Val::Tuple(
+                tuple
+                    .types()
+                    .map(|ty| self.generate_at(&ty, nested))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            Type::Variant(variant) => {
+                let cases = variant.cases().collect::<Vec<_>>();
+                let case = cases
+                    .choose(&mut self.rng)
+                    .ok_or_else(|| anyhow::anyhow!("variant has no cases"))?;
+                let payload = case
+                    .ty
+                    .as_ref()
+                    .map(|ty| self.generate_at(ty, nested))
+                    .transpose()?;
+                Val::Variant(case.name.to_owned(), payload.map(Box::new))
+            }
+            Type::Enum(e) => {
+                let names = e.names().collect::<Vec<_>>();
+                let name = names
+                    .choose(&mut self.rng)
+                    .ok_or_else(|| anyhow::anyhow!("enum has no cases"))?;
+                Val::Enum((*name).to_owned())
+            }
+            Type::Option(option) => match depth < MAX_DEPTH && self.rng.gen_bool(0.5) {
+                true => Val::Option(Some(Box::new(self.generate_at(&option.ty(), nested)?))),
+                false => Val::Option(None),
+            },
+            Type::Result(result) => {
+                let (ok, ty) = match self.rng.gen_bool(0.5) {
+                    true => (true, result.ok()),
+                    false => (false, result.err()),
+                };
+                let payload = ty
+                    .map(|ty| self.generate_at(&ty, nested))
+                    .transpose()?
+                    .map(Box::new);
+                Val::Result(if ok { Ok(payload) } else { Err(payload) })
+            }
+            Type::Flags(flags) => Val::Flags(
+                flags
+                    .names()
+                    .filter(|_| self.rng.gen_bool(0.5))
+                    .map(str::to_owned)
+                    .collect(),
+            ),
+            Type::Own(_) | Type::Borrow(_) => {
+                anyhow::bail!("resource handles cannot be generated")
+            }
+        })
+    }
+
+    /// The length of a list or string nested `depth` levels deep
+    fn len(&mut self, depth: usize) -> usize {
+        match depth < MAX_DEPTH {
+            true => self.rng.gen_range(0..=self.max_len),
+            false => 0,
+        }
+    }
+
+    /// A character that is mostly printable ASCII
+    fn char(&mut self) -> char {
+        match self.rng.gen_bool(0.9) {
+            true => self.rng.gen_range(' '..='~'),
+            false => self.rng.gen(),
+        }
+    }
+}
+
+/// Values smaller than `val` of the same type, the simplest first
+pub fn shrink(val: &Val) -> Vec<Val> {
+    macro_rules! int {
+        ($val:ident, $int:expr) => {{
+            let int = *$int;
+            let mut smaller = Vec::new();
+            if int != 0 {
+                smaller.push(Val::$val(0));
+            }
+            // Halve the step towards zero to close in on the smallest failure
+            let mut step = int / 2;
+            while step != 0 {
+                smaller.push(Val::$val(int - step));
+                step /= 2;
+            }
+            smaller
+        }};
+    }
+    macro_rules! float {
+        ($val:ident, $float:expr) => {{
+            let float = *$float;
+            let mut smaller = Vec::new();
+            if float != 0.0 {
+                smaller.push(Val::$val(0.0));
+            }
+            if float.is_finite() && float.trunc() != float {
+                smaller.push(Val::$val(float.trunc()));
+            }
+            smaller
+        }};
+    }
+    /// Shrink the value at each position of `vals` in place
+    fn each(vals: &[Val], wrap: impl Fn(Vec<Val>) -> Val) -> Vec<Val> {
+        let mut smaller = Vec::new();
+        for (i, val) in vals.iter().enumerate() {
+            for candidate in shrink(val) {
+                let mut vals = vals.to_vec();
+                vals[i] = candidate;
+                smaller.push(wrap(vals));
+            }
+        }
+        smaller
+    }
+    match val {
+        Val::Bool(true) => vec![Val::Bool(false)],
+        Val::S8(i) => int!(S8, i),
+        Val::U8(i) => int!(U8, i),
+        Val::S16(i) => int!(S16, i),
+        Val::U16(i) => int!(U16, i),
+        Val::S32(i) => int!(S32, i),
+        Val::U32(i) => int!(U32, i),
+        Val::S64(i) => int!(S64, i),
+        Val::U64(i) => int!(U64, i),
+        Val::Float32(f) => float!(Float32, f),
+        Val::Float64(f) => float!(Float64, f),
+        Val::Char(c) if *c != 'a' => vec![Val::Char('a')],
+        Val::String(s) if !s.is_empty() => {
+            let chars = s.chars().collect::<Vec<_>>();
+            let mut smaller = vec![Val::String(String::new())];
+            if chars.len() > 2 {
+                smaller.push(Val::String(chars[..chars.len() / 2].iter().collect()));
+                smaller.push(Val::String(chars[chars.len() / 2..].iter().collect()));
+            }
+            if chars.len() > 1 {
+                smaller.push(Val::String(chars[..chars.len() - 1].iter().collect()));
+                smaller.push(Val::String(chars[1..].iter().collect()));
+            }
+            smaller
+        }
+        Val::List(vals) if !vals.is_empty() => {
+            let mut smaller = vec![Val::List(Vec::new())];
+            if vals.len() > 2 {
+                smaller.push(Val::List(vals[..vals.len() / 2].to_vec()));
+                smaller.push(Val::List(vals[vals.len() / 2..].to_vec()));
+            }
+            for i in 0..vals.len() {
+                let mut vals = vals.clone();
+                vals.remove(i);
+                smaller.push(Val::List(vals));
+            }
+            smaller.extend(each(vals, Val::List));
+            smaller
+        }
+        Val::Record(fields) => {
+            let (names, vals): (Vec<_>, Vec<_>) = fields.iter().cloned().unzip();
+            each(&vals, |vals| {
+                Val::Record(names.iter().cloned().zip(vals).collect())
+            })
+        }
+        Val::Tuple(vals) => each(vals, Val::Tuple),
+        Val::Variant(case, Some(payload)) => shrink(payload)
+            .into_iter()
+            .map(|payload| Val::Variant(case.clone(), Some(Box::new(payload))))
+            .collect(),
+        Val::Option(Some(val)) => std::iter::once(Val::Option(None))
+            .chain(
+                shrink(val)
+                    .into_iter()
+                    .map(|val| Val::Option(Some(Box::new(val)))),
+            )
+            .collect(),
+        Val::Result(Ok(Some(val))) => shrink(val)
+            .into_iter()
+            .map(|val| Val::Result(Ok(Some(Box::new(val)))))
+            .collect(),
+        Val::Result(Err(Some(val))) => shrink(val)
+            .into_iter()
+            .map(|val| Val::Result(Err(Some(Box::new(val)))))
+            .collect(),
+        Val::Flags(flags) => (0..flags.len())
+            .map(|i| {
+                let mut flags = flags.clone();
+                flags.remove(i);
+                Val::Flags(flags)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Shrink the arguments of a failing call for as long as the smaller
+/// arguments keep failing, trying at most `max_attempts` calls
+pub fn shrink_args(
+    mut args: Vec<Val>,
+    max_attempts: usize,
+    mut fails: impl FnMut(&[Val]) -> bool,
+) -> Vec<Val> {
+    let mut attempts = 0;
+    'shrink: loop {
+        for i in 0..args.len() {
+            for candidate in shrink(&args[i]) {
+                if attempts == max_attempts {
+                    break 'shrink;
+                }
+                attempts += 1;
+                let mut smaller = args.clone();
+                smaller[i] = candidate;
+                if fails(&smaller) {
+                    args = smaller;
+                    continue 'shrink;
+                }
+            }
+        }
+        break;
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrink_to_boundary() {
+        let args = vec![
+            Val::U32(1000),
+            Val::List(vec![Val::String("hello".into()); 6]),
+        ];
+        // Fails with a number of at least 10 and a list of at least two strings
+        let shrunk = shrink_args(args, 1000, |args| match args {
+            [Val::U32(n), Val::List(list)] => *n >= 10 && list.len() >= 2,
+            _ => false,
+        });
+        assert_eq!(
+            shrunk,
+            [Val::U32(10), Val::List(vec![Val::String(String::new()); 2])]
+        );
+        assert!(shrink(&Val::S8(0)).is_empty());
+        assert_eq!(
+            shrink(&Val::U8(100)),
+            [0, 50, 75, 88, 94, 97, 99].map(Val::U8)
+        );
+        assert_eq!(shrink(&Val::S8(-3)), [Val::S8(0), Val::S8(-2)]);
+    }
+
+    #[test]
+    fn generate_is_deterministic() {
+        let generate = |seed| {
+            let mut generator = ValGenerator::new(seed, 8);
+            (0..20)
+                .map(|_| generator.generate(&Type::String).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+}
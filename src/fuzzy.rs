@@ -0,0 +1,142 @@
+/// How well `query` matches `name`, higher being better, or `None` if it does
+/// not match at all
+///
+/// Case and the difference between `_` and `-` are ignored. Names containing
+/// the query rank above names containing its characters in order, which rank
+/// above names that are a typo or two away from it.
+pub fn score(query: &str, name: &str) -> Option<i64> {
+    let query = normalize(query);
+    let name = normalize(name);
+    if query.is_empty() {
+        return None;
+    }
+    let extra = (name.len() as i64 - query.len() as i64).abs();
+    if name == query {
+        return Some(1000);
+    }
+    if let Some(start) = find(&name, &query) {
+        let bonus = if is_boundary(&name, start) { 100 } else { 0 };
+        return Some(500 + bonus - extra);
+    }
+    if let Some(bonus) = subsequence(&query, &name) {
+        return Some(200 + bonus - extra);
+    }
+    let distance = edit_distance(&query, &name);
+    (distance <= max_typos(&query)).then(|| 100 - 10 * distance as i64 - extra)
+}
+
+/// The name among `names` that `name` was most likely meant to be, if any is
+/// close enough
+pub fn suggest<'a>(name: &str, names: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let normalized = normalize(name);
+    names
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .filter_map(|candidate| {
+            let distance = edit_distance(&normalized, &normalize(candidate));
+            (distance <= max_typos(&normalized)).then_some((distance, candidate))
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn normalize(name: &str) -> Vec<char> {
+    name.chars()
+        .map(|c| {
+            if c == '_' {
+                '-'
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+/// The most edits a name can be away from `query` to still match it
+fn max_typos(query: &[char]) -> usize {
+    (query.len() / 3).max(1)
+}
+
+/// Whether a word of `name` starts at `index`
+fn is_boundary(name: &[char], index: usize) -> bool {
+    index == 0 || !name[index - 1].is_alphanumeric()
+}
+
+fn find(name: &[char], query: &[char]) -> Option<usize> {
+    name.windows(query.len()).position(|window| window == query)
+}
+
+/// A bonus for how well `query` matches `name` if the characters of `query`
+/// appear in `name` in order, rewarding runs of them and ones starting words
+fn subsequence(query: &[char], name: &[char]) -> Option<i64> {
+    let mut bonus = 0;
+    let mut next = 0;
+    let mut previous = None;
+    for c in query {
+        let index = next + name[next..].iter().position(|n| n == c)?;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            bonus += 10;
+        }
+        if is_boundary(name, index) {
+            bonus += 20;
+        }
+        previous = Some(index);
+        next = index + 1;
+    }
+    Some(bonus)
+}
+
+/// The number of characters to insert, remove or replace and of adjacent
+/// characters to swap to turn `a` into `b`
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replace = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = replace
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 4] = ["get-user", "get-users", "delete-user", "blob-store"];
+
+    #[test]
+    fn suggest_typos() {
+        assert_eq!(suggest("get_user", NAMES), Some("get-user"));
+        assert_eq!(suggest("delte-user", NAMES), Some("delete-user"));
+        assert_eq!(suggest("gte-user", NAMES), Some("get-user"));
+        assert_eq!(suggest("get-usr", NAMES), Some("get-user"));
+    }
+
+    #[test]
+    fn no_suggestion() {
+        assert_eq!(suggest("unrelated", NAMES), None);
+    }
+
+    #[test]
+    fn rank() {
+        let score = |name| score("blob", name);
+        assert!(score("blob") > score("blob-store"));
+        assert!(score("blob-store") > score("my-blob-store"));
+        assert!(score("my-blob-store") > score("big-lob"));
+        assert!(score("big-lob") > score("blb"));
+        assert_eq!(score("get-user"), None);
+    }
+}
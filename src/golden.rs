@@ -0,0 +1,208 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Context as _;
+use colored::Colorize;
+
+/// The extension of the scripts `wepl test` runs
+const SCRIPT_EXTENSION: &str = "wepl";
+/// The extension of the files holding the expected output of scripts
+const EXPECTED_EXTENSION: &str = "expected";
+
+/// Run every `.wepl` script in `dir` against the component at `component` and
+/// compare the output of each command with the script's `.expected` file,
+/// which is written instead with `bless`
+///
+/// Each script runs in its own `wepl` process started with `args` (the
+/// options given before `test`), which prints every command before running it.
+/// Returns whether every script produced the expected output.
+pub fn run(component: &Path, dir: &Path, bless: bool, args: &[OsString]) -> anyhow::Result<bool> {
+    let mut scripts = std::fs::read_dir(dir)
+        .with_context(|| format!("could not read the directory '{}'", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    scripts.retain(|path| path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION));
+    scripts.sort();
+    if scripts.is_empty() {
+        anyhow::bail!("no .{SCRIPT_EXTENSION} scripts in '{}'", dir.display());
+    }
+    let exe = std::env::current_exe().context("could not find the wepl executable")?;
+    let (mut passed, mut failed, mut blessed) = (0, 0, 0);
+    for script in &scripts {
+        let name = script.file_name().unwrap_or_default().to_string_lossy();
+        let actual = transcript(&exe, args, component, script)
+            .with_context(|| format!("could not run '{}'", script.display()))?;
+        let expected_path = script.with_extension(EXPECTED_EXTENSION);
+        if bless {
+            std::fs::write(&expected_path, &actual)
+                .with_context(|| format!("could not write '{}'", expected_path.display()))?;
+            println!("test {name} ... {}", "blessed".yellow());
+            blessed += 1;
+            continue;
+        }
+        let Ok(expected) = std::fs::read_to_string(&expected_path) else {
+            println!("test {name} ... {}", "FAILED".red().bold());
+            println!(
+                "  no expected output in '{}' (run with --bless to create it)",
+                expected_path.display()
+            );
+            failed += 1;
+            continue;
+        };
+        let mismatches = compare(&expected, &actual);
+        if mismatches.is_empty() {
+            println!("test {name} ... {}", "ok".green());
+            passed += 1;
+            continue;
+        }
+        println!("test {name} ... {}", "FAILED".red().bold());
+        for mismatch in mismatches {
+            println!("{mismatch}");
+        }
+        failed += 1;
+    }
+    let status = if failed == 0 {
+        "ok".green()
+    } else {
+        "FAILED".red().bold()
+    };
+    if bless {
+        println!("\ntest result: {status}. {blessed} blessed");
+    } else {
+        println!("\ntest result: {status}. {passed} passed; {failed} failed");
+    }
+    Ok(failed == 0)
+}
+
+/// The output of running `script`, with each command echoed before its output
+fn transcript(
+    exe: &Path,
+    args: &[OsString],
+    component: &Path,
+    script: &Path,
+) -> anyhow::Result<String> {
+    // Both streams go to the same file so their lines stay in order
+    let output_path = temp_output_path(script);
+    let output = File::create(&output_path)?;
+    let status = Command::new(exe)
+        .args(args)
+        .arg("--echo-commands")
+        .arg(component)
+        .stdin(File::open(script)?)
+        .stdout(output.try_clone()?)
+        .stderr(output)
+        .env("NO_COLOR", "1")
+        .status();
+    let transcript = std::fs::read_to_string(&output_path);
+    let _ = std::fs::remove_file(&output_path);
+    status?;
+    Ok(transcript?)
+}
+
+fn temp_output_path(script: &Path) -> PathBuf {
+    let name = script.file_stem().unwrap_or_default().to_string_lossy();
+    std::env::temp_dir().join(format!("wepl-test-{}-{name}.out", std::process::id()))
+}
+
+/// Split a transcript into the commands it ran with the output of each
+///
+/// Output before the first command (e.g. the world of the component) is
+/// attributed to the empty command.
+fn commands(transcript: &str) -> Vec<(&str, Vec<&str>)> {
+    let mut commands = vec![("", Vec::new())];
+    for line in transcript.lines() {
+        match line.strip_prefix("> ") {
+            Some(command) => commands.push((command, Vec::new())),
+            None => commands.last_mut().unwrap().1.push(line),
+        }
+    }
+    commands
+}
+
+/// Describe every command whose output in `actual` differs from `expected`
+fn compare(expected: &str, actual: &str) -> Vec<String> {
+    let expected = commands(expected);
+    let actual = commands(actual);
+    let mut mismatches = Vec::new();
+    for (expected, actual) in expected.iter().zip(&actual) {
+        if expected == actual {
+            continue;
+        }
+        let indent = |lines: &[&str]| {
+            lines
+                .iter()
+                .map(|line| format!("      {line}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut mismatch = if expected.0 == actual.0 {
+            format!(
+                "  {} {}",
+                "output of".bold(),
+                format!("> {}", actual.0).bold()
+            )
+        } else {
+            format!(
+                "  {} '{}' but ran '{}'",
+                "expected the command".bold(),
+                expected.0,
+                actual.0
+            )
+        };
+        mismatch.push_str(&format!(
+            "\n    expected:\n{}\n    actual:\n{}",
+            indent(&expected.1),
+            indent(&actual.1)
+        ));
+        mismatches.push(mismatch);
+    }
+    if expected.len() != actual.len() {
+        mismatches.push(format!(
+            "  {} {} commands but {} ran",
+            "expected".bold(),
+            expected.len() - 1,
+            actual.len() - 1
+        ));
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPECTED: &str = "World: root\n> add(1, 2)\n3\n> .env set a b\n";
+
+    #[test]
+    fn same_transcripts() {
+        assert!(compare(EXPECTED, EXPECTED).is_empty());
+    }
+
+    #[test]
+    fn different_output() {
+        let mismatches = compare(EXPECTED, "World: root\n> add(1, 2)\n4\n> .env set a b\n");
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].contains("add(1, 2)"));
+    }
+
+    #[test]
+    fn missing_commands() {
+        assert_eq!(compare(EXPECTED, "World: root\n> add(1, 2)\n3\n").len(), 1);
+    }
+
+    #[test]
+    fn commands_and_output() {
+        assert_eq!(
+            commands(EXPECTED),
+            [
+                ("", vec!["World: root"]),
+                ("add(1, 2)", vec!["3"]),
+                (".env set a b", vec![])
+            ]
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use anyhow::Context as _;
+use wasmtime::component::Val;
+
+/// The cases of `wasi:http/types#method` that carry no name
+const METHODS: &[&str] = &[
+    "get", "head", "post", "put", "delete", "connect", "options", "trace", "patch",
+];
+
+/// Whether `interface` is `wasi:http/types` (e.g. `wasi:http/types@0.2.0`),
+/// whose `fields` and `outgoing-request` resources wepl implements
+pub fn is_types_interface(interface: &str) -> bool {
+    interface == "wasi:http/types" || interface.starts_with("wasi:http/types@")
+}
+
+/// Header names and values in the order they were added
+pub type Fields = Vec<(String, Vec<u8>)>;
+
+/// An HTTP request built by `.http-request` or by the component through the
+/// `outgoing-request` constructor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    /// The case of `method` (e.g. `get`), or the name of any other method
+    pub method: String,
+    /// The case of `scheme` (e.g. `HTTPS`), or the name of any other scheme
+    pub scheme: Option<String>,
+    pub authority: Option<String>,
+    pub path_with_query: Option<String>,
+    pub headers: Fields,
+}
+
+impl Request {
+    /// A request with the method `method` (e.g. `GET`) for the absolute URL
+    /// `url` (e.g. `https://example.com/items?page=2`)
+    pub fn new(method: &str, url: &str, headers: Fields) -> anyhow::Result<Self> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .with_context(|| format!("'{url}' is not an absolute URL like https://example.com/"))?;
+        let (authority, path_with_query) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+            Some(i) => (&rest[..i], rest[i..].to_owned()),
+            None => (rest, "/".to_owned()),
+        };
+        if authority.is_empty() {
+            anyhow::bail!("'{url}' has no host");
+        }
+        let method = match method.to_ascii_lowercase() {
+            lower if METHODS.contains(&lower.as_str()) => lower,
+            _ => method.to_owned(),
+        };
+        let scheme = match scheme.to_ascii_uppercase() {
+            upper if upper == "HTTP" || upper == "HTTPS" => upper,
+            _ => scheme.to_owned(),
+        };
+        Ok(Self {
+            method,
+            scheme: Some(scheme),
+            authority: Some(authority.to_owned()),
+            path_with_query: Some(path_with_query),
+            headers,
+        })
+    }
+}
+
+/// The host side of an instance of a `wasi:http/types` resource
+#[derive(Clone, Debug, PartialEq)]
+pub enum HttpResource {
+    Fields(Fields),
+    Request(Request),
+}
+
+/// What a function of `wasi:http/types` returns
+pub enum Returned {
+    Val(Val),
+    /// A new resource, which is passed to the function to get the value
+    /// returned (e.g. to wrap it in `ok`)
+    Resource(HttpResource, fn(Val) -> Val),
+}
+
+/// Whether wepl implements the function `name` of `wasi:http/types`
+pub fn implements(name: &str) -> bool {
+    if let Some(method) = name.strip_prefix("[method]fields.") {
+        return matches!(
+            method,
+            "get" | "has" | "set" | "delete" | "append" | "entries" | "clone"
+        );
+    }
+    if let Some(method) = name.strip_prefix("[method]outgoing-request.") {
+        return matches!(
+            method,
+            "method"
+                | "set-method"
+                | "path-with-query"
+                | "set-path-with-query"
+                | "scheme"
+                | "set-scheme"
+                | "authority"
+                | "set-authority"
+                | "headers"
+        );
+    }
+    matches!(
+        name,
+        "[constructor]fields" | "[static]fields.from-list" | "[constructor]outgoing-request"
+    )
+}
+
+/// Call the function `name` of `wasi:http/types` with the arguments `args`
+/// following the resource `this` it is called on, if any
+///
+/// The `outgoing-request` constructor is called on the fields it takes.
+pub fn call(name: &str, this: Option<&mut HttpResource>, args: &[Val]) -> anyhow::Result<Returned> {
+    let unchanged = |val| val;
+    let ok = |val| Val::Result(Ok(Some(Box::new(val))));
+    let done = Returned::Val(Val::Result(Ok(None)));
+    Ok(match (name, this, args) {
+        ("[constructor]fields", None, []) => {
+            Returned::Resource(HttpResource::Fields(Vec::new()), unchanged)
+        }
+        ("[static]fields.from-list", None, [Val::List(entries)]) => {
+            let fields = entries
+                .iter()
+                .map(|entry| match entry {
+                    Val::Tuple(entry) => match entry.as_slice() {
+                        [Val::String(name), value] => Ok((name.clone(), bytes(value)?)),
+                        _ => anyhow::bail!("unexpected field {entry:?}"),
+                    },
+                    _ => anyhow::bail!("unexpected field {entry:?}"),
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Returned::Resource(HttpResource::Fields(fields), ok)
+        }
+        ("[constructor]outgoing-request", Some(HttpResource::Fields(headers)), []) => {
+            let request = Request {
+                method: "get".to_owned(),
+                scheme: None,
+                authority: None,
+                path_with_query: None,
+                headers: headers.clone(),
+            };
+            Returned::Resource(HttpResource::Request(request), unchanged)
+        }
+        (name, Some(HttpResource::Fields(fields)), args) => {
+            let method = name.strip_prefix("[method]fields.").unwrap_or(name);
+            let matching =
+                |field: &(String, Vec<u8>), name: &str| field.0.eq_ignore_ascii_case(name);
+            match (method, args) {
+                ("get", [Val::String(name)]) => Returned::Val(Val::List(
+                    fields
+                        .iter()
+                        .filter(|field| matching(field, name))
+                        .map(|(_, value)| list(value))
+                        .collect(),
+                )),
+                ("has", [Val::String(name)]) => {
+                    Returned::Val(Val::Bool(fields.iter().any(|field| matching(field, name))))
+                }
+                ("set", [Val::String(name), Val::List(values)]) => {
+                    fields.retain(|field| !matching(field, name));
+                    for value in values {
+                        fields.push((name.clone(), bytes(value)?));
+                    }
+                    done
+                }
+                ("delete", [Val::String(name)]) => {
+                    fields.retain(|field| !matching(field, name));
+                    done
+                }
+                ("append", [Val::String(name), value]) => {
+                    fields.push((name.clone(), bytes(value)?));
+                    done
+                }
+                ("entries", []) => Returned::Val(Val::List(
+                    fields
+                        .iter()
+                        .map(|(name, value)| {
+                            Val::Tuple(vec![Val::String(name.clone()), list(value)])
+                        })
+                        .collect(),
+                )),
+                ("clone", []) => {
+                    Returned::Resource(HttpResource::Fields(fields.clone()), unchanged)
+                }
+                _ => anyhow::bail!("unexpected arguments to wasi:http/types#{name}"),
+            }
+        }
+        (name, Some(HttpResource::Request(request)), args) => {
+            let method = name
+                .strip_prefix("[method]outgoing-request.")
+                .unwrap_or(name);
+            match (method, args) {
+                ("method", []) => Returned::Val(case(&request.method, METHODS)),
+                ("set-method", [method]) => {
+                    request.method = case_name(method)?;
+                    done
+                }
+                ("path-with-query", []) => Returned::Val(optional_string(&request.path_with_query)),
+                ("set-path-with-query", [path]) => {
+                    request.path_with_query = string_option(path)?;
+                    done
+                }
+                ("scheme", []) => Returned::Val(Val::Option(
+                    request
+                        .scheme
+                        .as_deref()
+                        .map(|scheme| Box::new(case(scheme, &["HTTP", "HTTPS"]))),
+                )),
+                ("set-scheme", [Val::Option(scheme)]) => {
+                    request.scheme = scheme.as_deref().map(case_name).transpose()?;
+                    done
+                }
+                ("authority", []) => Returned::Val(optional_string(&request.authority)),
+                ("set-authority", [authority]) => {
+                    request.authority = string_option(authority)?;
+                    done
+                }
+                ("headers", []) => {
+                    Returned::Resource(HttpResource::Fields(request.headers.clone()), unchanged)
+                }
+                _ => anyhow::bail!("unexpected arguments to wasi:http/types#{name}"),
+            }
+        }
+        _ => anyhow::bail!("unexpected arguments to wasi:http/types#{name}"),
+    })
+}
+
+/// The case named `name` of a variant whose cases without a payload are
+/// `cases`, or its `other` case
+fn case(name: &str, cases: &[&str]) -> Val {
+    if cases.contains(&name) {
+        Val::Variant(name.to_owned(), None)
+    } else {
+        Val::Variant(
+            "other".to_owned(),
+            Some(Box::new(Val::String(name.to_owned()))),
+        )
+    }
+}
+
+/// The name of the case `val` of a `method` or `scheme`
+fn case_name(val: &Val) -> anyhow::Result<String> {
+    match val {
+        Val::Variant(name, None) => Ok(name.clone()),
+        Val::Variant(other, Some(name)) if other == "other" => match name.as_ref() {
+            Val::String(name) => Ok(name.clone()),
+            _ => anyhow::bail!("unexpected name {name:?}"),
+        },
+        _ => anyhow::bail!("unexpected case {val:?}"),
+    }
+}
+
+fn optional_string(s: &Option<String>) -> Val {
+    Val::Option(s.clone().map(|s| Box::new(Val::String(s))))
+}
+
+fn string_option(val: &Val) -> anyhow::Result<Option<String>> {
+    match val {
+        Val::Option(None) => Ok(None),
+        Val::Option(Some(s)) => match s.as_ref() {
+            Val::String(s) => Ok(Some(s.clone())),
+            _ => anyhow::bail!("expected a string, got {s:?}"),
+        },
+        _ => anyhow::bail!("expected an option, got {val:?}"),
+    }
+}
+
+fn list(bytes: &[u8]) -> Val {
+    Val::List(bytes.iter().copied().map(Val::U8).collect())
+}
+
+fn bytes(val: &Val) -> anyhow::Result<Vec<u8>> {
+    match val {
+        Val::List(items) => items
+            .iter()
+            .map(|item| match item {
+                Val::U8(byte) => Ok(*byte),
+                _ => anyhow::bail!("expected a byte, got {item:?}"),
+            })
+            .collect(),
+        _ => anyhow::bail!("expected a list<u8>, got {val:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> HttpResource {
+        let headers = vec![("Accept".to_owned(), b"*/*".to_vec())];
+        HttpResource::Request(Request::new("GET", "https://example.com?q=1", headers).unwrap())
+    }
+
+    #[test]
+    fn request_methods() {
+        let Returned::Val(method) =
+            call("[method]outgoing-request.method", Some(&mut request()), &[]).unwrap()
+        else {
+            panic!("expected a value");
+        };
+        assert_eq!(method, Val::Variant("get".to_owned(), None));
+    }
+
+    #[test]
+    fn request_paths() {
+        let Returned::Val(path) = call(
+            "[method]outgoing-request.path-with-query",
+            Some(&mut request()),
+            &[],
+        )
+        .unwrap() else {
+            panic!("expected a value");
+        };
+        assert_eq!(path, optional_string(&Some("/?q=1".to_owned())));
+    }
+
+    #[test]
+    fn request_headers() {
+        let Returned::Resource(mut headers, _) = call(
+            "[method]outgoing-request.headers",
+            Some(&mut request()),
+            &[],
+        )
+        .unwrap() else {
+            panic!("expected a resource");
+        };
+        let Returned::Val(accept) = call(
+            "[method]fields.get",
+            Some(&mut headers),
+            &[Val::String("accept".to_owned())],
+        )
+        .unwrap() else {
+            panic!("expected a value");
+        };
+        assert_eq!(accept, Val::List(vec![list(b"*/*")]));
+    }
+
+    #[test]
+    fn other_methods_and_schemes() {
+        let purge = Request::new("PURGE", "gopher://host/a", Vec::new()).unwrap();
+        assert_eq!(case(&purge.method, METHODS), case("PURGE", &[]));
+        assert_eq!(purge.scheme.as_deref(), Some("gopher"));
+    }
+
+    #[test]
+    fn urls_without_schemes() {
+        assert!(Request::new("GET", "example.com", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn implemented_functions() {
+        assert!(implements("[method]fields.append"));
+        assert!(!implements("[method]outgoing-request.body"));
+    }
+}
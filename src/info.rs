@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+use colored::Colorize;
+use wasmparser::{KnownCustom, Parser, Payload};
+
+use crate::events::Events;
+use crate::wit::WorldResolver;
+
+/// The name of the custom section holding the metadata of warg registries
+const REGISTRY_METADATA: &str = "registry-metadata";
+
+/// A component or core module and the sections it is made of
+struct Binary {
+    kind: &'static str,
+    size: usize,
+    /// The total size of the sections of each kind
+    sections: BTreeMap<String, usize>,
+    /// The tools that produced the binary (e.g. `processed-by`) with their
+    /// names and versions
+    producers: Vec<(String, Vec<String>)>,
+    /// The components and modules nested in this one
+    nested: Vec<Binary>,
+}
+
+impl Binary {
+    fn new(kind: &'static str, size: usize) -> Self {
+        Self {
+            kind,
+            size,
+            sections: BTreeMap::new(),
+            producers: Vec::new(),
+            nested: Vec::new(),
+        }
+    }
+
+    /// Parse `bytes`, a component or core module, with every binary nested
+    /// in it, returning it along with its registry metadata if any
+    fn parse(bytes: &[u8]) -> anyhow::Result<(Self, Option<serde_json::Value>)> {
+        let mut stack = vec![Self::new("component", bytes.len())];
+        let mut registry_metadata = None;
+        for payload in Parser::new(0).parse_all(bytes) {
+            let payload = payload?;
+            let current = stack.last_mut().unwrap();
+            match &payload {
+                Payload::Version { encoding, .. } => {
+                    current.kind = match encoding {
+                        wasmparser::Encoding::Module => "module",
+                        wasmparser::Encoding::Component => "component",
+                    };
+                }
+                Payload::CustomSection(custom) => {
+                    if let KnownCustom::Producers(producers) = custom.as_known() {
+                        for field in producers {
+                            let field = field?;
+                            let values = field
+                                .values
+                                .into_iter()
+                                .map(|value| {
+                                    let value = value?;
+                                    Ok(match value.version {
+                                        "" => value.name.to_owned(),
+                                        version => format!("{} {version}", value.name),
+                                    })
+                                })
+                                .collect::<anyhow::Result<_>>()?;
+                            current.producers.push((field.name.to_owned(), values));
+                        }
+                    } else if custom.name() == REGISTRY_METADATA && stack.len() == 1 {
+                        registry_metadata = Some(
+                            serde_json::from_slice(custom.data())
+                                .context("invalid registry metadata")?,
+                        );
+                    }
+                }
+                Payload::End(_) => {
+                    let done = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.nested.push(done),
+                        None => return Ok((done, registry_metadata)),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            let Some((id, range)) = payload.as_section() else {
+                continue;
+            };
+            let current = stack.last_mut().unwrap();
+            let name = match &payload {
+                Payload::CustomSection(custom) => format!("custom \"{}\"", custom.name()),
+                _ => section_name(current.kind, id).to_owned(),
+            };
+            *current.sections.entry(name).or_default() += range.len();
+            match payload {
+                Payload::ModuleSection {
+                    unchecked_range, ..
+                } => stack.push(Self::new("module", unchecked_range.len())),
+                Payload::ComponentSection {
+                    unchecked_range, ..
+                } => stack.push(Self::new("component", unchecked_range.len())),
+                _ => {}
+            }
+        }
+        anyhow::bail!("unexpected end of the component")
+    }
+
+    /// Print the size, producers and sections of this binary and those nested
+    /// in it, indented by `depth` levels
+    fn print(&self, events: &Events, label: &str, depth: usize) {
+        let indent = "  ".repeat(depth);
+        events.print(format!(
+            "{indent}{} ({})",
+            label.bold(),
+            format_size(self.size)
+        ));
+        for (field, values) in &self.producers {
+            events.print(format!("{indent}  {field}: {}", values.join(", ")));
+        }
+        let mut sections = self.sections.iter().collect::<Vec<_>>();
+        sections.sort_by(|(n1, s1), (n2, s2)| s2.cmp(s1).then(n1.cmp(n2)));
+        for (name, size) in sections {
+            events.print(format!("{indent}  {name:<24} {:>10}", format_size(*size)));
+        }
+        let mut counts = BTreeMap::<&str, usize>::new();
+        for nested in &self.nested {
+            let index = counts.entry(nested.kind).or_default();
+            nested.print(events, &format!("{} {index}", nested.kind), depth + 1);
+            *index += 1;
+        }
+    }
+}
+
+/// The name of the section with `id` in a binary of `kind`
+pub(crate) fn section_name(kind: &str, id: u8) -> &'static str {
+    let names: &[&str] = match kind {
+        "component" => &[
+            "custom",
+            "core module",
+            "core instance",
+            "core type",
+            "component",
+            "instance",
+            "alias",
+            "type",
+            "canon",
+            "start",
+            "import",
+            "export",
+            "value",
+        ],
+        _ => &[
+            "custom",
+            "type",
+            "import",
+            "function",
+            "table",
+            "memory",
+            "global",
+            "export",
+            "start",
+            "element",
+            "code",
+            "data",
+            "data count",
+            "tag",
+        ],
+    };
+    names.get(usize::from(id)).copied().unwrap_or("unknown")
+}
+
+/// `size` in bytes in the largest unit it is at least one of
+fn format_size(size: usize) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Emit to `events` what the component in `bytes` is made of: its WIT packages, the
+/// producers and registry metadata embedded in it, and the size of each of
+/// its sections and of the modules and components nested in it
+pub fn print(events: &Events, bytes: &[u8], resolver: &WorldResolver) -> anyhow::Result<()> {
+    let (component, registry_metadata) = Binary::parse(bytes)?;
+    events.print(format!(
+        "{}: {} ({} bytes)",
+        "Size".blue().bold(),
+        format_size(bytes.len()),
+        bytes.len()
+    ));
+    let mut packages = resolver
+        .packages()
+        .map(|package| package.name.to_string())
+        .collect::<Vec<_>>();
+    packages.sort();
+    events.print(format!("{}:", "WIT packages".blue().bold()));
+    for package in packages {
+        events.print(format!("  {package}"));
+    }
+    if let Some(serde_json::Value::Object(metadata)) = registry_metadata {
+        events.print(format!("{}:", "Registry metadata".blue().bold()));
+        for (key, value) in metadata {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                value => value.to_string(),
+            };
+            events.print(format!("  {key}: {value}"));
+        }
+    }
+    events.print(format!("{}:", "Sections".blue().bold()));
+    component.print(events, component.kind, 1);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_sizes() {
+        let bytes = wat::parse_str(
+            r#"(component
+                (core module $m (func (export "f")))
+                (core module $n)
+                (core instance (instantiate $m))
+            )"#,
+        )
+        .unwrap();
+        let (component, metadata) = Binary::parse(&bytes).unwrap();
+        assert!(metadata.is_none());
+        assert_eq!(component.kind, "component");
+        assert_eq!(component.size, bytes.len());
+        let kinds = component.nested.iter().map(|n| n.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, ["module", "module"]);
+        let modules = component.nested.iter().map(|n| n.size).sum::<usize>();
+        assert_eq!(component.sections["core module"], modules);
+        assert!(component.nested[0].sections.contains_key("code"));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+    }
+}
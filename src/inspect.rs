@@ -0,0 +1,247 @@
+use anyhow::{bail, Context as _};
+use colored::Colorize;
+use wasmtime::component::Val;
+
+use crate::command::format_val;
+use crate::events::Events;
+
+/// The most elements of a list or characters of a nested string shown
+const MAX_SHOWN: usize = 20;
+/// The most characters of an inspected string shown
+const MAX_CHARS: usize = 1000;
+
+/// A step into a value on a path like `x.items[3].meta`
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    /// A field of a record
+    Field(&'a str),
+    /// An element of a list or tuple
+    Index(usize),
+}
+
+/// Split a path like `x.items[3].meta` into the name of the variable it
+/// starts from and the steps into the variable's value
+pub fn parse_path(path: &str) -> anyhow::Result<(&str, Vec<Segment<'_>>)> {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    let (root, mut rest) = path.split_at(end);
+    if root.is_empty() {
+        bail!("expected a variable at the start of '{path}'");
+    }
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(field) = rest.strip_prefix('.') {
+            let end = field.find(['.', '[']).unwrap_or(field.len());
+            if end == 0 {
+                bail!("expected a field name after '.' in '{path}'");
+            }
+            segments.push(Segment::Field(&field[..end]));
+            rest = &field[end..];
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let (index, after) = index
+                .split_once(']')
+                .with_context(|| format!("unclosed '[' in '{path}'"))?;
+            let index = index
+                .trim()
+                .parse()
+                .with_context(|| format!("'{index}' is not an index in '{path}'"))?;
+            segments.push(Segment::Index(index));
+            rest = after;
+        } else {
+            bail!("unexpected '{rest}' in '{path}'");
+        }
+    }
+    Ok((root, segments))
+}
+
+/// The part of `val` at the end of `segments`
+///
+/// Options, results and variants are looked through to their payload.
+pub fn lookup<'a>(mut val: &'a Val, segments: &[Segment<'_>]) -> anyhow::Result<&'a Val> {
+    for segment in segments {
+        val = payload(val);
+        val = match (segment, val) {
+            (Segment::Field(name), Val::Record(fields)) => fields
+                .iter()
+                .find_map(|(n, v)| (n == name).then_some(v))
+                .with_context(|| {
+                    let names = fields.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+                    format!(
+                        "the record has no field '{name}' (its fields are: {})",
+                        names.join(", ")
+                    )
+                })?,
+            (Segment::Index(index), Val::List(items) | Val::Tuple(items)) => {
+                items.get(*index).with_context(|| {
+                    format!(
+                        "index {index} is out of bounds for {} of {} elements",
+                        type_name(val),
+                        items.len()
+                    )
+                })?
+            }
+            (Segment::Field(name), val) => {
+                bail!("cannot get the field '{name}' of {}", type_name(val))
+            }
+            (Segment::Index(index), val) => {
+                bail!("cannot get the element {index} of {}", type_name(val))
+            }
+        };
+    }
+    Ok(val)
+}
+
+/// The payload of an option, result or variant, or `val` itself
+fn payload(val: &Val) -> &Val {
+    match val {
+        Val::Option(Some(val))
+        | Val::Result(Ok(Some(val)) | Err(Some(val)))
+        | Val::Variant(_, Some(val)) => payload(val),
+        val => val,
+    }
+}
+
+/// Emit the type and size of `val` and its contents one level deep to
+/// `events`
+pub fn print(events: &Events, path: &str, val: &Val) {
+    events.print(format!("{}: {}", path.bold(), type_name(val)));
+    match val {
+        Val::String(s) => {
+            events.print(format!(
+                "{} bytes, {} characters",
+                s.len(),
+                s.chars().count()
+            ));
+            events.print(summary(val, MAX_CHARS));
+        }
+        Val::List(items) | Val::Tuple(items) => {
+            events.print(format!("{} elements", items.len()));
+            for (index, item) in items.iter().enumerate().take(MAX_SHOWN) {
+                events.print(format!("  [{index}]: {}", summary(item, MAX_SHOWN)));
+            }
+            if items.len() > MAX_SHOWN {
+                events.print(format!("  ... {} more", items.len() - MAX_SHOWN));
+            }
+        }
+        Val::Record(fields) => {
+            events.print(format!("{} fields", fields.len()));
+            for (name, field) in fields {
+                events.print(format!("  {name}: {}", summary(field, MAX_SHOWN)));
+            }
+        }
+        Val::Option(Some(inner)) => events.print(format!("  some: {}", summary(inner, MAX_SHOWN))),
+        Val::Result(Ok(Some(inner))) => {
+            events.print(format!("  ok: {}", summary(inner, MAX_SHOWN)))
+        }
+        Val::Result(Err(Some(inner))) => {
+            events.print(format!("  err: {}", summary(inner, MAX_SHOWN)))
+        }
+        Val::Variant(case, Some(inner)) => {
+            events.print(format!("  {case}: {}", summary(inner, MAX_SHOWN)))
+        }
+        val => events.print(format_val(val)),
+    }
+}
+
+/// A one line description of `val`, showing at most `max` characters of
+/// strings and the contents of values only if they are small
+fn summary(val: &Val, max: usize) -> String {
+    match val {
+        Val::String(s) if s.chars().count() > max => {
+            let shown = s.chars().take(max).collect::<String>();
+            format!("{shown:?}… ({} bytes)", s.len())
+        }
+        Val::List(items) | Val::Tuple(items) if format_val(val).len() > 60 => {
+            format!("{} ({} elements)", type_name(val), items.len())
+        }
+        Val::Record(fields) if format_val(val).len() > 60 => {
+            format!("record ({} fields)", fields.len())
+        }
+        val => format_val(val),
+    }
+}
+
+/// The type of `val`, as far as it can be told from the value alone
+///
+/// The type of the elements of lists is that of the first element.
+fn type_name(val: &Val) -> String {
+    match val {
+        Val::List(items) => match items.first() {
+            Some(item) => format!("list<{}>", type_name(item)),
+            None => "list".into(),
+        },
+        Val::Tuple(items) => {
+            let types = items.iter().map(type_name).collect::<Vec<_>>();
+            format!("tuple<{}>", types.join(", "))
+        }
+        Val::Option(Some(val)) => format!("option<{}>", type_name(val)),
+        Val::String(_) => "string".into(),
+        Val::Bool(_) => "bool".into(),
+        Val::U8(_) => "u8".into(),
+        Val::U16(_) => "u16".into(),
+        Val::U32(_) => "u32".into(),
+        Val::U64(_) => "u64".into(),
+        Val::S8(_) => "s8".into(),
+        Val::S16(_) => "s16".into(),
+        Val::S32(_) => "s32".into(),
+        Val::S64(_) => "s64".into(),
+        Val::Float32(_) => "float32".into(),
+        Val::Float64(_) => "float64".into(),
+        Val::Char(_) => "char".into(),
+        Val::Option(None) => "option".into(),
+        Val::Result(_) => "result".into(),
+        Val::Record(_) => "record".into(),
+        Val::Variant(_, _) => "variant".into(),
+        Val::Enum(_) => "enum".into(),
+        Val::Flags(_) => "flags".into(),
+        Val::Resource(_) => "resource".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn val() -> Val {
+        Val::Record(vec![(
+            "items".into(),
+            Val::List(vec![Val::Option(Some(Box::new(Val::Record(vec![(
+                "meta".into(),
+                Val::U32(7),
+            )]))))]),
+        )])
+    }
+
+    #[test]
+    fn paths() {
+        let (root, segments) = parse_path("x.items[3].meta").unwrap();
+        assert_eq!(root, "x");
+        assert_eq!(
+            segments,
+            [
+                Segment::Field("items"),
+                Segment::Index(3),
+                Segment::Field("meta")
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_paths() {
+        assert!(parse_path("x.").is_err());
+        assert!(parse_path("x[1").is_err());
+    }
+
+    #[test]
+    fn lookups() {
+        let (_, segments) = parse_path("x.items[0].meta").unwrap();
+        assert_eq!(lookup(&val(), &segments).unwrap(), &Val::U32(7));
+    }
+
+    #[test]
+    fn missing_items() {
+        let (_, segments) = parse_path("x.items[1]").unwrap();
+        assert!(lookup(&val(), &segments).is_err());
+        let (_, segments) = parse_path("x.missing").unwrap();
+        assert!(lookup(&val(), &segments).is_err());
+    }
+}
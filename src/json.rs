@@ -0,0 +1,187 @@
+use anyhow::{bail, Context as _};
+use serde_json::{json, Map, Value};
+use wasmtime::component::{Type, Val};
+
+use crate::evaluator::display_component_type;
+
+/// Convert a component value to JSON
+///
+/// Variants and results are represented as `{"tag": ..., "val": ...}` objects,
+/// options as either `null` or their payload and flags as a list of the names
+/// of the flags that are set.
+pub fn val_to_json(val: &Val) -> anyhow::Result<Value> {
+    Ok(match val {
+        Val::Bool(b) => json!(b),
+        Val::S8(n) => json!(n),
+        Val::U8(n) => json!(n),
+        Val::S16(n) => json!(n),
+        Val::U16(n) => json!(n),
+        Val::S32(n) => json!(n),
+        Val::U32(n) => json!(n),
+        Val::S64(n) => json!(n),
+        Val::U64(n) => json!(n),
+        Val::Float32(n) => json!(n),
+        Val::Float64(n) => json!(n),
+        Val::Char(c) => json!(c),
+        Val::String(s) => json!(s),
+        Val::List(items) | Val::Tuple(items) => Value::Array(
+            items
+                .iter()
+                .map(val_to_json)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Val::Record(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, val)| Ok((name.clone(), val_to_json(val)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Val::Variant(case, payload) => tagged(case, payload.as_deref())?,
+        Val::Enum(case) => json!(case),
+        Val::Option(None) => Value::Null,
+        Val::Option(Some(val)) => val_to_json(val)?,
+        Val::Result(Ok(payload)) => tagged("ok", payload.as_deref())?,
+        Val::Result(Err(payload)) => tagged("err", payload.as_deref())?,
+        Val::Flags(flags) => json!(flags),
+        Val::Resource(_) => bail!("resources cannot be represented as JSON"),
+    })
+}
+
+fn tagged(tag: &str, payload: Option<&Val>) -> anyhow::Result<Value> {
+    let mut object = Map::new();
+    object.insert("tag".into(), json!(tag));
+    if let Some(payload) = payload {
+        object.insert("val".into(), val_to_json(payload)?);
+    }
+    Ok(Value::Object(object))
+}
+
+/// Convert JSON in the form produced by [`val_to_json`] to a value of type `ty`
+pub fn json_to_val(json: &Value, ty: &Type) -> anyhow::Result<Val> {
+    let mismatch = || {
+        format!(
+            "expected a value of type {}, found '{json}'",
+            display_component_type(ty)
+        )
+    };
+    Ok(match ty {
+        Type::Bool => Val::Bool(json.as_bool().with_context(mismatch)?),
+        Type::S8 => Val::S8(int(json).with_context(mismatch)?),
+        Type::U8 => Val::U8(int(json).with_context(mismatch)?),
+        Type::S16 => Val::S16(int(json).with_context(mismatch)?),
+        Type::U16 => Val::U16(int(json).with_context(mismatch)?),
+        Type::S32 => Val::S32(int(json).with_context(mismatch)?),
+        Type::U32 => Val::U32(int(json).with_context(mismatch)?),
+        Type::S64 => Val::S64(int(json).with_context(mismatch)?),
+        Type::U64 => Val::U64(int(json).with_context(mismatch)?),
+        Type::Float32 => Val::Float32(json.as_f64().with_context(mismatch)? as f32),
+        Type::Float64 => Val::Float64(json.as_f64().with_context(mismatch)?),
+        Type::Char => {
+            let mut chars = json.as_str().with_context(mismatch)?.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Val::Char(c),
+                _ => bail!(mismatch()),
+            }
+        }
+        Type::String => Val::String(json.as_str().with_context(mismatch)?.to_owned()),
+        Type::List(l) => Val::List(
+            json.as_array()
+                .with_context(mismatch)?
+                .iter()
+                .map(|item| json_to_val(item, &l.ty()))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Type::Tuple(t) => {
+            let items = json.as_array().with_context(mismatch)?;
+            if items.len() != t.types().len() {
+                bail!(mismatch())
+            }
+            Val::Tuple(
+                items
+                    .iter()
+                    .zip(t.types())
+                    .map(|(item, ty)| json_to_val(item, &ty))
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        Type::Record(r) => {
+            let object = json.as_object().with_context(mismatch)?;
+            if let Some(name) = object.keys().find(|k| !r.fields().any(|f| f.name == *k)) {
+                bail!("unexpected field '{name}' in '{json}'")
+            }
+            Val::Record(
+                r.fields()
+                    .map(|field| {
+                        let val = match (object.get(field.name), &field.ty) {
+                            (Some(val), ty) => json_to_val(val, ty)?,
+                            (None, Type::Option(_)) => Val::Option(None),
+                            (None, _) => bail!("missing field '{}' in '{json}'", field.name),
+                        };
+                        Ok((field.name.to_owned(), val))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        Type::Variant(v) => {
+            let (tag, payload) = untag(json).with_context(mismatch)?;
+            let case = v.cases().find(|c| c.name == tag).with_context(|| {
+                format!("'{tag}' is not a case of {}", display_component_type(ty))
+            })?;
+            Val::Variant(tag.to_owned(), payload_val(payload, case.ty.as_ref())?)
+        }
+        Type::Enum(e) => {
+            let case = json.as_str().with_context(mismatch)?;
+            if !e.names().any(|n| n == case) {
+                bail!("'{case}' is not a case of {}", display_component_type(ty))
+            }
+            Val::Enum(case.to_owned())
+        }
+        Type::Option(o) => match json {
+            Value::Null => Val::Option(None),
+            json => Val::Option(Some(Box::new(json_to_val(json, &o.ty())?))),
+        },
+        Type::Result(r) => match untag(json).with_context(mismatch)? {
+            ("ok", payload) => Val::Result(Ok(payload_val(payload, r.ok().as_ref())?)),
+            ("err", payload) => Val::Result(Err(payload_val(payload, r.err().as_ref())?)),
+            (tag, _) => bail!("'{tag}' is not a case of a result (expected 'ok' or 'err')"),
+        },
+        Type::Flags(f) => Val::Flags(
+            json.as_array()
+                .with_context(mismatch)?
+                .iter()
+                .map(|flag| {
+                    let flag = flag.as_str().with_context(mismatch)?;
+                    if !f.names().any(|n| n == flag) {
+                        bail!("'{flag}' is not a flag of {}", display_component_type(ty))
+                    }
+                    Ok(flag.to_owned())
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Type::Own(_) | Type::Borrow(_) => bail!("resources cannot be represented as JSON"),
+    })
+}
+
+fn int<T: TryFrom<i64> + TryFrom<u64>>(json: &Value) -> Option<T> {
+    match (json.as_i64(), json.as_u64()) {
+        (_, Some(n)) => n.try_into().ok(),
+        (Some(n), None) => n.try_into().ok(),
+        (None, None) => None,
+    }
+}
+
+/// Split a `{"tag": ..., "val": ...}` object into its parts
+fn untag(json: &Value) -> Option<(&str, Option<&Value>)> {
+    let object = json.as_object()?;
+    let tag = object.get("tag")?.as_str()?;
+    Some((tag, object.get("val")))
+}
+
+fn payload_val(payload: Option<&Value>, ty: Option<&Type>) -> anyhow::Result<Option<Box<Val>>> {
+    match (payload, ty) {
+        (Some(payload), Some(ty)) => Ok(Some(Box::new(json_to_val(payload, ty)?))),
+        (None, None) | (Some(Value::Null), None) => Ok(None),
+        (None, Some(ty)) => bail!("missing payload of type {}", display_component_type(ty)),
+        (Some(payload), None) => bail!("unexpected payload '{payload}'"),
+    }
+}
@@ -0,0 +1,695 @@
+mod zmtp;
+
+use std::{
+    net::{IpAddr, TcpListener},
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::SystemTime,
+};
+
+use anyhow::{bail, Context as _};
+use hmac::{Hmac, Mac as _};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use wasmtime::component::Val;
+
+use crate::{
+    capture::Capture,
+    clock::format_timestamp,
+    command::{self, format_val},
+    events::OutputHandler,
+    repl_server, Event, Session,
+};
+
+/// The version of the Jupyter messaging protocol the kernel speaks
+const PROTOCOL_VERSION: &str = "5.3";
+/// The frame between the routing identities of a message and the message
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Run `session` as a Jupyter kernel listening where the connection file at
+/// `path` says until a client shuts it down
+///
+/// Each line of a code cell is run as a line typed into the repl. What the
+/// lines print is streamed to the notebook, and the values of an expression
+/// ending a cell are its result, shown as JSON and lists of records as tables
+/// too.
+pub fn run(session: &mut Session, path: &Path) -> anyhow::Result<()> {
+    let connection = Connection::read(path)?;
+    // Notebooks show the output of commands without a terminal's colors
+    colored::control::set_override(false);
+    let bind = |port| {
+        TcpListener::bind((connection.ip, port))
+            .with_context(|| format!("could not bind to port {port} of {}", connection.ip))
+    };
+    let (requests, incoming) = mpsc::channel();
+    listen(
+        bind(connection.shell_port)?,
+        "ROUTER",
+        Some(requests.clone()),
+    );
+    listen(bind(connection.control_port)?, "ROUTER", Some(requests));
+    // Nothing asks for input, so what arrives on stdin is dropped
+    listen(bind(connection.stdin_port)?, "ROUTER", None);
+    let iopub = Arc::new(Mutex::new(Vec::new()));
+    publish_to_subscribers(bind(connection.iopub_port)?, iopub.clone());
+    echo_heartbeats(bind(connection.hb_port)?);
+
+    let mut kernel = Kernel {
+        session,
+        publisher: Publisher {
+            key: connection.key,
+            session_id: random_id(),
+            iopub,
+        },
+        output: Capture::new()?,
+        execution_count: 0,
+    };
+    for (frames, mut peer) in incoming {
+        let request = match Message::decode(frames, &kernel.publisher.key) {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::debug!("dropping a message: {e:?}");
+                continue;
+            }
+        };
+        if !kernel.handle(&request, &mut peer)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Where a kernel listens and how it signs messages, as Jupyter says in the
+/// connection file it starts the kernel with
+struct Connection {
+    ip: IpAddr,
+    key: Vec<u8>,
+    shell_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    control_port: u16,
+    hb_port: u16,
+}
+
+impl Connection {
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read the connection file '{}'", path.display()))?;
+        let file: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("'{}' is not a connection file", path.display()))?;
+        let field = |name: &str| {
+            file.get(name)
+                .with_context(|| format!("the connection file has no '{name}'"))
+        };
+        let port = |name: &str| {
+            field(name)?
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .with_context(|| format!("'{name}' is not a port"))
+        };
+        if let Some(transport) = file.get("transport").and_then(Value::as_str) {
+            if transport != "tcp" {
+                bail!("the transport '{transport}' is not supported, only 'tcp'");
+            }
+        }
+        let key = field("key")?.as_str().unwrap_or_default();
+        let scheme = file.get("signature_scheme").and_then(Value::as_str);
+        if !key.is_empty() && scheme.is_some_and(|scheme| scheme != "hmac-sha256") {
+            bail!("messages can only be signed with 'hmac-sha256'");
+        }
+        Ok(Self {
+            ip: field("ip")?
+                .as_str()
+                .and_then(|ip| ip.parse().ok())
+                .context("'ip' is not an IP address")?,
+            key: key.as_bytes().to_vec(),
+            shell_port: port("shell_port")?,
+            iopub_port: port("iopub_port")?,
+            stdin_port: port("stdin_port")?,
+            control_port: port("control_port")?,
+            hb_port: port("hb_port")?,
+        })
+    }
+}
+
+/// Accept the clients connecting to `listener`, sending every message they
+/// send to `requests` along with the peer to reply to
+fn listen(
+    listener: TcpListener,
+    socket_type: &'static str,
+    requests: Option<mpsc::Sender<(Vec<Vec<u8>>, zmtp::Peer)>>,
+) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let requests = requests.clone();
+            thread::spawn(move || -> anyhow::Result<()> {
+                let mut peer = zmtp::Peer::accept(stream, socket_type)?;
+                loop {
+                    let frames = peer.recv()?;
+                    if let Some(requests) = &requests {
+                        requests.send((frames, peer.try_clone()?))?;
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Add the clients connecting to `listener` to the subscribers of `iopub`
+fn publish_to_subscribers(listener: TcpListener, iopub: Arc<Mutex<Vec<zmtp::Peer>>>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(mut peer) = zmtp::Peer::accept(stream, "PUB") else {
+                continue;
+            };
+            let Ok(subscriber) = peer.try_clone() else {
+                continue;
+            };
+            iopub.lock().unwrap().push(subscriber);
+            // Every message is published to every subscriber, so what they
+            // subscribe to is dropped
+            thread::spawn(move || while peer.recv().is_ok() {});
+        }
+    });
+}
+
+/// Send the clients connecting to `listener` back what they send, which tells
+/// them the kernel is alive
+fn echo_heartbeats(listener: TcpListener) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || -> anyhow::Result<()> {
+                let mut peer = zmtp::Peer::accept(stream, "REP")?;
+                loop {
+                    let frames = peer.recv()?;
+                    peer.send(&frames)?;
+                }
+            });
+        }
+    });
+}
+
+/// A message of the Jupyter messaging protocol
+#[derive(Clone)]
+struct Message {
+    /// The frames routing the message to its receiver
+    identities: Vec<Vec<u8>>,
+    header: Value,
+    parent_header: Value,
+    metadata: Value,
+    content: Value,
+}
+
+impl Message {
+    /// Split the frames of a message, checking that it is signed with `key`
+    fn decode(mut frames: Vec<Vec<u8>>, key: &[u8]) -> anyhow::Result<Self> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .context("the message has no delimiter")?;
+        let parts = frames.split_off(delimiter + 1);
+        frames.pop();
+        let [signature, header, parent_header, metadata, content, ..] = parts.as_slice() else {
+            bail!("the message has too few parts");
+        };
+        verify(key, &[header, parent_header, metadata, content], signature)?;
+        Ok(Self {
+            identities: frames,
+            header: serde_json::from_slice(header)?,
+            parent_header: serde_json::from_slice(parent_header)?,
+            metadata: serde_json::from_slice(metadata)?,
+            content: serde_json::from_slice(content)?,
+        })
+    }
+
+    fn encode(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let parts = [
+            &self.header,
+            &self.parent_header,
+            &self.metadata,
+            &self.content,
+        ]
+        .map(|part| part.to_string().into_bytes());
+        let mut frames = self.identities.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(sign(key, &parts.each_ref().map(Vec::as_slice)).into_bytes());
+        frames.extend(parts);
+        frames
+    }
+
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+/// The HMAC-SHA256 of `parts` with `key`, unless there is no key
+fn mac(key: &[u8], parts: &[&[u8]]) -> Option<Hmac<Sha256>> {
+    if key.is_empty() {
+        return None;
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    Some(mac)
+}
+
+/// The signature of `parts` with `key` in hex, or nothing without a key
+fn sign(key: &[u8], parts: &[&[u8]]) -> String {
+    let Some(mac) = mac(key, parts) else {
+        return String::new();
+    };
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Check that `signature` is the signature of `parts` with `key`, in constant
+/// time so that it cannot be guessed byte by byte
+fn verify(key: &[u8], parts: &[&[u8]], signature: &[u8]) -> anyhow::Result<()> {
+    let valid = match mac(key, parts) {
+        Some(mac) => from_hex(signature).is_some_and(|bytes| mac.verify_slice(&bytes).is_ok()),
+        None => signature.is_empty(),
+    };
+    if !valid {
+        bail!("the message has a wrong signature");
+    }
+    Ok(())
+}
+
+/// The bytes written in `hex`, if it is hexadecimal
+fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// A random id like a version 4 UUID
+fn random_id() -> String {
+    let id = format!("{:032x}", rand::random::<u128>());
+    format!(
+        "{}-{}-4{}-{}-{}",
+        &id[..8],
+        &id[8..12],
+        &id[13..16],
+        &id[16..20],
+        &id[20..]
+    )
+}
+
+struct Kernel<'a> {
+    session: &'a mut Session,
+    publisher: Publisher,
+    output: Capture,
+    execution_count: u64,
+}
+
+/// Signs messages and publishes them to the clients subscribed to the kernel,
+/// which output streamed while a call runs is published by as well
+#[derive(Clone)]
+struct Publisher {
+    key: Vec<u8>,
+    /// The id of the kernel's session, which the messages it sends carry
+    session_id: String,
+    /// The clients subscribed to what the kernel publishes
+    iopub: Arc<Mutex<Vec<zmtp::Peer>>>,
+}
+
+impl Kernel<'_> {
+    /// Answer `request`, returning whether the kernel keeps running
+    fn handle(&mut self, request: &Message, peer: &mut zmtp::Peer) -> anyhow::Result<bool> {
+        self.publish(request, "status", json!({"execution_state": "busy"}));
+        let (reply_type, content) = match request.msg_type() {
+            "kernel_info_request" => ("kernel_info_reply", self.kernel_info()),
+            "execute_request" => ("execute_reply", self.execute(request)?),
+            "complete_request" => ("complete_reply", self.complete(request)),
+            "inspect_request" => ("inspect_reply", self.inspect(request)?),
+            "is_complete_request" => ("is_complete_reply", json!({"status": "complete"})),
+            "comm_info_request" => ("comm_info_reply", json!({"status": "ok", "comms": {}})),
+            "history_request" => ("history_reply", json!({"status": "ok", "history": []})),
+            // Calls cannot be interrupted, so the request is only acknowledged
+            "interrupt_request" => ("interrupt_reply", json!({"status": "ok"})),
+            "shutdown_request" => {
+                let restart = request.content["restart"].as_bool().unwrap_or_default();
+                let content = json!({"status": "ok", "restart": restart});
+                self.reply(peer, request, "shutdown_reply", content)?;
+                return Ok(false);
+            }
+            msg_type => {
+                tracing::debug!("ignoring a '{msg_type}' message");
+                self.publish(request, "status", json!({"execution_state": "idle"}));
+                return Ok(true);
+            }
+        };
+        self.reply(peer, request, reply_type, content)?;
+        self.publish(request, "status", json!({"execution_state": "idle"}));
+        Ok(true)
+    }
+
+    fn kernel_info(&self) -> Value {
+        let world = self.session.instance.resolver.world_name();
+        json!({
+            "status": "ok",
+            "protocol_version": PROTOCOL_VERSION,
+            "implementation": "wepl",
+            "implementation_version": env!("CARGO_PKG_VERSION"),
+            "language_info": {
+                "name": "wepl",
+                "version": env!("CARGO_PKG_VERSION"),
+                "mimetype": "text/x-wepl",
+                "file_extension": ".wepl",
+            },
+            "banner": format!("World: {world}"),
+            "help_links": [],
+        })
+    }
+
+    /// Run the lines of a code cell until one fails
+    fn execute(&mut self, request: &Message) -> anyhow::Result<Value> {
+        self.execution_count += 1;
+        let count = self.execution_count;
+        let code = request.content["code"].as_str().unwrap_or_default();
+        self.publish(
+            request,
+            "execute_input",
+            json!({"code": code, "execution_count": count}),
+        );
+        // What the guest prints is published while its calls run rather than
+        // once the line is done
+        let streamed: OutputHandler = Arc::new({
+            let (publisher, parent) = (self.publisher.clone(), request.clone());
+            move |output: Vec<Event>| {
+                let text = output.iter().map(Event::render).collect::<String>();
+                publisher.publish(&parent, "stream", json!({"name": "stdout", "text": text}));
+            }
+        });
+        let lines = code.lines().filter(|line| !line.trim().is_empty());
+        let last = lines.clone().count().saturating_sub(1);
+        for (i, line) in lines.enumerate() {
+            let ((result, events), printed) = self.output.run(|| {
+                self.session
+                    .run_line_collecting(line, Some(streamed.clone()))
+            })?;
+            self.stream(request, "stdout", &printed);
+            for event in &events {
+                match event {
+                    // The values of an expression ending the cell are its result
+                    Event::Value { .. } | Event::Results { .. } if i == last => {
+                        let data = rich_data(&event.render(), event.values());
+                        self.publish(
+                            request,
+                            "execute_result",
+                            json!({"execution_count": count, "data": data, "metadata": {}}),
+                        );
+                    }
+                    Event::Output { .. } => self.stream(request, "stdout", &event.render()),
+                    Event::Warning(_) | Event::Denied(_) | Event::Log(_) => {
+                        self.stream(request, "stderr", &format!("{}\n", event.render()))
+                    }
+                    _ => self.stream(request, "stdout", &format!("{}\n", event.render())),
+                }
+            }
+            match result {
+                Ok(command::Outcome::ClearScreen) => {
+                    self.publish(request, "clear_output", json!({"wait": false}))
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let error = crate::error_to_json(&e, Some(line));
+                    let traceback = crate::error_messages(&e);
+                    let content = json!({
+                        "ename": error["kind"],
+                        "evalue": error["message"],
+                        "traceback": traceback,
+                    });
+                    self.publish(request, "error", content.clone());
+                    let mut reply = content;
+                    reply["status"] = json!("error");
+                    reply["execution_count"] = json!(count);
+                    return Ok(reply);
+                }
+            }
+        }
+        Ok(json!({"status": "ok", "execution_count": count, "user_expressions": {}}))
+    }
+
+    /// Complete the word before the cursor
+    fn complete(&self, request: &Message) -> Value {
+        let (code, cursor) = code_and_cursor(request);
+        let line_start = code[..cursor].rfind('\n').map_or(0, |i| i + 1);
+        let (start, matches) = repl_server::complete(self.session, &code[line_start..cursor]);
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": code[..line_start + start].chars().count(),
+            "cursor_end": code[..cursor].chars().count(),
+            "metadata": {},
+        })
+    }
+
+    /// Show what `.inspect` shows for the word at the cursor
+    fn inspect(&mut self, request: &Message) -> anyhow::Result<Value> {
+        let (code, cursor) = code_and_cursor(request);
+        let start = repl_server::word_start(&code[..cursor]);
+        let end = code[cursor..]
+            .find(repl_server::is_word_boundary)
+            .map_or(code.len(), |i| cursor + i);
+        let word = &code[start..end];
+        if word.is_empty() {
+            return Ok(json!({"status": "ok", "found": false, "data": {}, "metadata": {}}));
+        }
+        let (result, printed) = self
+            .output
+            .run(|| self.session.run_line(&format!(".inspect {word}")))?;
+        Ok(json!({
+            "status": "ok",
+            "found": result.is_ok(),
+            "data": if result.is_ok() { json!({"text/plain": printed}) } else { json!({}) },
+            "metadata": {},
+        }))
+    }
+
+    /// Publish what a line of a cell printed to the stream `name` (`stdout`
+    /// or `stderr`)
+    fn stream(&self, parent: &Message, name: &str, text: &str) {
+        if !text.is_empty() {
+            self.publish(parent, "stream", json!({"name": name, "text": text}));
+        }
+    }
+
+    fn reply(
+        &self,
+        peer: &mut zmtp::Peer,
+        request: &Message,
+        msg_type: &str,
+        content: Value,
+    ) -> anyhow::Result<()> {
+        let reply = self
+            .publisher
+            .message(request, request.identities.clone(), msg_type, content);
+        peer.send(&reply.encode(&self.publisher.key))
+    }
+
+    fn publish(&self, parent: &Message, msg_type: &str, content: Value) {
+        self.publisher.publish(parent, msg_type, content)
+    }
+}
+
+impl Publisher {
+    /// Send a message to every client subscribed to the kernel, dropping the
+    /// ones that are gone
+    fn publish(&self, parent: &Message, msg_type: &str, content: Value) {
+        let message = self.message(
+            parent,
+            vec![msg_type.as_bytes().to_vec()],
+            msg_type,
+            content,
+        );
+        let frames = message.encode(&self.key);
+        self.iopub
+            .lock()
+            .unwrap()
+            .retain_mut(|subscriber| subscriber.send(&frames).is_ok());
+    }
+
+    fn message(
+        &self,
+        parent: &Message,
+        identities: Vec<Vec<u8>>,
+        msg_type: &str,
+        content: Value,
+    ) -> Message {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Message {
+            identities,
+            header: json!({
+                "msg_id": random_id(),
+                "session": self.session_id,
+                "username": "wepl",
+                "date": format_timestamp(now),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            }),
+            parent_header: parent.header.clone(),
+            metadata: json!({}),
+            content,
+        }
+    }
+}
+
+/// The code of a completion or inspection request and the byte offset of its
+/// cursor, which Jupyter counts in characters
+fn code_and_cursor(request: &Message) -> (&str, usize) {
+    let code = request.content["code"].as_str().unwrap_or_default();
+    let cursor = request.content["cursor_pos"].as_u64().unwrap_or_default() as usize;
+    let cursor = code
+        .char_indices()
+        .nth(cursor)
+        .map_or(code.len(), |(i, _)| i);
+    (code, cursor)
+}
+
+/// The representations of the values `values` printed as `text` that
+/// notebooks can show: JSON and, for lists of records, an HTML table
+fn rich_data(text: &str, values: &[Val]) -> Value {
+    let mut data = json!({"text/plain": text});
+    let json = match values {
+        [value] => crate::json::val_to_json(value),
+        values => values.iter().map(crate::json::val_to_json).collect(),
+    };
+    if let Ok(json) = json {
+        data["application/json"] = json;
+    }
+    if let [value] = values {
+        if let Some(table) = html_table(value) {
+            data["text/html"] = json!(table);
+        }
+    }
+    data
+}
+
+/// A list of records as an HTML table with a column for each field
+fn html_table(value: &Val) -> Option<String> {
+    let Val::List(rows) = value else {
+        return None;
+    };
+    let Some(Val::Record(first)) = rows.first() else {
+        return None;
+    };
+    let mut table = String::from("<table>\n<tr>");
+    for (name, _) in first {
+        table.push_str(&format!("<th>{}</th>", escape_html(name)));
+    }
+    table.push_str("</tr>\n");
+    for row in rows {
+        let Val::Record(fields) = row else {
+            return None;
+        };
+        table.push_str("<tr>");
+        for (_, value) in fields {
+            table.push_str(&format!("<td>{}</td>", escape_html(&format_val(value))));
+        }
+        table.push_str("</tr>\n");
+    }
+    table.push_str("</table>");
+    Some(table)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        // The second test case of RFC 4231
+        let signature = sign(b"Jefe", &[b"what do ya ", b"want for nothing?"]);
+        assert_eq!(
+            signature,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(sign(b"", &[b"unsigned"]), "");
+    }
+
+    /// The frames of a message signed with the key `secret` by Python's `hmac`
+    fn signed_frames() -> Vec<Vec<u8>> {
+        [
+            &b"client"[..],
+            DELIMITER,
+            b"20d1c542a03aee4ff79d9899adc9d0a3741e7d8b2d2d1bb2912077aa4cdffd17",
+            br#"{"msg_id":"1","msg_type":"execute_request"}"#,
+            b"{}",
+            b"{}",
+            br#"{"code":"add(1, 2)"}"#,
+        ]
+        .map(<[u8]>::to_vec)
+        .to_vec()
+    }
+
+    #[test]
+    fn signed_messages() {
+        let message = Message::decode(signed_frames(), b"secret").unwrap();
+        assert_eq!(message.content, json!({"code": "add(1, 2)"}));
+        assert_eq!(message.encode(b"secret"), signed_frames());
+    }
+
+    #[test]
+    fn wrong_signatures() {
+        assert!(Message::decode(signed_frames(), b"other secret").is_err());
+        assert!(Message::decode(signed_frames(), b"").is_err());
+        let mut frames = signed_frames();
+        frames[2][0] = b'3';
+        assert!(Message::decode(frames.clone(), b"secret").is_err());
+        frames[2].truncate(63);
+        assert!(Message::decode(frames, b"secret").is_err());
+    }
+
+    #[test]
+    fn messages() {
+        let message = Message {
+            identities: vec![b"client".to_vec()],
+            header: json!({"msg_type": "execute_request"}),
+            parent_header: json!({}),
+            metadata: json!({}),
+            content: json!({"code": "add(1, 2)"}),
+        };
+        let frames = message.encode(b"key");
+        assert_eq!(frames[1], DELIMITER);
+        let decoded = Message::decode(frames.clone(), b"key").unwrap();
+        assert_eq!(decoded.identities, message.identities);
+        assert_eq!(decoded.msg_type(), "execute_request");
+        assert_eq!(decoded.content, message.content);
+        assert!(Message::decode(frames, b"other key").is_err());
+    }
+
+    #[test]
+    fn tables() {
+        let user = |name: &str, age| {
+            Val::Record(vec![
+                ("name".into(), Val::String(name.into())),
+                ("age".into(), Val::U32(age)),
+            ])
+        };
+        let users = Val::List(vec![user("<b>", 3), user("ann", 40)]);
+        assert_eq!(
+            html_table(&users).unwrap(),
+            "<table>\n<tr><th>name</th><th>age</th></tr>\n\
+             <tr><td>\"&lt;b&gt;\"</td><td>3</td></tr>\n\
+             <tr><td>\"ann\"</td><td>40</td></tr>\n</table>"
+        );
+        assert_eq!(html_table(&Val::List(vec![Val::U32(1)])), None);
+    }
+}
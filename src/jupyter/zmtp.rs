@@ -0,0 +1,216 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use anyhow::{bail, Context as _};
+
+/// The frame is followed by more frames of the same message
+const MORE: u8 = 0x01;
+/// The frame's size takes 8 bytes instead of 1
+const LONG: u8 = 0x02;
+/// The frame is a command rather than part of a message
+const COMMAND: u8 = 0x04;
+/// The largest frame peers may send, which is far more than any message of
+/// Jupyter's needs but keeps a peer from making the kernel allocate whatever
+/// size it claims
+const MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+/// The most bytes the frames of a message may add up to, so that a message
+/// made of many frames cannot grow without bounds either
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+/// The most frames a message may have, which Jupyter's messages, made of the
+/// routing identities and six parts, are far from
+const MAX_FRAMES: usize = 1024;
+
+/// A peer connected to one of the kernel's sockets
+///
+/// Peers speak ZMTP 3, the wire protocol of ZeroMQ, over TCP without
+/// authentication (its `NULL` mechanism), which is just enough for Jupyter.
+pub struct Peer {
+    stream: TcpStream,
+}
+
+impl Peer {
+    /// Greet the peer that connected over `stream` and tell it the kernel's
+    /// socket is of type `socket_type` (e.g. `ROUTER`)
+    pub fn accept(mut stream: TcpStream, socket_type: &str) -> anyhow::Result<Self> {
+        stream.write_all(&greeting())?;
+        let mut theirs = [0; 64];
+        stream.read_exact(&mut theirs)?;
+        if theirs[0] != 0xff || theirs[9] != 0x7f {
+            bail!("the peer does not speak ZMTP");
+        }
+        if theirs[10] < 3 {
+            bail!("the peer speaks ZMTP {}, not 3", theirs[10]);
+        }
+        if !theirs[12..32].starts_with(b"NULL\0") {
+            bail!("the peer wants to authenticate, which is not supported");
+        }
+        let mut ready = vec![5];
+        ready.extend_from_slice(b"READY");
+        ready.extend(property("Socket-Type", socket_type.as_bytes()));
+        write_frame(&mut stream, COMMAND, &ready)?;
+        let (flags, _) = read_frame(&mut stream)?;
+        if flags & COMMAND == 0 {
+            bail!("expected the peer to be ready");
+        }
+        Ok(Self { stream })
+    }
+
+    /// The next message the peer sent, skipping the commands between messages
+    pub fn recv(&mut self) -> anyhow::Result<Vec<Vec<u8>>> {
+        read_message(&mut self.stream)
+    }
+
+    pub fn send<T: AsRef<[u8]>>(&mut self, frames: &[T]) -> anyhow::Result<()> {
+        for (i, frame) in frames.iter().enumerate() {
+            let flags = if i + 1 < frames.len() { MORE } else { 0 };
+            write_frame(&mut self.stream, flags, frame.as_ref())?;
+        }
+        Ok(self.stream.flush()?)
+    }
+
+    pub fn try_clone(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            stream: self.stream.try_clone()?,
+        })
+    }
+}
+
+/// The greeting of ZMTP 3.0 with the `NULL` mechanism, sent as a server
+fn greeting() -> [u8; 64] {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+/// A property of the metadata of a `READY` command
+fn property(name: &str, value: &[u8]) -> Vec<u8> {
+    let mut property = vec![name.len() as u8];
+    property.extend_from_slice(name.as_bytes());
+    property.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    property.extend_from_slice(value);
+    property
+}
+
+fn write_frame(writer: &mut impl Write, flags: u8, body: &[u8]) -> anyhow::Result<()> {
+    match u8::try_from(body.len()) {
+        Ok(size) => writer.write_all(&[flags, size])?,
+        Err(_) => {
+            writer.write_all(&[flags | LONG])?;
+            writer.write_all(&(body.len() as u64).to_be_bytes())?;
+        }
+    }
+    Ok(writer.write_all(body)?)
+}
+
+/// The frames of the next message in `reader`, skipping the commands before
+/// and between them
+fn read_message(reader: &mut impl Read) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut frames = Vec::new();
+    let mut size = 0;
+    loop {
+        let (flags, body) = read_frame(reader)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        size += body.len();
+        if size > MAX_MESSAGE_SIZE {
+            bail!("the message is larger than the {MAX_MESSAGE_SIZE} bytes allowed");
+        }
+        if frames.len() == MAX_FRAMES {
+            bail!("the message has more than the {MAX_FRAMES} frames allowed");
+        }
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+fn read_frame(reader: &mut impl Read) -> anyhow::Result<(u8, Vec<u8>)> {
+    let mut flags = [0];
+    reader.read_exact(&mut flags)?;
+    let flags = flags[0];
+    let size = if flags & LONG == 0 {
+        let mut size = [0];
+        reader.read_exact(&mut size)?;
+        size[0] as usize
+    } else {
+        let mut size = [0; 8];
+        reader.read_exact(&mut size)?;
+        let size = u64::from_be_bytes(size);
+        if size > MAX_FRAME_SIZE {
+            bail!("the frame of {size} bytes is larger than the {MAX_FRAME_SIZE} bytes allowed");
+        }
+        usize::try_from(size).context("the frame is too large")?
+    };
+    let mut body = vec![0; size];
+    reader.read_exact(&mut body)?;
+    Ok((flags, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames() {
+        let long = vec![7; 300];
+        let mut wire = Vec::new();
+        write_frame(&mut wire, MORE, b"hello").unwrap();
+        write_frame(&mut wire, 0, &long).unwrap();
+        assert_eq!(&wire[..7], b"\x01\x05hello");
+        assert_eq!(&wire[7..16], b"\x02\0\0\0\0\0\0\x01\x2c");
+        let mut reader = wire.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), (MORE, b"hello".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), (LONG, long));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn refuses_huge_frames() {
+        let mut wire = vec![LONG];
+        wire.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(read_frame(&mut wire.as_slice()).is_err());
+    }
+
+    #[test]
+    fn messages() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, COMMAND, b"\x04PING").unwrap();
+        write_frame(&mut wire, MORE, b"id").unwrap();
+        write_frame(&mut wire, COMMAND, b"\x04PING").unwrap();
+        write_frame(&mut wire, 0, b"body").unwrap();
+        let message = read_message(&mut wire.as_slice()).unwrap();
+        assert_eq!(message, [b"id".to_vec(), b"body".to_vec()]);
+    }
+
+    #[test]
+    fn refuses_messages_with_too_many_frames() {
+        let mut wire = Vec::new();
+        for _ in 0..=MAX_FRAMES {
+            write_frame(&mut wire, MORE, b"").unwrap();
+        }
+        write_frame(&mut wire, 0, b"").unwrap();
+        let error = read_message(&mut wire.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("frames"), "{error}");
+    }
+
+    #[test]
+    fn refuses_huge_messages() {
+        // Frames that are each allowed but too large together
+        let frame = MAX_MESSAGE_SIZE / 2 + 1;
+        let mut wire = Vec::new();
+        for _ in 0..2 {
+            wire.push(MORE | LONG);
+            wire.extend_from_slice(&(frame as u64).to_be_bytes());
+            wire.resize(wire.len() + frame, 0);
+        }
+        let error = read_message(&mut wire.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("larger"), "{error}");
+    }
+}
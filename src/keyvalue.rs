@@ -0,0 +1,264 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use wasmtime::component::Val;
+
+/// The interfaces of `wasi:keyvalue` wepl can implement
+pub const INTERFACES: &[&str] = &["store", "atomics", "batch"];
+
+/// The name of the `wasi:keyvalue` interface `interface` (e.g. `store` for
+/// `wasi:keyvalue/store@0.2.0-draft`), if it is one wepl can implement
+pub fn interface_name(interface: &str) -> Option<&str> {
+    let name = interface.strip_prefix("wasi:keyvalue/")?;
+    let name = name.split_once('@').map_or(name, |(name, _)| name);
+    INTERFACES.contains(&name).then_some(name)
+}
+
+/// The in-memory stores behind `wasi:keyvalue`, by the identifier buckets are
+/// opened with
+///
+/// Stores are created when a bucket is first opened or a key is first set in
+/// them.
+#[derive(Clone, Default)]
+pub struct KeyValueStores(Arc<Mutex<BTreeMap<String, Store>>>);
+
+/// The values of a store by their key
+pub type Store = BTreeMap<String, Vec<u8>>;
+
+impl KeyValueStores {
+    pub fn open(&self, store: &str) {
+        self.0.lock().unwrap().entry(store.to_owned()).or_default();
+    }
+
+    pub fn get(&self, store: &str, key: &str) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().get(store)?.get(key).cloned()
+    }
+
+    pub fn set(&self, store: &str, key: &str, value: Vec<u8>) {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(store.to_owned())
+            .or_default()
+            .insert(key.to_owned(), value);
+    }
+
+    /// The contents of every store
+    pub fn dump(&self) -> BTreeMap<String, Store> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Whether wepl implements the function `name` of a `wasi:keyvalue`
+    /// interface, where methods of buckets are named without their prefix
+    pub fn implements(name: &str) -> bool {
+        matches!(
+            name,
+            "open"
+                | "get"
+                | "set"
+                | "delete"
+                | "exists"
+                | "list-keys"
+                | "increment"
+                | "get-many"
+                | "set-many"
+                | "delete-many"
+        )
+    }
+
+    /// Call the function `name` on the bucket of `store` with the arguments
+    /// following the bucket, returning its result
+    ///
+    /// `increment` treats values as 8 little-endian bytes, like wasmtime's
+    /// implementation of `wasi:keyvalue`.
+    pub fn call(&self, store: &str, name: &str, args: &[Val]) -> anyhow::Result<Val> {
+        let mut stores = self.0.lock().unwrap();
+        let entries = stores.entry(store.to_owned()).or_default();
+        let result = match (name, args) {
+            ("get", [Val::String(key)]) => Ok(Some(Val::Option(
+                entries.get(key).map(|value| Box::new(bytes_val(value))),
+            ))),
+            ("set", [Val::String(key), Val::List(value)]) => {
+                entries.insert(key.clone(), val_bytes(value)?);
+                Ok(None)
+            }
+            ("delete", [Val::String(key)]) => {
+                entries.remove(key);
+                Ok(None)
+            }
+            ("exists", [Val::String(key)]) => Ok(Some(Val::Bool(entries.contains_key(key)))),
+            ("list-keys", [Val::Option(cursor)]) => {
+                // Every key is listed at once, after the cursor if there is one
+                let keys = entries.keys().map(|key| Val::String(key.clone()));
+                let keys = match cursor.as_deref() {
+                    Some(Val::U64(skip)) => keys.skip(*skip as usize).collect(),
+                    Some(Val::String(after)) => keys
+                        .filter(|key| matches!(key, Val::String(key) if key > after))
+                        .collect(),
+                    _ => keys.collect(),
+                };
+                Ok(Some(Val::Record(vec![
+                    ("keys".into(), Val::List(keys)),
+                    ("cursor".into(), Val::Option(None)),
+                ])))
+            }
+            ("increment", [Val::String(key), delta]) => {
+                let delta = match delta {
+                    Val::U64(delta) => *delta,
+                    Val::S64(delta) => *delta as u64,
+                    _ => anyhow::bail!("unexpected arguments to wasi:keyvalue#increment"),
+                };
+                let current = entries
+                    .get(key)
+                    .map(|value| <[u8; 8]>::try_from(&value[..]).map(u64::from_le_bytes));
+                match current.unwrap_or(Ok(0)) {
+                    Ok(value) => {
+                        let value = value.wrapping_add(delta);
+                        entries.insert(key.clone(), value.to_le_bytes().to_vec());
+                        Ok(Some(Val::U64(value)))
+                    }
+                    Err(_) => Err(format!("the value of '{key}' is not a 64-bit integer")),
+                }
+            }
+            ("get-many", [Val::List(keys)]) => {
+                let values = keys
+                    .iter()
+                    .map(|key| {
+                        let Val::String(key) = key else {
+                            anyhow::bail!("unexpected arguments to wasi:keyvalue#get-many");
+                        };
+                        Ok(Val::Option(entries.get(key).map(|value| {
+                            Box::new(Val::Tuple(vec![Val::String(key.clone()), bytes_val(value)]))
+                        })))
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Some(Val::List(values)))
+            }
+            ("set-many", [Val::List(pairs)]) => {
+                for pair in pairs {
+                    let Val::Tuple(pair) = pair else {
+                        anyhow::bail!("unexpected arguments to wasi:keyvalue#set-many");
+                    };
+                    let [Val::String(key), Val::List(value)] = pair.as_slice() else {
+                        anyhow::bail!("unexpected arguments to wasi:keyvalue#set-many");
+                    };
+                    entries.insert(key.clone(), val_bytes(value)?);
+                }
+                Ok(None)
+            }
+            ("delete-many", [Val::List(keys)]) => {
+                for key in keys {
+                    if let Val::String(key) = key {
+                        entries.remove(key);
+                    }
+                }
+                Ok(None)
+            }
+            _ => anyhow::bail!("unexpected call of wasi:keyvalue#{name}"),
+        };
+        Ok(Val::Result(match result {
+            Ok(val) => Ok(val.map(Box::new)),
+            Err(message) => Err(Some(Box::new(error_val(message)))),
+        }))
+    }
+}
+
+/// The `other` case of `wasi:keyvalue`'s error with `message`
+fn error_val(message: String) -> Val {
+    Val::Variant("other".into(), Some(Box::new(Val::String(message))))
+}
+
+/// `bytes` as a `list<u8>`
+fn bytes_val(bytes: &[u8]) -> Val {
+    Val::List(bytes.iter().copied().map(Val::U8).collect())
+}
+
+/// The bytes in a `list<u8>`
+fn val_bytes(list: &[Val]) -> anyhow::Result<Vec<u8>> {
+    list.iter()
+        .map(|val| match val {
+            Val::U8(byte) => Ok(*byte),
+            _ => anyhow::bail!("expected a list of bytes"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> Val {
+        Val::String(key.to_owned())
+    }
+
+    fn ok(val: Val) -> Val {
+        Val::Result(Ok(Some(Box::new(val))))
+    }
+
+    #[test]
+    fn interface_names() {
+        assert_eq!(
+            interface_name("wasi:keyvalue/atomics@0.2.0-draft"),
+            Some("atomics")
+        );
+        assert_eq!(interface_name("wasi:keyvalue/watcher"), None);
+    }
+
+    #[test]
+    fn buckets() {
+        let stores = KeyValueStores::default();
+        stores.set("app", "name", b"wepl".to_vec());
+        assert_eq!(
+            stores.call("app", "get", &[key("name")]).unwrap(),
+            ok(Val::Option(Some(Box::new(bytes_val(b"wepl")))))
+        );
+        assert_eq!(
+            stores.call("other", "exists", &[key("name")]).unwrap(),
+            ok(Val::Bool(false))
+        );
+    }
+
+    #[test]
+    fn increments() {
+        let stores = KeyValueStores::default();
+        for (delta, expected) in [(5, 5), (2, 7)] {
+            assert_eq!(
+                stores
+                    .call("app", "increment", &[key("hits"), Val::U64(delta)])
+                    .unwrap(),
+                ok(Val::U64(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn increments_of_other_values() {
+        let stores = KeyValueStores::default();
+        stores.set("app", "name", b"wepl".to_vec());
+        assert!(matches!(
+            stores.call("app", "increment", &[key("name"), Val::U64(1)]),
+            Ok(Val::Result(Err(Some(_))))
+        ));
+    }
+
+    #[test]
+    fn batches_and_keys() {
+        let stores = KeyValueStores::default();
+        stores.set("app", "name", b"wepl".to_vec());
+        stores.set("app", "hits", b"1".to_vec());
+        stores
+            .call("app", "delete-many", &[Val::List(vec![key("hits")])])
+            .unwrap();
+        assert_eq!(
+            stores
+                .call("app", "list-keys", &[Val::Option(None)])
+                .unwrap(),
+            ok(Val::Record(vec![
+                ("keys".into(), Val::List(vec![key("name")])),
+                ("cursor".into(), Val::Option(None)),
+            ]))
+        );
+    }
+}
@@ -0,0 +1,1743 @@
+mod audit;
+mod cache;
+#[cfg(unix)]
+mod capture;
+mod cli;
+mod clock;
+mod command;
+mod componentize;
+mod csv;
+mod diagnostics;
+mod diff;
+mod engine;
+mod evaluator;
+mod events;
+mod files;
+mod fuzz;
+mod fuzzy;
+mod golden;
+mod http;
+mod info;
+mod inspect;
+mod json;
+#[cfg(unix)]
+mod jupyter;
+mod keyvalue;
+mod logging;
+mod network;
+mod profile;
+mod progress;
+mod prompt;
+mod rc;
+mod recording;
+mod registry;
+#[cfg(unix)]
+mod repl_server;
+mod runtime;
+mod scaffold;
+mod serve;
+mod stats;
+mod streams;
+mod table;
+#[cfg(unix)]
+mod tui;
+mod validate;
+mod watch;
+mod wit;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    io::Read as _,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use anyhow::Context as _;
+use clap::Parser;
+use colored::*;
+pub use wasmtime::component::Val;
+
+pub use cli::main;
+pub use events::{Event, OutputSource};
+
+/// The component a session is started for
+fn main_component(cli: &Cli) -> anyhow::Result<PathBuf> {
+    // Without a component the WIT stands in for it
+    cli.component
+        .clone()
+        .or_else(|| cli.wit.clone())
+        .context("no component given")
+}
+
+/// Load `component` (or the WIT standing in for it) into a new session
+/// configured by `cli`
+fn start_session(mut cli: Cli, component: PathBuf) -> anyhow::Result<Session> {
+    let modified = watch::modified(&component);
+    let (component_bytes, resolver) = read_component(&component, &cli, &events::Events::default())?;
+    // Precompiled components keep the engine settings they were compiled with
+    cli.engine.stream_output &= !is_precompiled_path(&component);
+    let stdin = match &cli.stdin {
+        Some(path) => runtime::GuestStdin::Bytes(
+            std::fs::read(path)
+                .with_context(|| format!("could not read stdin file '{}'", path.display()))?
+                .into(),
+        ),
+        None => runtime::GuestStdin::Empty,
+    };
+    let bundle = cli
+        .world_bundle
+        .as_deref()
+        .map(recording::read_world)
+        .transpose()?;
+    let (env, random_seed) = match &bundle {
+        Some(world) => (world.env.clone(), Some(world.random_seed)),
+        None => (cli.env.clone(), cli.random_seed),
+    };
+    if cli.sandbox && !env.is_empty() {
+        anyhow::bail!(
+            "the world bundle sets environment variables, which --sandbox does not allow"
+        );
+    }
+    let wasi_config = runtime::WasiConfig {
+        env,
+        config: cli.config.clone(),
+        stdin,
+        random_seed,
+        network: cli
+            .allow_network
+            .as_deref()
+            .map(network::NetworkPolicy::parse)
+            .transpose()?,
+        sandbox: cli.sandbox,
+        ..Default::default()
+    };
+    if let Some(world) = &bundle {
+        wasi_config.clock.freeze(world.clock);
+    }
+    let mut runtime = init_runtime(
+        component_bytes,
+        is_precompiled_path(&component),
+        &resolver,
+        &cli,
+        wasi_config.clone(),
+    )?;
+    runtime.set_dry_run(is_wit_only(&component, &cli));
+    replay_world_bundle(&mut runtime, &cli, &component)?;
+    Ok(Session {
+        name: instance_name(&component),
+        instance: Instance {
+            watcher: cli
+                .watch
+                .then(|| watch::Watcher::new(component.clone()))
+                .filter(|_| !is_stdin(&component)),
+            modified,
+            component,
+            runtime: Arc::new(Mutex::new(runtime)),
+            resolver,
+            replay: Vec::new(),
+        },
+        others: Vec::new(),
+        cli,
+        wasi_config,
+        scope: HashMap::default(),
+        aliases: BTreeMap::new(),
+        rc_file: None,
+        keybindings: Default::default(),
+        prompt: None,
+        history: Vec::new(),
+        output: None,
+        host_code: true,
+    })
+}
+
+/// Explain what a component exports when none of its functions can be called
+/// from the repl, and what it can be used with instead
+fn print_uncallable_exports(resolver: &wit::WorldResolver, events: &events::Events) {
+    if resolver.has_callable_exports() {
+        return;
+    }
+    let exports = resolver
+        .world()
+        .exports
+        .keys()
+        .map(|key| resolver.world_item_name(key))
+        .collect::<Vec<_>>();
+    if exports.is_empty() {
+        events.print("The component exports nothing to call; it may only be meant to be composed with other components (see `.compose` and `.link`)");
+        return;
+    }
+    events.print(format!(
+        "The component exports no functions the repl can call, only {}",
+        exports.join(", ")
+    ));
+    if resolver.exports_wasi_http_handler() {
+        events.print("  `.serve [$addr]` serves HTTP requests with it");
+    }
+    if resolver.exported_wasi_cli_run().is_some() {
+        events.print("  `.run [$args]` runs it as a command");
+    }
+    events.print("  `.exports` and `.type $name` show what its interfaces are made of");
+}
+
+fn init_runtime(
+    component_bytes: Vec<u8>,
+    precompiled: bool,
+    resolver: &wit::WorldResolver,
+    cli: &Cli,
+    wasi_config: runtime::WasiConfig,
+) -> anyhow::Result<runtime::Runtime> {
+    let component = match precompiled {
+        true => runtime::ComponentBytes::Precompiled(component_bytes),
+        false => runtime::ComponentBytes::Binary(component_bytes),
+    };
+    let mut runtime = runtime::Runtime::init(
+        component,
+        resolver,
+        wasi_config,
+        |import_name, events| {
+            events.emit(Event::Warning(format!(
+                "unimplemented import: {import_name}"
+            )));
+        },
+        |resource, events| events.print(format!("{} {resource}", "Dropped:".yellow().bold())),
+        // Cached compilations are native code, which --sandbox does not load
+        (!cli.no_cache && !cli.sandbox)
+            .then(cache::ComponentCache::new)
+            .flatten(),
+        cli.engine.clone(),
+    )?;
+    runtime.set_prompt_imports(cli.prompt_imports);
+    Ok(runtime)
+}
+
+/// Answer the main component's calls to imports with the ones recorded in the
+/// world bundle given with `--world-bundle`, if any
+fn replay_world_bundle(
+    runtime: &mut runtime::Runtime,
+    cli: &Cli,
+    component: &Path,
+) -> anyhow::Result<()> {
+    let Some(bundle) = &cli.world_bundle else {
+        return Ok(());
+    };
+    if main_component(cli)? != component {
+        return Ok(());
+    }
+    runtime
+        .replay_imports(bundle)
+        .with_context(|| format!("could not replay '{}'", bundle.display()))?;
+    Ok(())
+}
+
+/// Read the component at `path`, which can also be `-` for stdin or a package
+/// in a warg registry, turning core modules into components
+///
+/// Fetched packages are shown on `events`.
+fn read_component(
+    path: &Path,
+    cli: &Cli,
+    events: &events::Events,
+) -> anyhow::Result<(Vec<u8>, wit::WorldResolver)> {
+    if is_wit_only(path, cli) {
+        let resolver = wit::WorldResolver::from_wit(path, cli.world.as_deref())?;
+        return Ok((resolver.dummy_component()?, resolver));
+    }
+    let precompiled = is_precompiled_path(path);
+    if precompiled && cli.sandbox {
+        anyhow::bail!("precompiled components are native code, which --sandbox does not load");
+    }
+    let bytes = if is_stdin(path) {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .context("could not read the component from stdin")?;
+        bytes
+    } else if precompiled {
+        std::fs::read(path)
+            .with_context(|| format!("could not read component '{}'", path.display()))?
+    } else {
+        registry::read_component(path, cli.registry.as_deref(), events)?
+    };
+    match (precompiled, runtime::is_precompiled(&bytes)) {
+        (true, false) => anyhow::bail!(
+            "'{}' is not a precompiled component, which `wepl compile` writes",
+            path.display()
+        ),
+        (false, true) => anyhow::bail!(
+            "'{}' is a precompiled component, which is only loaded from a file with the `.cwasm` extension",
+            path.display()
+        ),
+        _ => {}
+    }
+    let bytes = match precompiled {
+        true => bytes,
+        false => {
+            let (bytes, adapter) = componentize::componentize(bytes, cli.adapter)?;
+            if let (Some(adapter), false) = (adapter, cli.quiet) {
+                events.print(format!(
+                    "{} core module with the WASI preview1 {adapter} adapter",
+                    "Componentized:".green().bold()
+                ));
+            }
+            bytes
+        }
+    };
+    // `--wit` and `--world` are about the component given on the command line
+    let is_main = cli.component.as_deref() == Some(path);
+    let mut resolver = match &cli.wit {
+        Some(wit) if is_main => wit::WorldResolver::from_wit(wit, cli.world.as_deref())?,
+        _ if !precompiled => wit::WorldResolver::from_bytes(&bytes)?,
+        _ => {
+            // Precompiled components have no WIT so their types come from the
+            // component they were compiled from
+            let original = path.with_extension("wasm");
+            let original_bytes = std::fs::read(&original).with_context(|| {
+                format!(
+                    "could not read '{}', which precompiled components need next to them for their types (or pass `--wit`)",
+                    original.display()
+                )
+            })?;
+            wit::WorldResolver::from_bytes(&original_bytes)?
+        }
+    };
+    if let (true, None, Some(world)) = (is_main, &cli.wit, &cli.world) {
+        resolver.select_world(world)?;
+    }
+    Ok((bytes, resolver))
+}
+
+/// Whether `path` is the WIT given with `--wit` when there is no component,
+/// which only lets calls be type-checked
+fn is_wit_only(path: &Path, cli: &Cli) -> bool {
+    cli.component.is_none() && cli.wit.as_deref() == Some(path)
+}
+
+/// Whether `path` means reading from stdin
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Whether `path` is a component precompiled by `wepl compile`, which only
+/// files with the `.cwasm` extension are taken to be since they are loaded as
+/// native code
+fn is_precompiled_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "cwasm")
+}
+
+/// A component loaded into the session
+struct Instance {
+    /// The path of the component
+    component: PathBuf,
+    /// When the component was modified on disk before it was loaded, if known
+    modified: Option<SystemTime>,
+    watcher: Option<watch::Watcher>,
+    /// Shared with the instances whose imports are linked to this one
+    runtime: Arc<Mutex<runtime::Runtime>>,
+    resolver: wit::WorldResolver,
+    /// The commands that configured the runtime, which are run again when the
+    /// component is reloaded
+    replay: Vec<String>,
+}
+
+/// A session of the repl, which tools can drive without a terminal
+///
+/// ```no_run
+/// let mut session = wepl::Session::new("component.wasm")?;
+/// session.eval("x = add(1, 2)");
+/// assert_eq!(session.var("x"), Some(&wepl::Val::U32(3)));
+/// # anyhow::Ok(())
+/// ```
+///
+/// The session outlives the components loaded into it, keeping its variables
+/// when they are reloaded.
+///
+/// Evaluating lines never redirects the process's stdout or stderr, so
+/// sessions can be moved to and used on other threads, and what a component
+/// writes to stderr goes to the process's stderr. The frontends built on
+/// sessions that capture everything a line prints (`serve-repl`, kernels and
+/// the TUI) redirect the streams of the whole process and so only run one
+/// line of one session at a time.
+pub struct Session {
+    cli: Cli,
+    /// The WASI configuration given on the command line
+    wasi_config: runtime::WasiConfig,
+    /// The name of the instance commands run against
+    name: String,
+    instance: Instance,
+    /// The other loaded instances along with their names
+    others: Vec<(String, Instance)>,
+    scope: HashMap<String, Val>,
+    /// The commands lines starting with an alias's name stand for, by name
+    aliases: BTreeMap<String, String>,
+    /// The rc file aliases are saved in, if they are saved
+    rc_file: Option<PathBuf>,
+    /// The keybindings the terminal edits lines with
+    keybindings: command::Keybindings,
+    /// The template of the prompt, if not the default one
+    prompt: Option<String>,
+    /// The lines typed in the session, which `.!` runs again
+    history: Vec<String>,
+    /// What the output of calls is passed to while they run, if not returned
+    /// once the line is done
+    output: Option<events::OutputHandler>,
+    /// Whether lines can run code on the host outside of the component, which
+    /// served sessions only allow with `--allow-host-code`
+    host_code: bool,
+}
+
+/// What evaluating a line with [`Session::eval`] gave
+#[derive(Debug)]
+pub struct EvalResult {
+    /// The lines builtins showed, which are also among the events as
+    /// [`Event::Message`]s
+    pub output: String,
+    /// What happened while the line was evaluated, in order, like what the
+    /// component wrote to stdout and the values it returned
+    pub events: Vec<Event>,
+    /// The values of the expression the line is, which are empty for
+    /// assignments and builtins
+    pub values: Vec<Val>,
+    /// The error the line failed with
+    pub error: Option<anyhow::Error>,
+}
+
+impl Session {
+    /// Start a session for the component at `component`, which like for
+    /// `wepl` itself can also be a package in a warg registry
+    pub fn new(component: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        Self::with_args([component.into()])
+    }
+
+    /// Start a session configured by the arguments `wepl` takes on the
+    /// command line, which include the component (e.g. `["--env", "KEY=VAL",
+    /// "component.wasm"]`)
+    pub fn with_args<I, T>(args: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args = std::iter::once(OsString::from("wepl")).chain(args.into_iter().map(Into::into));
+        let cli = Cli::try_parse_from(args)?;
+        if cli.command.is_some() {
+            anyhow::bail!("sessions cannot be started with a subcommand");
+        }
+        let component = main_component(&cli)?;
+        start_session(cli, component)
+    }
+
+    /// Run the line `line` as if it were typed into the repl, which can be
+    /// an expression, an assignment or a builtin (e.g. `add(1, 2)` or
+    /// `.stub my:pkg/iface#get => 42`)
+    pub fn eval(&mut self, line: &str) -> EvalResult {
+        self.eval_with(line, self.output.clone())
+    }
+
+    /// Pass what calls write to stdout to `handler` while they run, rather
+    /// than among the events of what [`Session::eval`] gives
+    ///
+    /// Calls are interrupted periodically to pass it on, so the instances are
+    /// reloaded to allow it the first time, unless they are precompiled.
+    pub fn on_output(&mut self, handler: impl Fn(Vec<Event>) + Send + Sync + 'static) {
+        self.output = Some(Arc::new(handler));
+        self.stream_output();
+    }
+
+    /// Interrupt calls periodically so that their output can be passed on
+    /// while they run, reloading the instances the first time
+    pub(crate) fn stream_output(&mut self) {
+        let precompiled = self.instance.runtime.lock().unwrap().precompiled();
+        if !self.cli.engine.stream_output && !precompiled {
+            let mut options = self.cli.engine.clone();
+            options.stream_output = true;
+            self.configure(options);
+        }
+    }
+
+    /// Evaluate `line` like [`Session::eval`], passing the output of calls
+    /// to `output` while they run if given
+    pub(crate) fn eval_with(
+        &mut self,
+        line: &str,
+        output: Option<events::OutputHandler>,
+    ) -> EvalResult {
+        let (result, events) = self.run_line_collecting(line, output);
+        let output = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Message(text) => Some(format!("{text}\n")),
+                _ => None,
+            })
+            .collect();
+        let values = events
+            .iter()
+            .flat_map(|event| event.values().iter().cloned())
+            .collect();
+        EvalResult {
+            output,
+            events,
+            values,
+            error: result.err(),
+        }
+    }
+
+    /// The value of the variable `name`
+    pub fn var(&self, name: &str) -> Option<&Val> {
+        self.scope.get(name)
+    }
+
+    /// Set the variable `name` to `val` for the lines evaluated after,
+    /// returning the value it had
+    pub fn set_var(&mut self, name: impl Into<String>, val: Val) -> Option<Val> {
+        self.scope.insert(name.into(), val)
+    }
+
+    /// Remove the variable `name`, returning its value
+    pub fn remove_var(&mut self, name: &str) -> Option<Val> {
+        self.scope.remove(name)
+    }
+
+    /// The variables and their values, in no particular order
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &Val)> {
+        self.scope.iter().map(|(name, val)| (name.as_str(), val))
+    }
+
+    /// Make the imported function `import` (e.g. `my:pkg/iface#get`) return
+    /// `val`, like `.stub $import => $value`
+    ///
+    /// Unlike stubs set up by evaluating builtins, stubs set up with these
+    /// methods are not set up again when the component is reloaded.
+    pub fn stub_value(&mut self, import: &str, val: Val) -> anyhow::Result<()> {
+        let import_ident = command::parser::ItemIdent::parse_str(import)
+            .with_context(|| format!("'{import}' is not a proper item identifier"))?;
+        let Instance {
+            runtime, resolver, ..
+        } = &self.instance;
+        if resolver.imported_function(import_ident).is_none() {
+            anyhow::bail!("no import with name '{import_ident}'");
+        }
+        let mut runtime = runtime.lock().unwrap();
+        let results = runtime.imported_function_results(import_ident)?;
+        let [ty] = results.as_slice() else {
+            anyhow::bail!(
+                "'{import_ident}' returns {} values but only functions returning one value can be stubbed with a constant",
+                results.len()
+            );
+        };
+        let val = runtime::compat::coerce_val(val.clone(), ty).unwrap_or(val);
+        evaluator::type_check(&val, ty)?;
+        runtime.stub_constant(import_ident, vec![val])
+    }
+
+    /// Satisfy the imported interface `interface` with the component or core
+    /// module in the WAT file at `path`, like `.stub $interface --wat $path`
+    pub fn stub_wat(&mut self, interface: &str, path: &Path) -> anyhow::Result<()> {
+        let interface_ident = command::parser::InterfaceIdent::parse_str(interface)
+            .with_context(|| format!("'{interface}' is not a proper interface identifier"))?;
+        let Instance {
+            runtime, resolver, ..
+        } = &self.instance;
+        runtime
+            .lock()
+            .unwrap()
+            .stub_with_wat(resolver, interface_ident, path)
+    }
+
+    /// Stub every function of the imported interface `interface` with one
+    /// returning default values, like `.mock $interface`, returning how many
+    /// functions were stubbed
+    pub fn mock(&mut self, interface: &str) -> anyhow::Result<usize> {
+        let interface_ident = command::parser::InterfaceIdent::parse_str(interface)
+            .with_context(|| format!("'{interface}' is not a proper interface identifier"))?;
+        let Instance {
+            runtime, resolver, ..
+        } = &self.instance;
+        runtime.lock().unwrap().mock(resolver, interface_ident)
+    }
+
+    /// Remove the stub of the imported function `import`, like `.unstub $import`
+    pub fn unstub(&mut self, import: &str) -> anyhow::Result<()> {
+        let import_ident = command::parser::ItemIdent::parse_str(import)
+            .with_context(|| format!("'{import}' is not a proper item identifier"))?;
+        self.instance.runtime.lock().unwrap().unstub(import_ident)
+    }
+
+    /// Remove every stub, like `.unstub all`
+    pub fn unstub_all(&mut self) -> anyhow::Result<()> {
+        self.instance.runtime.lock().unwrap().unstub_all()
+    }
+
+    /// Run the repl input `line` against the instance it is routed to
+    ///
+    /// Returns what is left for the frontend to do, which is either nothing
+    /// ([`command::Outcome::Done`]) or clearing the screen.
+    fn run_line(&mut self, line: &str) -> anyhow::Result<command::Outcome> {
+        self.reload_changed();
+        let line = self.expand_alias(line);
+        let line = line.as_str();
+        let Some(mut cmd) = command::Cmd::parse(line)? else {
+            return Ok(command::Outcome::Done);
+        };
+        if self.cli.sandbox && cmd.escapes_sandbox(&self.scope) {
+            anyhow::bail!(
+                "commands that run code on the host or use its files are disabled by --sandbox"
+            );
+        }
+        if !self.host_code && cmd.runs_host_code() {
+            anyhow::bail!(
+                "commands that run code on the host are disabled for served sessions unless `--allow-host-code` is given"
+            );
+        }
+        let replayed = cmd.is_replayed_on_reload();
+        let Session {
+            instance,
+            others,
+            scope,
+            ..
+        } = self;
+        // Variables shadow instances with the same name
+        let target =
+            cmd.route(|name| !scope.contains_key(name) && others.iter().any(|(n, _)| n == name));
+        let instance = match target {
+            Some(target) => others
+                .iter_mut()
+                .find_map(|(name, other)| (name == target).then_some(other))
+                .expect("routed to an instance that is not loaded"),
+            None => instance,
+        };
+        let outcome = {
+            // Calls through links come back to this instance as its own
+            // imports, which have to fail instead of waiting for the line
+            let mut runtime = runtime::CallLock::new(&instance.runtime)
+                .context("the instance is already running a line")?;
+            let result = cmd.run(&mut runtime, &mut instance.resolver, scope);
+            // Refresh the runtime after a failed call so we start fresh.
+            // Other errors leave the instance and its resources alone.
+            if result.is_err() && runtime.is_poisoned() {
+                runtime.refresh().context("error refreshing wasm runtime")?;
+            }
+            result?
+        };
+        if replayed && !matches!(outcome, command::Outcome::Link { .. }) {
+            instance.replay.push(line.to_owned());
+        }
+        match outcome {
+            // Links are replayed when the importer is reloaded
+            command::Outcome::Link {
+                importer,
+                import,
+                exporter,
+            } => self.link(&importer, &import, &exporter, Some(line.to_owned()))?,
+            command::Outcome::Load { path, name } => self
+                .try_load(path, name)
+                .context("could not load the component")?,
+            command::Outcome::Instances => self.print_instances(),
+            command::Outcome::Use(name) => self.use_instance(&name)?,
+            command::Outcome::Configure(options) => self.configure(options),
+            command::Outcome::Alias { name, command } => self.alias(name, command)?,
+            command::Outcome::Unalias(name) => self.unalias(&name)?,
+            command::Outcome::Aliases => self.print_aliases(),
+            command::Outcome::History(count) => self.print_history(count),
+            command::Outcome::Rerun { entry, edit } => return self.rerun(entry, edit),
+            command::Outcome::Prompt(template) => self.prompt = template,
+            command::Outcome::ShowPrompt => self.events().print(match &self.prompt {
+                Some(template) => format!("{}: {template:?}", "Prompt".blue().bold()),
+                None => format!("{}: default", "Prompt".blue().bold()),
+            }),
+            command::Outcome::Keybindings(Some(keybindings)) => self.keybindings = keybindings,
+            command::Outcome::Keybindings(None) => {
+                let keybindings = match self.keybindings {
+                    command::Keybindings::Emacs => "emacs",
+                    command::Keybindings::Vi => "vi",
+                };
+                self.events()
+                    .print(format!("{}: {keybindings}", "Keybindings".blue().bold()));
+            }
+            outcome @ (command::Outcome::Done
+            | command::Outcome::ClearScreen
+            | command::Outcome::Edit(_)
+            | command::Outcome::Exit(_)) => return Ok(outcome),
+        }
+        Ok(command::Outcome::Done)
+    }
+
+    /// The prompt showing the instance commands run against, rendered from
+    /// the template set with `.prompt`
+    ///
+    /// The default prompt only names the instance once there are several.
+    pub(crate) fn prompt(&self) -> String {
+        let Some(template) = &self.prompt else {
+            return if self.others.is_empty() {
+                "> ".to_owned()
+            } else {
+                format!("{}> ", self.name)
+            };
+        };
+        let component = &self.instance.component;
+        prompt::render(template, |placeholder| match placeholder {
+            "instance" => self.name.clone(),
+            "component" => component
+                .file_name()
+                .unwrap_or(component.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            "world" => self.instance.resolver.world_name(),
+            "dirty" => {
+                let modified = watch::modified(component);
+                let dirty = modified.is_some() && modified != self.instance.modified;
+                if dirty { "*" } else { "" }.to_owned()
+            }
+            _ => String::new(),
+        })
+    }
+
+    /// Remember that `line` was typed so that `.!` can run it again
+    ///
+    /// Lines running history entries are left out, the entries they run are
+    /// added instead.
+    pub(crate) fn add_history(&mut self, line: &str) {
+        if !line.trim().is_empty() && !line.trim_start().starts_with(".!") {
+            self.history.push(line.to_owned());
+        }
+    }
+
+    fn print_history(&self, count: usize) {
+        let events = self.events();
+        let start = self.history.len().saturating_sub(count);
+        for (i, line) in self.history.iter().enumerate().skip(start) {
+            events.print(format!("{} {line}", format!("{:>5}", i + 1).bold()));
+        }
+    }
+
+    /// Run the line numbered `entry` in the history again, or have the
+    /// frontend let the user edit it first
+    fn rerun(&mut self, entry: usize, edit: bool) -> anyhow::Result<command::Outcome> {
+        let line = entry
+            .checked_sub(1)
+            .and_then(|i| self.history.get(i))
+            .with_context(|| format!("no line numbered {entry} in the history"))?
+            .clone();
+        if edit {
+            return Ok(command::Outcome::Edit(line));
+        }
+        self.events().print(line.clone());
+        self.history.push(line.clone());
+        self.run_line(&line)
+    }
+
+    /// `line` with the alias its first word names replaced by the command
+    /// the alias stands for
+    fn expand_alias(&self, line: &str) -> String {
+        let trimmed = line.trim_start();
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        match self.aliases.get(&trimmed[..end]) {
+            Some(command) => format!("{command}{}", &trimmed[end..]),
+            None => line.to_owned(),
+        }
+    }
+
+    fn alias(&mut self, name: String, command: String) -> anyhow::Result<()> {
+        if let Some(path) = &self.rc_file {
+            rc::save_alias(path, &name, Some(&command))?;
+        }
+        self.aliases.insert(name, command);
+        Ok(())
+    }
+
+    fn unalias(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.aliases.remove(name).is_none() {
+            anyhow::bail!("no alias named '{name}'");
+        }
+        if let Some(path) = &self.rc_file {
+            rc::save_alias(path, name, None)?;
+        }
+        Ok(())
+    }
+
+    fn print_aliases(&self) {
+        let events = self.events();
+        for (name, command) in &self.aliases {
+            events.print(format!("{}: {command}", name.bold()));
+        }
+    }
+
+    /// Run the lines of the rc file at `path`, printing the errors they fail
+    /// with, and save the aliases defined from now on in it
+    fn run_rc_file(&mut self, path: PathBuf) {
+        match rc::read(&path) {
+            Ok(lines) => {
+                for (number, line) in lines {
+                    if let Err(e) = self.run_line(&line) {
+                        let context = format!("line {number} of '{}' failed", path.display());
+                        print_error_of_line(&e.context(context), Some(&line));
+                    }
+                }
+            }
+            Err(e) => print_error(&e),
+        }
+        self.rc_file = Some(path);
+    }
+
+    /// Run the repl input `line` like [`Session::run_line`], collecting the
+    /// events of every instance instead of printing them
+    ///
+    /// The events of different instances are not interleaved, each instance's
+    /// follow the ones of the instance before it. With an `output` handler,
+    /// the output of calls is passed to it while they run instead.
+    pub(crate) fn run_line_collecting(
+        &mut self,
+        line: &str,
+        output: Option<events::OutputHandler>,
+    ) -> (anyhow::Result<command::Outcome>, Vec<Event>) {
+        for runtime in self.runtimes() {
+            let runtime = runtime.lock().unwrap();
+            match &output {
+                Some(handler) => runtime.events().collect_streaming(handler.clone()),
+                None => runtime.events().collect(),
+            }
+        }
+        let result = self.run_line(line);
+        let events = self
+            .runtimes()
+            .flat_map(|runtime| runtime.lock().unwrap().events().take())
+            .collect();
+        (result, events)
+    }
+
+    /// The events of the current instance, which builtins the session runs
+    /// itself show what they print with
+    fn events(&self) -> events::Events {
+        self.instance.runtime.lock().unwrap().events().clone()
+    }
+
+    /// The runtimes of the current instance and the other loaded instances
+    fn runtimes(&self) -> impl Iterator<Item = &Arc<Mutex<runtime::Runtime>>> {
+        std::iter::once(&self.instance)
+            .chain(self.others.iter().map(|(_, instance)| instance))
+            .map(|instance| &instance.runtime)
+    }
+
+    /// Load the component at `path` into the instance `name`, or load the
+    /// instance's component again from disk, printing any error
+    ///
+    /// Without a name the current instance is replaced.
+    fn load(&mut self, path: Option<PathBuf>, name: Option<String>) {
+        if let Err(e) = self.try_load(path, name) {
+            print_error(&e.context("could not load the component"));
+        }
+    }
+
+    fn try_load(&mut self, path: Option<PathBuf>, name: Option<String>) -> anyhow::Result<()> {
+        let name = name.unwrap_or_else(|| self.name.clone());
+        let existing = if name == self.name {
+            Some(&self.instance)
+        } else {
+            self.others
+                .iter()
+                .find_map(|(n, instance)| (*n == name).then_some(instance))
+        };
+        let reload = path.is_none();
+        let path = match (path, existing) {
+            (Some(path), _) => path,
+            (None, Some(existing)) => existing.component.clone(),
+            (None, None) => anyhow::bail!("no instance named '{name}'"),
+        };
+        if is_stdin(&path) {
+            anyhow::bail!("components can only be read from stdin on startup")
+        }
+        if !self.host_code && is_precompiled_path(&path) {
+            anyhow::bail!(
+                "precompiled components are native code, which served sessions only load with `--allow-host-code`"
+            )
+        }
+        let modified = watch::modified(&path);
+        let (component_bytes, mut resolver) = read_component(&path, &self.cli, &self.events())?;
+        let mut runtime = init_runtime(
+            component_bytes,
+            is_precompiled_path(&path),
+            &resolver,
+            &self.cli,
+            self.wasi_config.clone(),
+        )?;
+        runtime.set_dry_run(is_wit_only(&path, &self.cli));
+        // What the new instance emits belongs to the line loading it
+        let current = existing.unwrap_or(&self.instance).runtime.lock().unwrap();
+        runtime.events().collect_like(current.events());
+        drop(current);
+        if reload {
+            replay_world_bundle(&mut runtime, &self.cli, &path)?;
+        }
+        let mut replay = Vec::new();
+        // Links need the new instance to be in place so they are applied last
+        let mut links = Vec::new();
+        if let (true, Some(existing)) = (reload, existing) {
+            // The key-value stores and stream queues are kept like the variables
+            let existing_runtime = existing.runtime.lock().unwrap();
+            runtime.set_keyvalue(existing_runtime.keyvalue().clone());
+            runtime.set_streams(existing_runtime.streams().clone());
+            drop(existing_runtime);
+            for line in &existing.replay {
+                let result = command::Cmd::parse(line).and_then(|cmd| match cmd {
+                    Some(cmd) => cmd.run(&mut runtime, &mut resolver, &mut self.scope),
+                    None => Ok(command::Outcome::Done),
+                });
+                match result {
+                    Ok(command::Outcome::Link {
+                        importer,
+                        import,
+                        exporter,
+                    }) => links.push((line.clone(), importer, import, exporter)),
+                    Ok(_) => replay.push(line.clone()),
+                    Err(e) => print_error(&e.context(format!("could not reapply '{line}'"))),
+                }
+            }
+        }
+        let watcher = (!reload && self.cli.watch).then(|| watch::Watcher::new(path.clone()));
+        // Resource handles belong to the old instance
+        let mut stale = Vec::new();
+        if let Some(existing) = existing {
+            stale = self
+                .scope
+                .iter()
+                .filter(|(_, val)| existing.runtime.lock().unwrap().holds_resource(val))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            stale.sort();
+        }
+        for name in &stale {
+            self.scope.remove(name);
+        }
+        let events = runtime.events().clone();
+        if !self.cli.quiet {
+            let verb = if reload { "Reloaded" } else { "Loaded" };
+            let mut loaded = format!("{} {}", format!("{verb}:").green().bold(), path.display());
+            if name != self.name || !self.others.is_empty() {
+                loaded.push_str(&format!(" as {name}"));
+            }
+            events.print(loaded);
+        }
+        if !stale.is_empty() {
+            events.print(format!(
+                "{} {}",
+                "Removed resource handles:".yellow().bold(),
+                stale.join(", ")
+            ));
+        }
+        if !self.cli.quiet {
+            events.print(format!(
+                "{}: {}",
+                "World".blue().bold(),
+                resolver.world_name()
+            ));
+        }
+        let existing = if name == self.name {
+            Some(&mut self.instance)
+        } else {
+            self.others
+                .iter_mut()
+                .find_map(|(n, instance)| (*n == name).then_some(instance))
+        };
+        match existing {
+            Some(existing) => {
+                // Replace the runtime in place so links to the instance follow it
+                *existing.runtime.lock().unwrap() = runtime;
+                if !reload {
+                    existing.watcher = watcher;
+                }
+                existing.component = path;
+                existing.modified = modified;
+                existing.resolver = resolver;
+                existing.replay = replay;
+            }
+            None => {
+                let instance = Instance {
+                    watcher,
+                    component: path,
+                    modified,
+                    runtime: Arc::new(Mutex::new(runtime)),
+                    resolver,
+                    replay,
+                };
+                self.others.push((name, instance));
+            }
+        }
+        for (line, importer, import, exporter) in links {
+            if let Err(e) = self.link(&importer, &import, &exporter, Some(line)) {
+                print_error(&e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Satisfy the import `import` of the instance `importer` with the exports
+    /// of the instance `exporter`
+    ///
+    /// The `line` linking the instances is replayed when the importer is reloaded.
+    fn link(
+        &mut self,
+        importer: &str,
+        import: &str,
+        exporter: &str,
+        line: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.try_link(importer, import, exporter)
+            .context(runtime::LinkError {
+                importer: importer.to_owned(),
+                import: import.to_owned(),
+                exporter: exporter.to_owned(),
+            })?;
+        if let Some(line) = line {
+            self.instance_mut(importer)
+                .expect("linked an instance that is not loaded")
+                .replay
+                .push(line);
+        }
+        Ok(())
+    }
+
+    fn try_link(&self, importer: &str, import: &str, exporter: &str) -> anyhow::Result<()> {
+        if importer == exporter {
+            anyhow::bail!("an instance cannot satisfy its own imports");
+        }
+        let import_ident = command::parser::Ident::parse_str(import)
+            .with_context(|| format!("'{import}' is not a proper identifier"))?;
+        let importer = self
+            .instance(importer)
+            .with_context(|| format!("no instance named '{importer}'"))?;
+        let exporter_instance = self
+            .instance(exporter)
+            .with_context(|| format!("no instance named '{exporter}'"))?;
+        importer.runtime.lock().unwrap().link_instance(
+            &importer.resolver,
+            import_ident,
+            &exporter_instance.resolver,
+            (exporter, exporter_instance.runtime.clone()),
+        )
+    }
+
+    /// The instance named `name`
+    fn instance(&self, name: &str) -> Option<&Instance> {
+        if name == self.name {
+            return Some(&self.instance);
+        }
+        self.others
+            .iter()
+            .find_map(|(n, instance)| (n == name).then_some(instance))
+    }
+
+    fn instance_mut(&mut self, name: &str) -> Option<&mut Instance> {
+        if name == self.name {
+            return Some(&mut self.instance);
+        }
+        self.others
+            .iter_mut()
+            .find_map(|(n, instance)| (n == name).then_some(instance))
+    }
+
+    /// Compile components with `options` from now on, reloading every
+    /// instance with them
+    fn configure(&mut self, options: engine::EngineOptions) {
+        if let Err(e) = runtime::check_engine_options(&options) {
+            print_error(&e.context("invalid configuration"));
+            return;
+        }
+        self.cli.engine = options;
+        let names = std::iter::once(self.name.clone())
+            .chain(self.others.iter().map(|(name, _)| name.clone()))
+            .collect::<Vec<_>>();
+        for name in names {
+            self.load(None, Some(name));
+        }
+    }
+
+    /// Reload the instances whose component changed on disk
+    fn reload_changed(&mut self) {
+        let changed = std::iter::once((&self.name, &self.instance))
+            .chain(self.others.iter().map(|(name, instance)| (name, instance)))
+            .filter(|(_, instance)| instance.watcher.as_ref().is_some_and(|w| w.changed()))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in changed {
+            self.load(None, Some(name));
+        }
+    }
+
+    /// Run commands against the instance `name` from now on
+    fn use_instance(&mut self, name: &str) -> anyhow::Result<()> {
+        if name == self.name {
+            return Ok(());
+        }
+        let Some((other_name, other)) = self.others.iter_mut().find(|(n, _)| n == name) else {
+            anyhow::bail!("no instance named '{name}'");
+        };
+        std::mem::swap(&mut self.name, other_name);
+        std::mem::swap(&mut self.instance, other);
+        self.events().print(format!(
+            "{} {name} ({})",
+            "Using:".green().bold(),
+            self.instance.resolver.world_name()
+        ));
+        Ok(())
+    }
+
+    fn print_instances(&self) {
+        let events = self.events();
+        let current = std::iter::once((&self.name, &self.instance));
+        let others = self.others.iter().map(|(name, instance)| (name, instance));
+        for (name, instance) in current.chain(others) {
+            let marker = if *name == self.name { "*" } else { " " };
+            events.print(format!(
+                "{marker} {}: {} ({})",
+                name.bold(),
+                instance.component.display(),
+                instance.resolver.world_name()
+            ));
+        }
+    }
+}
+
+/// The name of the instance of the component at `path` (e.g. `backend` for
+/// `./backend.wasm` or `my-pkg` for `registry:my-ns:my-pkg@1.2.0`)
+fn instance_name(path: &Path) -> String {
+    if is_stdin(path) {
+        return "stdin".to_owned();
+    }
+    let path = path.to_string_lossy();
+    if let Some(package) = registry::PackageRef::parse(&path) {
+        let (_, name) = package.name.split_once(':').unwrap_or(("", package.name));
+        return name.to_owned();
+    }
+    Path::new(&*path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_owned())
+}
+
+/// Whether errors are printed as JSON objects, as chosen with `--format`
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Print an error along with the errors that caused it
+fn print_error(e: &anyhow::Error) {
+    print_error_of_line(e, None)
+}
+
+/// Print an error, which running the input `line` failed with if there is one
+fn print_error_of_line(e: &anyhow::Error, line: Option<&str>) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        eprintln!("{}", error_to_json(e, line));
+        return;
+    }
+    let backtrace = e.downcast_ref::<wasmtime::WasmBacktrace>();
+    let mut messages = error_messages(e).into_iter();
+    print_error_prefix();
+    eprintln!("{}", messages.next().unwrap_or_default());
+    let causes = messages.collect::<Vec<_>>();
+    if !causes.is_empty() {
+        eprintln!("\nCaused by:");
+    }
+    for cause in causes {
+        eprintln!("  {cause}")
+    }
+    if let Some(backtrace) = backtrace {
+        eprintln!("\nBacktrace:\n{}", runtime::format_backtrace(backtrace));
+    }
+}
+
+/// The message of every error in the chain of `e`
+fn error_messages(e: &anyhow::Error) -> Vec<String> {
+    // The backtrace of a trap is shown on its own after the causes instead
+    // of wasmtime's terser rendering
+    let backtrace = e
+        .downcast_ref::<wasmtime::WasmBacktrace>()
+        .map(ToString::to_string);
+    // Core dumps of traps are attached to errors whether they are written or
+    // not, and their summary of the whole store is too long to show
+    e.chain()
+        .map(ToString::to_string)
+        .filter(|message| Some(message) != backtrace.as_ref())
+        .filter(|message| !message.starts_with("wasm coredump generated while executing"))
+        .collect()
+}
+
+/// Describe the error `e` of running the input `line` as a JSON object
+///
+/// `kind` is `parse` for lines that are not commands, with the `span` of the
+/// line where parsing failed, `type` for values of the wrong type, `trap` for
+/// calls that trapped or whose imports failed, `link` for imports that could
+/// not be linked and `error` for everything else. `import` names the import
+/// that failed or could not be linked.
+fn error_to_json(e: &anyhow::Error, line: Option<&str>) -> serde_json::Value {
+    let (kind, span, import) = classify_error(e);
+    let span = span.map(|span| serde_json::json!({"start": span.start, "end": span.end}));
+    let mut messages = error_messages(e).into_iter();
+    serde_json::json!({
+        "kind": kind,
+        "message": messages.next().unwrap_or_default(),
+        "causes": messages.collect::<Vec<_>>(),
+        "line": line,
+        "span": span,
+        "import": import,
+    })
+}
+
+/// The status `wepl` exits with when a script fails with `e`, which tells
+/// CI what kind of error it was: 2 for parse errors, 3 for type errors, 4 for
+/// traps, 5 for link errors and 1 for everything else
+fn exit_status(e: &anyhow::Error) -> i32 {
+    match classify_error(e).0 {
+        "parse" => 2,
+        "type" => 3,
+        "trap" => 4,
+        "link" => 5,
+        _ => 1,
+    }
+}
+
+/// The kind of the error `e` as described by [`error_to_json`], along with
+/// where parsing failed and the import that failed if it is known
+fn classify_error(e: &anyhow::Error) -> (&'static str, Option<Range<usize>>, Option<String>) {
+    let mut kind = "error";
+    let mut span = None;
+    let mut import = None;
+    // The error of an argument is rendered in its message but not chained
+    let cause = match e.downcast_ref::<evaluator::ArgumentError>() {
+        Some(argument) => &argument.error,
+        None => e,
+    };
+    if let Some(error) = cause.downcast_ref::<command::ParseError>() {
+        kind = "parse";
+        span = Some(error.span.clone());
+    } else if let Some(error) = cause.downcast_ref::<runtime::LinkError>() {
+        kind = "link";
+        import = Some(error.import.clone());
+    } else if cause.downcast_ref::<wasmtime::Trap>().is_some()
+        || cause.downcast_ref::<wasmtime::WasmBacktrace>().is_some()
+        || cause.downcast_ref::<runtime::ImportError>().is_some()
+    {
+        kind = "trap";
+        import = cause
+            .downcast_ref::<runtime::ImportError>()
+            .map(|error| error.import.clone());
+    } else if cause.downcast_ref::<evaluator::TypeError>().is_some() {
+        kind = "type";
+    }
+    (kind, span, import)
+}
+
+fn print_error_prefix() {
+    print_prefix("Error: ", colored::Color::Red)
+}
+
+fn print_prefix(prefix: &str, color: colored::Color) {
+    use std::io::Write;
+    let mut stderr = std::io::stderr();
+    let _ = write!(&mut stderr, "{}", prefix.color(color).bold());
+    let _ = stderr.flush();
+}
+
+/// The WebAssembly Component repl.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to component binary (`-` for stdin), a component precompiled with
+    /// `wepl compile` or a package in a warg registry (e.g.
+    /// `registry:my-ns:my-pkg@1.2.0`)
+    ///
+    /// Without a component only the WIT given with `--wit` is explored.
+    #[arg(required_unless_present = "wit")]
+    component: Option<PathBuf>,
+    /// Environment variable to pass to the component
+    #[arg(long = "env", value_name = "KEY=VAL", value_parser = parse_key_val)]
+    env: Vec<(String, String)>,
+    /// Configuration value the component reads through `wasi:config/store`
+    #[arg(long = "config", value_name = "KEY=VAL", value_parser = parse_key_val)]
+    config: Vec<(String, String)>,
+    /// Path to a file whose contents are fed to the component's stdin
+    #[arg(long, value_name = "PATH")]
+    stdin: Option<std::path::PathBuf>,
+    /// Seed making the randomness the component sees deterministic
+    #[arg(long, value_name = "N")]
+    random_seed: Option<u64>,
+    /// Restore the clocks, randomness, environment and import calls frozen
+    /// into this bundle with `.freeze-world`
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["env", "random_seed"])]
+    world_bundle: Option<PathBuf>,
+    /// Let the component use the network through `wasi:sockets`, limited to
+    /// the given CIDR blocks, addresses or host names if there are any (e.g.
+    /// `--allow-network=10.0.0.0/8,example.com`)
+    #[arg(long, value_name = "NETWORKS", num_args = 0.., value_delimiter = ',', require_equals = true)]
+    allow_network: Option<Vec<String>>,
+    /// Prompt for the return values of imports that are not satisfied
+    #[arg(long)]
+    prompt_imports: bool,
+    /// Reload the component whenever the file changes
+    #[arg(long)]
+    watch: bool,
+    /// Compile the component from scratch instead of using cached compilations
+    #[arg(long)]
+    no_cache: bool,
+    /// The WASI preview1 adapter for core modules (by default `command` if the
+    /// module exports `_start` and `reactor` otherwise)
+    #[arg(long, value_enum)]
+    adapter: Option<componentize::Adapter>,
+    /// The warg registry to fetch packages from instead of the configured default
+    #[arg(long, value_name = "URL")]
+    registry: Option<String>,
+    /// Read the component's types from the WIT file or directory at this path
+    /// instead of from the component
+    #[arg(long, value_name = "PATH")]
+    wit: Option<PathBuf>,
+    /// The world of the WIT the component implements, when there are several
+    #[arg(long, value_name = "NAME")]
+    world: Option<String>,
+    #[command(flatten)]
+    engine: engine::EngineOptions,
+    /// How errors are printed: as text, or as one JSON object per error for
+    /// editors and CI
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Text)]
+    format: ErrorFormat,
+    /// Only print what commands print, leaving out banners and messages like
+    /// the world of loaded components
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log how imports are linked and stubbed and how long loading and
+    /// instantiating components takes (`-vv` logs more)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// How wepl's logs are written to stderr: as lines, as one JSON object
+    /// per event, or not at all
+    #[arg(long, value_enum, default_value_t = diagnostics::LogFormat::Pretty)]
+    log: diagnostics::LogFormat,
+    /// Print everything without colors, which are otherwise only used when
+    /// stdout and stderr are terminals and `NO_COLOR` is not set
+    #[arg(long)]
+    no_color: bool,
+    /// Run as a Jupyter kernel started with this connection file instead of
+    /// reading commands from the terminal
+    #[arg(long, value_name = "CONNECTION_FILE")]
+    jupyter_kernel: Option<PathBuf>,
+    /// Run in a full-screen terminal UI with panes for the exports, the
+    /// variables and the guest's output
+    #[arg(long, conflicts_with = "jupyter_kernel")]
+    tui: bool,
+    /// Deny the component and its stubs the host's environment, directories,
+    /// network and stdin, and disable the commands that run code on the host
+    /// outside of the component: shell commands, `.stub-proc` and `.plugin`,
+    /// as well as loading precompiled components
+    #[arg(long, conflicts_with_all = ["env", "allow_network"])]
+    sandbox: bool,
+    /// Print each command before running it, which `wepl test` uses to tell
+    /// the output of the commands apart
+    #[arg(long, hide = true)]
+    echo_commands: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Precompile a component so that loading it skips compilation
+    Compile {
+        /// Path to the component binary
+        component: PathBuf,
+        /// Where to write the precompiled component (e.g. `component.cwasm`)
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Run every `.wepl` script in a directory against a component, comparing
+    /// the output of each command with the script's `.expected` file
+    Test {
+        /// Path to the component binary
+        component: PathBuf,
+        /// The directory containing the scripts
+        dir: PathBuf,
+        /// Write the output of the scripts to their `.expected` files instead
+        #[arg(long)]
+        bless: bool,
+    },
+    /// Compare the worlds of two components, printing the imports, exports
+    /// and types that were added, removed or changed and how compatible the
+    /// new component is with the old one
+    Diff {
+        /// Path to the old component binary
+        old: PathBuf,
+        /// Path to the new component binary
+        new: PathBuf,
+    },
+    /// Serve a persistent repl session that editors and other tools drive
+    /// with one request per line (`evaluate $line`, `complete $input` or
+    /// `inspect $path`), each answered with a line of JSON
+    #[command(group(clap::ArgGroup::new("address").required(true)))]
+    ServeRepl {
+        /// Path to the component binary
+        component: PathBuf,
+        /// Listen on the Unix socket at this path
+        #[arg(long, value_name = "PATH", group = "address")]
+        socket: Option<PathBuf>,
+        /// Listen on this TCP address on the loopback interface (e.g.
+        /// `127.0.0.1:7000`)
+        #[arg(long, value_name = "ADDR", group = "address")]
+        tcp: Option<std::net::SocketAddr>,
+        /// Let the tools connecting run code on the host with shell commands,
+        /// `.stub-proc` and `.plugin`, and load precompiled components
+        #[arg(long)]
+        allow_host_code: bool,
+    },
+}
+
+/// Parse a `KEY=VAL` pair
+fn parse_key_val(s: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .with_context(|| format!("expected KEY=VAL but found '{s}'"))?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A component that adds numbers, returns what its imported `get` returns,
+    /// makes `thing` resources and prints `tick` before spinning for a while
+    const COMPONENT: &str = r#"(component
+        (import "wasi:io/error@0.2.0" (instance $error
+            (export "error" (type (sub resource)))
+        ))
+        (alias export $error "error" (type $error))
+        (import "wasi:io/streams@0.2.0" (instance $streams
+            (alias outer 1 $error (type $e))
+            (export "error" (type $error (eq $e)))
+            (export "output-stream" (type $output-stream (sub resource)))
+            (type $stream-error (variant
+                (case "last-operation-failed" (own $error))
+                (case "closed")
+            ))
+            (export "stream-error" (type $stream-error' (eq $stream-error)))
+            (export "[method]output-stream.blocking-write-and-flush" (func
+                (param "self" (borrow $output-stream))
+                (param "contents" (list u8))
+                (result (result (error $stream-error')))
+            ))
+        ))
+        (alias export $streams "output-stream" (type $output-stream))
+        (import "wasi:cli/stdout@0.2.0" (instance $stdout
+            (alias outer 1 $output-stream (type $t))
+            (export "output-stream" (type $output-stream (eq $t)))
+            (export "get-stdout" (func (result (own $output-stream))))
+        ))
+        (import "my:test/store" (instance $store
+            (export "get" (func (result u32)))
+        ))
+        (core module $memory (memory (export "memory") 1))
+        (core instance $memory (instantiate $memory))
+        (alias core export $memory "memory" (core memory $memory))
+        (core func $get-stdout (canon lower (func $stdout "get-stdout")))
+        (core func $write (canon lower
+            (func $streams "[method]output-stream.blocking-write-and-flush")
+            (memory $memory)
+        ))
+        (core func $get (canon lower (func $store "get")))
+        (type $thing (resource (rep i32)))
+        (core func $new-thing (canon resource.new $thing))
+        (core module $m
+            (import "host" "memory" (memory 1))
+            (import "host" "get-stdout" (func $get-stdout (result i32)))
+            (import "host" "write" (func $write (param i32 i32 i32 i32)))
+            (import "host" "get" (func $get (result i32)))
+            (import "host" "new-thing" (func $new-thing (param i32) (result i32)))
+            (data (i32.const 16) "tick\n")
+            (func (export "add") (param i32 i32) (result i32)
+                (i32.add (local.get 0) (local.get 1)))
+            (func (export "get") (result i32) (call $get))
+            (func (export "make") (result i32) (call $new-thing (i32.const 0)))
+            (func (export "tick") (param $spins i32)
+                (call $write (call $get-stdout) (i32.const 16) (i32.const 5) (i32.const 32))
+                (loop $spin
+                    (local.set $spins (i32.sub (local.get $spins) (i32.const 1)))
+                    (br_if $spin (i32.gt_s (local.get $spins) (i32.const 0)))))
+        )
+        (core instance $host
+            (export "memory" (memory $memory))
+            (export "get-stdout" (func $get-stdout))
+            (export "write" (func $write))
+            (export "get" (func $get))
+            (export "new-thing" (func $new-thing))
+        )
+        (core instance $m (instantiate $m (with "host" (instance $host))))
+        (func (export "add") (param "a" u32) (param "b" u32) (result u32)
+            (canon lift (core func $m "add")))
+        (func (export "get") (result u32) (canon lift (core func $m "get")))
+        (func $make (result (own $thing)) (canon lift (core func $m "make")))
+        (instance $things
+            (export $thing' "thing" (type $thing))
+            (export "make" (func $make) (func (result (own $thing'))))
+        )
+        (export "my:test/things" (instance $things))
+        (func (export "tick") (param "spins" u32) (canon lift (core func $m "tick")))
+    )"#;
+
+    /// A file in the temporary directory for the test `name`
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wepl-session-{}-{name}", std::process::id()))
+    }
+
+    /// A file that is removed when this is dropped
+    pub(crate) struct TempFile(PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// A session of [`COMPONENT`] written to a file for the test `name`,
+    /// which stays until the file is dropped since reloading reads it again
+    pub(crate) fn session(name: &str) -> (Session, TempFile) {
+        let file = TempFile(temp_file(&format!("{name}.wasm")));
+        std::fs::write(&file.0, wat::parse_str(COMPONENT).unwrap()).unwrap();
+        let session = Session::with_args(["-q".into(), file.0.clone()]).unwrap();
+        (session, file)
+    }
+
+    /// The text of the output events among `events`
+    fn output(events: &[Event]) -> String {
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::Output { .. }))
+            .map(Event::render)
+            .collect()
+    }
+
+    #[test]
+    fn evaluating_calls() {
+        let (mut session, _file) = session("calls");
+        let result = session.eval("add(1, 2)");
+        assert!(result.error.is_none());
+        assert_eq!(result.values, [Val::U32(3)]);
+        assert!(matches!(result.events[..], [Event::Results { .. }]));
+        assert_eq!(result.output, "");
+    }
+
+    #[test]
+    fn evaluating_builtins() {
+        let (mut session, _file) = session("builtins");
+        let result = session.eval(".exports");
+        assert!(result.error.is_none());
+        assert!(result.values.is_empty());
+        assert!(result.output.contains("add: func(a: u32, b: u32) -> u32"));
+        assert!(result
+            .events
+            .iter()
+            .all(|event| matches!(event, Event::Message(_))));
+    }
+
+    #[test]
+    fn evaluating_errors() {
+        let (mut session, _file) = session("errors");
+        let result = session.eval("add(1)");
+        assert!(result.error.is_some());
+        assert!(result.values.is_empty());
+        assert!(session.eval("nope()").error.is_some());
+        assert!(session.eval("add(1, ").error.is_some());
+    }
+
+    #[test]
+    fn output_is_among_the_events() {
+        let (mut session, _file) = session("output");
+        let result = session.eval("tick(1)");
+        assert!(result.error.is_none());
+        assert_eq!(output(&result.events), "tick\n");
+    }
+
+    #[test]
+    fn output_is_passed_on_while_calls_run() {
+        let (mut session, _file) = session("on-output");
+        let passed = Arc::new(Mutex::new(Vec::new()));
+        session.on_output({
+            let passed = passed.clone();
+            move |events| passed.lock().unwrap().extend(events)
+        });
+        // Spinning for long enough to be interrupted at least once
+        let result = session.eval("tick(1000000000)");
+        assert!(result.error.is_none());
+        assert_eq!(output(&passed.lock().unwrap()), "tick\n");
+        assert_eq!(output(&result.events), "");
+    }
+
+    #[test]
+    fn variables() {
+        let (mut session, _file) = session("variables");
+        let result = session.eval("x = add(1, 2)");
+        assert!(result.error.is_none());
+        assert!(result.values.is_empty());
+        assert_eq!(session.var("x"), Some(&Val::U32(3)));
+        assert_eq!(session.set_var("y", Val::U32(4)), None);
+        assert_eq!(session.eval("add(x, y)").values, [Val::U32(7)]);
+        let mut vars = session.vars().map(|(name, _)| name).collect::<Vec<_>>();
+        vars.sort();
+        assert_eq!(vars, ["x", "y"]);
+        assert_eq!(session.remove_var("x"), Some(Val::U32(3)));
+        assert_eq!(session.var("x"), None);
+        assert!(session.eval("add(x, y)").error.is_some());
+    }
+
+    #[test]
+    fn stubbing_with_values() {
+        let (mut session, _file) = session("stub-value");
+        session
+            .stub_value("my:test/store#get", Val::U32(42))
+            .unwrap();
+        assert_eq!(session.eval("get()").values, [Val::U32(42)]);
+        assert!(session
+            .stub_value("my:test/store#get", Val::String("no".into()))
+            .is_err());
+        assert!(session
+            .stub_value("my:test/store#nope", Val::U32(1))
+            .is_err());
+    }
+
+    #[test]
+    fn stubbing_with_wat() {
+        let (mut session, _file) = session("stub-wat");
+        let path = temp_file("store.wat");
+        std::fs::write(
+            &path,
+            r#"(component
+                (core module $m (func (export "get") (result i32) (i32.const 7)))
+                (core instance $m (instantiate $m))
+                (func $get (result u32) (canon lift (core func $m "get")))
+                (instance $store (export "get" (func $get)))
+                (export "my:test/store" (instance $store))
+            )"#,
+        )
+        .unwrap();
+        let stubbed = session.stub_wat("my:test/store", &path);
+        std::fs::remove_file(&path).unwrap();
+        stubbed.unwrap();
+        assert_eq!(session.eval("get()").values, [Val::U32(7)]);
+    }
+
+    #[test]
+    fn mocking() {
+        let (mut session, _file) = session("mock");
+        assert_eq!(session.mock("my:test/store").unwrap(), 1);
+        let result = session.eval("get()");
+        assert!(result.error.is_none());
+        assert_eq!(result.values, [Val::U32(0)]);
+    }
+
+    #[test]
+    fn unstubbing() {
+        let (mut session, _file) = session("unstub");
+        session
+            .stub_value("my:test/store#get", Val::U32(42))
+            .unwrap();
+        session.unstub("my:test/store#get").unwrap();
+        let result = session.eval("get()");
+        assert_ne!(result.values, [Val::U32(42)]);
+        assert!(result
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::Warning(_))));
+
+        session
+            .stub_value("my:test/store#get", Val::U32(42))
+            .unwrap();
+        session.unstub_all().unwrap();
+        assert_ne!(session.eval("get()").values, [Val::U32(42)]);
+    }
+
+    #[test]
+    fn watched_components_are_reloaded() {
+        let file = TempFile(temp_file("watch.wasm"));
+        std::fs::write(&file.0, wat::parse_str(COMPONENT).unwrap()).unwrap();
+        let args = ["-q".into(), "--watch".into(), file.0.clone()];
+        let mut session = Session::with_args(args).unwrap();
+        assert!(session
+            .eval(".stub my:test/store#get => 42")
+            .error
+            .is_none());
+        assert!(session.eval("x = add(1, 2)").error.is_none());
+        assert!(session
+            .eval("thing = my:test/things#make()")
+            .error
+            .is_none());
+
+        // Rewriting the file is noticed the next time it is polled
+        std::fs::write(&file.0, wat::parse_str(COMPONENT).unwrap()).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        let rewritten = std::fs::File::options().write(true).open(&file.0).unwrap();
+        rewritten.set_modified(later).unwrap();
+        std::thread::sleep(watch::POLL_INTERVAL * 3);
+
+        let result = session.eval("get()");
+        assert!(result.error.is_none());
+        assert_eq!(result.values, [Val::U32(42)]);
+        assert_eq!(session.var("x"), Some(&Val::U32(3)));
+        assert_eq!(session.var("thing"), None);
+        let messages = result
+            .events
+            .iter()
+            .filter(|event| matches!(event, Event::Message(_)))
+            .map(Event::render)
+            .collect::<String>();
+        assert!(
+            messages.contains("Removed resource handles:") && messages.contains("thing"),
+            "{messages}"
+        );
+    }
+
+    #[test]
+    fn snapshots() {
+        let (mut session, _file) = session("snapshots");
+        assert!(session.eval(".snapshot start").error.is_none());
+        assert!(session
+            .eval("thing = my:test/things#make()")
+            .error
+            .is_none());
+        // Restoring what was not saved leaves the variables alone
+        assert!(session.eval(".restore nothing").error.is_some());
+        assert!(session.var("thing").is_some());
+        let error = session.eval(".snapshot later").error.unwrap();
+        assert!(
+            format!("{error:#}").contains("resource handles"),
+            "{error:#}"
+        );
+        let result = session.eval(".restore start");
+        assert!(result.error.is_none());
+        assert!(
+            result.output.contains("Removed resource handles: thing"),
+            "{}",
+            result.output
+        );
+        assert_eq!(session.var("thing"), None);
+        // The new instance has not been given resource handles yet
+        assert!(session.eval(".snapshot later").error.is_none());
+    }
+
+    #[test]
+    fn sessions_move_between_threads() {
+        let (session, _file) = session("threads");
+        let mut session = std::thread::spawn(move || session).join().unwrap();
+        assert_eq!(session.eval("add(1, 2)").values, [Val::U32(3)]);
+    }
+
+    #[test]
+    fn sessions_need_a_component() {
+        assert!(Session::new(temp_file("missing.wasm")).is_err());
+        assert!(Session::with_args(["--nope"]).is_err());
+    }
+}
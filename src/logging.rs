@@ -0,0 +1,148 @@
+use std::sync::{Arc, Mutex};
+
+use colored::{ColoredString, Colorize};
+use wasmtime::component::Val;
+
+use crate::events::{Event, Events};
+
+/// The levels of `wasi:logging`, from the least to the most severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Critical,
+}
+
+impl Level {
+    /// The level named `name` in `wasi:logging`
+    pub fn parse(name: &str) -> Option<Self> {
+        <Self as clap::ValueEnum>::from_str(name, false).ok()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Critical => "critical",
+        }
+    }
+
+    fn colored(self) -> ColoredString {
+        let label = format!("{:<8}", self.name().to_uppercase());
+        match self {
+            Level::Trace => label.dimmed(),
+            Level::Debug => label.blue(),
+            Level::Info => label.green(),
+            Level::Warn => label.yellow(),
+            Level::Error => label.red(),
+            Level::Critical => label.red().bold().reversed(),
+        }
+    }
+}
+
+/// A record the guest logged through `wasi:logging/logging#log`
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub level: Level,
+    pub context: String,
+    pub message: String,
+}
+
+impl LogRecord {
+    /// The record as a value with the fields `level`, `context` and `message`
+    pub fn to_val(&self) -> Val {
+        Val::Record(vec![
+            ("level".into(), Val::Enum(self.level.name().into())),
+            ("context".into(), Val::String(self.context.clone())),
+            ("message".into(), Val::String(self.message.clone())),
+        ])
+    }
+
+    /// The record as the repl prints it, with a colored level and its context
+    pub fn render(&self) -> String {
+        let context = if self.context.is_empty() {
+            String::new()
+        } else {
+            format!("{} ", format!("{}:", self.context).bold())
+        };
+        format!("{} {context}{}", self.level.colored(), self.message)
+    }
+}
+
+/// Receives the records logged by the guest, emitting those at or above the
+/// configured level and keeping all of them while they are being captured
+#[derive(Clone)]
+pub struct GuestLogger {
+    events: Events,
+    /// The least severe level printed, if any is
+    level: Arc<Mutex<Option<Level>>>,
+    /// The records logged while capturing
+    captured: Arc<Mutex<Vec<LogRecord>>>,
+}
+
+impl GuestLogger {
+    /// A logger emitting the records it shows to `events`
+    pub fn new(events: Events) -> Self {
+        Self {
+            events,
+            level: Arc::new(Mutex::new(Some(Level::Trace))),
+            captured: Default::default(),
+        }
+    }
+
+    pub fn log(&self, record: LogRecord, capture: bool) {
+        if capture {
+            self.captured.lock().unwrap().push(record.clone());
+        }
+        if self.level().is_some_and(|level| record.level >= level) {
+            self.events.emit(Event::Log(record));
+        }
+    }
+
+    /// The least severe level printed, if any is
+    pub fn level(&self) -> Option<Level> {
+        *self.level.lock().unwrap()
+    }
+
+    pub fn set_level(&self, level: Option<Level>) {
+        *self.level.lock().unwrap() = level;
+    }
+
+    /// Take the records captured since the last call to this function
+    pub fn take_captured(&self) -> Vec<LogRecord> {
+        std::mem::take(&mut *self.captured.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_are_emitted_as_events() {
+        let events = Events::default();
+        events.collect();
+        let logger = GuestLogger::new(events.clone());
+        logger.set_level(Some(Level::Info));
+        let record = |level| LogRecord {
+            level,
+            context: "db".to_owned(),
+            message: "connected".to_owned(),
+        };
+        logger.log(record(Level::Debug), true);
+        logger.log(record(Level::Warn), false);
+        let events = events.take();
+        let [Event::Log(logged)] = events.as_slice() else {
+            panic!("expected one log event, got {events:?}")
+        };
+        assert_eq!(logged.level, Level::Warn);
+        assert_eq!(events[0].to_json()["level"], "warn");
+        assert_eq!(logger.take_captured().len(), 1);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr, ToSocketAddrs as _},
+};
+
+use anyhow::Context as _;
+
+/// The addresses the guest may use through `wasi:sockets`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetworkPolicy {
+    /// The allowed networks, where no networks allow every address
+    networks: Vec<Network>,
+}
+
+impl NetworkPolicy {
+    /// Parse the networks, which are CIDR blocks (e.g. `10.0.0.0/8`), single
+    /// addresses or host names
+    ///
+    /// Host names are resolved once, allowing the addresses they resolve to.
+    pub fn parse(networks: &[String]) -> anyhow::Result<Self> {
+        let mut policy = Self::default();
+        for network in networks.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            match Network::parse(network) {
+                Some(network) => policy.networks.push(network),
+                None => {
+                    let addrs = (network, 0)
+                        .to_socket_addrs()
+                        .with_context(|| format!("could not resolve '{network}'"))?;
+                    policy
+                        .networks
+                        .extend(addrs.map(|addr| Network::host(addr.ip())));
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Whether the guest may use `addr`
+    pub fn allows(&self, addr: &SocketAddr) -> bool {
+        self.networks.is_empty() || self.networks.iter().any(|n| n.contains(addr.ip()))
+    }
+}
+
+impl fmt::Display for NetworkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.networks.is_empty() {
+            return f.write_str("any address");
+        }
+        let networks = self
+            .networks
+            .iter()
+            .map(|n| format!("{}/{}", n.addr, n.prefix))
+            .collect::<Vec<_>>();
+        f.write_str(&networks.join(", "))
+    }
+}
+
+/// A block of addresses in CIDR notation
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// The network of just `addr`
+    fn host(addr: IpAddr) -> Self {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix }
+    }
+
+    /// Parse `addr/prefix` or a single address
+    fn parse(s: &str) -> Option<Self> {
+        let Some((addr, prefix)) = s.split_once('/') else {
+            return s.parse().ok().map(Self::host);
+        };
+        let addr: IpAddr = addr.parse().ok()?;
+        let prefix: u8 = prefix.parse().ok()?;
+        (prefix <= Self::host(addr).prefix).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, addr: IpAddr) -> bool {
+        // Addresses like `::ffff:127.0.0.1` are the IPv4 addresses they map
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            addr => addr,
+        };
+        let (network, addr, bits) = match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                (u32::from(network).into(), u32::from(addr).into(), 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => (u128::from(network), u128::from(addr), 128),
+            _ => return false,
+        };
+        let mask = match self.prefix {
+            0 => 0,
+            prefix => u128::MAX << (bits - u32::from(prefix)),
+        };
+        network & mask == addr & mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn policy() -> NetworkPolicy {
+        NetworkPolicy::parse(&[
+            "10.0.0.0/8".to_owned(),
+            "192.168.1.7".to_owned(),
+            "fd00::/8".to_owned(),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn allowed_addresses() {
+        let policy = policy();
+        assert!(policy.allows(&addr("10.1.2.3:80")));
+        assert!(policy.allows(&addr("192.168.1.7:443")));
+        assert!(policy.allows(&addr("[::ffff:10.0.0.1]:80")));
+        assert!(policy.allows(&addr("[fd12::1]:80")));
+    }
+
+    #[test]
+    fn denied_addresses() {
+        let policy = policy();
+        assert!(!policy.allows(&addr("11.0.0.1:80")));
+        assert!(!policy.allows(&addr("192.168.1.8:443")));
+        assert!(!policy.allows(&addr("[fe80::1]:80")));
+    }
+
+    #[test]
+    fn no_policy() {
+        assert!(NetworkPolicy::default().allows(&addr("1.1.1.1:53")));
+    }
+
+    #[test]
+    fn networks() {
+        assert_eq!(Network::parse("10.0.0.0/33"), None);
+        assert_eq!(
+            Network::parse("0.0.0.0/0").map(|n| n.contains("8.8.8.8".parse().unwrap())),
+            Some(true)
+        );
+    }
+}
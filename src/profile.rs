@@ -0,0 +1,143 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use wasmtime::{Engine, WasmBacktrace};
+
+/// How often the stack of the component is sampled while profiling
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A sampling profile of calls into the component
+///
+/// Wasmtime's `GuestProfiler` can only name the functions of core modules it
+/// is given, which components do not expose, so the stack is sampled with a
+/// backtrace of the store instead.
+pub struct Profile {
+    /// The names of the functions seen in samples
+    frames: Vec<String>,
+    frame_ids: HashMap<String, usize>,
+    /// The stacks sampled, outermost frame first
+    samples: Vec<Vec<usize>>,
+    /// The time spent in each sampled stack
+    weights: Vec<Duration>,
+    last_sample: Instant,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            frame_ids: HashMap::new(),
+            samples: Vec::new(),
+            weights: Vec::new(),
+            last_sample: Instant::now(),
+        }
+    }
+
+    /// Add the stack in `backtrace` as a sample
+    pub fn sample(&mut self, backtrace: &WasmBacktrace) {
+        let now = Instant::now();
+        let weight = now - std::mem::replace(&mut self.last_sample, now);
+        let stack = backtrace
+            .frames()
+            .iter()
+            .rev()
+            .map(|frame| {
+                let name = match (frame.module().name(), frame.func_name()) {
+                    (Some(module), Some(func)) => format!("{module}::{func}"),
+                    (None, Some(func)) => func.to_owned(),
+                    (Some(module), None) => {
+                        format!("{module}::<wasm function {}>", frame.func_index())
+                    }
+                    (None, None) => format!("<wasm function {}>", frame.func_index()),
+                };
+                *self.frame_ids.entry(name).or_insert_with_key(|name| {
+                    self.frames.push(name.clone());
+                    self.frames.len() - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        if stack.is_empty() {
+            return;
+        }
+        self.samples.push(stack);
+        self.weights.push(weight);
+    }
+
+    /// The number of samples taken
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Write the profile named `name` in the JSON format of speedscope
+    /// (https://www.speedscope.app)
+    pub fn write(&self, name: &str, output: impl std::io::Write) -> anyhow::Result<()> {
+        let weights = self
+            .weights
+            .iter()
+            .map(|weight| weight.as_nanos() as u64)
+            .collect::<Vec<_>>();
+        let profile = serde_json::json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "exporter": concat!("wepl@", env!("CARGO_PKG_VERSION")),
+            "name": name,
+            "shared": {
+                "frames": self.frames.iter().map(|name| serde_json::json!({ "name": name })).collect::<Vec<_>>(),
+            },
+            "profiles": [{
+                "type": "sampled",
+                "name": name,
+                "unit": "nanoseconds",
+                "startValue": 0,
+                "endValue": weights.iter().sum::<u64>(),
+                "samples": self.samples,
+                "weights": weights,
+            }],
+        });
+        serde_json::to_writer(output, &profile)?;
+        Ok(())
+    }
+}
+
+/// A thread advancing the epoch of an engine at a fixed interval, which makes
+/// stores sample their stack and pass on collected output, until it is
+/// dropped
+pub struct Ticker {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Ticker {
+    pub fn start(engine: &Engine, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let engine = engine.clone();
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::SeqCst) {
+                    std::thread::sleep(interval);
+                    engine.increment_epoch();
+                }
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
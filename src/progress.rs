@@ -0,0 +1,131 @@
+use std::{
+    io::{IsTerminal as _, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use colored::Colorize;
+
+/// Whether spinners are shown, which they only are when stderr is a terminal
+/// that nothing else reads output from
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The spinner being shown, if one is
+static ACTIVE: Mutex<Option<Arc<Shared>>> = Mutex::new(None);
+
+/// How long an operation runs before its spinner is shown, so that quick
+/// operations do not make the terminal flicker
+const DELAY: Duration = Duration::from_millis(300);
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+const FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Show spinners for long operations from now on if stderr is a terminal
+pub fn enable() {
+    ENABLED.store(std::io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+/// Erase the spinner being shown, if any, and stop showing it because
+/// something else is about to be printed
+pub fn interrupt() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(shared) = ACTIVE.lock().unwrap().as_ref() {
+        shared.stop();
+    }
+}
+
+/// A spinner on stderr labeled with what is happening, shown until it is
+/// dropped
+pub struct Spinner {
+    inner: Option<(Arc<Shared>, JoinHandle<()>)>,
+}
+
+struct Shared {
+    label: String,
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    stopped: bool,
+    drawn: bool,
+}
+
+impl Spinner {
+    /// Show a spinner labeled `label` (e.g. `Compiling the component`) once
+    /// the operation it is for has run for a moment
+    ///
+    /// Nothing is shown when spinners are disabled or while another spinner
+    /// is shown, like the one of an operation this one is part of.
+    pub fn start(label: impl Into<String>) -> Self {
+        let mut active = ACTIVE.lock().unwrap();
+        if !ENABLED.load(Ordering::Relaxed) || active.is_some() {
+            return Self { inner: None };
+        }
+        let shared = Arc::new(Shared {
+            label: label.into(),
+            state: Mutex::default(),
+            wake: Condvar::new(),
+        });
+        *active = Some(shared.clone());
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || shared.spin())
+        };
+        Self {
+            inner: Some((shared, thread)),
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some((shared, thread)) = self.inner.take() {
+            ACTIVE.lock().unwrap().take();
+            shared.stop();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn spin(&self) {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .wake
+            .wait_timeout_while(state, DELAY, |state| !state.stopped)
+            .unwrap();
+        for frame in FRAMES.iter().cycle() {
+            if state.stopped {
+                return;
+            }
+            eprint!("\r{} {}", frame.cyan(), self.label);
+            let _ = std::io::stderr().flush();
+            state.drawn = true;
+            state = self
+                .wake
+                .wait_timeout_while(state, FRAME_INTERVAL, |state| !state.stopped)
+                .unwrap()
+                .0;
+        }
+    }
+
+    fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.drawn {
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+            state.drawn = false;
+        }
+        state.stopped = true;
+        self.wake.notify_all();
+    }
+}
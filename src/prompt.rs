@@ -0,0 +1,85 @@
+/// The placeholders prompt templates can use, which are replaced by the
+/// instance's name, the file name of its component, the name of its world and
+/// `*` when the component changed on disk since it was loaded
+pub const PLACEHOLDERS: &[&str] = &["instance", "component", "world", "dirty"];
+
+/// Check that the prompt template `template` only uses known placeholders
+pub fn check(template: &str) -> anyhow::Result<()> {
+    for placeholder in placeholders(template) {
+        if !PLACEHOLDERS.contains(&placeholder) {
+            anyhow::bail!(
+                "unknown placeholder '{{{placeholder}}}' in the prompt, expected one of {}",
+                PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("{{{p}}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The prompt template `template` with its placeholders replaced by what
+/// `value` gives for their names
+pub fn render(template: &str, value: impl Fn(&str) -> String) -> String {
+    let mut prompt = String::new();
+    let mut rest = template;
+    while let Some((placeholder, start, end)) = next_placeholder(rest) {
+        prompt.push_str(&rest[..start]);
+        prompt.push_str(&value(placeholder));
+        rest = &rest[end..];
+    }
+    prompt.push_str(rest);
+    prompt
+}
+
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        let (placeholder, _, end) = next_placeholder(rest)?;
+        rest = &rest[end..];
+        Some(placeholder)
+    })
+}
+
+/// The name of the first placeholder in `template` along with where it starts
+/// and ends
+fn next_placeholder(template: &str) -> Option<(&str, usize, usize)> {
+    let start = template.find('{')?;
+    let len = template[start..].find('}')?;
+    Some((&template[start + 1..start + len], start, start + len + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(name: &str) -> String {
+        match name {
+            "component" => "foo.wasm".to_owned(),
+            "world" => "my:pkg/app".to_owned(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            render("{component}:{world}{dirty}> ", value),
+            "foo.wasm:my:pkg/app> "
+        );
+        assert_eq!(render("> ", value), "> ");
+    }
+
+    #[test]
+    fn unclosed_braces() {
+        assert_eq!(render("{ > ", value), "{ > ");
+    }
+
+    #[test]
+    fn unknown_placeholders() {
+        assert!(check("{instance}{dirty}> ").is_ok());
+        assert!(check("{file}> ").is_err());
+    }
+}
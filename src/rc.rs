@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+/// The file of repl lines run when an interactive session starts
+pub fn path() -> Option<PathBuf> {
+    home::home_dir().map(|home| home.join(".weplrc"))
+}
+
+/// The lines of the rc file at `path` to run, skipping blank lines and
+/// comments starting with `#`, along with their line numbers
+pub fn read(path: &Path) -> anyhow::Result<Vec<(usize, String)>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("could not read '{}'", path.display())),
+    };
+    Ok(contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| (number, line.to_owned()))
+        .collect())
+}
+
+/// Define the alias `name` as `command` in the rc file at `path`, replacing
+/// its previous definition, or remove its definition without a command
+pub fn save_alias(path: &Path, name: &str, command: Option<&str>) -> anyhow::Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("could not read '{}'", path.display())),
+    };
+    std::fs::write(path, with_alias(&contents, name, command))
+        .with_context(|| format!("could not write '{}'", path.display()))
+}
+
+/// The rc file `contents` with the alias `name` defined as `command` at its
+/// end, or not defined at all without a command
+fn with_alias(contents: &str, name: &str, command: Option<&str>) -> String {
+    let mut lines = contents
+        .lines()
+        .filter(|line| defined_alias(line) != Some(name))
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    if let Some(command) = command {
+        lines.push_str(&format!(".alias {name} {command}\n"));
+    }
+    lines
+}
+
+/// The name of the alias the rc file line `line` defines, if it defines one
+fn defined_alias(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix(".alias")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    rest.split_whitespace().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "# stubs\n.alias st .stub my:kv/store => 1\n.trace on\n.aliases st\n";
+
+    #[test]
+    fn replaced_aliases() {
+        assert_eq!(
+            with_alias(CONTENTS, "st", Some(".stub my:kv/store => 2")),
+            "# stubs\n.trace on\n.aliases st\n.alias st .stub my:kv/store => 2\n"
+        );
+    }
+
+    #[test]
+    fn removed_aliases() {
+        assert_eq!(
+            with_alias(CONTENTS, "st", None),
+            "# stubs\n.trace on\n.aliases st\n"
+        );
+    }
+
+    #[test]
+    fn new_aliases() {
+        assert_eq!(
+            with_alias("", "t", Some(".trace on")),
+            ".alias t .trace on\n"
+        );
+    }
+}
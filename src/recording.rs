@@ -0,0 +1,215 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use serde_json::Value;
+use wasmtime::component::Val;
+
+use crate::{clock, json};
+
+/// Records the calls the component makes to its imports into a JSON file
+///
+/// The file is written again after every call so that the recording is
+/// complete even if the repl exits while recording.
+pub struct ImportRecorder {
+    path: PathBuf,
+    /// The world the calls are made in, when recording a world bundle
+    world: Option<World>,
+    calls: Vec<Value>,
+}
+
+impl ImportRecorder {
+    /// Start recording into the file at `path`, replacing its contents
+    pub fn start(path: PathBuf) -> anyhow::Result<Self> {
+        Self::start_bundle(path, None)
+    }
+
+    /// Start recording into the file at `path` along with the world the
+    /// calls are made in, if any, so that the file can restore both
+    pub fn start_bundle(path: PathBuf, world: Option<World>) -> anyhow::Result<Self> {
+        let recorder = Self {
+            path,
+            world,
+            calls: Vec::new(),
+        };
+        recorder.write()?;
+        Ok(recorder)
+    }
+
+    /// Record a call of the import `name` that returned `results`
+    pub fn record(&mut self, name: &str, args: &[Val], results: &[Val]) -> anyhow::Result<()> {
+        let to_json = |vals: &[Val]| vals.iter().map(json::val_to_json).collect::<Result<_, _>>();
+        self.calls.push(serde_json::json!({
+            "import": name,
+            "params": Value::Array(to_json(args)?),
+            "results": Value::Array(to_json(results)?),
+        }));
+        self.write()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of calls recorded
+    pub fn call_count(&self) -> usize {
+        self.calls.len()
+    }
+
+    fn write(&self) -> anyhow::Result<()> {
+        let mut recording = serde_json::json!({ "calls": self.calls });
+        if let Some(world) = &self.world {
+            recording["world"] = world.to_json();
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&recording)?)
+            .with_context(|| format!("could not write '{}'", self.path.display()))
+    }
+}
+
+/// What the component sees of the host that changes from one session to the
+/// next, frozen so that a session can be reproduced
+#[derive(Debug, PartialEq)]
+pub struct World {
+    /// The time since the unix epoch the clocks are frozen at
+    pub clock: Duration,
+    pub random_seed: u64,
+    pub env: Vec<(String, String)>,
+}
+
+impl World {
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "clock": clock::format_timestamp(self.clock),
+            "random-seed": self.random_seed,
+            "env": self
+                .env
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect::<serde_json::Map<_, _>>(),
+        })
+    }
+
+    fn from_json(world: &Value) -> anyhow::Result<Self> {
+        let clock = world
+            .get("clock")
+            .and_then(Value::as_str)
+            .context("expected the `clock` as a timestamp")?;
+        let random_seed = world
+            .get("random-seed")
+            .and_then(Value::as_u64)
+            .context("expected the `random-seed` as a number")?;
+        let env = match world.get("env") {
+            Some(Value::Object(env)) => env
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(value) => Ok((key.clone(), value.clone())),
+                    _ => anyhow::bail!("expected the value of `{key}` in `env` to be a string"),
+                })
+                .collect::<anyhow::Result<_>>()?,
+            None => Vec::new(),
+            Some(_) => anyhow::bail!("expected `env` to be an object"),
+        };
+        Ok(Self {
+            clock: clock::parse_timestamp(clock)?,
+            random_seed,
+            env,
+        })
+    }
+}
+
+/// Read the world frozen into the bundle at `path`
+pub fn read_world(path: &Path) -> anyhow::Result<World> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read '{}'", path.display()))?;
+    parse_world(&contents).with_context(|| format!("invalid world bundle '{}'", path.display()))
+}
+
+fn parse_world(contents: &str) -> anyhow::Result<World> {
+    let bundle: Value = serde_json::from_str(contents)?;
+    World::from_json(bundle.get("world").context("expected a `world` object")?)
+}
+
+/// A call recorded by [`ImportRecorder`], with its parameters and results in
+/// the JSON form of [`json::val_to_json`]
+#[derive(Debug, PartialEq)]
+pub struct RecordedCall {
+    pub params: Vec<Value>,
+    pub results: Vec<Value>,
+}
+
+/// Read the calls recorded in the file at `path`, in the order they were made
+/// by the name of the import they were made to
+pub fn read(path: &Path) -> anyhow::Result<BTreeMap<String, VecDeque<RecordedCall>>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read '{}'", path.display()))?;
+    parse(&contents).with_context(|| format!("invalid recording '{}'", path.display()))
+}
+
+fn parse(contents: &str) -> anyhow::Result<BTreeMap<String, VecDeque<RecordedCall>>> {
+    let recording: Value = serde_json::from_str(contents)?;
+    let calls = recording
+        .get("calls")
+        .and_then(Value::as_array)
+        .context("expected an object with a list of `calls`")?;
+    let mut imports = BTreeMap::<_, VecDeque<_>>::new();
+    for call in calls {
+        let list = |field| match call.get(field) {
+            Some(Value::Array(vals)) => Ok(vals.clone()),
+            _ => anyhow::bail!("expected a list of `{field}` in {call}"),
+        };
+        let import = call
+            .get("import")
+            .and_then(Value::as_str)
+            .with_context(|| format!("expected the name of the `import` in {call}"))?;
+        imports
+            .entry(import.to_owned())
+            .or_default()
+            .push_back(RecordedCall {
+                params: list("params")?,
+                results: list("results")?,
+            });
+    }
+    Ok(imports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_recording() {
+        let imports = parse(
+            r#"{"calls": [
+                {"import": "my:pkg/clock#now", "params": [], "results": [1]},
+                {"import": "get", "params": ["a"], "results": [{"tag": "ok", "val": 2}]},
+                {"import": "my:pkg/clock#now", "params": [], "results": [3]}
+            ]}"#,
+        )
+        .unwrap();
+        let now = imports["my:pkg/clock#now"]
+            .iter()
+            .map(|call| call.results[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(now, [1, 3]);
+        assert_eq!(imports["get"][0].params, [Value::from("a")]);
+        assert!(parse(r#"{"calls": [{"import": "get"}]}"#).is_err());
+        assert!(parse("[]").is_err());
+    }
+
+    #[test]
+    fn world_bundles() {
+        let world = World {
+            clock: Duration::from_secs(1_700_000_000),
+            random_seed: 42,
+            env: vec![("HOME".to_owned(), "/home/me".to_owned())],
+        };
+        let bundle = serde_json::json!({ "world": world.to_json(), "calls": [] });
+        assert_eq!(parse_world(&bundle.to_string()).unwrap(), world);
+        assert!(parse(&bundle.to_string()).unwrap().is_empty());
+        assert!(parse_world(r#"{"calls": []}"#).is_err());
+        assert!(parse_world(r#"{"world": {"clock": "2024-01-01T00:00:00Z"}}"#).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use anyhow::Context as _;
+#[cfg(feature = "registry")]
+use colored::Colorize as _;
+
+use crate::events::Events;
+
+/// The prefix of arguments that are packages in a warg registry rather than
+/// files
+const PREFIX: &str = "registry:";
+
+/// A reference to a component package in a warg registry (e.g.
+/// `registry:my-ns:my-pkg@1.2.0`)
+#[derive(Debug, PartialEq)]
+pub struct PackageRef<'a> {
+    /// The name of the package in the form `namespace:package`
+    pub name: &'a str,
+    /// The version, if one was given
+    pub version: Option<&'a str>,
+}
+
+impl<'a> PackageRef<'a> {
+    /// Parse a package reference of the form
+    /// `registry:namespace:package[@version]`
+    pub fn parse(s: &'a str) -> Option<Self> {
+        let s = s.strip_prefix(PREFIX)?;
+        let (name, version) = match s.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (s, None),
+        };
+        let (namespace, package) = name.split_once(':')?;
+        let is_id = |s: &str| {
+            !s.is_empty()
+                && s.split('-').all(|part| {
+                    !part.is_empty()
+                        && part
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                })
+        };
+        if !is_id(namespace) || !is_id(package) || version.is_some_and(str::is_empty) {
+            return None;
+        }
+        Some(Self { name, version })
+    }
+}
+
+impl std::fmt::Display for PackageRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)?;
+        if let Some(version) = self.version {
+            write!(f, "@{version}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a component given on the command line comes from
+#[derive(Debug, PartialEq)]
+enum Source<'a> {
+    File(&'a Path),
+    Package(PackageRef<'a>),
+}
+
+impl<'a> Source<'a> {
+    /// Only arguments starting with `registry:` are packages, so a mistyped
+    /// file name is never looked up in a registry
+    fn of(component: &'a Path) -> Self {
+        match component.to_str().and_then(PackageRef::parse) {
+            Some(package) => Self::Package(package),
+            None => Self::File(component),
+        }
+    }
+}
+
+/// Read the component at `component`, which is either a path or a reference
+/// to a package in a warg registry
+///
+/// Packages are fetched from `registry` or the default registry of the warg
+/// configuration, and shown as fetched on `events`.
+pub fn read_component(
+    component: &Path,
+    registry: Option<&str>,
+    events: &Events,
+) -> anyhow::Result<Vec<u8>> {
+    match Source::of(component) {
+        Source::Package(package) => fetch(&package, registry, events),
+        Source::File(path) => std::fs::read(path)
+            .with_context(|| format!("could not read component '{}'", path.display())),
+    }
+}
+
+/// The exact version `version` of a package reference names, which may be
+/// written with a leading `=`
+///
+/// Registries serve releases by version, so a bare `1.2.0` means that release
+/// rather than the newest compatible one.
+#[cfg(feature = "registry")]
+fn exact_version(version: &str) -> anyhow::Result<semver::Version> {
+    let exact = version.strip_prefix('=').unwrap_or(version);
+    semver::Version::parse(exact).map_err(|_| {
+        anyhow::anyhow!(
+            "'{version}' is not an exact version like 1.2.0, which fetching a package needs"
+        )
+    })
+}
+
+/// Download the release of `package` it names, or its latest release
+///
+/// Downloads are kept in warg's local content cache so packages are only
+/// downloaded once.
+#[cfg(feature = "registry")]
+fn fetch(
+    package: &PackageRef<'_>,
+    registry: Option<&str>,
+    events: &Events,
+) -> anyhow::Result<Vec<u8>> {
+    let name = warg_protocol::registry::PackageName::new(package.name)?;
+    let version = package.version.map(exact_version).transpose()?;
+    let config = warg_client::Config::from_default_file()?.unwrap_or_default();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let spinner = crate::progress::Spinner::start(format!("Downloading {package}"));
+    let download = runtime.block_on(async {
+        let client = warg_client::FileSystemClient::new_with_config(registry, &config, None)
+            .await
+            .context("could not create registry client")?;
+        let download = match &version {
+            Some(version) => client.download_exact(&name, version).await.map(Some),
+            None => client.download(&name, &semver::VersionReq::STAR).await,
+        };
+        download.with_context(|| format!("could not download '{package}'"))
+    });
+    drop(spinner);
+    let download =
+        download?.with_context(|| format!("no release of '{package}' found in the registry"))?;
+    events.print(format!(
+        "{} {}@{}",
+        "Fetched:".green().bold(),
+        package.name,
+        download.version
+    ));
+    std::fs::read(&download.path).with_context(|| {
+        format!(
+            "could not read the cached contents of '{package}' at '{}'",
+            download.path.display()
+        )
+    })
+}
+
+#[cfg(not(feature = "registry"))]
+fn fetch(package: &PackageRef<'_>, _: Option<&str>, _: &Events) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("cannot fetch '{package}': wepl was built without registry support")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_refs() {
+        assert_eq!(
+            PackageRef::parse("registry:my-ns:my-pkg@1.2.0"),
+            Some(PackageRef {
+                name: "my-ns:my-pkg",
+                version: Some("1.2.0")
+            })
+        );
+        assert_eq!(
+            PackageRef::parse("registry:wasi:cli"),
+            Some(PackageRef {
+                name: "wasi:cli",
+                version: None
+            })
+        );
+    }
+
+    #[test]
+    fn paths_are_not_package_refs() {
+        assert_eq!(PackageRef::parse("./component.wasm"), None);
+        assert_eq!(PackageRef::parse("C:component.wasm"), None);
+        assert_eq!(PackageRef::parse("my-ns:my-pkg@1.2.0"), None);
+        assert_eq!(PackageRef::parse("registry:ns:pkg@"), None);
+    }
+
+    #[test]
+    fn package_sources() {
+        assert_eq!(
+            Source::of(Path::new("registry:my-ns:my-pkg@1.2.0")),
+            Source::Package(PackageRef {
+                name: "my-ns:my-pkg",
+                version: Some("1.2.0")
+            })
+        );
+    }
+
+    #[test]
+    fn missing_files_are_not_packages() {
+        let typo = Path::new("compnent.wasm");
+        assert_eq!(Source::of(typo), Source::File(typo));
+    }
+
+    #[cfg(feature = "registry")]
+    #[test]
+    fn bare_versions_are_exact() {
+        assert_eq!(
+            exact_version("1.2.0").unwrap(),
+            semver::Version::new(1, 2, 0)
+        );
+        assert_eq!(
+            exact_version("=1.2.0").unwrap(),
+            semver::Version::new(1, 2, 0)
+        );
+        assert!(exact_version("^1.2").is_err());
+        assert!(exact_version("1").is_err());
+    }
+}
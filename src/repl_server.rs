@@ -0,0 +1,364 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    os::unix::{
+        fs::FileTypeExt as _,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context as _;
+use colored::Colorize;
+
+use crate::{capture::Capture, command, events::OutputHandler, Event, Session};
+
+/// Where `wepl serve-repl` listens for connections
+pub enum Address {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Unix(path) => write!(f, "{}", path.display()),
+            Address::Tcp(addr) => write!(f, "{addr}"),
+        }
+    }
+}
+
+/// Let the tools connecting to `address` drive `session`, one connection at a
+/// time, until the server is interrupted
+///
+/// Each line a tool sends is a request like `evaluate foo(1)`, `complete fo`
+/// or `inspect x.items`, answered with a line holding a JSON object. What
+/// calls write to stdout is sent while they run, an event per line. The
+/// session outlives the connections, so variables and stubs are kept for the
+/// next one.
+pub fn serve(session: &mut Session, address: &Address) -> anyhow::Result<()> {
+    let output = Capture::new()?;
+    match address {
+        Address::Unix(path) => {
+            remove_stale_socket(path);
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("could not bind to '{}'", path.display()))?;
+            print_serving(address, session.cli.quiet);
+            accept_loop(
+                listener.incoming(),
+                |stream| Ok((BufReader::new(stream.try_clone()?), stream)),
+                session,
+                &output,
+            )
+        }
+        Address::Tcp(addr) => {
+            // Anyone who can connect drives the session, so it is only served
+            // to the machine it runs on
+            if !addr.ip().is_loopback() {
+                anyhow::bail!(
+                    "the repl is only served on loopback addresses (e.g. `127.0.0.1:7000`), since anyone connecting can drive the session"
+                );
+            }
+            let listener =
+                TcpListener::bind(addr).with_context(|| format!("could not bind to '{addr}'"))?;
+            print_serving(&Address::Tcp(listener.local_addr()?), session.cli.quiet);
+            accept_loop(
+                listener.incoming(),
+                |stream| Ok((BufReader::new(stream.try_clone()?), stream)),
+                session,
+                &output,
+            )
+        }
+    }
+}
+
+/// Remove the socket at `path` if a server that is gone left it behind, so
+/// that it can be taken over, leaving anything else at `path` alone
+fn remove_stale_socket(path: &Path) {
+    let is_socket =
+        std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket());
+    if is_socket && UnixStream::connect(path).is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn print_serving(address: &Address, quiet: bool) {
+    if !quiet {
+        println!(
+            "{} the repl on {address} (press Ctrl-C to stop)",
+            "Serving".blue().bold()
+        );
+    }
+    // Responses are read by tools rather than shown in a terminal
+    colored::control::set_override(false);
+}
+
+fn accept_loop<S, R: BufRead, W: Write + Send + 'static>(
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+    split: impl Fn(S) -> std::io::Result<(R, W)>,
+    session: &mut Session,
+    output: &Capture,
+) -> anyhow::Result<()> {
+    for stream in incoming {
+        let (reader, writer) = split(stream?)?;
+        if let Err(e) = handle_connection(session, reader, writer, output) {
+            tracing::debug!("error serving connection: {e:?}");
+        }
+    }
+    Ok(())
+}
+
+/// Answer the requests of a connection until it is closed
+fn handle_connection(
+    session: &mut Session,
+    reader: impl BufRead,
+    writer: impl Write + Send + 'static,
+    output: &Capture,
+) -> anyhow::Result<()> {
+    // The output of calls is sent on the connection while they run
+    session.stream_output();
+    let writer = Arc::new(Mutex::new(writer));
+    let streamed: OutputHandler = Arc::new({
+        let writer = writer.clone();
+        move |output: Vec<Event>| {
+            let mut writer = writer.lock().unwrap();
+            for event in &output {
+                let _ = writeln!(writer, "{}", event.to_json());
+            }
+            let _ = writer.flush();
+        }
+    });
+    for line in reader.lines() {
+        let line = line?;
+        let request = line.trim();
+        if request.is_empty() {
+            continue;
+        }
+        let (method, argument) = request.split_once(' ').unwrap_or((request, ""));
+        let response = match method {
+            "evaluate" => evaluate(session, argument, output, &streamed)?,
+            "inspect" => evaluate(session, &format!(".inspect {argument}"), output, &streamed)?,
+            "complete" => {
+                let (start, completions) = complete(session, argument);
+                serde_json::json!({"start": start, "completions": completions})
+            }
+            _ => serde_json::json!({
+                "error": crate::error_to_json(
+                    &anyhow::anyhow!(
+                        "unknown request '{method}', expected 'evaluate', 'complete' or 'inspect'"
+                    ),
+                    Some(request),
+                ),
+            }),
+        };
+        let mut writer = writer.lock().unwrap();
+        writeln!(writer, "{response}")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Run the repl input `line`, passing the output of calls to `streamed` while
+/// they run and answering with what it printed, its other events and the
+/// error it failed with if any
+fn evaluate(
+    session: &mut Session,
+    line: &str,
+    output: &Capture,
+    streamed: &OutputHandler,
+) -> anyhow::Result<serde_json::Value> {
+    // What the component wrote to stderr is printed rather than emitted
+    let (result, printed) = output.run(|| session.eval_with(line, Some(streamed.clone())))?;
+    let events = result.events.iter().map(Event::to_json).collect::<Vec<_>>();
+    let error = match result.error {
+        Some(e) => crate::error_to_json(&e, Some(line)),
+        None => serde_json::Value::Null,
+    };
+    let output = result.output + &printed;
+    Ok(serde_json::json!({"output": output, "events": events, "error": error}))
+}
+
+/// The completions of the word at the end of `input` along with the offset
+/// the word starts at, which they replace
+pub fn complete(session: &Session, input: &str) -> (usize, Vec<String>) {
+    let start = word_start(input);
+    let word = &input[start..];
+    let mut candidates = if word.starts_with('.') && input[..start].trim().is_empty() {
+        command::BUILTINS
+            .iter()
+            .map(|name| format!(".{name}"))
+            .collect::<Vec<_>>()
+    } else {
+        let mut names = session.instance.resolver.exported_function_names();
+        names.extend(session.scope.keys().cloned());
+        names.extend(session.others.iter().map(|(name, _)| name.clone()));
+        names
+    };
+    candidates.retain(|candidate| candidate.starts_with(word));
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+/// Where the word being typed at the end of `input` starts
+pub fn word_start(input: &str) -> usize {
+    input.rfind(is_word_boundary).map_or(0, |i| i + 1)
+}
+
+/// Whether `c` separates the words of repl input
+pub fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || "(),=[]{}\"".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(word_start(""), 0);
+    }
+
+    #[test]
+    fn builtins() {
+        assert_eq!(word_start(".ex"), 0);
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(word_start("x = get-us"), 4);
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(word_start("add(1, su"), 7);
+        assert_eq!(word_start("foo("), 4);
+    }
+
+    #[test]
+    fn interface_paths() {
+        assert_eq!(word_start("my:pkg/users#get"), 0);
+    }
+
+    /// A connection writing to a buffer that can be read after it is closed
+    #[derive(Clone, Default)]
+    struct Connection(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The lines `session` answers `requests` with, parsed as JSON
+    fn serve_requests(session: &mut Session, requests: &str) -> Vec<serde_json::Value> {
+        let output = Capture::new().unwrap();
+        let connection = Connection::default();
+        handle_connection(session, requests.as_bytes(), connection.clone(), &output).unwrap();
+        let written = String::from_utf8(connection.0.lock().unwrap().clone()).unwrap();
+        written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn evaluating() {
+        let (mut session, _file) = crate::tests::session("serve-evaluate");
+        let responses = serve_requests(&mut session, "evaluate add(1, 2)\n\nevaluate add(1)\n");
+        let [added, failed] = &responses[..] else {
+            panic!("expected two responses but got {responses:?}");
+        };
+        assert_eq!(added["error"], serde_json::Value::Null);
+        assert_eq!(added["events"][0]["event"], "results");
+        assert_eq!(added["events"][0]["values"], serde_json::json!([3]));
+        assert!(added["output"].is_string());
+        assert!(failed["error"]["message"].is_string());
+        assert_eq!(failed["error"]["line"], "add(1)");
+    }
+
+    #[test]
+    fn completing() {
+        let (mut session, _file) = crate::tests::session("serve-complete");
+        let responses = serve_requests(&mut session, "complete x = ad\ncomplete .exp\n");
+        assert_eq!(
+            responses,
+            [
+                serde_json::json!({"start": 4, "completions": ["add"]}),
+                serde_json::json!({"start": 0, "completions": [".export-csv", ".exports"]}),
+            ]
+        );
+    }
+
+    #[test]
+    fn inspecting() {
+        let (mut session, _file) = crate::tests::session("serve-inspect");
+        let responses = serve_requests(&mut session, "evaluate x = add(1, 2)\ninspect x\n");
+        let inspected = &responses[1];
+        assert_eq!(inspected["error"], serde_json::Value::Null);
+        assert!(inspected["output"].as_str().unwrap().contains('3'));
+    }
+
+    #[test]
+    fn output_is_streamed_before_the_response() {
+        let (mut session, _file) = crate::tests::session("serve-stream");
+        let responses = serve_requests(&mut session, "evaluate tick(1000000000)\n");
+        let [streamed @ .., response] = &responses[..] else {
+            panic!("expected a response");
+        };
+        let text = streamed
+            .iter()
+            .filter(|event| event["event"] == "output")
+            .map(|event| event["text"].as_str().unwrap())
+            .collect::<String>();
+        assert_eq!(text, "tick\n");
+        assert_eq!(response["error"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn unknown_requests() {
+        let (mut session, _file) = crate::tests::session("serve-unknown");
+        let responses = serve_requests(&mut session, "run add(1, 2)\n");
+        let message = responses[0]["error"]["message"].as_str().unwrap();
+        assert!(message.contains("unknown request 'run'"), "{message}");
+    }
+
+    #[test]
+    fn host_code_is_refused_unless_allowed() {
+        let (mut session, _file) = crate::tests::session("serve-host-code");
+        session.host_code = false;
+        let responses = serve_requests(
+            &mut session,
+            "evaluate !true\nevaluate .stub-proc my:test/store -- true\n",
+        );
+        for response in &responses {
+            let message = response["error"]["message"].as_str().unwrap();
+            assert!(message.contains("--allow-host-code"), "{message}");
+        }
+        assert_eq!(responses.len(), 2);
+
+        session.host_code = true;
+        let responses = serve_requests(&mut session, "evaluate !true\n");
+        assert_eq!(responses[0]["error"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn only_stale_sockets_are_removed() {
+        let dir = std::env::temp_dir();
+        let file = dir.join(format!("wepl-not-a-socket-{}", std::process::id()));
+        std::fs::write(&file, "notes").unwrap();
+        remove_stale_socket(&file);
+        assert!(file.exists());
+        std::fs::remove_file(&file).unwrap();
+
+        let socket = dir.join(format!("wepl-stale-{}.sock", std::process::id()));
+        drop(UnixListener::bind(&socket).unwrap());
+        remove_stale_socket(&socket);
+        assert!(!socket.exists());
+    }
+}
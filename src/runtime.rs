@@ -1,148 +1,823 @@
+pub mod compat;
+mod plugin;
+
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Context as _;
 use colored::Colorize;
+use rand::{RngCore, SeedableRng as _};
+use rand_chacha::ChaCha8Rng;
 use wasmtime::{
-    component::{Component, Func, Instance, Linker, ResourceTable, Val},
-    Config, Engine, Store,
+    component::{
+        self, types, Component, Func, Instance, InstancePre, Linker, Resource, ResourceAny,
+        ResourceTable, Val,
+    },
+    AsContextMut as _, CallHook, Config, Engine, Store, UpdateDeadline, WasmBacktrace,
+    WasmBacktraceDetails, WasmCoreDump,
 };
 use wasmtime_wasi::{
-    HostOutputStream, Stdout, StdoutStream, StreamResult, Subscribe, WasiCtx, WasiCtxBuilder,
+    pipe::MemoryInputPipe, DirPerms, FilePerms, HostMonotonicClock, HostOutputStream,
+    HostWallClock, InputStream, OutputStream, Pollable, SocketAddrUse, Stderr, Stdout,
+    StdoutStream, StreamError, StreamResult, Subscribe, WasiCtx, WasiCtxBuilder, WasiImpl,
     WasiView,
 };
+use wasmtime_wasi_http::WasiHttpCtx;
+
+use self::compat::{check_function_types, coerce_val};
 
 use crate::{
-    command::parser::{self, ItemIdent},
-    wit::WorldResolver,
+    audit::{self, Exercised},
+    cache::ComponentCache,
+    clock::VirtualClock,
+    command::{
+        format_function, format_val,
+        parser::{self, ItemIdent},
+        Cmd,
+    },
+    componentize,
+    engine::EngineOptions,
+    evaluator::Evaluator,
+    events::{Event, Events, OutputSource, OutputStyle, OUTPUT_INTERVAL},
+    http::{self, HttpResource, Returned},
+    json,
+    keyvalue::{self, KeyValueStores},
+    logging::{GuestLogger, Level, LogRecord},
+    network::NetworkPolicy,
+    profile::{Profile, Ticker, SAMPLE_INTERVAL},
+    progress,
+    recording::{self, ImportRecorder},
+    stats::Footprint,
+    streams::HostStreams,
+    wit::{Expansion, WorldResolver},
 };
 
 pub struct Runtime {
     engine: Engine,
     pub store: Store<Context>,
     instance: Instance,
-    linker: Linker<Context>,
+    /// The component linked with the current imports, ready to be instantiated
+    pre: InstancePre<Context>,
     component: (Component, Vec<u8>),
+    /// Whether the component was precompiled by `wepl compile`
+    precompiled: bool,
     import_impls: ImportImpls,
+    wasi_config: WasiConfig,
+    capture: OutputCapture,
+    /// Receives the records the guest logs, if it imports `wasi:logging`
+    logger: Option<GuestLogger>,
+    /// The values returned by `wasi:config/store`, if the guest imports it
+    config: Option<ConfigValues>,
+    /// The stores behind `wasi:keyvalue` once `.provide` implements it
+    keyvalue: KeyValueStores,
+    /// The host-side queues pushed to with `.stream push`
+    streams: HostStreams,
+    prompt_imports: Arc<AtomicBool>,
+    /// The current host definitions of the imports
+    imports: ImportDefs,
+    /// The names of the imported functions being spied on
+    spied: HashSet<String>,
+    /// The calls recorded by spies by the name of the imported function
+    calls: Arc<Mutex<HashMap<String, Vec<ImportCall>>>>,
+    /// The resource handles the component returned that are still alive
+    resources: Vec<(ResourceAny, InterfaceResource)>,
+    /// Whether the last call into the component failed
+    poisoned: bool,
+    /// The calls to the component's exports made since it was instantiated
+    history: CallHistory,
+    /// The calls saved by `.snapshot` by the name of the snapshot
+    snapshots: BTreeMap<String, Vec<ExportCall>>,
+    /// Whether calls are only type-checked because there is no component,
+    /// only the WIT of one
+    dry_run: bool,
+    /// Whether core dumps of traps are written to files
+    coredump: bool,
+    /// Whether the growth of the instance during each call is shown, which
+    /// `.stats on` turns on
+    stats: bool,
+    /// Whether changes try to keep the guest's state
+    state_mode: StateMode,
+    /// Where compiled components are cached, if anywhere
+    cache: Option<ComponentCache>,
+    /// How the engine compiles components
+    engine_options: EngineOptions,
 }
 
 impl Runtime {
+    #[tracing::instrument(name = "instantiate", level = "debug", skip_all)]
     pub fn init(
-        component_bytes: Vec<u8>,
+        component: ComponentBytes,
         resolver: &WorldResolver,
-        stub_import: impl Fn(&str) + Sync + Send + Clone + 'static,
+        wasi_config: WasiConfig,
+        stub_import: impl Fn(&str, &Events) + Sync + Send + Clone + 'static,
+        drop_resource: impl Fn(&InterfaceResource, &Events) + Sync + Send + 'static,
+        cache: Option<ComponentCache>,
+        engine_options: EngineOptions,
     ) -> anyhow::Result<Self> {
-        let engine = load_engine()?;
-        let component = load_component(&engine, &component_bytes)?;
-        let mut linker = Linker::<Context>::new(&engine);
-        linker.allow_shadowing(true);
-
+        let engine = load_engine(cache.is_some(), &engine_options, false)?;
+        let start = Instant::now();
+        let spinner = progress::Spinner::start("Compiling the component");
+        let (component_bytes, precompiled) = match component {
+            ComponentBytes::Binary(bytes) => (bytes, false),
+            ComponentBytes::Precompiled(bytes) => (bytes, true),
+        };
+        let component = match &cache {
+            Some(cache) if !precompiled => cache.load(&engine, &component_bytes)?,
+            _ => load_component(&engine, &component_bytes, precompiled)?,
+        };
+        drop(spinner);
+        tracing::debug!("Loaded the component in {:?}", start.elapsed());
         let imports_wasi_cli = resolver.imports_wasi_cli();
-        if imports_wasi_cli {
-            log::debug!("Linking with wasi");
-            wasmtime_wasi::add_to_linker_sync(&mut linker)?;
-        }
+        let mut imports = ImportDefs {
+            wasi: imports_wasi_cli,
+            on_resource_drop: Some(Arc::new(drop_resource)),
+            ..Default::default()
+        };
+        let prompt_imports = Arc::new(AtomicBool::new(false));
+        let component_type = component.component_type();
+        let shared_resolver = Arc::new(resolver.clone());
         for (import_name, import) in resolver.imports(!imports_wasi_cli) {
             let import_name = resolver.world_item_name(import_name);
-            let stub_import = stub_import.clone();
             match import {
                 wit_parser::WorldItem::Function(f) => {
-                    linker.root().func_new(&f.name, move |_ctx, _args, _rets| {
-                        stub_import(&import_name);
-                        Ok(())
-                    })?;
+                    let unstubbed = UnstubbedImport {
+                        name: import_name.clone(),
+                        function: f.clone(),
+                        result_types: import_result_types(&engine, &component_type, None, &f.name)
+                            .unwrap_or_default(),
+                        resolver: shared_resolver.clone(),
+                        prompt: prompt_imports.clone(),
+                        events: imports.events.clone(),
+                    };
+                    let stub_import = stub_import.clone();
+                    tracing::trace!("Import '{import_name}' is unstubbed");
+                    imports.funcs.insert(
+                        (None, f.name.clone()),
+                        Arc::new(move |_ctx, args, rets| {
+                            unstubbed.call(args, rets, |events| stub_import(&import_name, events))
+                        }),
+                    );
                 }
                 wit_parser::WorldItem::Interface { id, .. } => {
                     let interface = resolver.interface_by_id(*id).unwrap();
-                    let mut root = linker.root();
-                    let mut instance = root.instance(&import_name)?;
                     for (_, f) in interface.functions.iter() {
                         let stub_import = stub_import.clone();
+                        let unstubbed = UnstubbedImport {
+                            name: format!("{import_name}#{}", f.name),
+                            function: f.clone(),
+                            result_types: import_result_types(
+                                &engine,
+                                &component_type,
+                                Some(&import_name),
+                                &f.name,
+                            )
+                            .unwrap_or_default(),
+                            resolver: shared_resolver.clone(),
+                            prompt: prompt_imports.clone(),
+                            events: imports.events.clone(),
+                        };
                         let import_name = import_name.clone();
-                        instance.func_new(&f.name, move |_ctx, _args, _rets| {
-                            stub_import(&import_name);
-                            Ok(())
-                        })?;
+                        tracing::trace!("Import '{import_name}#{}' is unstubbed", f.name);
+                        imports.funcs.insert(
+                            (Some(import_name.clone()), f.name.clone()),
+                            Arc::new(move |_ctx, args, rets| {
+                                unstubbed
+                                    .call(args, rets, |events| stub_import(&import_name, events))
+                            }),
+                        );
                     }
                     for (name, t) in &interface.types {
                         let t = resolver.type_by_id(*t).unwrap();
                         if let wit_parser::TypeDefKind::Resource = &t.kind {
-                            let ty = wasmtime::component::ResourceType::host::<()>();
-                            instance.resource(name, ty, |_, _| Ok(())).unwrap();
+                            imports.resources.push(ImportedResource {
+                                resource: InterfaceResource {
+                                    interface: import_name.clone(),
+                                    name: name.clone(),
+                                },
+                                ty: import_resource_type(
+                                    &engine,
+                                    &component_type,
+                                    &import_name,
+                                    name,
+                                ),
+                            });
                         }
                     }
                 }
                 _ => {}
             }
         }
-        let pre = linker
+        let capture = OutputCapture::default();
+        let logger = provide_logging(&mut imports, &capture);
+        let config = provide_config(&mut imports, &wasi_config.config);
+        provide_http(&mut imports);
+        imports.defaults = imports.funcs.clone();
+        let start = Instant::now();
+        let pre = imports
+            .linker(&engine)?
             .instantiate_pre(&component)
             .context("could not instantiate component")?;
-        let mut store = build_store(&engine);
+        let mut store = build_store(
+            &engine,
+            &wasi_config,
+            &capture,
+            &imports.events,
+            &imports.trace,
+            &imports.exercised,
+        );
         let instance = pre.instantiate(&mut store)?;
-        let import_impls = ImportImpls::new(&engine, String::from("import"));
+        tracing::debug!("Instantiated the component in {:?}", start.elapsed());
+        let import_impls = ImportImpls::new(imports.events.clone(), wasi_config.sandbox);
         Ok(Self {
             engine,
             store,
             instance,
-            linker,
+            pre,
             component: (component, component_bytes),
+            precompiled,
             import_impls,
+            wasi_config,
+            capture,
+            logger,
+            config,
+            keyvalue: KeyValueStores::default(),
+            streams: HostStreams::default(),
+            prompt_imports,
+            imports,
+            spied: HashSet::new(),
+            calls: Default::default(),
+            resources: Vec::new(),
+            poisoned: false,
+            history: CallHistory::default(),
+            snapshots: BTreeMap::new(),
+            dry_run: false,
+            coredump: false,
+            stats: false,
+            state_mode: StateMode::default(),
+            cache,
+            engine_options,
         })
     }
 
     pub fn get_func(&mut self, ident: ItemIdent) -> anyhow::Result<Func> {
-        let func = match ident.interface {
-            Some(i) => {
-                let mut exports = self.instance.exports(&mut self.store);
-                let instance_name = i.to_string();
-                exports
-                    .instance(&instance_name)
-                    .with_context(|| {
-                        format!("could not find exported instance with name '{instance_name}'")
-                    })?
-                    .func(ident.item)
-            }
-            None => self
-                .instance
-                .exports(&mut self.store)
-                .root()
-                .func(ident.item),
-        };
-        func.with_context(|| format!("could not find function '{ident}' in instance"))
+        let interface = ident.interface.map(|i| i.to_string());
+        self.export_func(interface.as_deref(), ident.item)
+    }
+
+    /// Call the exported function `ident` with `args`, remembering the call so
+    /// that [`Runtime::restore`] can make it again
+    pub fn call(&mut self, ident: ItemIdent, args: &[Val]) -> anyhow::Result<CallOutcome> {
+        let interface = ident.interface.map(|i| i.to_string());
+        self.call_recorded(interface.as_deref(), ident.item, args)
     }
 
-    pub fn call_func(
+    /// Call the exported function `name` of the exported instance
+    /// `interface`, adding the call to the history if it succeeds
+    fn call_recorded(
         &mut self,
-        func: Func,
+        interface: Option<&str>,
+        name: &str,
         args: &[Val],
-        result_count: usize,
-    ) -> anyhow::Result<Vec<Val>> {
+    ) -> anyhow::Result<CallOutcome> {
+        let func = self.export_func(interface, name)?;
+        let outcome = self.call_func(func, args)?;
+        self.history.record(interface, name, args, &outcome.results);
+        Ok(outcome)
+    }
+
+    /// Save the calls made to the exports since the component was
+    /// instantiated as the snapshot `name`, returning how many there are
+    ///
+    /// Instances cannot be copied, so the snapshot is restored by making the
+    /// calls again in a new instance. Calls passing or returning resource
+    /// handles cannot be made again since the handles belong to the instance
+    /// they were made in.
+    pub fn snapshot(&mut self, name: &str) -> anyhow::Result<usize> {
+        if let Some(call) = &self.history.unrepeatable {
+            anyhow::bail!(
+                "'{call}' passed or returned resource handles, which cannot be passed to another instance, so the state cannot be saved (`.reset instance` starts over)"
+            );
+        }
+        let calls = self.history.calls.clone();
+        let count = calls.len();
+        self.snapshots.insert(name.to_owned(), calls);
+        Ok(count)
+    }
+
+    /// Get a new instance and make the calls saved as the snapshot `name`
+    /// again, returning how many were made
+    pub fn restore(&mut self, name: &str) -> anyhow::Result<usize> {
+        let calls = self
+            .snapshots
+            .get(name)
+            .with_context(|| format!("no snapshot named '{name}'"))?
+            .clone();
+        self.refresh()?;
+        for call in &calls {
+            self.call_recorded(call.interface.as_deref(), &call.name, &call.args)
+                .with_context(|| format!("could not make the call {call} again"))?;
+        }
+        Ok(calls.len())
+    }
+
+    /// The names of the snapshots with the number of calls they make
+    pub fn snapshots(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.snapshots
+            .iter()
+            .map(|(name, calls)| (name.as_str(), calls.len()))
+    }
+
+    /// The exported function `name` of the exported instance `interface`, or
+    /// of the root if there is no interface
+    fn export_func(&mut self, interface: Option<&str>, name: &str) -> anyhow::Result<Func> {
+        export_func(&self.instance, &mut self.store, interface, name)
+    }
+
+    /// Call the exported function `func` with `args`
+    ///
+    /// As many results are returned as the function's type has, so callers
+    /// cannot get their number wrong.
+    pub fn call_func(&mut self, func: Func, args: &[Val]) -> anyhow::Result<CallOutcome> {
+        if self.dry_run {
+            anyhow::bail!("the call type-checks but is not made since only WIT is loaded");
+        }
+        let result_count = func.results(&self.store).len();
         let mut results = vec![Val::Bool(Default::default()); result_count];
-        func.call(&mut self.store, args, &mut results)?;
-        func.post_return(&mut self.store)?;
-        Ok(results)
+        self.capture.call_stdout.lock().unwrap().clear();
+        let footprint = self.store.data().footprint;
+        let fuel = self.store.get_fuel().ok();
+        // Advancing the epoch makes the guest yield to pass on the output a
+        // frontend collects while the call runs, which profiling already does
+        let ticker = (self.imports.events.streams_output()
+            && self.engine_options.interrupts()
+            && self.store.data().profile.is_none())
+        .then(|| Ticker::start(&self.engine, OUTPUT_INTERVAL));
+        let start = Instant::now();
+        let result = func.call(&mut self.store, args, &mut results);
+        let duration = start.elapsed();
+        drop(ticker);
+        self.poisoned = result.is_err();
+        if self.stats {
+            let mut stats = self.store.data().footprint.growth_since(
+                &footprint,
+                fuel,
+                self.store.get_fuel().ok(),
+            );
+            if result.is_ok() {
+                stats.resources_created += results.iter().map(resource_count).sum::<usize>();
+            }
+            self.imports.events.emit(Event::Stats(stats));
+        }
+        match result {
+            Ok(()) => {
+                if let Err(e) = func.post_return(&mut self.store) {
+                    let returned = results.iter().map(format_val).collect::<Vec<_>>();
+                    let e = e.context(format!("the call returned {}", returned.join(", ")));
+                    return Err(self.recover_from_post_return(e));
+                }
+                let stdout = std::mem::take(&mut *self.capture.call_stdout.lock().unwrap());
+                Ok(CallOutcome {
+                    results,
+                    stdout: String::from_utf8_lossy(&stdout).into_owned(),
+                    duration,
+                })
+            }
+            Err(e) if self.coredump && e.is::<WasmCoreDump>() => {
+                let path = self.write_coredump(e.downcast_ref::<WasmCoreDump>().unwrap())?;
+                Err(e.context(format!(
+                    "the call trapped, core dump written to '{}'",
+                    path.display()
+                )))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Call the exported function `ident` once with each of `inputs`, each
+    /// time in a fresh instance with a store of its own, on up to `parallel`
+    /// threads at once
+    ///
+    /// The outcomes are in the order of `inputs`. The current instance is
+    /// left as it is, and so is the state of stubs and host resources. What
+    /// each call writes to stdout and stderr is emitted together once the
+    /// calls before it are done, so the output of calls made at once does not
+    /// interleave, while the output of stubs is emitted as they write it.
+    #[tracing::instrument(name = "call", level = "debug", skip_all, fields(func = %ident, inputs = inputs.len()))]
+    pub fn map(
+        &self,
+        ident: ItemIdent,
+        inputs: &[Vec<Val>],
+        parallel: usize,
+    ) -> anyhow::Result<Vec<anyhow::Result<Vec<Val>>>> {
+        if self.dry_run {
+            anyhow::bail!("the calls type-check but are not made since only WIT is loaded");
+        }
+        if inputs.iter().flatten().any(|val| resource_count(val) > 0) {
+            anyhow::bail!("resource handles belong to the current instance, so they cannot be passed to the instances of other calls");
+        }
+        let interface = ident.interface.map(|i| i.to_string());
+        let (engine, pre, wasi_config, capture) =
+            (&self.engine, &self.pre, &self.wasi_config, &self.capture);
+        let (events, trace, exercised) = (
+            &self.imports.events,
+            &self.imports.trace,
+            &self.imports.exercised,
+        );
+        let call = |args: &[Val], events: &Events| -> anyhow::Result<Vec<Val>> {
+            let mut store = build_store(engine, wasi_config, capture, events, trace, exercised);
+            let instance = pre
+                .instantiate(&mut store)
+                .context("could not instantiate component")?;
+            let func = export_func(&instance, &mut store, interface.as_deref(), ident.item)?;
+            let mut results = vec![Val::Bool(Default::default()); func.results(&store).len()];
+            func.call(&mut store, args, &mut results)?;
+            func.post_return(&mut store)
+                .context("the component failed to clean up after the call (post-return)")?;
+            if results.iter().any(|val| resource_count(val) > 0) {
+                anyhow::bail!("the call returned a resource handle, which does not outlive the instance it was made in");
+            }
+            Ok(results)
+        };
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let done = Mutex::new(MapProgress {
+            outcomes: inputs.iter().map(|_| None).collect(),
+            events: inputs.iter().map(|_| None).collect(),
+            emitted: 0,
+        });
+        std::thread::scope(|scope| {
+            for _ in 0..parallel.clamp(1, inputs.len().max(1)) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(args) = inputs.get(i) else {
+                        break;
+                    };
+                    let call_events = Events::default();
+                    call_events.collect();
+                    let outcome = call(args, &call_events);
+                    let done = &mut *done.lock().unwrap();
+                    done.outcomes[i] = Some(outcome);
+                    done.events[i] = Some(call_events.take());
+                    // Events are emitted in the order of the calls, each
+                    // call's once the calls before it are done
+                    while let Some(emitted) =
+                        done.events.get_mut(done.emitted).and_then(Option::take)
+                    {
+                        emitted.into_iter().for_each(|event| events.emit(event));
+                        done.emitted += 1;
+                    }
+                });
+            }
+        });
+        Ok(done
+            .into_inner()
+            .unwrap()
+            .outcomes
+            .into_iter()
+            .map(|outcome| outcome.expect("every input is called"))
+            .collect())
+    }
+
+    /// Replace the instance after the cleanup of a call that returned failed
+    /// with `e`, since the store cannot make calls anymore, and explain what
+    /// happened along with the state that was lost
+    fn recover_from_post_return(&mut self, e: anyhow::Error) -> anyhow::Error {
+        let handles = self.resources.len();
+        let lost = match handles {
+            0 => "the guest's state was lost".to_owned(),
+            1 => "the guest's state and 1 resource handle were lost".to_owned(),
+            n => format!("the guest's state and {n} resource handles were lost"),
+        };
+        match self.refresh() {
+            Ok(()) => e.context(format!(
+                "the component failed to clean up after the call (post-return), so it was given a fresh instance and {lost}"
+            )),
+            Err(refresh) => {
+                self.poisoned = true;
+                e.context(format!(
+                    "the component failed to clean up after the call (post-return) and could not be given a fresh instance: {refresh:#}"
+                ))
+            }
+        }
+    }
+
+    /// Write `coredump` to a new file in the current directory
+    fn write_coredump(&mut self, coredump: &WasmCoreDump) -> anyhow::Result<std::path::PathBuf> {
+        let (name, path) = (1..)
+            .map(|n| {
+                let name = format!("wepl-{}-{n}", std::process::id());
+                let path = std::path::PathBuf::from(format!("{name}.coredump"));
+                (name, path)
+            })
+            .find(|(_, path)| !path.exists())
+            .unwrap();
+        std::fs::write(&path, coredump.serialize(&mut self.store, &name))
+            .with_context(|| format!("could not write core dump to '{}'", path.display()))?;
+        Ok(path)
+    }
+
+    /// Whether calls are only type-checked instead of made
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Whether core dumps of traps are written to files
+    pub fn coredump(&self) -> bool {
+        self.coredump
+    }
+
+    pub fn set_coredump(&mut self, coredump: bool) {
+        self.coredump = coredump;
+    }
+
+    /// Whether the growth of the instance during each call is shown
+    pub fn stats(&self) -> bool {
+        self.stats
+    }
+
+    pub fn set_stats(&mut self, stats: bool) {
+        self.stats = stats;
+    }
+
+    /// Whether a call into the component failed, leaving the instance unusable
+    /// until it is refreshed
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Remember that `handle` is a handle to the exported `resource`
+    pub fn track_resource(&mut self, handle: ResourceAny, resource: InterfaceResource) {
+        if self.resource(handle).is_none() {
+            self.resources.push((handle, resource));
+        }
+    }
+
+    /// The exported resource `handle` is a handle to, if it is still alive
+    pub fn resource(&self, handle: ResourceAny) -> Option<&InterfaceResource> {
+        self.resources
+            .iter()
+            .find_map(|(h, resource)| (*h == handle).then_some(resource))
+    }
+
+    /// Whether `val` contains a resource handle the component returned that is
+    /// still alive
+    pub fn holds_resource(&self, val: &Val) -> bool {
+        match val {
+            Val::Resource(handle) => self.resource(*handle).is_some(),
+            Val::List(vals) | Val::Tuple(vals) => vals.iter().any(|val| self.holds_resource(val)),
+            Val::Record(fields) => fields.iter().any(|(_, val)| self.holds_resource(val)),
+            Val::Variant(_, Some(val))
+            | Val::Option(Some(val))
+            | Val::Result(Ok(Some(val)) | Err(Some(val))) => self.holds_resource(val),
+            _ => false,
+        }
+    }
+
+    /// The resource handles the component returned that are still alive
+    pub fn resources(&self) -> &[(ResourceAny, InterfaceResource)] {
+        &self.resources
+    }
+
+    /// Stop tracking `handle` without dropping it, e.g. because its ownership
+    /// was passed to the component
+    pub fn forget_resource(&mut self, handle: ResourceAny) {
+        self.resources.retain(|(h, _)| *h != handle);
+    }
+
+    /// Drop the resource `handle`, running its destructor if it is owned
+    pub fn drop_resource(&mut self, handle: ResourceAny) -> anyhow::Result<()> {
+        self.forget_resource(handle);
+        handle.resource_drop(&mut self.store)
+    }
+
+    /// Wait until the `wasi:io/poll.pollable` `handle` is ready, or until
+    /// `timeout` passes, returning whether it became ready and the handle to
+    /// use from now on
+    ///
+    /// Input and output streams are subscribed to for the wait, so the stream
+    /// itself can be awaited before a non-blocking read or write.
+    pub fn await_ready(
+        &mut self,
+        handle: ResourceAny,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<(bool, ResourceAny)> {
+        use wasmtime_wasi::bindings::sync::io::streams::{HostInputStream, HostOutputStream};
+        let ty = handle.ty();
+        let (ready, handle) = if ty == component::ResourceType::host::<Pollable>() {
+            self.with_host_resource::<Pollable, _>(handle, |this, rep| {
+                this.wait_ready(rep, timeout)
+            })?
+        } else if ty == component::ResourceType::host::<InputStream>() {
+            self.with_host_resource::<InputStream, _>(handle, |this, rep| {
+                let pollable =
+                    HostInputStream::subscribe(&mut this.wasi(), Resource::new_borrow(rep))?;
+                this.wait_subscribed(pollable, timeout)
+            })?
+        } else if ty == component::ResourceType::host::<OutputStream>() {
+            self.with_host_resource::<OutputStream, _>(handle, |this, rep| {
+                let pollable =
+                    HostOutputStream::subscribe(&mut this.wasi(), Resource::new_borrow(rep))?;
+                this.wait_subscribed(pollable, timeout)
+            })?
+        } else {
+            anyhow::bail!("only pollables and input and output streams can be awaited")
+        };
+        Ok((ready?, handle))
+    }
+
+    /// Read up to `len` bytes from the `wasi:io/streams.input-stream`
+    /// `handle`, blocking until some are available, returning them (or `None`
+    /// when the stream is closed) and the handle to use from now on
+    pub fn read_stream(
+        &mut self,
+        handle: ResourceAny,
+        len: u64,
+    ) -> anyhow::Result<(Option<Vec<u8>>, ResourceAny)> {
+        use wasmtime_wasi::bindings::sync::io::streams::HostInputStream;
+        if handle.ty() != component::ResourceType::host::<InputStream>() {
+            anyhow::bail!("only input streams can be read from");
+        }
+        let (read, handle) = self.with_host_resource::<InputStream, _>(handle, |this, rep| {
+            HostInputStream::blocking_read(&mut this.wasi(), Resource::new_borrow(rep), len)
+        })?;
+        match read {
+            Ok(bytes) => Ok((Some(bytes), handle)),
+            Err(StreamError::Closed) => Ok((None, handle)),
+            Err(StreamError::LastOperationFailed(e) | StreamError::Trap(e)) => Err(e),
+        }
+    }
+
+    /// Write all of `bytes` to the `wasi:io/streams.output-stream` `handle`
+    /// and flush it, returning the handle to use from now on
+    pub fn write_stream(
+        &mut self,
+        handle: ResourceAny,
+        bytes: &[u8],
+    ) -> anyhow::Result<ResourceAny> {
+        use wasmtime_wasi::bindings::sync::io::streams::HostOutputStream;
+        // `blocking-write-and-flush` takes at most 4096 bytes at a time
+        const MAX_WRITE: usize = 4096;
+        if handle.ty() != component::ResourceType::host::<OutputStream>() {
+            anyhow::bail!("only output streams can be written to");
+        }
+        let (written, handle) =
+            self.with_host_resource::<OutputStream, _>(handle, |this, rep| {
+                for chunk in bytes.chunks(MAX_WRITE) {
+                    let stream = Resource::new_borrow(rep);
+                    HostOutputStream::blocking_write_and_flush(
+                        &mut this.wasi(),
+                        stream,
+                        chunk.to_vec(),
+                    )?;
+                }
+                Ok(())
+            })?;
+        match written {
+            Ok(()) => Ok(handle),
+            Err(StreamError::Closed) => anyhow::bail!("the stream is closed"),
+            Err(StreamError::LastOperationFailed(e) | StreamError::Trap(e)) => Err(e),
+        }
+    }
+
+    /// Use the host resource `handle` of type `T` in `f` by its
+    /// representation, returning what `f` does and the handle to use from now
+    /// on
+    ///
+    /// Host resources are taken out of the store while they are used, which
+    /// can give them a new handle.
+    fn with_host_resource<T: 'static, R>(
+        &mut self,
+        handle: ResourceAny,
+        f: impl FnOnce(&mut Self, u32) -> R,
+    ) -> anyhow::Result<(R, ResourceAny)> {
+        let resource = handle.try_into_resource::<T>(&mut self.store)?;
+        let result = f(self, resource.rep());
+        let handle = ResourceAny::try_from_resource(resource, &mut self.store)?;
+        Ok((result, handle))
+    }
+
+    /// Wait until the `pollable` subscribed to a stream is ready and drop it
+    fn wait_subscribed(
+        &mut self,
+        pollable: Resource<Pollable>,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<bool> {
+        use wasmtime_wasi::bindings::sync::io::poll::HostPollable;
+        let ready = self.wait_ready(pollable.rep(), timeout);
+        HostPollable::drop(&mut self.wasi(), pollable)?;
+        ready
+    }
+
+    /// The WASI implementation of the store, which host resources like
+    /// pollables and streams are used through
+    fn wasi(&mut self) -> WasiImpl<&mut Context> {
+        WasiImpl(self.store.data_mut())
+    }
+
+    /// Block until the pollable `rep` is ready, checking it every few
+    /// milliseconds when there is a `timeout`
+    fn wait_ready(&mut self, rep: u32, timeout: Option<Duration>) -> anyhow::Result<bool> {
+        use wasmtime_wasi::bindings::sync::io::poll::HostPollable;
+        let mut host = self.wasi();
+        let Some(timeout) = timeout else {
+            HostPollable::block(&mut host, Resource::new_borrow(rep))?;
+            return Ok(true);
+        };
+        let start = Instant::now();
+        loop {
+            if HostPollable::ready(&mut host, Resource::new_borrow(rep))? {
+                return Ok(true);
+            }
+            if start.elapsed() >= timeout {
+                return Ok(false);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Invoke the `run` function of the exported `wasi:cli/run` interface named
+    /// `run_interface` on a fresh instance with `args` as the guest's arguments.
+    ///
+    /// Returns the exit code of the guest. All resource handles become invalid.
+    #[tracing::instrument(name = "call", level = "debug", skip_all, fields(func = %run_interface))]
+    pub fn run(&mut self, run_interface: &str, args: &[String]) -> anyhow::Result<i32> {
+        self.resources.clear();
+        let mut wasi_config = self.wasi_config.clone();
+        wasi_config.args = args.to_vec();
+        // Commands are commonly interactive so pass stdin through unless the
+        // user explicitly configured it or the guest is sandboxed.
+        if !wasi_config.sandbox && matches!(wasi_config.stdin, GuestStdin::Empty) {
+            wasi_config.stdin = GuestStdin::Inherit;
+        }
+        self.store = build_store(
+            &self.engine,
+            &wasi_config,
+            &self.capture,
+            &self.imports.events,
+            &self.imports.trace,
+            &self.imports.exercised,
+        );
+        self.instance = self.pre.instantiate(&mut self.store)?;
+        let func = {
+            let mut exports = self.instance.exports(&mut self.store);
+            exports
+                .instance(run_interface)
+                .with_context(|| {
+                    format!("could not find exported instance with name '{run_interface}'")
+                })?
+                .func("run")
+        }
+        .with_context(|| format!("could not find function 'run' in '{run_interface}'"))?;
+        let run = func.typed::<(), (Result<(), ()>,)>(&self.store)?;
+        match run.call(&mut self.store, ()) {
+            Ok((result,)) => {
+                if let Err(e) = run.post_return(&mut self.store) {
+                    return Err(self.recover_from_post_return(e));
+                }
+                Ok(if result.is_ok() { 0 } else { 1 })
+            }
+            Err(e) => match e.downcast_ref::<wasmtime_wasi::I32Exit>() {
+                Some(exit) => Ok(exit.0),
+                None => {
+                    self.poisoned = true;
+                    Err(e)
+                }
+            },
+        }
     }
 
     /// Stub a function with an export from the component encoded in `component_bytes`
     ///
     /// This function does not check that the component in `components_bytes` has the
     /// export needed.
+    ///
+    /// When `lenient` the types of the export only have to be compatible with
+    /// the types of the import instead of equal, and values are converted
+    /// between them on every call.
+    #[tracing::instrument(name = "stub", level = "debug", skip_all, fields(import = %import_ident, stub = %stub.name))]
     pub fn stub(
         &mut self,
         resolver: &WorldResolver,
         import_ident: parser::Ident<'_>,
         export_ident: parser::Ident<'_>,
-        component_bytes: &[u8],
+        stub: &StubComponent,
+        lenient: bool,
     ) -> anyhow::Result<()> {
         match (import_ident, export_ident) {
             (parser::Ident::Item(import_ident), parser::Ident::Item(export_ident)) => {
-                self.stub_function(resolver, import_ident, export_ident, component_bytes)
+                self.stub_function(resolver, import_ident, export_ident, stub, lenient)
             }
             (parser::Ident::Interface(import_ident), parser::Ident::Interface(export_ident)) => {
-                self.stub_interface(resolver, import_ident, export_ident, component_bytes)
+                self.stub_interface(resolver, import_ident, export_ident, stub, lenient)
             }
             (parser::Ident::Interface(_), parser::Ident::Item(_)) => {
                 anyhow::bail!("cannot satisfy interface import with a function")
@@ -153,94 +828,81 @@ impl Runtime {
         }
     }
 
+    /// Stub the imported interface `import_ident` with a component built from
+    /// the WAT in the file at `path`
+    ///
+    /// The WAT can either describe a component that exports the interface or a
+    /// core module that exports the interface's functions using the canonical
+    /// ABI's naming scheme (e.g. `my:pkg/iface#func`).
+    #[tracing::instrument(name = "stub", level = "debug", skip_all, fields(import = %import_ident))]
+    pub fn stub_with_wat(
+        &mut self,
+        resolver: &WorldResolver,
+        import_ident: parser::InterfaceIdent<'_>,
+        path: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        let mut bytes = wat::parse_file(path)?;
+        if !componentize::is_component(&bytes) {
+            let (resolve, world) = resolver.stub_world(import_ident)?;
+            wit_component::embed_component_metadata(
+                &mut bytes,
+                &resolve,
+                world,
+                wit_component::StringEncoding::UTF8,
+            )?;
+            bytes = wit_component::ComponentEncoder::default()
+                .module(&bytes)?
+                .validate(true)
+                .encode()
+                .context("could not turn the core module into a component")?;
+        }
+        let stub = StubComponent::new(path, bytes);
+        self.stub_interface(resolver, import_ident, import_ident, &stub, false)
+    }
+
     pub fn stub_interface(
         &mut self,
         resolver: &WorldResolver,
         import_ident: parser::InterfaceIdent<'_>,
         export_ident: parser::InterfaceIdent<'_>,
-        component_bytes: &[u8],
+        stub: &StubComponent,
+        lenient: bool,
     ) -> anyhow::Result<()> {
-        let component = load_component(&self.engine, component_bytes)?;
-        let mut linker = Linker::<ImportImplsContext>::new(&self.engine);
-        wasmtime_wasi::add_to_linker_sync(&mut linker)?;
-        let mut root = self.linker.root();
-        let mut import_instance = root
-            .instance(&import_ident.to_string())
-            .with_context(|| format!("no imported instance named '{import_ident}' found"))?;
         let import = resolver
             .imported_interface(import_ident)
             .with_context(|| format!("no imported interface named '{import_ident}' found"))?;
-        let other = WorldResolver::from_bytes(component_bytes)?;
+        let other = WorldResolver::from_bytes(&stub.bytes)?;
         let export = other
             .exported_interface(export_ident)
             .with_context(|| format!("no exported interface named '{export_ident}' found"))?;
-        {
-            let mut store_lock = self.import_impls.store.lock().unwrap();
-            let export_instance = linker.instantiate(&mut *store_lock, &component)?;
-            for (fun_name, imported_function) in &import.functions {
-                let exported_function = export
-                    .functions
-                    .get(fun_name)
-                    .with_context(|| format!("no exported function named '{fun_name}' found"))?;
-                if imported_function.params.len() != exported_function.params.len() {
-                    anyhow::bail!("different number of parameters")
-                }
-                for ((arg_name, p1), (_, p2)) in imported_function
-                    .params
-                    .iter()
-                    .zip(&exported_function.params)
-                {
-                    if !types_equal(resolver, p1, &other, p2) {
-                        anyhow::bail!(
-                            "different types for arg '{arg_name}' in function '{fun_name}'"
-                        )
-                    }
-                }
-                match (&imported_function.results, &exported_function.results) {
-                    (wit_parser::Results::Named(is), wit_parser::Results::Named(es)) => {
-                        if is.len() != es.len() {
-                            anyhow::bail!("different number of return types")
-                        }
-                        let es = es
-                            .iter()
-                            .map(|(name, ty)| (name, ty))
-                            .collect::<HashMap<&String, &wit_parser::Type>>();
-                        for (name, ty) in is {
-                            let e = es.get(name).with_context(|| format!("exported function '{fun_name}' does not have return value '{name}'"))?;
-                            if !types_equal(resolver, ty, &other, e) {
-                                anyhow::bail!("return value '{name}' has differing types");
-                            }
-                        }
-                    }
-                    (wit_parser::Results::Anon(t1), wit_parser::Results::Anon(t2)) => {
-                        if !types_equal(resolver, t1, &other, t2) {
-                            anyhow::bail!("return types did not match for function {fun_name}");
-                        }
-                    }
-                    _ => anyhow::bail!("different return type kinds for function '{fun_name}'"),
-                }
-                let store = self.import_impls.store.clone();
-
-                let export_func = {
-                    let mut exports = export_instance.exports(&mut *store_lock);
-                    let mut export_instance = exports
-                        .instance(&export_ident.to_string())
-                        .with_context(|| {
-                            format!("no exported instance named '{export_ident} found'")
-                        })?;
-                    export_instance
-                        .func(fun_name)
-                        .with_context(|| format!("no exported function named '{fun_name}' found"))?
-                };
-                import_instance.func_new(fun_name, move |_ctx, args, results| {
-                    let mut store = store.lock().unwrap();
-                    export_func.call(&mut *store, args, results)?;
-                    export_func.post_return(&mut *store)?;
-                    Ok(())
-                })?;
-            }
+        for (fun_name, imported_function) in &import.functions {
+            let exported_function = export
+                .functions
+                .get(fun_name)
+                .with_context(|| format!("no exported function named '{fun_name}' found"))?;
+            check_function_types(
+                resolver,
+                imported_function,
+                &other,
+                exported_function,
+                lenient,
+            )?;
         }
-        self.refresh()?;
+        tracing::debug!(
+            "Stubbing '{import_ident}' with the export '{export_ident}' of '{}'",
+            stub.name
+        );
+        let instance = self.import_impls.instantiate(&self.engine, stub)?;
+        for fun_name in import.functions.keys() {
+            let ident = parser::ItemIdent {
+                interface: Some(import_ident),
+                item: fun_name,
+            };
+            let export = (Some(export_ident.to_string()), fun_name.clone());
+            let func = self.forward_to_export(ident, instance.clone(), export, lenient)?;
+            self.define_import(ident, func);
+        }
+        self.update_imports()?;
         Ok(())
     }
 
@@ -249,202 +911,2814 @@ impl Runtime {
         resolver: &WorldResolver,
         import_ident: parser::ItemIdent<'_>,
         export_ident: parser::ItemIdent<'_>,
-        component_bytes: &[u8],
+        stub: &StubComponent,
+        lenient: bool,
     ) -> anyhow::Result<()> {
         // type checking
         let import = resolver
             .imported_function(import_ident)
             .with_context(|| format!("no import with name '{import_ident}'"))?;
-        let other = WorldResolver::from_bytes(component_bytes)?;
+        let other = WorldResolver::from_bytes(&stub.bytes)?;
         let export = other
             .exported_function(export_ident)
             .with_context(|| format!("no export with name '{export_ident}'"))?;
-        if import.params != export.params {
-            anyhow::bail!("params not equal")
-        }
-        if import.results != export.results {
-            anyhow::bail!("return values not equal")
-        }
-
-        let component = load_component(&self.engine, component_bytes)?;
-        let mut linker = Linker::<ImportImplsContext>::new(&self.engine);
-        wasmtime_wasi::add_to_linker_sync(&mut linker)?;
-        let export_func = {
-            let mut store_lock = self.import_impls.store.lock().unwrap();
-            let export_instance = linker.instantiate(&mut *store_lock, &component)?;
-            match export_ident.interface {
-                Some(interface) => {
-                    let mut export = export_instance.exports(&mut *store_lock);
-                    let mut instance = export
-                        .instance(&interface.to_string())
-                        .with_context(|| format!("no export named '{interface} found'"))?;
-                    instance.func(export_ident.item)
-                }
-                None => export_instance.get_func(&mut *store_lock, export_ident.item),
-            }
+        check_function_types(resolver, import, &other, export, lenient)?;
+        tracing::debug!(
+            "Stubbing '{import_ident}' with the export '{export_ident}' of '{}'",
+            stub.name
+        );
+        let instance = self.import_impls.instantiate(&self.engine, stub)?;
+        let func =
+            self.forward_to_export(import_ident, instance, import_key(export_ident), lenient)?;
+        self.define_import(import_ident, func);
+        self.update_imports()?;
+        Ok(())
+    }
+
+    /// A definition for the imported function `import_ident` which calls the
+    /// function `export` of the stub `instance`
+    ///
+    /// The function is looked up on every call so that it keeps working when
+    /// the stub is reset. When `lenient` the arguments and results are
+    /// converted between the types of the import and export.
+    fn forward_to_export(
+        &self,
+        import_ident: parser::ItemIdent<'_>,
+        instance: Arc<Mutex<StubInstance>>,
+        export: ImportKey,
+        lenient: bool,
+    ) -> anyhow::Result<ImportFunc> {
+        // Make sure the function exists before it is called
+        let (export_func, stub) = {
+            let mut instance = instance.lock().unwrap();
+            (instance.func(&export)?, instance.name.clone())
+        };
+        let name = import_ident.to_string();
+        if !lenient {
+            return Ok(Arc::new(move |_ctx, args, results| {
+                lock_stub(&instance, &stub, &name)?.call(&name, &export, args, results)
+            }));
         }
-        .with_context(|| format!("no function found named '{export_ident}'"))?;
+        let import_results = self.imported_function_results(import_ident)?;
+        let (export_params, export_results) = {
+            let instance = instance.lock().unwrap();
+            (
+                export_func.params(&instance.store),
+                export_func.results(&instance.store),
+            )
+        };
+        Ok(Arc::new(move |_ctx, args, results| {
+            let args = args
+                .iter()
+                .zip(export_params.iter())
+                .map(|(arg, ty)| coerce_val(arg.clone(), ty))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let mut export_values = vec![Val::Bool(false); export_results.len()];
+            lock_stub(&instance, &stub, &name)?.call(&name, &export, &args, &mut export_values)?;
+            for ((result, value), ty) in results.iter_mut().zip(export_values).zip(&import_results)
+            {
+                *result = coerce_val(value, ty)?;
+            }
+            Ok(())
+        }))
+    }
 
-        let store = self.import_impls.store.clone();
-        let name = import_ident.item.to_owned();
-        match import_ident.interface {
-            Some(interface) => {
-                let mut instance = self
-                    .linker
-                    .instance(&interface.to_string())
-                    .with_context(|| format!("no interface named '{interface}' found"))?;
-                instance.func_new(&name, move |_ctx, args, results| {
-                    let mut store = store.lock().unwrap();
-                    export_func.call(&mut *store, args, results)?;
-                    export_func.post_return(&mut *store)?;
-                    Ok(())
-                })?;
+    /// Satisfy the imported interface or function `import_ident` with the
+    /// export of the same name of the live instance `exporter`, whose world is
+    /// described by `other`
+    ///
+    /// Calls go to the exporter's current instance so they share its state,
+    /// which is why links between instances cannot be cyclic.
+    #[tracing::instrument(name = "link", level = "debug", skip_all, fields(import = %import_ident, exporter = exporter.0))]
+    pub fn link_instance(
+        &mut self,
+        resolver: &WorldResolver,
+        import_ident: parser::Ident<'_>,
+        other: &WorldResolver,
+        exporter: (&str, Arc<Mutex<Runtime>>),
+    ) -> anyhow::Result<()> {
+        let functions = match import_ident {
+            parser::Ident::Item(ident) => {
+                let import = resolver
+                    .imported_function(ident)
+                    .with_context(|| format!("no import with name '{ident}'"))?;
+                let export = other
+                    .exported_function(ident)
+                    .with_context(|| format!("'{}' has no export named '{ident}'", exporter.0))?;
+                check_function_types(resolver, import, other, export, false)?;
+                vec![ident]
             }
-            None => {
-                self.linker
-                    .root()
-                    .func_new(&name, move |_ctx, args, results| {
-                        let mut store = store.lock().unwrap();
-                        export_func.call(&mut *store, args, results)?;
-                        export_func.post_return(&mut *store)?;
-                        Ok(())
+            parser::Ident::Interface(ident) => {
+                let import = resolver
+                    .imported_interface(ident)
+                    .with_context(|| format!("no imported interface named '{ident}' found"))?;
+                let export = other.exported_interface(ident).with_context(|| {
+                    format!("'{}' has no exported interface named '{ident}'", exporter.0)
+                })?;
+                for (fun_name, imported_function) in &import.functions {
+                    let exported_function = export.functions.get(fun_name).with_context(|| {
+                        format!("no exported function named '{fun_name}' found")
                     })?;
+                    check_function_types(
+                        resolver,
+                        imported_function,
+                        other,
+                        exported_function,
+                        false,
+                    )?;
+                }
+                import
+                    .functions
+                    .keys()
+                    .map(|fun_name| parser::ItemIdent {
+                        interface: Some(ident),
+                        item: fun_name,
+                    })
+                    .collect()
             }
+        };
+        for ident in functions {
+            let (name, instance) = (exporter.0.to_owned(), exporter.1.clone());
+            let export = import_key(ident);
+            self.define_import(
+                ident,
+                Arc::new(move |_ctx, args, results| {
+                    let Some(mut runtime) = CallLock::new(&instance) else {
+                        anyhow::bail!(
+                            "'{name}' is already running a call, links between instances cannot be cyclic"
+                        );
+                    };
+                    let result = runtime.call_export(&export, args, results);
+                    if runtime.is_poisoned() {
+                        runtime.refresh()?;
+                    }
+                    result.with_context(|| format!("call into '{name}' failed"))
+                }),
+            );
         }
-        self.refresh()?;
-        Ok(())
+        self.update_imports()
     }
 
-    pub fn set_component(&mut self, component: Vec<u8>) -> anyhow::Result<()> {
-        self.component = (Component::from_binary(&self.engine, &component)?, component);
-        self.refresh()
+    /// Call the exported function `name` of the exported instance `interface`
+    fn call_export(
+        &mut self,
+        (interface, name): &ImportKey,
+        args: &[Val],
+        results: &mut [Val],
+    ) -> anyhow::Result<()> {
+        let values = self
+            .call_recorded(interface.as_deref(), name, args)?
+            .results;
+        if values.len() != results.len() {
+            anyhow::bail!(
+                "the export returns {} results but the import expects {}",
+                values.len(),
+                results.len()
+            );
+        }
+        results.clone_from_slice(&values);
+        Ok(())
     }
 
-    pub fn compose(&mut self, adapter: &[u8]) -> Result<(), anyhow::Error> {
-        let temp = std::env::temp_dir();
-        let tmp_virt = temp.join("virt.wasm");
-        std::fs::write(&tmp_virt, adapter)?;
-        let tmp_component = temp.join("component.wasm");
-        std::fs::write(&tmp_component, &self.component.1)?;
-
-        let bytes = wasm_compose::composer::ComponentComposer::new(
-            &tmp_component,
-            &wasm_compose::config::Config {
-                definitions: vec![tmp_virt],
-                ..Default::default()
-            },
+    /// The result types of the imported function `import_ident`
+    pub fn imported_function_results(
+        &self,
+        import_ident: parser::ItemIdent<'_>,
+    ) -> anyhow::Result<Vec<component::Type>> {
+        let interface = import_ident.interface.map(|i| i.to_string());
+        import_result_types(
+            &self.engine,
+            &self.component.0.component_type(),
+            interface.as_deref(),
+            import_ident.item,
         )
-        .compose()?;
-        self.set_component(bytes)
+        .with_context(|| format!("no imported function named '{import_ident}' found"))
     }
 
-    pub fn component_bytes(&self) -> &[u8] {
-        &self.component.1
+    /// Stub the imported function `import_ident` with one that returns `results`
+    /// every time it is called
+    ///
+    /// The results are expected to already be type checked against the import.
+    #[tracing::instrument(name = "stub", level = "debug", skip_all, fields(import = %import_ident))]
+    pub fn stub_constant(
+        &mut self,
+        import_ident: parser::ItemIdent<'_>,
+        results: Vec<Val>,
+    ) -> anyhow::Result<()> {
+        self.define_import(
+            import_ident,
+            Arc::new(move |_ctx, _args, rets| {
+                rets.clone_from_slice(&results);
+                Ok(())
+            }),
+        );
+        self.update_imports()
     }
 
-    /// Get a new instance
-    pub fn refresh(&mut self) -> anyhow::Result<()> {
-        self.store = build_store(&self.engine);
-        self.instance = self
-            .linker
-            .instantiate(&mut self.store, &self.component.0)?;
-        Ok(())
+    /// Stub every function of the imported interface `import_ident` with one
+    /// that returns default values of its result types
+    ///
+    /// Functions returning imported resources return new instances of them.
+    ///
+    /// Returns the number of functions that were stubbed.
+    pub fn mock(
+        &mut self,
+        resolver: &WorldResolver,
+        import_ident: parser::InterfaceIdent<'_>,
+    ) -> anyhow::Result<usize> {
+        let import = resolver
+            .imported_interface(import_ident)
+            .with_context(|| format!("no imported interface named '{import_ident}' found"))?;
+        for fun_name in import.functions.keys() {
+            let ident = parser::ItemIdent {
+                interface: Some(import_ident),
+                item: fun_name,
+            };
+            let result_types = self.imported_function_results(ident)?;
+            let resources = self.imports.resources.clone();
+            let name = ident.to_string();
+            self.define_import(
+                ident,
+                Arc::new(move |mut ctx, _args, rets| {
+                    for (ret, ty) in rets.iter_mut().zip(&result_types) {
+                        *ret =
+                            default_val(ty, &mut |ty| new_host_resource(&mut ctx, ty, &resources))
+                                .with_context(|| format!("cannot mock '{name}'"))?;
+                    }
+                    Ok(())
+                }),
+            );
+        }
+        self.update_imports()?;
+        Ok(import.functions.len())
     }
-}
-
-/// A collection of instances that implement the main components imports
-struct ImportImpls {
-    store: Arc<Mutex<Store<ImportImplsContext>>>,
-}
-
-impl ImportImpls {
-    fn new(engine: &Engine, prefix: String) -> Self {
-        let table = ResourceTable::new();
-        let mut builder = WasiCtxBuilder::new();
-        builder.inherit_stderr();
-        builder.stdout(ImportImplStdout::new(prefix));
-        let wasi = builder.build();
-        let context = ImportImplsContext::new(table, wasi);
-        let store = Store::new(engine, context);
 
-        Self {
-            store: Arc::new(Mutex::new(store)),
+    /// Implement the imported interfaces of `wasi:keyvalue` with in-memory
+    /// stores, returning the number of functions implemented
+    ///
+    /// Buckets are host resources remembering the store they were opened
+    /// from, and the stores outlive instances so they can be inspected and
+    /// seeded with [`Runtime::keyvalue`].
+    pub fn provide_keyvalue(&mut self) -> anyhow::Result<usize> {
+        let (index, imported) = self
+            .imports
+            .resources
+            .iter()
+            .enumerate()
+            .find(|(_, imported)| {
+                keyvalue::interface_name(&imported.resource.interface) == Some("store")
+                    && imported.resource.name == "bucket"
+            })
+            .context("the component does not import wasi:keyvalue/store")?;
+        let kind = HOST_RESOURCE_KINDS.get(index).with_context(|| {
+            format!(
+                "the component imports more than {} resources",
+                HOST_RESOURCE_KINDS.len()
+            )
+        })?;
+        let (new_own, rep) = (kind.new_own, kind.rep);
+        let bucket = imported.resource.clone();
+        let keys = self
+            .imports
+            .funcs
+            .keys()
+            .filter(|(interface, name)| {
+                interface
+                    .as_deref()
+                    .and_then(keyvalue::interface_name)
+                    .is_some()
+                    && KeyValueStores::implements(
+                        name.strip_prefix("[method]bucket.").unwrap_or(name),
+                    )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for key @ (_, name) in &keys {
+            let stores = self.keyvalue.clone();
+            let bucket = bucket.clone();
+            let name = name
+                .strip_prefix("[method]bucket.")
+                .unwrap_or(name)
+                .to_owned();
+            let func: ImportFunc = Arc::new(move |mut ctx, args, results| {
+                let result = match (name.as_str(), args) {
+                    ("open", [Val::String(identifier)]) => {
+                        stores.open(identifier);
+                        let handle = ctx.data_mut().push_host_resource(HostResource {
+                            resource: bucket.clone(),
+                            bucket: Some(identifier.clone()),
+                            http: None,
+                        })?;
+                        let handle = new_own(ctx.as_context_mut(), handle.rep())?;
+                        Val::Result(Ok(Some(Box::new(Val::Resource(handle)))))
+                    }
+                    (_, [Val::Resource(handle), args @ ..]) => {
+                        let rep = rep(*handle, ctx.as_context_mut())?;
+                        let store = ctx
+                            .data()
+                            .table
+                            .get(&Resource::<HostResource>::new_borrow(rep))?
+                            .bucket
+                            .clone()
+                            .context("the bucket was not opened from the stores wepl provides")?;
+                        stores.call(&store, &name, args)?
+                    }
+                    _ => anyhow::bail!("unexpected arguments to wasi:keyvalue#{name}"),
+                };
+                let [ret] = results else {
+                    anyhow::bail!("unexpected results of wasi:keyvalue#{name}");
+                };
+                *ret = result;
+                Ok(())
+            });
+            self.define_import_key(key.clone(), func);
         }
+        self.update_imports()?;
+        Ok(keys.len())
     }
-}
-
-struct ImportImplStdout {
-    stream: Box<dyn HostOutputStream>,
-    prefix: String,
-}
 
-impl ImportImplStdout {
-    fn new(prefix: String) -> Self {
-        let prefix = format!("<{}>", prefix).green().bold();
-        let stream = Stdout.stream();
-        Self {
-            stream,
-            prefix: prefix.to_string(),
+    /// A new `wasi:http/types#outgoing-request` for `request`, which can be
+    /// passed to the component's exports
+    ///
+    /// The request is tracked like the resources the component returns so
+    /// that it is valid until it is passed to the component or dropped.
+    pub fn new_http_request(&mut self, request: http::Request) -> anyhow::Result<Val> {
+        let resources = http_resources(&self.imports.resources)
+            .context("the component does not import wasi:http/types#outgoing-request")?;
+        let val = new_http_resource(
+            self.store.as_context_mut(),
+            &resources,
+            HttpResource::Request(request),
+        )?;
+        if let Val::Resource(handle) = val {
+            self.track_resource(handle, resources[1].1.clone());
         }
+        Ok(val)
     }
-}
 
-#[async_trait::async_trait]
-impl HostOutputStream for ImportImplStdout {
-    fn write(&mut self, bytes: bytes::Bytes) -> StreamResult<()> {
-        let output = String::from_utf8_lossy(&bytes);
-        let output = format!("{} {output}", self.prefix);
-        self.stream.write(output.into_bytes().into())
+    /// The stores behind `wasi:keyvalue` when it is provided by wepl
+    pub fn keyvalue(&self) -> &KeyValueStores {
+        &self.keyvalue
     }
 
-    fn flush(&mut self) -> StreamResult<()> {
-        self.stream.flush()
+    /// Use `stores` behind `wasi:keyvalue` from now on, e.g. to keep the
+    /// stores of the runtime being replaced
+    ///
+    /// Must be called before `wasi:keyvalue` is provided.
+    pub fn set_keyvalue(&mut self, stores: KeyValueStores) {
+        self.keyvalue = stores;
     }
 
-    fn check_write(&mut self) -> StreamResult<usize> {
-        self.stream.check_write()
+    /// The host-side queues standing in for streams and futures
+    pub fn streams(&self) -> &HostStreams {
+        &self.streams
     }
 
-    async fn write_ready(&mut self) -> StreamResult<usize> {
-        self.stream.write_ready().await
+    /// Use `streams` from now on, e.g. to keep the queues of the runtime being
+    /// replaced
+    pub fn set_streams(&mut self, streams: HostStreams) {
+        self.streams = streams;
     }
-}
 
-#[async_trait::async_trait]
-impl Subscribe for ImportImplStdout {
-    async fn ready(&mut self) {
-        self.stream.ready().await
+    /// Start recording every call to an import along with its results into
+    /// the JSON file at `path`, or stop recording with `None`
+    ///
+    /// Returns the recorder that was stopped, if any.
+    pub fn record_imports(
+        &mut self,
+        path: Option<std::path::PathBuf>,
+    ) -> anyhow::Result<Option<ImportRecorder>> {
+        let recorder = path.map(ImportRecorder::start).transpose()?;
+        Ok(std::mem::replace(
+            &mut *self.imports.recorder.lock().unwrap(),
+            recorder,
+        ))
+    }
+
+    /// Freeze the clocks and seed the randomness the component sees, unless
+    /// they already are, and start recording every call to an import into
+    /// the world bundle at `path` along with them and the environment, or
+    /// stop recording with `None`
+    ///
+    /// Returns the recorder that was stopped, if any.
+    pub fn freeze_world(
+        &mut self,
+        path: Option<std::path::PathBuf>,
+    ) -> anyhow::Result<Option<ImportRecorder>> {
+        let Some(path) = path else {
+            return self.record_imports(None);
+        };
+        let clock = &self.wasi_config.clock;
+        let now = clock.frozen_at().unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+        });
+        // The bundle only keeps whole seconds
+        let now = Duration::from_secs(now.as_secs());
+        clock.freeze(now);
+        let random_seed = match self.wasi_config.random_seed {
+            Some(seed) => seed,
+            None => {
+                let seed = rand::random();
+                self.set_random_seed(Some(seed))?;
+                seed
+            }
+        };
+        let world = recording::World {
+            clock: now,
+            random_seed,
+            env: self.wasi_config.env.clone(),
+        };
+        let recorder = ImportRecorder::start_bundle(path, Some(world))?;
+        Ok(self.imports.recorder.lock().unwrap().replace(recorder))
+    }
+
+    /// Stub every import with calls in the recording at `path` with one
+    /// returning the recorded results in the order they were recorded,
+    /// returning the number of calls recorded for each import
+    ///
+    /// Calls made with other arguments than the ones recorded are answered
+    /// all the same but print a warning, and calls beyond the recorded ones
+    /// fail.
+    pub fn replay_imports(
+        &mut self,
+        path: &std::path::Path,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let mut replayed = Vec::new();
+        for (name, calls) in recording::read(path)? {
+            let key = match name.split_once('#') {
+                Some((interface, item)) => (Some(interface.to_owned()), item.to_owned()),
+                None => (None, name.clone()),
+            };
+            if !self.imports.funcs.contains_key(&key) {
+                anyhow::bail!("the component does not import '{name}'");
+            }
+            let result_types = import_result_types(
+                &self.engine,
+                &self.component.0.component_type(),
+                key.0.as_deref(),
+                &key.1,
+            )
+            .with_context(|| format!("no imported function named '{name}' found"))?;
+            replayed.push((name.clone(), calls.len()));
+            let calls = Mutex::new(calls);
+            let import = name.clone();
+            let events = self.imports.events.clone();
+            let func: ImportFunc = Arc::new(move |_ctx, args, results| {
+                let call =
+                    calls.lock().unwrap().pop_front().with_context(|| {
+                        format!("every recorded call to '{import}' was replayed")
+                    })?;
+                let params = args
+                    .iter()
+                    .map(json::val_to_json)
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                if params != call.params {
+                    events.emit(Event::Warning(format!(
+                        "'{import}' was called with {} but recorded with {}",
+                        serde_json::Value::Array(params),
+                        serde_json::Value::Array(call.params),
+                    )));
+                }
+                if call.results.len() != results.len() {
+                    anyhow::bail!(
+                        "expected {} results from '{import}' but {} were recorded",
+                        results.len(),
+                        call.results.len()
+                    )
+                }
+                for ((result, value), ty) in
+                    results.iter_mut().zip(&call.results).zip(&result_types)
+                {
+                    *result = json::json_to_val(value, ty)
+                        .with_context(|| format!("invalid recorded result of '{import}'"))?;
+                }
+                Ok(())
+            });
+            self.define_import_key(key, func);
+        }
+        self.update_imports()?;
+        Ok(replayed)
+    }
+
+    /// Stub the imported function `import_ident` with its current definition
+    /// but record every call to it
+    pub fn spy(&mut self, import_ident: parser::ItemIdent<'_>) -> anyhow::Result<()> {
+        let name = import_ident.to_string();
+        if self.spied.contains(&name) {
+            anyhow::bail!("already spying on '{name}'")
+        }
+        let inner = self
+            .imports
+            .funcs
+            .get(&import_key(import_ident))
+            .cloned()
+            .with_context(|| {
+                format!("no host definition of the imported function '{name}' to spy on")
+            })?;
+        let calls = self.calls.clone();
+        calls.lock().unwrap().entry(name.clone()).or_default();
+        let spy_name = name.clone();
+        self.define_import(
+            import_ident,
+            Arc::new(move |ctx, args, results| {
+                let result = inner(ctx, args, results);
+                calls
+                    .lock()
+                    .unwrap()
+                    .entry(spy_name.clone())
+                    .or_default()
+                    .push(ImportCall {
+                        args: args.to_vec(),
+                        results: results.to_vec(),
+                        at: SystemTime::now(),
+                    });
+                result
+            }),
+        );
+        self.spied.insert(name);
+        self.update_imports()
+    }
+
+    /// The calls recorded by spies by the name of the imported function
+    pub fn calls(&self) -> HashMap<String, Vec<ImportCall>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Forget all calls recorded by spies
+    pub fn clear_calls(&mut self) {
+        for calls in self.calls.lock().unwrap().values_mut() {
+            calls.clear();
+        }
+    }
+
+    /// Restore the imported function `import_ident` to its definition on startup
+    pub fn unstub(&mut self, import_ident: parser::ItemIdent<'_>) -> anyhow::Result<()> {
+        let key = import_key(import_ident);
+        let current = self.imports.funcs.get(&key);
+        match (current, self.imports.defaults.get(&key)) {
+            (Some(current), Some(default)) if !Arc::ptr_eq(current, default) => {
+                self.imports.funcs.insert(key, default.clone());
+            }
+            // Functions without a default are provided by WASI
+            (Some(_), None) => {
+                self.imports.funcs.remove(&key);
+            }
+            _ => anyhow::bail!("'{import_ident}' is not stubbed"),
+        }
+        self.spied.remove(&import_ident.to_string());
+        self.update_imports()
+    }
+
+    /// Restore all imported functions to their definitions on startup
+    pub fn unstub_all(&mut self) -> anyhow::Result<()> {
+        self.imports.funcs = self.imports.defaults.clone();
+        self.spied.clear();
+        self.update_imports()
+    }
+
+    /// Define the imported function `import_ident`, shadowing any previous
+    /// definition once the instance is refreshed
+    fn define_import(&mut self, import_ident: parser::ItemIdent<'_>, func: ImportFunc) {
+        self.define_import_key(import_key(import_ident), func);
+    }
+
+    /// Define the imported function with the key `key`, which unlike an
+    /// identifier can name interfaces with a version
+    fn define_import_key(&mut self, key: ImportKey, func: ImportFunc) {
+        let name = match &key {
+            (Some(interface), name) => format!("{interface}#{name}"),
+            (None, name) => name.clone(),
+        };
+        tracing::debug!("Defining import '{name}'");
+        self.spied.remove(&name);
+        self.imports.funcs.insert(key, func);
+    }
+
+    pub fn set_component(&mut self, component: Vec<u8>) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let spinner = progress::Spinner::start("Compiling the component");
+        let compiled = match &self.cache {
+            Some(cache) => cache.load(&self.engine, &component)?,
+            None => compile_component(&self.engine, &component)?,
+        };
+        drop(spinner);
+        tracing::debug!("Loaded the component in {:?}", start.elapsed());
+        self.component = (compiled, component);
+        self.precompiled = false;
+        // The linked component only saves linking the same component again
+        self.link()?;
+        self.rebuild("the component changed")
+    }
+
+    /// Compose the component according to `config` (e.g. with the adapters in
+    /// its definitions) and switch to the composed component
+    #[tracing::instrument(name = "compose", level = "debug", skip_all)]
+    pub fn compose(&mut self, config: wasm_compose::config::Config) -> Result<(), anyhow::Error> {
+        let _spinner = progress::Spinner::start("Composing the component");
+        // wasm-compose reads the component from a file (named after the
+        // component), which goes in a directory of its own so that sessions
+        // composing at once do not share it
+        let tmp_dir = std::env::temp_dir().join(format!(
+            "wepl-compose-{}-{:016x}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir(&tmp_dir)
+            .with_context(|| format!("could not create '{}'", tmp_dir.display()))?;
+        let tmp_component = tmp_dir.join("component.wasm");
+        let composed = std::fs::write(&tmp_component, &self.component.1)
+            .with_context(|| format!("could not write '{}'", tmp_component.display()))
+            .and_then(|()| {
+                wasm_compose::composer::ComponentComposer::new(&tmp_component, &config).compose()
+            });
+        let _ = std::fs::remove_dir_all(&tmp_dir);
+        self.set_component(composed?)
+    }
+
+    /// The call budgets set with [`Runtime::set_limit`] by the imports they
+    /// limit
+    pub fn limits(&self) -> BTreeMap<String, CallBudget> {
+        self.imports.budgets.lock().unwrap().clone()
+    }
+
+    /// Let the component make at most `max` more calls to the import
+    /// `import`, an interface or a function, after which calls to it fail, or
+    /// lift the limit without a maximum
+    pub fn set_limit(&mut self, import: &str, max: Option<usize>) -> anyhow::Result<()> {
+        let mut budgets = self.imports.budgets.lock().unwrap();
+        let Some(max) = max else {
+            if budgets.remove(import).is_none() {
+                anyhow::bail!("calls to '{import}' are not limited");
+            }
+            return Ok(());
+        };
+        if !self
+            .imports
+            .funcs
+            .keys()
+            .any(|key| limits_import(import, key))
+        {
+            anyhow::bail!(
+                "no import named '{import}' is defined by wepl, so calls to it cannot be limited"
+            );
+        }
+        budgets.insert(import.to_owned(), CallBudget { max, calls: 0 });
+        Ok(())
+    }
+
+    /// Whether the component used the import `import` during the session
+    ///
+    /// `None` when wepl cannot tell because wasmtime implements the import
+    /// without anything wepl handed to the guest.
+    pub fn exercised(&self, import: &str) -> Option<bool> {
+        let defined = self
+            .imports
+            .funcs
+            .keys()
+            .any(|(interface, name)| interface.as_deref().unwrap_or(name) == import);
+        let observed = defined || audit::OBSERVED_WASI.contains(&audit::unversioned(import));
+        observed.then(|| self.imports.exercised.contains(import))
+    }
+
+    pub fn wasi_config(&self) -> &WasiConfig {
+        &self.wasi_config
+    }
+
+    /// Set the environment variable `key` to `value` and refresh the instance
+    pub fn set_env(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        if self.wasi_config.sandbox {
+            anyhow::bail!("environment variables are not allowed with --sandbox");
+        }
+        match self.wasi_config.env.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self
+                .wasi_config
+                .env
+                .push((key.to_owned(), value.to_owned())),
+        }
+        self.rebuild("its environment changed")
+    }
+
+    /// Remove the environment variable `key` and refresh the instance
+    pub fn unset_env(&mut self, key: &str) -> anyhow::Result<()> {
+        self.wasi_config.env.retain(|(k, _)| k != key);
+        self.rebuild("its environment changed")
+    }
+
+    /// Set the seed for deterministic randomness (or `None` to use real
+    /// randomness) and refresh the instance
+    pub fn set_random_seed(&mut self, seed: Option<u64>) -> anyhow::Result<()> {
+        self.wasi_config.random_seed = seed;
+        self.rebuild("its source of randomness changed")
+    }
+
+    /// Set what the guest reads from stdin and refresh the instance
+    pub fn set_stdin(&mut self, stdin: GuestStdin) -> anyhow::Result<()> {
+        if self.wasi_config.sandbox && matches!(stdin, GuestStdin::Inherit) {
+            anyhow::bail!("inheriting stdin is not allowed with --sandbox");
+        }
+        self.wasi_config.stdin = stdin;
+        self.rebuild("its stdin changed")
+    }
+
+    /// Whether the guest's stdout and stderr are being captured
+    pub fn capture_enabled(&self) -> bool {
+        self.capture.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Start or stop capturing the guest's stdout and stderr
+    ///
+    /// Output is still printed while being captured.
+    pub fn set_capture(&mut self, enabled: bool) {
+        self.capture.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Take the stdout and stderr captured since the last call to this function
+    pub fn take_captured(&mut self) -> (String, String) {
+        let take = |buffer: &Mutex<Vec<u8>>| {
+            let bytes = std::mem::take(&mut *buffer.lock().unwrap());
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+        (take(&self.capture.stdout), take(&self.capture.stderr))
+    }
+
+    /// Receives the records the guest logs, if it imports `wasi:logging`
+    pub fn logger(&self) -> Option<&GuestLogger> {
+        self.logger.as_ref()
+    }
+
+    /// Take the records the guest logged while its output was captured since
+    /// the last call to this function
+    pub fn take_captured_logs(&mut self) -> Vec<LogRecord> {
+        self.logger
+            .as_ref()
+            .map(GuestLogger::take_captured)
+            .unwrap_or_default()
+    }
+
+    /// The values the guest reads through `wasi:config/store`, if it imports
+    /// it
+    pub fn config_values(&self) -> Option<BTreeMap<String, String>> {
+        Some(self.config.as_ref()?.lock().unwrap().clone())
+    }
+
+    /// Set the value of `key` in `wasi:config/store`, which the guest sees
+    /// from its next read on
+    pub fn set_config_value(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let config = self
+            .config
+            .as_ref()
+            .context("the component does not import wasi:config/store")?;
+        config
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), value.to_owned());
+        match self.wasi_config.config.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self
+                .wasi_config
+                .config
+                .push((key.to_owned(), value.to_owned())),
+        }
+        Ok(())
+    }
+
+    /// Whether calls to imports that were not satisfied prompt the user for
+    /// the return values
+    pub fn prompt_imports(&self) -> bool {
+        self.prompt_imports.load(Ordering::SeqCst)
+    }
+
+    /// Set whether calls to imports that were not satisfied prompt the user
+    /// for the return values instead of just warning about the call
+    pub fn set_prompt_imports(&mut self, prompt: bool) {
+        self.prompt_imports.store(prompt, Ordering::SeqCst);
+    }
+
+    /// The WASI configuration of the stub named `name`
+    pub fn stub_config(&self, name: &str) -> StubConfig {
+        self.import_impls
+            .configs
+            .get(name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The WASI configurations of all stubs that have one by their names
+    pub fn stub_configs(&self) -> &BTreeMap<String, StubConfig> {
+        &self.import_impls.configs
+    }
+
+    /// Change the WASI configuration of the stub named `name`
+    ///
+    /// The configuration is used the next time the stub is instantiated.
+    pub fn set_stub_config(&mut self, name: &str, config: StubConfig) -> anyhow::Result<()> {
+        if self.wasi_config.sandbox {
+            if !config.env.is_empty() {
+                anyhow::bail!("environment variables are not allowed with --sandbox");
+            }
+            if !config.dirs.is_empty() {
+                anyhow::bail!("directories are not allowed with --sandbox");
+            }
+            if let GuestStdin::Inherit = config.stdin {
+                anyhow::bail!("inheriting stdin is not allowed with --sandbox");
+            }
+        }
+        self.import_impls.configs.insert(name.to_owned(), config);
+        Ok(())
+    }
+
+    /// Instantiate the stub named `name` again in a fresh store, dropping its
+    /// state and applying its current WASI configuration
+    pub fn reset_stub(&mut self, name: &str) -> anyhow::Result<()> {
+        self.import_impls.reset(&self.engine, name)
+    }
+
+    /// The style of the output of `source`
+    pub fn output_style(&self, source: OutputSource) -> OutputStyle {
+        self.imports.events.style(source)
+    }
+
+    /// Change the style of the output of `source`
+    pub fn set_output_style(&mut self, source: OutputSource, style: OutputStyle) {
+        self.imports.events.set_style(source, style);
+    }
+
+    /// Where the runtime emits what happens while the component is called
+    pub fn events(&self) -> &Events {
+        &self.imports.events
+    }
+
+    pub fn component_bytes(&self) -> &[u8] {
+        &self.component.1
+    }
+
+    /// Whether the component was precompiled by `wepl compile`, so it keeps
+    /// the engine settings it was compiled with
+    pub fn precompiled(&self) -> bool {
+        self.precompiled
+    }
+
+    /// How the engine compiles components
+    pub fn engine_options(&self) -> &EngineOptions {
+        &self.engine_options
+    }
+
+    /// Instances of the component for handling HTTP requests, set up like the
+    /// session's: with its WASI configuration, imports and output
+    ///
+    /// Handling requests needs async support, which the session's engine does
+    /// not have, so the component is compiled again. `link_http` links the
+    /// async WASI and `wasi:http` implementations, which replace the session's
+    /// definitions of the interfaces they implement.
+    pub fn http_instances(
+        &self,
+        link_http: impl FnOnce(&mut Linker<Context>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<HttpInstances> {
+        let engine = load_engine(self.cache.is_some(), &self.engine_options, true)?;
+        let component = match &self.cache {
+            Some(cache) if !self.precompiled => cache.load(&engine, &self.component.1)?,
+            _ => load_component(&engine, &self.component.1, self.precompiled)?,
+        };
+        let mut linker = Linker::<Context>::new(&engine);
+        linker.allow_shadowing(true);
+        self.imports.define(&mut linker)?;
+        link_http(&mut linker)?;
+        let pre = linker
+            .instantiate_pre(&component)
+            .context("could not instantiate component")?;
+        Ok(HttpInstances {
+            engine,
+            pre,
+            wasi_config: self.wasi_config.clone(),
+            capture: self.capture.clone(),
+            events: self.imports.events.clone(),
+            trace: self.imports.trace.clone(),
+            exercised: self.imports.exercised.clone(),
+        })
+    }
+
+    /// Whether calls across the component's boundary are printed
+    pub fn tracing(&self) -> bool {
+        self.imports.trace.load(Ordering::SeqCst)
+    }
+
+    pub fn set_tracing(&mut self, trace: bool) {
+        self.imports.trace.store(trace, Ordering::SeqCst);
+    }
+
+    /// Run `f` while sampling the stack of the component
+    ///
+    /// Fails if `f` replaced the store, losing the profile.
+    pub fn profile<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> anyhow::Result<(T, Profile)> {
+        if !self.engine_options.profiling {
+            anyhow::bail!(
+                "profiling is off, which `.config profiling on` or `--profiling` turns on"
+            );
+        }
+        self.store.data_mut().profile = Some(Profile::new());
+        let ticker = Ticker::start(&self.engine, SAMPLE_INTERVAL);
+        let result = f(self);
+        drop(ticker);
+        let profile = self
+            .store
+            .data_mut()
+            .profile
+            .take()
+            .context("the instance was replaced while it was being profiled")?;
+        Ok((result, profile))
+    }
+
+    pub fn state_mode(&self) -> StateMode {
+        self.state_mode
+    }
+
+    pub fn set_state_mode(&mut self, mode: StateMode) {
+        self.state_mode = mode;
+    }
+
+    /// Start using the current import definitions
+    ///
+    /// With [`StateMode::Keep`] the definitions are swapped in without a new
+    /// instance if every changed import was already linked.
+    fn update_imports(&mut self) -> anyhow::Result<()> {
+        if self.state_mode == StateMode::Keep && self.imports.swap_in() {
+            return Ok(());
+        }
+        self.rebuild("its imports had to be linked again")
+    }
+
+    /// Get a new instance after a change the current one cannot pick up,
+    /// warning that the guest's state is lost if it was meant to be kept
+    fn rebuild(&mut self, reason: &str) -> anyhow::Result<()> {
+        if self.state_mode == StateMode::Keep {
+            self.imports.events.emit(Event::Warning(format!(
+                "the guest's state was reset because {reason}"
+            )));
+        }
+        self.refresh()
+    }
+
+    /// Link the component with the current import definitions
+    fn link(&mut self) -> anyhow::Result<()> {
+        self.pre = self
+            .imports
+            .linker(&self.engine)?
+            .instantiate_pre(&self.component.0)
+            .context("could not instantiate component")?;
+        Ok(())
+    }
+
+    /// Get a new instance
+    ///
+    /// All resource handles become invalid.
+    #[tracing::instrument(name = "instantiate", level = "debug", skip_all)]
+    pub fn refresh(&mut self) -> anyhow::Result<()> {
+        self.resources.clear();
+        self.poisoned = false;
+        self.history = CallHistory::default();
+        self.import_impls.reset_failed(&self.engine)?;
+        let start = Instant::now();
+        // The linker's functions call whatever their import is currently
+        // linked to, so the component only has to be linked again when
+        // imports were added or removed
+        if !self.imports.swap_in() {
+            self.link()?;
+        }
+        self.store = build_store(
+            &self.engine,
+            &self.wasi_config,
+            &self.capture,
+            &self.imports.events,
+            &self.imports.trace,
+            &self.imports.exercised,
+        );
+        self.instance = self.pre.instantiate(&mut self.store)?;
+        tracing::debug!("Instantiated the component in {:?}", start.elapsed());
+        Ok(())
+    }
+}
+
+/// Whether changes to the runtime keep the guest's state
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StateMode {
+    /// Get a new instance after every change
+    #[default]
+    Fresh,
+    /// Keep the instance when possible and warn when it is not
+    Keep,
+}
+
+/// A host definition of an imported function
+type ImportFunc = Arc<
+    dyn Fn(wasmtime::StoreContextMut<'_, Context>, &[Val], &mut [Val]) -> anyhow::Result<()>
+        + Send
+        + Sync,
+>;
+
+/// The name of the instance an imported function is in (`None` for the root)
+/// and the name of the function
+type ImportKey = (Option<String>, String);
+
+fn import_key(ident: parser::ItemIdent<'_>) -> ImportKey {
+    (
+        ident.interface.map(|i| i.to_string()),
+        ident.item.to_owned(),
+    )
+}
+
+/// The host definitions the component's imports are linked with
+#[derive(Default)]
+struct ImportDefs {
+    /// Whether to link WASI
+    wasi: bool,
+    funcs: BTreeMap<ImportKey, ImportFunc>,
+    /// The imported resources, each of which gets its own host resource type
+    resources: Vec<ImportedResource>,
+    /// Called whenever the component drops an instance of an imported resource
+    on_resource_drop: Option<ResourceDropHook>,
+    /// The definitions the functions of the last linker call into
+    linked: Arc<RwLock<BTreeMap<ImportKey, ImportFunc>>>,
+    /// The host definitions of imported functions on startup
+    defaults: BTreeMap<ImportKey, ImportFunc>,
+    /// The imports whose definition on startup is implemented by wepl, like
+    /// `wasi:logging`, instead of reporting that nothing satisfies them
+    provided: BTreeSet<ImportKey>,
+    /// Whether calls across the component's boundary are printed
+    trace: Arc<AtomicBool>,
+    /// Records the calls to imports while `.record-imports` is on
+    recorder: Arc<Mutex<Option<ImportRecorder>>>,
+    /// Where traced calls and warnings about calls are emitted
+    events: Events,
+    /// The imports the component used, for `.audit`
+    exercised: Exercised,
+    /// The call budgets set with `.limit` by the interfaces or functions
+    /// they limit
+    budgets: Arc<Mutex<BTreeMap<String, CallBudget>>>,
+}
+
+/// The most calls the component may make to an import and how many it made
+#[derive(Clone, Copy)]
+pub struct CallBudget {
+    pub max: usize,
+    pub calls: usize,
+}
+
+type ResourceDropHook = Arc<dyn Fn(&InterfaceResource, &Events) + Send + Sync>;
+
+impl ImportDefs {
+    /// Build a linker containing all of the definitions
+    #[tracing::instrument(name = "link", level = "debug", skip_all)]
+    fn linker(&self, engine: &Engine) -> anyhow::Result<Linker<Context>> {
+        tracing::debug!(
+            "Linking {} imported functions and {} imported resources",
+            self.funcs.len(),
+            self.resources.len()
+        );
+        let mut linker = Linker::<Context>::new(engine);
+        linker.allow_shadowing(true);
+        if self.wasi {
+            tracing::debug!("Linking with wasi");
+            wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+        }
+        *self.linked.write().unwrap() = self.funcs.clone();
+        self.define(&mut linker)?;
+        Ok(linker)
+    }
+
+    /// Define the imported functions and resources in `linker`, calling
+    /// whatever they were last linked to by [`ImportDefs::linker`]
+    fn define(&self, linker: &mut Linker<Context>) -> anyhow::Result<()> {
+        // Opening an instance again replaces its previous definitions so each
+        // instance must be defined all at once.
+        let instances = self
+            .funcs
+            .keys()
+            .filter_map(|(instance, _)| instance.as_deref())
+            .chain(self.resources.iter().map(|r| r.resource.interface.as_str()))
+            .collect::<BTreeSet<_>>();
+        for key @ (_, name) in self.funcs.keys().filter(|(i, _)| i.is_none()) {
+            tracing::trace!("Linking '{name}'");
+            let func = self.linked_func(key);
+            linker.root().func_new(name, func)?;
+        }
+        for instance_name in instances {
+            let mut instance = linker.instance(instance_name)?;
+            for key @ (_, name) in self
+                .funcs
+                .keys()
+                .filter(|(i, _)| i.as_deref() == Some(instance_name))
+            {
+                tracing::trace!("Linking '{instance_name}#{name}'");
+                let func = self.linked_func(key);
+                instance.func_new(name, func)?;
+            }
+            for (index, ImportedResource { resource, .. }) in self.resources.iter().enumerate() {
+                if resource.interface != instance_name {
+                    continue;
+                }
+                let kind = HOST_RESOURCE_KINDS.get(index).with_context(|| {
+                    format!(
+                        "the component imports more than {} resources",
+                        HOST_RESOURCE_KINDS.len()
+                    )
+                })?;
+                let on_drop = self.on_resource_drop.clone();
+                let events = self.events.clone();
+                instance.resource(&resource.name, (kind.ty)(), move |mut store, rep| {
+                    store.data_mut().traced_call = true;
+                    let dropped = store.data_mut().delete_host_resource(rep)?;
+                    if let Some(on_drop) = &on_drop {
+                        on_drop(&dropped.resource, &events);
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A function calling whatever the import `key` is currently linked to
+    fn linked_func(
+        &self,
+        key: &ImportKey,
+    ) -> impl Fn(wasmtime::StoreContextMut<'_, Context>, &[Val], &mut [Val]) -> anyhow::Result<()>
+           + Send
+           + Sync
+           + 'static {
+        let linked = self.linked.clone();
+        let default = self.defaults.get(key).cloned();
+        let provided = self.provided.contains(key);
+        let trace = self.trace.clone();
+        let recorder = self.recorder.clone();
+        let events = self.events.clone();
+        let exercised = self.exercised.clone();
+        let budgets = self.budgets.clone();
+        let key = key.clone();
+        let name = match &key {
+            (Some(interface), name) => format!("{interface}#{name}"),
+            (None, name) => name.clone(),
+        };
+        move |mut ctx, args, results| {
+            exercised.record(key.0.as_deref().unwrap_or(&key.1));
+            spend_budget(&budgets, &key)?;
+            let func = linked.read().unwrap().get(&key).cloned();
+            let func = func.context("import is no longer defined")?;
+            let failed = |e: anyhow::Error| {
+                e.context(ImportError {
+                    import: name.clone(),
+                })
+            };
+            if !trace.load(Ordering::SeqCst) {
+                call_import(&func, ctx, args, results).map_err(failed)?;
+                record_call(&recorder, &events, &name, args, results);
+                return Ok(());
+            }
+            ctx.data_mut().traced_call = true;
+            let call = format!(
+                "{name}({})",
+                args.iter().map(format_val).collect::<Vec<_>>().join(", ")
+            );
+            let start = Instant::now();
+            let result = call_import(&func, ctx, args, results);
+            let source = match &default {
+                Some(default) if Arc::ptr_eq(default, &func) && provided => "provided by wepl",
+                Some(default) if Arc::ptr_eq(default, &func) => "unstubbed",
+                Some(_) => "stubbed",
+                // Imports without a definition on startup are provided by WASI
+                None => "stubbed WASI",
+            };
+            events.emit(Event::Trace {
+                call,
+                results: match &result {
+                    Ok(()) => Ok(results.to_vec()),
+                    Err(e) => Err(e.to_string()),
+                },
+                elapsed: start.elapsed(),
+                source,
+            });
+            if result.is_ok() {
+                record_call(&recorder, &events, &name, args, results);
+            }
+            result.map_err(failed)
+        }
+    }
+
+    /// Link the current definitions without a new linker, which is only
+    /// possible if the same functions are defined as when it was built
+    fn swap_in(&self) -> bool {
+        let mut linked = self.linked.write().unwrap();
+        if !linked.keys().eq(self.funcs.keys()) {
+            return false;
+        }
+        linked.clone_from(&self.funcs);
+        true
+    }
+}
+
+/// The definition of the import `import` failed while the component called it
+#[derive(Debug)]
+pub struct ImportError {
+    pub import: String,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the import '{}' failed", self.import)
+    }
+}
+
+/// Linking the import `import` of the instance `importer` to the exports of
+/// the instance `exporter` failed
+#[derive(Debug)]
+pub struct LinkError {
+    pub importer: String,
+    pub import: String,
+    pub exporter: String,
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "could not link '{}' to '{}'",
+            self.importer, self.exporter
+        )
+    }
+}
+
+/// Count a call to the import `key` against the budgets limiting it, failing
+/// the call instead once one of them is used up
+fn spend_budget(
+    budgets: &Mutex<BTreeMap<String, CallBudget>>,
+    key: &ImportKey,
+) -> anyhow::Result<()> {
+    let mut budgets = budgets.lock().unwrap();
+    for (limit, budget) in budgets
+        .iter()
+        .filter(|(limit, _)| limits_import(limit, key))
+    {
+        if budget.calls >= budget.max {
+            anyhow::bail!(
+                "the budget of {} calls to '{limit}' is used up (raise it with `.limit {limit} $n`)",
+                budget.max
+            );
+        }
+    }
+    for (_, budget) in budgets
+        .iter_mut()
+        .filter(|(limit, _)| limits_import(limit, key))
+    {
+        budget.calls += 1;
+    }
+    Ok(())
+}
+
+/// Whether the limit on `limit`, an interface or a function, covers the
+/// import `key`
+///
+/// Versions are ignored so that `wasi:http/outgoing-handler` limits
+/// `wasi:http/outgoing-handler@0.2.0`.
+fn limits_import(limit: &str, (interface, name): &ImportKey) -> bool {
+    match (limit.split_once('#'), interface) {
+        (Some((limit_interface, limit_name)), Some(interface)) => {
+            audit::unversioned(limit_interface) == audit::unversioned(interface)
+                && limit_name == name
+        }
+        (None, Some(interface)) => audit::unversioned(limit) == audit::unversioned(interface),
+        (None, None) => limit == name,
+        (Some(_), None) => false,
+    }
+}
+
+/// Add a call of the import `name` to the recording if one is being made,
+/// warning about calls that cannot be recorded instead of failing them
+fn record_call(
+    recorder: &Mutex<Option<ImportRecorder>>,
+    events: &Events,
+    name: &str,
+    args: &[Val],
+    results: &[Val],
+) {
+    if let Some(recorder) = recorder.lock().unwrap().as_mut() {
+        if let Err(e) = recorder.record(name, args, results) {
+            events.emit(Event::Warning(format!(
+                "could not record the call to '{name}': {e:#}"
+            )));
+        }
+    }
+}
+
+/// Implement `wasi:logging/logging#log` if the component imports it, emitting
+/// the records and capturing them along with the guest's output
+fn provide_logging(imports: &mut ImportDefs, capture: &OutputCapture) -> Option<GuestLogger> {
+    let key = imports
+        .funcs
+        .keys()
+        .find(|(interface, name)| {
+            interface.as_deref().is_some_and(|interface| {
+                interface == "wasi:logging/logging"
+                    || interface.starts_with("wasi:logging/logging@")
+            }) && name == "log"
+        })?
+        .clone();
+    let logger = GuestLogger::new(imports.events.clone());
+    let capture = capture.enabled.clone();
+    imports.funcs.insert(key.clone(), {
+        let logger = logger.clone();
+        Arc::new(move |_ctx, args, _results| {
+            let [Val::Enum(level), Val::String(context), Val::String(message)] = args else {
+                anyhow::bail!("unexpected arguments to wasi:logging/logging#log");
+            };
+            let level = Level::parse(level)
+                .with_context(|| format!("unknown wasi:logging level '{level}'"))?;
+            let record = LogRecord {
+                level,
+                context: context.clone(),
+                message: message.clone(),
+            };
+            logger.log(record, capture.load(Ordering::SeqCst));
+            Ok(())
+        })
+    });
+    imports.provided.insert(key);
+    Some(logger)
+}
+
+/// The host resource types and names of the `fields` and `outgoing-request`
+/// resources of `wasi:http/types`, if the component imports both
+fn http_resources(
+    resources: &[ImportedResource],
+) -> Option<[(HostResourceKind, InterfaceResource); 2]> {
+    let resource = |name: &str| {
+        let index = resources.iter().position(|imported| {
+            http::is_types_interface(&imported.resource.interface) && imported.resource.name == name
+        })?;
+        Some((
+            *HOST_RESOURCE_KINDS.get(index)?,
+            resources[index].resource.clone(),
+        ))
+    };
+    Some([resource("fields")?, resource("outgoing-request")?])
+}
+
+/// Create an instance of the `wasi:http/types` resource among `resources`
+/// that `created` is the host side of
+fn new_http_resource(
+    mut store: wasmtime::StoreContextMut<'_, Context>,
+    resources: &[(HostResourceKind, InterfaceResource); 2],
+    created: HttpResource,
+) -> anyhow::Result<Val> {
+    let (kind, resource) = match created {
+        HttpResource::Fields(_) => &resources[0],
+        HttpResource::Request(_) => &resources[1],
+    };
+    let rep = store
+        .data_mut()
+        .push_host_resource(HostResource {
+            resource: resource.clone(),
+            bucket: None,
+            http: Some(created),
+        })?
+        .rep();
+    Ok(Val::Resource((kind.new_own)(store, rep)?))
+}
+
+/// Implement the `fields` and `outgoing-request` resources of
+/// `wasi:http/types` if the component imports them, so that requests can be
+/// built by the component as well as by `.http-request`
+fn provide_http(imports: &mut ImportDefs) {
+    let Some(resources) = http_resources(&imports.resources) else {
+        return;
+    };
+    let keys = imports
+        .funcs
+        .keys()
+        .filter(|(interface, name)| {
+            interface.as_deref().is_some_and(http::is_types_interface) && http::implements(name)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in keys {
+        let name = key.1.clone();
+        let resources = resources.clone();
+        let func: ImportFunc = Arc::new(move |mut ctx, args, results| {
+            let (this, args) = match args {
+                [Val::Resource(handle), args @ ..] => {
+                    let (kind, _) = resources
+                        .iter()
+                        .find(|(kind, _)| (kind.ty)() == handle.ty())
+                        .context("only resources created by wepl can be used with wasi:http")?;
+                    (Some((kind.rep)(*handle, ctx.as_context_mut())?), args)
+                }
+                args => (None, args),
+            };
+            let this = match this {
+                Some(rep) => Some(
+                    ctx.data_mut()
+                        .table
+                        .get_mut(&Resource::<HostResource>::new_borrow(rep))?
+                        .http
+                        .as_mut()
+                        .context("the resource was not created by wepl")?,
+                ),
+                None => None,
+            };
+            let result = match http::call(&name, this, args)? {
+                Returned::Val(val) => val,
+                Returned::Resource(created, wrap) => wrap(new_http_resource(
+                    ctx.as_context_mut(),
+                    &resources,
+                    created,
+                )?),
+            };
+            let [ret] = results else {
+                anyhow::bail!("unexpected results of wasi:http/types#{name}");
+            };
+            *ret = result;
+            Ok(())
+        });
+        imports.funcs.insert(key.clone(), func);
+        imports.provided.insert(key);
+    }
+}
+
+/// The values of `wasi:config/store` by their key
+type ConfigValues = Arc<Mutex<BTreeMap<String, String>>>;
+
+/// Implement `wasi:config/store` if the component imports it, returning the
+/// `values` the guest asks for
+fn provide_config(imports: &mut ImportDefs, values: &[(String, String)]) -> Option<ConfigValues> {
+    let keys = imports
+        .funcs
+        .keys()
+        .filter(|(interface, name)| {
+            interface.as_deref().is_some_and(|interface| {
+                interface == "wasi:config/store" || interface.starts_with("wasi:config/store@")
+            }) && matches!(name.as_str(), "get" | "get-all")
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if keys.is_empty() {
+        return None;
+    }
+    let values: ConfigValues = Arc::new(Mutex::new(values.iter().cloned().collect()));
+    for key in keys {
+        let values = values.clone();
+        let func: ImportFunc = match key.1.as_str() {
+            "get" => Arc::new(move |_ctx, args, results| {
+                let ([Val::String(key)], [result]) = (args, results) else {
+                    anyhow::bail!("unexpected arguments to wasi:config/store#get");
+                };
+                let value = values.lock().unwrap().get(key).cloned();
+                let value = Val::Option(value.map(|value| Box::new(Val::String(value))));
+                *result = Val::Result(Ok(Some(Box::new(value))));
+                Ok(())
+            }),
+            _ => Arc::new(move |_ctx, _args, results| {
+                let [result] = results else {
+                    anyhow::bail!("unexpected results of wasi:config/store#get-all");
+                };
+                let values = values
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(key, value)| {
+                        Val::Tuple(vec![Val::String(key.clone()), Val::String(value.clone())])
+                    })
+                    .collect();
+                *result = Val::Result(Ok(Some(Box::new(Val::List(values)))));
+                Ok(())
+            }),
+        };
+        imports.funcs.insert(key.clone(), func);
+        imports.provided.insert(key);
+    }
+    Some(values)
+}
+
+/// Render the frames of a trap's backtrace, innermost first, with the
+/// demangled names of the guest's functions, their offsets and the source
+/// locations the component's debug info gives
+pub fn format_backtrace(backtrace: &WasmBacktrace) -> String {
+    let mut lines = Vec::new();
+    for (i, frame) in backtrace.frames().iter().enumerate() {
+        let module = frame.module().name().unwrap_or("<module>");
+        let func = match frame.func_name() {
+            Some(name) => demangle(name),
+            None => format!("<wasm function {}>", frame.func_index()),
+        };
+        let func_offset = frame
+            .func_offset()
+            .map(|offset| format!("+{offset:#x}"))
+            .unwrap_or_default();
+        let module_offset = frame
+            .module_offset()
+            .map(|offset| format!(" (at {offset:#x})"))
+            .unwrap_or_default();
+        lines.push(format!(
+            "  {i:>2}: {module}!{func}{func_offset}{module_offset}"
+        ));
+        for symbol in frame.symbols() {
+            let Some(file) = symbol.file() else {
+                continue;
+            };
+            let mut location = file.to_owned();
+            if let Some(line) = symbol.line() {
+                location.push_str(&format!(":{line}"));
+                if let Some(column) = symbol.column() {
+                    location.push_str(&format!(":{column}"));
+                }
+            }
+            match symbol.name() {
+                Some(name) => lines.push(format!("        {} at {location}", demangle(name))),
+                None => lines.push(format!("        at {location}")),
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Demangle a Rust or C++ symbol, leaving other names as they are
+fn demangle(name: &str) -> String {
+    if let Ok(name) = rustc_demangle::try_demangle(name) {
+        format!("{name:#}")
+    } else if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+        symbol.to_string()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Call the host definition of an import and release the resources the
+/// component lent to it
+fn call_import(
+    func: &ImportFunc,
+    mut ctx: wasmtime::StoreContextMut<'_, Context>,
+    args: &[Val],
+    results: &mut [Val],
+) -> anyhow::Result<()> {
+    let result = func(ctx.as_context_mut(), args, results);
+    let released = std::mem::take(&mut ctx.data_mut().released_borrows);
+    result?;
+    for arg in args {
+        release_borrows(&mut ctx, arg, &released)?;
+    }
+    Ok(())
+}
+
+/// Drop every borrowed resource handle in `val` that was not `released` yet
+fn release_borrows(
+    ctx: &mut wasmtime::StoreContextMut<'_, Context>,
+    val: &Val,
+    released: &[ResourceAny],
+) -> anyhow::Result<()> {
+    let mut release = |v| release_borrows(ctx, v, released);
+    match val {
+        Val::Resource(handle) if !handle.owned() && !released.contains(handle) => {
+            handle.resource_drop(ctx.as_context_mut())
+        }
+        Val::List(vals) | Val::Tuple(vals) => vals.iter().try_for_each(release),
+        Val::Record(fields) => fields.iter().try_for_each(|(_, v)| release(v)),
+        Val::Variant(_, Some(v))
+        | Val::Option(Some(v))
+        | Val::Result(Ok(Some(v)) | Err(Some(v))) => release(v),
+        _ => Ok(()),
+    }
+}
+
+/// A resource imported by the component
+#[derive(Clone)]
+struct ImportedResource {
+    resource: InterfaceResource,
+    /// The type of the resource in the component's type, if it could be found
+    ty: Option<component::ResourceType>,
+}
+
+/// Marker types that give each imported resource a distinct host resource type
+struct HostResourceType<const N: usize>;
+
+/// The host side of an instance of an imported resource
+///
+/// Instances live in the store's `ResourceTable` and are removed from it when
+/// the component drops them.
+struct HostResource {
+    resource: InterfaceResource,
+    /// The store of a `wasi:keyvalue` bucket opened from the stores wepl
+    /// provides
+    bucket: Option<String>,
+    /// The fields or request of a `wasi:http/types` resource wepl implements
+    http: Option<HttpResource>,
+}
+
+/// The operations that depend on which host resource type is being used
+#[derive(Clone, Copy)]
+struct HostResourceKind {
+    ty: fn() -> component::ResourceType,
+    new_own: fn(wasmtime::StoreContextMut<'_, Context>, u32) -> anyhow::Result<ResourceAny>,
+    /// The index of a handle's instance in the store's `ResourceTable`,
+    /// which releases borrowed handles
+    rep: fn(ResourceAny, wasmtime::StoreContextMut<'_, Context>) -> anyhow::Result<u32>,
+}
+
+impl HostResourceKind {
+    const fn of<const N: usize>() -> Self {
+        Self {
+            ty: component::ResourceType::host::<HostResourceType<N>>,
+            new_own: new_own_host_resource::<N>,
+            rep: host_resource_rep::<N>,
+        }
+    }
+}
+
+fn new_own_host_resource<const N: usize>(
+    store: wasmtime::StoreContextMut<'_, Context>,
+    rep: u32,
+) -> anyhow::Result<ResourceAny> {
+    ResourceAny::try_from_resource(Resource::<HostResourceType<N>>::new_own(rep), store)
+}
+
+fn host_resource_rep<const N: usize>(
+    handle: ResourceAny,
+    mut store: wasmtime::StoreContextMut<'_, Context>,
+) -> anyhow::Result<u32> {
+    let rep = handle
+        .try_into_resource::<HostResourceType<N>>(store.as_context_mut())?
+        .rep();
+    if !handle.owned() {
+        store.data_mut().released_borrows.push(handle);
+    }
+    Ok(rep)
+}
+
+macro_rules! host_resource_kinds {
+    ($($n:literal),*) => {
+        [$(HostResourceKind::of::<$n>()),*]
+    };
+}
+
+/// The host resource types available to imported resources, in the order the
+/// resources are imported
+const HOST_RESOURCE_KINDS: [HostResourceKind; 32] = host_resource_kinds![
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+    26, 27, 28, 29, 30, 31
+];
+
+/// Create a new instance of the imported resource of type `ty`
+fn new_host_resource(
+    store: &mut wasmtime::StoreContextMut<'_, Context>,
+    ty: &component::ResourceType,
+    resources: &[ImportedResource],
+) -> anyhow::Result<Val> {
+    let (kind, imported) = HOST_RESOURCE_KINDS
+        .iter()
+        .zip(resources)
+        .find(|(kind, imported)| (kind.ty)() == *ty || imported.ty.as_ref() == Some(ty))
+        .context("only resources imported by the component can be created by the host")?;
+    let rep = store
+        .data_mut()
+        .push_host_resource(HostResource {
+            resource: imported.resource.clone(),
+            bucket: None,
+            http: None,
+        })?
+        .rep();
+    Ok(Val::Resource((kind.new_own)(store.as_context_mut(), rep)?))
+}
+
+/// A resource exported or imported by the component
+#[derive(Clone, Debug)]
+pub struct InterfaceResource {
+    /// The name of the interface defining the resource
+    pub interface: String,
+    pub name: String,
+}
+
+impl std::fmt::Display for InterfaceResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.interface, self.name)
+    }
+}
+
+/// A call to an imported function recorded by a spy
+#[derive(Clone)]
+pub struct ImportCall {
+    pub args: Vec<Val>,
+    pub results: Vec<Val>,
+    /// When the call was made
+    pub at: SystemTime,
+}
+
+/// The definition of an import that has not been satisfied by anything
+struct UnstubbedImport {
+    /// The full name of the imported function
+    name: String,
+    function: wit_parser::Function,
+    result_types: Vec<component::Type>,
+    resolver: Arc<WorldResolver>,
+    /// Whether to prompt the user for the return values
+    prompt: Arc<AtomicBool>,
+    events: Events,
+}
+
+impl UnstubbedImport {
+    fn call(
+        &self,
+        args: &[Val],
+        results: &mut [Val],
+        warn: impl Fn(&Events),
+    ) -> anyhow::Result<()> {
+        if !self.prompt.load(Ordering::SeqCst) || results.is_empty() {
+            warn(&self.events);
+            return Ok(());
+        }
+        if self.result_types.len() != results.len() {
+            anyhow::bail!(
+                "could not determine the result types of import '{}'",
+                self.name
+            )
+        }
+        // Frontends collecting events have no terminal to prompt on
+        if self.events.collects() {
+            anyhow::bail!(
+                "import '{}' was called, but return values can only be prompted for in the terminal",
+                self.name
+            )
+        }
+        let args = args.iter().map(format_val).collect::<Vec<_>>().join(", ");
+        self.events.print(format!(
+            "{} {}({args}): {}",
+            "Import called:".yellow().bold(),
+            self.name,
+            format_function(&self.function, &self.resolver)
+        ));
+        let labels: Vec<String> = match &self.function.results {
+            wit_parser::Results::Anon(t) => {
+                vec![self
+                    .resolver
+                    .display_wit_type(t, Expansion::Collapsed)
+                    .into()]
+            }
+            wit_parser::Results::Named(named) => named
+                .iter()
+                .map(|(name, t)| {
+                    let t = self.resolver.display_wit_type(t, Expansion::Collapsed);
+                    format!("{name}: {t}")
+                })
+                .collect(),
+        };
+        for ((result, ty), label) in results.iter_mut().zip(&self.result_types).zip(labels) {
+            *result = prompt_for_value(&format!("{label}> "), ty, &self.resolver, &self.events)
+                .with_context(|| format!("no return value provided for import '{}'", self.name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Prompt the user for a value of type `ty` until a valid one is provided,
+/// showing why invalid ones are on `events`
+fn prompt_for_value(
+    prompt: &str,
+    ty: &component::Type,
+    resolver: &WorldResolver,
+    events: &Events,
+) -> anyhow::Result<Val> {
+    let mut editor = rustyline::DefaultEditor::new()?;
+    let scope = HashMap::new();
+    loop {
+        let line = editor.readline(&prompt.yellow().bold().to_string())?;
+        let value = match Cmd::parse(&line) {
+            Ok(Some(Cmd::Eval(expr))) => {
+                Evaluator::without_runtime(resolver, &scope).eval(expr, Some(ty))
+            }
+            Ok(_) => Err(anyhow::anyhow!("expected a value")),
+            Err(e) => Err(e),
+        };
+        match value {
+            Ok(value) => return Ok(value),
+            Err(e) => events.print(format!("{}{e}", "Error: ".red().bold())),
+        }
+    }
+}
+
+/// A value of type `ty` to use when nothing else is known about it
+///
+/// Owned resources are created with `new_resource`.
+fn default_val(
+    ty: &component::Type,
+    new_resource: &mut impl FnMut(&component::ResourceType) -> anyhow::Result<Val>,
+) -> anyhow::Result<Val> {
+    use component::Type;
+    Ok(match ty {
+        Type::Bool => Val::Bool(false),
+        Type::S8 => Val::S8(0),
+        Type::U8 => Val::U8(0),
+        Type::S16 => Val::S16(0),
+        Type::U16 => Val::U16(0),
+        Type::S32 => Val::S32(0),
+        Type::U32 => Val::U32(0),
+        Type::S64 => Val::S64(0),
+        Type::U64 => Val::U64(0),
+        Type::Float32 => Val::Float32(0.0),
+        Type::Float64 => Val::Float64(0.0),
+        Type::Char => Val::Char('\0'),
+        Type::String => Val::String(String::new()),
+        Type::List(_) => Val::List(Vec::new()),
+        Type::Record(r) => Val::Record(
+            r.fields()
+                .map(|f| Ok((f.name.to_owned(), default_val(&f.ty, new_resource)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Type::Tuple(t) => Val::Tuple(
+            t.types()
+                .map(|t| default_val(&t, new_resource))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Type::Variant(v) => {
+            let case = v.cases().next().context("variant has no cases")?;
+            let payload = case.ty.map(|t| default_val(&t, new_resource)).transpose()?;
+            Val::Variant(case.name.to_owned(), payload.map(Box::new))
+        }
+        Type::Enum(e) => Val::Enum(e.names().next().context("enum has no cases")?.to_owned()),
+        Type::Option(_) => Val::Option(None),
+        Type::Result(r) => Val::Result(Ok(r
+            .ok()
+            .map(|t| default_val(&t, new_resource))
+            .transpose()?
+            .map(Box::new))),
+        Type::Flags(_) => Val::Flags(Vec::new()),
+        Type::Own(r) => new_resource(r)?,
+        Type::Borrow(_) => anyhow::bail!("borrowed resources have no default value"),
+    })
+}
+
+/// The result types of an imported function, if they can be found
+fn import_result_types(
+    engine: &Engine,
+    component_type: &types::Component,
+    instance: Option<&str>,
+    function: &str,
+) -> Option<Vec<component::Type>> {
+    let item = match instance {
+        Some(instance) => match component_type.get_import(engine, instance) {
+            Some(types::ComponentItem::ComponentInstance(i)) => i.get_export(engine, function),
+            _ => None,
+        },
+        None => component_type.get_import(engine, function),
+    };
+    match item {
+        Some(types::ComponentItem::ComponentFunc(f)) => Some(f.results().collect()),
+        _ => None,
+    }
+}
+
+/// The type of an imported resource in the component's type, if it can be found
+fn import_resource_type(
+    engine: &Engine,
+    component_type: &types::Component,
+    instance: &str,
+    resource: &str,
+) -> Option<component::ResourceType> {
+    match component_type.get_import(engine, instance) {
+        Some(types::ComponentItem::ComponentInstance(i)) => match i.get_export(engine, resource) {
+            Some(types::ComponentItem::Resource(ty)) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A component used to stub imports
+pub struct StubComponent {
+    /// The name the stub's output and configuration go by
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+impl StubComponent {
+    /// A stub named after the file stem of `path`
+    pub fn new(path: &std::path::Path, bytes: Vec<u8>) -> Self {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "stub".to_owned());
+        Self { name, bytes }
+    }
+}
+
+/// Configuration of the WASI context a stub's store is built with
+#[derive(Clone, Default)]
+pub struct StubConfig {
+    /// The environment variables the stub sees through `wasi:cli/environment`
+    pub env: Vec<(String, String)>,
+    /// Host directories the stub can access and the paths it sees them at
+    pub dirs: Vec<(String, String)>,
+    /// What the stub reads from stdin
+    pub stdin: GuestStdin,
+}
+
+/// The components that implement the main component's imports
+struct ImportImpls {
+    /// The WASI configurations of the stubs by their names
+    configs: BTreeMap<String, StubConfig>,
+    /// The instances of the stubs by their names
+    instances: BTreeMap<String, Arc<Mutex<StubInstance>>>,
+    events: Events,
+    /// Whether the stubs' stderr is kept off the host's stderr, as with
+    /// `--sandbox`
+    sandbox: bool,
+}
+
+impl ImportImpls {
+    fn new(events: Events, sandbox: bool) -> Self {
+        Self {
+            configs: BTreeMap::new(),
+            instances: BTreeMap::new(),
+            events,
+            sandbox,
+        }
+    }
+
+    /// The instance of `stub`
+    ///
+    /// Stubbing with the same component again reuses its instance, while a
+    /// different component with the same name replaces it.
+    #[tracing::instrument(name = "instantiate", level = "debug", skip_all, fields(stub = %stub.name))]
+    fn instantiate(
+        &mut self,
+        engine: &Engine,
+        stub: &StubComponent,
+    ) -> anyhow::Result<Arc<Mutex<StubInstance>>> {
+        if let Some(instance) = self.instances.get(&stub.name) {
+            if instance.lock().unwrap().bytes == stub.bytes {
+                tracing::debug!("Reusing the instance of the stub '{}'", stub.name);
+                return Ok(instance.clone());
+            }
+        }
+        let start = Instant::now();
+        let spinner = progress::Spinner::start(format!("Compiling the stub '{}'", stub.name));
+        let component = compile_component(engine, &stub.bytes)?;
+        drop(spinner);
+        let mut store = self.store(engine, &stub.name)?;
+        let instance = stub_linker(engine)?.instantiate(&mut store, &component)?;
+        tracing::debug!(
+            "Instantiated the stub '{}' in {:?}",
+            stub.name,
+            start.elapsed()
+        );
+        let instance = Arc::new(Mutex::new(StubInstance {
+            name: stub.name.clone(),
+            failed: false,
+            bytes: stub.bytes.clone(),
+            component,
+            store,
+            instance,
+        }));
+        self.instances.insert(stub.name.clone(), instance.clone());
+        Ok(instance)
+    }
+
+    /// Replace the instance of the stub named `name` with a fresh one
+    fn reset(&mut self, engine: &Engine, name: &str) -> anyhow::Result<()> {
+        let instance = self.instances.get(name).with_context(|| {
+            let names = self.instances.keys().cloned().collect::<Vec<_>>();
+            format!(
+                "no stub named '{name}' (stubs: {})",
+                if names.is_empty() {
+                    "none".to_owned()
+                } else {
+                    names.join(", ")
+                }
+            )
+        })?;
+        let mut store = self.store(engine, name)?;
+        let mut instance = instance.lock().unwrap();
+        instance.instance = stub_linker(engine)?.instantiate(&mut store, &instance.component)?;
+        instance.store = store;
+        instance.failed = false;
+        Ok(())
+    }
+
+    /// Reset the stubs whose instances were left unusable by a failed call
+    fn reset_failed(&mut self, engine: &Engine) -> anyhow::Result<()> {
+        let failed = self
+            .instances
+            .iter()
+            .filter(|(_, instance)| instance.lock().unwrap().failed)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in failed {
+            tracing::debug!("Resetting stub '{name}' after a failed call");
+            self.reset(engine, &name)?;
+        }
+        Ok(())
+    }
+
+    /// A new store for the stub named `name`
+    fn store(&self, engine: &Engine, name: &str) -> anyhow::Result<Store<ImportImplsContext>> {
+        let config = self.configs.get(name).cloned().unwrap_or_default();
+        let table = ResourceTable::new();
+        let mut builder = WasiCtxBuilder::new();
+        if self.sandbox {
+            builder.stderr(EventStdout::stub(self.events.clone(), name));
+        } else {
+            builder.inherit_stderr();
+        }
+        builder.stdout(EventStdout::stub(self.events.clone(), name));
+        builder.envs(&config.env);
+        for (host, guest) in &config.dirs {
+            builder
+                .preopened_dir(host, guest, DirPerms::all(), FilePerms::all())
+                .with_context(|| format!("could not open directory '{host}' for '{name}'"))?;
+        }
+        configure_stdin(&mut builder, &config.stdin);
+        let wasi = builder.build();
+        let context = ImportImplsContext::new(table, wasi);
+        let mut store = Store::new(engine, context);
+        give_fuel(&mut store);
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(|_| Ok(UpdateDeadline::Continue(1)));
+        Ok(store)
+    }
+}
+
+/// A stub component instantiated in its own store
+struct StubInstance {
+    name: String,
+    /// Whether a call failed, which can leave the instance in an unusable
+    /// state until it is reset
+    failed: bool,
+    bytes: Vec<u8>,
+    component: Component,
+    store: Store<ImportImplsContext>,
+    instance: Instance,
+}
+
+impl StubInstance {
+    /// The exported function `export`
+    fn func(&mut self, (interface, name): &ImportKey) -> anyhow::Result<Func> {
+        match interface {
+            Some(interface) => {
+                let mut exports = self.instance.exports(&mut self.store);
+                let mut instance = exports
+                    .instance(interface)
+                    .with_context(|| format!("no exported instance named '{interface}' found"))?;
+                instance.func(name)
+            }
+            None => self.instance.get_func(&mut self.store, name),
+        }
+        .with_context(|| format!("no exported function named '{name}' found"))
+    }
+
+    /// Call the exported function `export` to satisfy the import `import`
+    fn call(
+        &mut self,
+        import: &str,
+        export: &ImportKey,
+        args: &[Val],
+        results: &mut [Val],
+    ) -> anyhow::Result<()> {
+        let func = self.func(export)?;
+        if let Err(e) = func.call(&mut self.store, args, results) {
+            self.failed = true;
+            let what = if e.is::<wasmtime::Trap>() {
+                "trapped"
+            } else {
+                "failed"
+            };
+            return Err(e.context(format!(
+                "stub '{}' {what} while satisfying the import '{import}'",
+                self.name
+            )));
+        }
+        if let Err(e) = func.post_return(&mut self.store) {
+            // The stub's store cannot make calls anymore, so it is reset along
+            // with the instance whose import failed
+            self.failed = true;
+            return Err(e.context(format!(
+                "stub '{}' failed to clean up after satisfying the import '{import}' (post-return), so it gets a fresh instance and its state is lost",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// The addresses of the instances the current thread is calling into
+    /// through a [`CallLock`]
+    static CALLING: std::cell::RefCell<HashSet<usize>> = Default::default();
+}
+
+/// The lock on an instance that the current thread is calling into, such as a
+/// stub or a linked instance
+pub struct CallLock<'a, T> {
+    guard: MutexGuard<'a, T>,
+    address: usize,
+}
+
+impl<'a, T> CallLock<'a, T> {
+    /// Lock `instance` for a call, waiting for calls made on other threads to
+    /// finish first, or `None` if the current thread is already calling into
+    /// it since the call would re-enter the instance and waiting would never
+    /// end
+    pub fn new(instance: &'a Mutex<T>) -> Option<Self> {
+        let address = instance as *const Mutex<T> as usize;
+        if CALLING.with(|calling| calling.borrow().contains(&address)) {
+            return None;
+        }
+        let guard = instance.lock().unwrap();
+        CALLING.with(|calling| calling.borrow_mut().insert(address));
+        Some(Self { guard, address })
+    }
+}
+
+impl<T> Drop for CallLock<'_, T> {
+    fn drop(&mut self) {
+        CALLING.with(|calling| calling.borrow_mut().remove(&self.address));
+    }
+}
+
+impl<T> std::ops::Deref for CallLock<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> std::ops::DerefMut for CallLock<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+/// Lock the instance of the stub named `stub` to satisfy the import `import`
+fn lock_stub<'a>(
+    instance: &'a Mutex<StubInstance>,
+    stub: &str,
+    import: &str,
+) -> anyhow::Result<CallLock<'a, StubInstance>> {
+    CallLock::new(instance).with_context(|| {
+        format!(
+            "the stub '{stub}' is already running a call, so '{import}' cannot call back into it"
+        )
+    })
+}
+
+/// The linker stub components are instantiated with
+fn stub_linker(engine: &Engine) -> anyhow::Result<Linker<ImportImplsContext>> {
+    let mut linker = Linker::<ImportImplsContext>::new(engine);
+    wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+    Ok(linker)
+}
+
+/// Stdout of the guest or a stub, whose chunks are emitted as
+/// [`Event::Output`]s
+#[derive(Clone)]
+struct EventStdout {
+    events: Events,
+    source: OutputSource,
+    /// The name of the stub printing, if a stub is
+    stub: Option<String>,
+}
+
+impl EventStdout {
+    /// The stdout of the stub named `name`
+    fn stub(events: Events, name: &str) -> Self {
+        Self {
+            events,
+            source: OutputSource::Import,
+            stub: Some(name.to_owned()),
+        }
+    }
+}
+
+/// How much can be written to an [`EventStdout`] at once
+const EVENT_STDOUT_BUDGET: usize = 64 * 1024;
+
+#[async_trait::async_trait]
+impl HostOutputStream for EventStdout {
+    fn write(&mut self, bytes: bytes::Bytes) -> StreamResult<()> {
+        self.events.emit(Event::Output {
+            source: self.source,
+            stub: self.stub.clone(),
+            bytes,
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(EVENT_STDOUT_BUDGET)
+    }
+
+    async fn write_ready(&mut self) -> StreamResult<usize> {
+        Ok(EVENT_STDOUT_BUDGET)
+    }
+}
+
+#[async_trait::async_trait]
+impl Subscribe for EventStdout {
+    async fn ready(&mut self) {}
+}
+
+impl StdoutStream for EventStdout {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn isatty(&self) -> bool {
+        output_is_terminal(&self.events)
+    }
+}
+
+/// Whether output emitted to `events` ends up on a terminal, which is what
+/// `wasi:cli/terminal-stdout` tells the guest
+///
+/// Collected output is shown by a frontend like a notebook or the TUI's
+/// output pane, which would garble the escape codes of a terminal program.
+fn output_is_terminal(events: &Events) -> bool {
+    !events.collects() && Stdout.isatty()
+}
+
+/// Configuration of the WASI context the component's store is built with
+#[derive(Clone, Default)]
+pub struct WasiConfig {
+    /// The arguments the guest sees through `wasi:cli/environment`
+    pub args: Vec<String>,
+    /// The environment variables the guest sees through `wasi:cli/environment`
+    pub env: Vec<(String, String)>,
+    /// The values the guest reads through `wasi:config/store`
+    pub config: Vec<(String, String)>,
+    /// What the guest reads from stdin
+    pub stdin: GuestStdin,
+    /// The clock the guest sees through `wasi:clocks`
+    pub clock: VirtualClock,
+    /// The seed for deterministic `wasi:random` output, if any
+    ///
+    /// Every refresh restarts the random sequence from the seed.
+    pub random_seed: Option<u64>,
+    /// The addresses the guest may use through `wasi:sockets`, if any
+    pub network: Option<NetworkPolicy>,
+    /// Whether the guest and the stubs are denied the host's environment,
+    /// directories, network and stdin, as with `--sandbox`
+    pub sandbox: bool,
+}
+
+/// The source of the guest's stdin
+#[derive(Clone, Default)]
+pub enum GuestStdin {
+    /// The guest immediately sees the end of the stream
+    #[default]
+    Empty,
+    /// The guest reads the given bytes (again from the start on every refresh)
+    Bytes(bytes::Bytes),
+    /// The guest reads from the host's stdin
+    Inherit,
+}
+
+/// What a call of an exported function returned and printed
+pub struct CallOutcome {
+    pub results: Vec<Val>,
+    /// What the guest wrote to stdout during the call
+    pub stdout: String,
+    pub duration: Duration,
+}
+
+/// The calls made to the component's exports since it was instantiated
+#[derive(Default)]
+struct CallHistory {
+    calls: Vec<ExportCall>,
+    /// The first call that passed or returned resource handles, after which
+    /// the calls are no longer recorded
+    unrepeatable: Option<String>,
+}
+
+impl CallHistory {
+    fn record(&mut self, interface: Option<&str>, name: &str, args: &[Val], results: &[Val]) {
+        if self.unrepeatable.is_some() {
+            return;
+        }
+        let call = ExportCall {
+            interface: interface.map(str::to_owned),
+            name: name.to_owned(),
+            args: args.to_vec(),
+        };
+        if args
+            .iter()
+            .chain(results)
+            .any(|val| resource_count(val) > 0)
+        {
+            self.unrepeatable = Some(call.to_string());
+            self.calls.clear();
+        } else {
+            self.calls.push(call);
+        }
+    }
+}
+
+/// A call to an exported function
+#[derive(Clone)]
+struct ExportCall {
+    interface: Option<String>,
+    name: String,
+    args: Vec<Val>,
+}
+
+impl std::fmt::Display for ExportCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(interface) = &self.interface {
+            write!(f, "{interface}#")?;
+        }
+        let args = self.args.iter().map(format_val).collect::<Vec<_>>();
+        write!(f, "{}({})", self.name, args.join(", "))
+    }
+}
+
+/// Buffers the guest's stdout and stderr are copied into while capturing is enabled
+#[derive(Clone, Default)]
+struct OutputCapture {
+    enabled: Arc<AtomicBool>,
+    stdout: Arc<Mutex<Vec<u8>>>,
+    stderr: Arc<Mutex<Vec<u8>>>,
+    /// The stdout of the call being made, which is kept whether capturing is
+    /// enabled or not
+    call_stdout: Arc<Mutex<Vec<u8>>>,
+}
+
+/// An output stream that tees everything written to it into a capture buffer
+struct CaptureStream {
+    stream: Box<dyn HostOutputStream>,
+    enabled: Arc<AtomicBool>,
+    buffer: Arc<Mutex<Vec<u8>>>,
+    /// The stdout of the call being made, if this is stdout
+    call: Option<Arc<Mutex<Vec<u8>>>>,
+}
+
+#[async_trait::async_trait]
+impl HostOutputStream for CaptureStream {
+    fn write(&mut self, bytes: bytes::Bytes) -> StreamResult<()> {
+        progress::interrupt();
+        if self.enabled.load(Ordering::SeqCst) {
+            self.buffer.lock().unwrap().extend_from_slice(&bytes);
+        }
+        if let Some(call) = &self.call {
+            call.lock().unwrap().extend_from_slice(&bytes);
+        }
+        self.stream.write(bytes)
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        self.stream.flush()
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        self.stream.check_write()
+    }
+
+    async fn write_ready(&mut self) -> StreamResult<usize> {
+        self.stream.write_ready().await
     }
 }
 
 #[async_trait::async_trait]
-impl StdoutStream for ImportImplStdout {
-    fn stream(&self) -> Box<(dyn HostOutputStream + 'static)> {
-        Stdout.stream()
+impl Subscribe for CaptureStream {
+    async fn ready(&mut self) {
+        self.stream.ready().await
+    }
+}
+
+/// Stdout or stderr of the guest, tee'd into an [`OutputCapture`]
+///
+/// Stdout, and stderr under `--sandbox`, is emitted as the guest's
+/// [`Event::Output`]s.
+struct CaptureStdout {
+    /// Where the output is emitted, or `None` to write it to the host's stderr
+    events: Option<Events>,
+    enabled: Arc<AtomicBool>,
+    buffer: Arc<Mutex<Vec<u8>>>,
+    call: Option<Arc<Mutex<Vec<u8>>>>,
+}
+
+impl CaptureStdout {
+    fn stdout(capture: &OutputCapture, events: &Events) -> Self {
+        Self {
+            events: Some(events.clone()),
+            enabled: capture.enabled.clone(),
+            buffer: capture.stdout.clone(),
+            call: Some(capture.call_stdout.clone()),
+        }
+    }
+
+    fn stderr(capture: &OutputCapture, events: &Events, sandbox: bool) -> Self {
+        Self {
+            events: sandbox.then(|| events.clone()),
+            enabled: capture.enabled.clone(),
+            buffer: capture.stderr.clone(),
+            call: None,
+        }
+    }
+}
+
+impl StdoutStream for CaptureStdout {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        let stream: Box<dyn HostOutputStream> = match &self.events {
+            Some(events) => Box::new(EventStdout {
+                events: events.clone(),
+                source: OutputSource::Guest,
+                stub: None,
+            }),
+            None => Stderr.stream(),
+        };
+        Box::new(CaptureStream {
+            stream,
+            enabled: self.enabled.clone(),
+            buffer: self.buffer.clone(),
+            call: self.call.clone(),
+        })
     }
 
     fn isatty(&self) -> bool {
-        Stdout.isatty()
+        match &self.events {
+            Some(events) => output_is_terminal(events),
+            None => Stderr.isatty(),
+        }
+    }
+}
+
+/// The exported function `name` of the exported instance `interface` of
+/// `instance`, or of its root if there is no interface
+fn export_func(
+    instance: &Instance,
+    store: &mut Store<Context>,
+    interface: Option<&str>,
+    name: &str,
+) -> anyhow::Result<Func> {
+    let func = match interface {
+        Some(instance_name) => {
+            let mut exports = instance.exports(&mut *store);
+            exports
+                .instance(instance_name)
+                .with_context(|| {
+                    format!("could not find exported instance with name '{instance_name}'")
+                })?
+                .func(name)
+        }
+        None => instance.exports(&mut *store).root().func(name),
+    };
+    func.with_context(|| match interface {
+        Some(interface) => format!("could not find function '{interface}#{name}' in instance"),
+        None => format!("could not find function '{name}' in instance"),
+    })
+}
+
+/// The calls [`Runtime::map`] has made so far
+struct MapProgress {
+    /// The outcome of each call, once it is done
+    outcomes: Vec<Option<anyhow::Result<Vec<Val>>>>,
+    /// The events of each call that is done but not emitted yet
+    events: Vec<Option<Vec<Event>>>,
+    /// How many calls had their events emitted
+    emitted: usize,
+}
+
+/// The number of resource handles `val` is or holds
+fn resource_count(val: &Val) -> usize {
+    match val {
+        Val::Resource(_) => 1,
+        Val::List(vals) | Val::Tuple(vals) => vals.iter().map(resource_count).sum(),
+        Val::Record(fields) => fields.iter().map(|(_, val)| resource_count(val)).sum(),
+        Val::Variant(_, Some(val))
+        | Val::Option(Some(val))
+        | Val::Result(Ok(Some(val)) | Err(Some(val))) => resource_count(val),
+        _ => 0,
+    }
+}
+
+/// Instances of the component made by [`Runtime::http_instances`]
+pub struct HttpInstances {
+    engine: Engine,
+    pre: InstancePre<Context>,
+    wasi_config: WasiConfig,
+    capture: OutputCapture,
+    events: Events,
+    trace: Arc<AtomicBool>,
+    exercised: Exercised,
+}
+
+impl HttpInstances {
+    pub fn pre(&self) -> &InstancePre<Context> {
+        &self.pre
+    }
+
+    /// A store for a new instance, built like the session's stores
+    pub fn store(&self) -> Store<Context> {
+        build_store(
+            &self.engine,
+            &self.wasi_config,
+            &self.capture,
+            &self.events,
+            &self.trace,
+            &self.exercised,
+        )
     }
 }
 
-fn build_store(engine: &Engine) -> Store<Context> {
+fn build_store(
+    engine: &Engine,
+    config: &WasiConfig,
+    capture: &OutputCapture,
+    events: &Events,
+    trace: &Arc<AtomicBool>,
+    exercised: &Exercised,
+) -> Store<Context> {
     let table = ResourceTable::new();
     let mut builder = WasiCtxBuilder::new();
-    builder.inherit_stdout().inherit_stderr();
+    builder
+        .stdout(Observed::new(
+            CaptureStdout::stdout(capture, events),
+            exercised,
+            "wasi:cli/stdout",
+        ))
+        .stderr(Observed::new(
+            CaptureStdout::stderr(capture, events, config.sandbox),
+            exercised,
+            "wasi:cli/stderr",
+        ));
+    builder
+        .args(&config.args)
+        .envs(&config.env)
+        .wall_clock(Observed::new(
+            config.clock.wall_clock(),
+            exercised,
+            "wasi:clocks/wall-clock",
+        ))
+        .monotonic_clock(Observed::new(
+            config.clock.monotonic_clock(),
+            exercised,
+            "wasi:clocks/monotonic-clock",
+        ));
+    let (secure, insecure) = match config.random_seed {
+        Some(seed) => {
+            builder.insecure_random_seed(seed.into());
+            (seeded_random(seed), seeded_random(seed.wrapping_add(1)))
+        }
+        None => (ChaCha8Rng::from_entropy(), ChaCha8Rng::from_entropy()),
+    };
+    builder
+        .secure_random(Observed::new(secure, exercised, "wasi:random/random"))
+        .insecure_random(Observed::new(insecure, exercised, "wasi:random/insecure"));
+    configure_stdin(&mut builder, &config.stdin);
+    configure_network(
+        &mut builder,
+        config.network.clone(),
+        events.clone(),
+        exercised.clone(),
+    );
     let wasi = builder.build();
     let context = Context::new(table, wasi);
-    Store::new(engine, context)
+    let mut store = Store::new(engine, context);
+    store.limiter(|context| &mut context.footprint);
+    give_fuel(&mut store);
+    // Calls into WASI cannot be wrapped so they are traced around the call,
+    // without knowing which function is called
+    let trace = trace.clone();
+    let output = events.clone();
+    let events = events.clone();
+    store.call_hook(move |data, hook| {
+        if !trace.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        match hook {
+            CallHook::CallingHost => data.host_call = Some(Instant::now()),
+            CallHook::ReturningFromHost => {
+                let traced = std::mem::take(&mut data.traced_call);
+                if let (Some(start), false) = (data.host_call.take(), traced) {
+                    events.emit(Event::Trace {
+                        call: "<WASI function>".to_owned(),
+                        results: Ok(Vec::new()),
+                        elapsed: start.elapsed(),
+                        source: "WASI",
+                    });
+                }
+            }
+            CallHook::CallingWasm | CallHook::ReturningFromWasm => {}
+        }
+        Ok(())
+    });
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(move |mut ctx| {
+        output.flush_output();
+        if ctx.data().profile.is_some() {
+            let backtrace = WasmBacktrace::capture(&ctx);
+            if let Some(profile) = &mut ctx.data_mut().profile {
+                profile.sample(&backtrace);
+            }
+        }
+        Ok(UpdateDeadline::Continue(1))
+    });
+    store
+}
+
+/// Give `store` all the fuel it can have so calls never run out, which fails
+/// if the engine does not count fuel and then does not matter
+fn give_fuel<T>(store: &mut Store<T>) {
+    let _ = store.set_fuel(u64::MAX);
+}
+
+/// The random numbers seeded with `seed`, which are the same on every platform
+/// and with every version of `rand`, unlike those of `StdRng`
+fn seeded_random(seed: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+/// Let the guest use the addresses `policy` allows, reporting every address it
+/// is denied since the guest may not
+fn configure_network(
+    builder: &mut WasiCtxBuilder,
+    policy: Option<NetworkPolicy>,
+    events: Events,
+    exercised: Exercised,
+) {
+    builder
+        .allow_ip_name_lookup(policy.is_some())
+        .socket_addr_check(move |addr, addr_use| {
+            exercised.record(match addr_use {
+                SocketAddrUse::TcpBind | SocketAddrUse::TcpConnect => "wasi:sockets/tcp",
+                SocketAddrUse::UdpBind
+                | SocketAddrUse::UdpConnect
+                | SocketAddrUse::UdpOutgoingDatagram => "wasi:sockets/udp",
+            });
+            let allowed = policy.as_ref().is_some_and(|policy| policy.allows(&addr));
+            if !allowed {
+                let action = match addr_use {
+                    SocketAddrUse::TcpBind => "binding a TCP socket to",
+                    SocketAddrUse::TcpConnect => "connecting over TCP to",
+                    SocketAddrUse::UdpBind => "binding a UDP socket to",
+                    SocketAddrUse::UdpConnect => "connecting over UDP to",
+                    SocketAddrUse::UdpOutgoingDatagram => "sending a UDP datagram to",
+                };
+                let reason = match &policy {
+                    Some(policy) => format!("--allow-network only allows {policy}"),
+                    None => "the network is only available with --allow-network".to_owned(),
+                };
+                events.emit(Event::Denied(format!("{action} {addr} ({reason})")));
+            }
+            Box::pin(async move { allowed })
+        });
+}
+
+/// A clock, random generator or output stream handed to the guest that notes
+/// when the guest uses it
+struct Observed<T> {
+    inner: T,
+    exercised: Exercised,
+    /// The interface the guest uses `inner` through
+    interface: &'static str,
+}
+
+impl<T> Observed<T> {
+    fn new(inner: T, exercised: &Exercised, interface: &'static str) -> Self {
+        Self {
+            inner,
+            exercised: exercised.clone(),
+            interface,
+        }
+    }
+
+    fn note(&self) {
+        self.exercised.record(self.interface);
+    }
+}
+
+impl<T: HostWallClock> HostWallClock for Observed<T> {
+    fn resolution(&self) -> Duration {
+        self.note();
+        self.inner.resolution()
+    }
+
+    fn now(&self) -> Duration {
+        self.note();
+        self.inner.now()
+    }
+}
+
+impl<T: HostMonotonicClock> HostMonotonicClock for Observed<T> {
+    fn resolution(&self) -> u64 {
+        self.note();
+        self.inner.resolution()
+    }
+
+    fn now(&self) -> u64 {
+        self.note();
+        self.inner.now()
+    }
+}
+
+impl<T: RngCore> RngCore for Observed<T> {
+    fn next_u32(&mut self) -> u32 {
+        self.note();
+        self.inner.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.note();
+        self.inner.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.note();
+        self.inner.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.note();
+        self.inner.try_fill_bytes(dest)
+    }
+}
+
+impl<T: StdoutStream> StdoutStream for Observed<T> {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        self.note();
+        self.inner.stream()
+    }
+
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+}
+
+fn configure_stdin(builder: &mut WasiCtxBuilder, stdin: &GuestStdin) {
+    match stdin {
+        GuestStdin::Empty => {}
+        GuestStdin::Bytes(bytes) => {
+            builder.stdin(MemoryInputPipe::new(bytes.clone()));
+        }
+        GuestStdin::Inherit => {
+            builder.inherit_stdin();
+        }
+    }
 }
 
 pub struct Context {
     table: ResourceTable,
     wasi: WasiCtx,
+    /// When the call into the host that is running started
+    host_call: Option<Instant>,
+    /// Whether the call into the host that is running is traced by the
+    /// definition it is calling
+    traced_call: bool,
+    /// The profile being taken of the running call, if any
+    profile: Option<Profile>,
+    /// The borrowed handles the running import released itself, which
+    /// happens when the host looks up the instance of a handle
+    released_borrows: Vec<ResourceAny>,
+    /// What the instance allocated, which `.stats` shows the growth of
+    footprint: Footprint,
+    /// The state of `wasi:http`, when the instance handles requests
+    http: WasiHttpCtx,
 }
 
 impl Context {
     fn new(table: ResourceTable, wasi: WasiCtx) -> Self {
-        Self { table, wasi }
+        Self {
+            table,
+            wasi,
+            host_call: None,
+            traced_call: false,
+            profile: None,
+            released_borrows: Vec::new(),
+            footprint: Footprint::default(),
+            http: WasiHttpCtx::new(),
+        }
+    }
+
+    pub(crate) fn http(&mut self) -> &mut WasiHttpCtx {
+        &mut self.http
+    }
+
+    /// Add the instance of an imported resource `resource` to the table
+    fn push_host_resource(
+        &mut self,
+        resource: HostResource,
+    ) -> Result<Resource<HostResource>, component::ResourceTableError> {
+        self.footprint.resources_created += 1;
+        self.table.push(resource)
+    }
+
+    /// Remove the instance of an imported resource at `rep` from the table
+    fn delete_host_resource(
+        &mut self,
+        rep: u32,
+    ) -> Result<HostResource, component::ResourceTableError> {
+        self.footprint.resources_dropped += 1;
+        self.table.delete(Resource::new_own(rep))
     }
 }
 
@@ -458,15 +3732,83 @@ impl WasiView for Context {
     }
 }
 
-fn load_engine() -> anyhow::Result<Engine> {
+/// Load the engine, with async support for handling HTTP requests when
+/// `async_support` is set and caching compiled code with wasmtime's own cache
+/// when `cache` is set
+fn load_engine(
+    cache: bool,
+    options: &EngineOptions,
+    async_support: bool,
+) -> anyhow::Result<Engine> {
     let mut config = Config::new();
-    config.wasm_component_model(true);
+    config
+        .wasm_component_model(true)
+        .async_support(async_support);
+    options.configure(&mut config);
+    config.consume_fuel(options.fuel);
+    // Use the component's debug info, if it has any, to give trap backtraces
+    // source locations
+    config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+    if cache {
+        if let Err(e) = config.cache_config_load_default() {
+            tracing::debug!("could not enable wasmtime's compilation cache: {e}");
+        }
+    }
 
     Engine::new(&config)
 }
 
-fn load_component(engine: &Engine, component_bytes: &[u8]) -> anyhow::Result<Component> {
-    Component::new(engine, component_bytes)
+/// The bytes of a component to load
+pub enum ComponentBytes {
+    /// A binary component, which is compiled
+    Binary(Vec<u8>),
+    /// A component precompiled by `wepl compile`, which is native code and
+    /// so only loaded from the `.cwasm` files the user gives
+    Precompiled(Vec<u8>),
+}
+
+fn load_component(
+    engine: &Engine,
+    component_bytes: &[u8],
+    precompiled: bool,
+) -> anyhow::Result<Component> {
+    if !precompiled {
+        return compile_component(engine, component_bytes);
+    }
+    // SAFETY: precompiled components are only loaded from the `.cwasm` files
+    // the user gives, never from stubs, stdin or registries, and wasmtime
+    // checks that they were compiled for a compatible engine.
+    unsafe { Component::deserialize(engine, component_bytes) }.context(
+        "could not load the precompiled component, recompile it with this version of `wepl compile` and the same engine options (e.g. `--profiling`)",
+    )
+}
+
+/// Compile the binary component, pointing at the section it is invalid in
+/// if it fails to compile because it is invalid
+pub fn compile_component(engine: &Engine, component_bytes: &[u8]) -> anyhow::Result<Component> {
+    Component::new(engine, component_bytes).map_err(|e| {
+        match crate::validate::validate(component_bytes) {
+            Err(invalid) => e.context(format!("invalid component: {invalid}")),
+            Ok(_) => e,
+        }
+    })
+}
+
+/// Whether the bytes are a precompiled component rather than a binary component
+pub fn is_precompiled(bytes: &[u8]) -> bool {
+    // Precompiled artifacts are ELF files
+    bytes.starts_with(b"\x7fELF")
+}
+
+/// Check that an engine can be configured with `options`
+pub fn check_engine_options(options: &EngineOptions) -> anyhow::Result<()> {
+    load_engine(false, options, false).map(drop)
+}
+
+/// Precompile the component so it can be loaded without compiling it
+pub fn precompile(component_bytes: &[u8], options: &EngineOptions) -> anyhow::Result<Vec<u8>> {
+    let _spinner = progress::Spinner::start("Compiling the component");
+    load_engine(false, options, false)?.precompile_component(component_bytes)
 }
 
 struct ImportImplsContext {
@@ -490,75 +3832,742 @@ impl WasiView for ImportImplsContext {
     }
 }
 
-fn types_equal(
-    resolver1: &WorldResolver,
-    t1: &wit_parser::Type,
-    resolver2: &WorldResolver,
-    t2: &wit_parser::Type,
-) -> bool {
-    match (t1, t2) {
-        (wit_parser::Type::Id(t1), wit_parser::Type::Id(t2)) => {
-            let t1 = resolver1.type_by_id(*t1).unwrap();
-            let t2 = resolver2.type_by_id(*t2).unwrap();
-            type_defs_equal(resolver1, &t1.kind, resolver2, &t2.kind)
-        }
-        (wit_parser::Type::Id(t1), t2) => {
-            let t1 = resolver1.type_by_id(*t1).unwrap();
-            if let wit_parser::TypeDefKind::Type(t1) = &t1.kind {
-                types_equal(resolver1, t1, resolver2, t2)
-            } else {
-                false
-            }
-        }
-        (t1, wit_parser::Type::Id(t2)) => {
-            let t2 = resolver1.type_by_id(*t2).unwrap();
-            if let wit_parser::TypeDefKind::Type(t2) = &t2.kind {
-                types_equal(resolver1, t1, resolver2, t2)
-            } else {
-                false
-            }
-        }
-        (t1, t2) => t1 == t2,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangle_symbols() {
+        assert_eq!(
+            demangle("_ZN4core9panicking5panic17h0123456789abcdefE"),
+            "core::panicking::panic"
+        );
+        assert_eq!(demangle("_ZN3foo3barEv"), "foo::bar()");
+        assert_eq!(demangle("run"), "run");
     }
-}
 
-fn type_defs_equal(
-    resolver1: &WorldResolver,
-    t1: &wit_parser::TypeDefKind,
-    resolver2: &WorldResolver,
-    t2: &wit_parser::TypeDefKind,
-) -> bool {
-    match (t1, t2) {
-        (wit_parser::TypeDefKind::Result(r1), wit_parser::TypeDefKind::Result(r2)) => {
-            let oks = match (&r1.ok, &r2.ok) {
-                (None, None) => true,
-                (Some(t1), Some(t2)) => types_equal(resolver1, t1, resolver2, t2),
-                _ => false,
-            };
-            let errs = match (&r1.err, &r2.err) {
-                (None, None) => true,
-                (Some(t1), Some(t2)) => types_equal(resolver1, t1, resolver2, t2),
-                _ => false,
-            };
-            oks && errs
-        }
-        (wit_parser::TypeDefKind::List(t1), wit_parser::TypeDefKind::List(t2)) => {
-            types_equal(resolver1, t1, resolver2, t2)
-        }
-        (wit_parser::TypeDefKind::Variant(v1), wit_parser::TypeDefKind::Variant(v2)) => {
-            if v1.cases.len() != v2.cases.len() {
-                return false;
-            }
-            v1.cases.iter().zip(v2.cases.iter()).all(|(c1, c2)| {
-                let types_equal = match (&c1.ty, &c2.ty) {
-                    (Some(t1), Some(t2)) => types_equal(resolver1, t1, resolver2, t2),
-                    (None, None) => true,
-                    _ => false,
-                };
-                c1.name == c2.name && types_equal
+    fn runtime(wat: &str, wasi_config: WasiConfig) -> Runtime {
+        runtime_with(wat, wasi_config, EngineOptions::default())
+    }
+
+    fn runtime_with(wat: &str, wasi_config: WasiConfig, options: EngineOptions) -> Runtime {
+        let bytes = wat::parse_str(wat).unwrap();
+        let resolver = WorldResolver::from_bytes(&bytes).unwrap();
+        let component = ComponentBytes::Binary(bytes);
+        Runtime::init(
+            component,
+            &resolver,
+            wasi_config,
+            |_, _| {},
+            |_, _| {},
+            None,
+            options,
+        )
+        .unwrap()
+    }
+
+    /// A component whose `sleep` returns a pollable that is ready after `ns`
+    /// nanoseconds
+    const SLEEP: &str = r#"(component
+        (import "wasi:cli/exit@0.2.0" (instance
+            (export "exit" (func (param "status" (result))))
+        ))
+        (import "wasi:io/poll@0.2.0" (instance $poll
+            (export "pollable" (type (sub resource)))
+        ))
+        (alias export $poll "pollable" (type $pollable))
+        (import "wasi:clocks/monotonic-clock@0.2.0" (instance $clock
+            (alias outer 1 $pollable (type $t))
+            (export "pollable" (type $pollable (eq $t)))
+            (export "subscribe-duration" (func (param "when" u64) (result (own $pollable))))
+        ))
+        (core func $subscribe (canon lower (func $clock "subscribe-duration")))
+        (core module $m
+            (import "clock" "subscribe-duration" (func $subscribe (param i64) (result i32)))
+            (func (export "sleep") (param i64) (result i32) (call $subscribe (local.get 0)))
+        )
+        (core instance $clock (export "subscribe-duration" (func $subscribe)))
+        (core instance $m (instantiate $m (with "clock" (instance $clock))))
+        (func (export "sleep") (param "ns" u64) (result (own $pollable))
+            (canon lift (core func $m "sleep")))
+    )"#;
+
+    /// The pollable `sleep` returns for `ns` nanoseconds
+    fn sleep(runtime: &mut Runtime, ns: u64) -> ResourceAny {
+        let func = runtime.export_func(None, "sleep").unwrap();
+        let results = runtime.call_func(func, &[Val::U64(ns)]).unwrap().results;
+        let [Val::Resource(pollable)] = results[..] else {
+            panic!("expected a pollable, got {results:?}")
+        };
+        pollable
+    }
+
+    #[test]
+    fn awaiting_pollables() {
+        let mut runtime = runtime(SLEEP, WasiConfig::default());
+        let pollable = sleep(&mut runtime, 1_000_000);
+        let (ready, pollable) = runtime.await_ready(pollable, None).unwrap();
+        assert!(ready);
+        // The handle stays usable after the wait
+        let (ready, _) = runtime.await_ready(pollable, None).unwrap();
+        assert!(ready);
+    }
+
+    #[test]
+    fn awaiting_pollables_times_out() {
+        let mut runtime = runtime(SLEEP, WasiConfig::default());
+        let pollable = sleep(&mut runtime, 60_000_000_000);
+        let timeout = Some(Duration::from_millis(20));
+        let (ready, _) = runtime.await_ready(pollable, timeout).unwrap();
+        assert!(!ready);
+    }
+
+    /// A component whose `stdout` returns its stdout stream
+    const STDOUT: &str = r#"(component
+        (import "wasi:io/streams@0.2.0" (instance $streams
+            (export "output-stream" (type (sub resource)))
+        ))
+        (alias export $streams "output-stream" (type $output-stream))
+        (import "wasi:cli/stdout@0.2.0" (instance $stdout
+            (alias outer 1 $output-stream (type $t))
+            (export "output-stream" (type $output-stream (eq $t)))
+            (export "get-stdout" (func (result (own $output-stream))))
+        ))
+        (core func $get-stdout (canon lower (func $stdout "get-stdout")))
+        (core module $m
+            (import "stdout" "get-stdout" (func $get-stdout (result i32)))
+            (func (export "stdout") (result i32) (call $get-stdout))
+        )
+        (core instance $stdout (export "get-stdout" (func $get-stdout)))
+        (core instance $m (instantiate $m (with "stdout" (instance $stdout))))
+        (func (export "stdout") (result (own $output-stream))
+            (canon lift (core func $m "stdout")))
+    )"#;
+
+    #[test]
+    fn writing_to_streams() {
+        let mut runtime = runtime(STDOUT, WasiConfig::default());
+        runtime.set_capture(true);
+        let func = runtime.export_func(None, "stdout").unwrap();
+        let results = runtime.call_func(func, &[]).unwrap().results;
+        let [Val::Resource(stream)] = results[..] else {
+            panic!("expected a stream, got {results:?}")
+        };
+        let stream = runtime.write_stream(stream, b"hello\n").unwrap();
+        let (ready, _) = runtime.await_ready(stream, None).unwrap();
+        assert!(ready);
+        let (stdout, _) = runtime.take_captured();
+        assert_eq!(stdout, "hello\n");
+    }
+
+    /// A component whose `pair` returns `n` and twice `n` and whose `nothing`
+    /// returns nothing
+    const PAIR: &str = r#"(component
+        (core module $m
+            (memory (export "memory") 1)
+            (func (export "pair") (param $n i32) (result i32)
+                (i32.store (i32.const 8) (local.get $n))
+                (i32.store (i32.const 12) (i32.mul (local.get $n) (i32.const 2)))
+                (i32.const 8))
+            (func (export "nothing"))
+        )
+        (core instance $m (instantiate $m))
+        (alias core export $m "memory" (core memory $memory))
+        (func (export "pair") (param "n" u32) (result (tuple u32 u32))
+            (canon lift (core func $m "pair") (memory $memory)))
+        (func (export "nothing") (canon lift (core func $m "nothing")))
+    )"#;
+
+    #[test]
+    fn calls_return_as_many_results_as_the_function_has() {
+        let mut runtime = runtime(PAIR, WasiConfig::default());
+        let func = runtime.export_func(None, "pair").unwrap();
+        let outcome = runtime.call_func(func, &[Val::U32(21)]).unwrap();
+        assert_eq!(
+            outcome.results,
+            [Val::Tuple(vec![Val::U32(21), Val::U32(42)])]
+        );
+        assert_eq!(outcome.stdout, "");
+        let func = runtime.export_func(None, "nothing").unwrap();
+        assert!(runtime.call_func(func, &[]).unwrap().results.is_empty());
+    }
+
+    /// A component whose `count` returns how many times it was called and
+    /// whose `fail` does the same but traps in its post-return
+    const POST_RETURN: &str = r#"(component
+        (core module $m
+            (global $calls (mut i32) (i32.const 0))
+            (func $count (export "count") (result i32)
+                (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+                (global.get $calls))
+            (func (export "cabi_post_fail") (param i32) unreachable)
+        )
+        (core instance $m (instantiate $m))
+        (func (export "count") (result u32) (canon lift (core func $m "count")))
+        (func (export "fail") (result u32)
+            (canon lift (core func $m "count") (post-return (func $m "cabi_post_fail"))))
+    )"#;
+
+    #[test]
+    fn failing_post_returns_refresh_the_instance() {
+        let mut runtime = runtime(POST_RETURN, WasiConfig::default());
+        let mut call = |name: &str| {
+            let func = runtime.export_func(None, name).unwrap();
+            runtime.call_func(func, &[]).map(|outcome| outcome.results)
+        };
+        assert_eq!(call("count").unwrap(), [Val::U32(1)]);
+        let e = call("fail").unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<wasmtime::Trap>(),
+            Some(&wasmtime::Trap::UnreachableCodeReached)
+        );
+        let message = format!("{e:#}");
+        assert!(message.contains("the call returned 2"), "{message}");
+        assert!(message.contains("given a fresh instance"), "{message}");
+        // The fresh instance can be called, starting over
+        assert_eq!(call("count").unwrap(), [Val::U32(1)]);
+    }
+
+    #[test]
+    fn new_components_are_linked_again() {
+        let mut runtime = runtime(POST_RETURN, WasiConfig::default());
+        runtime
+            .set_component(wat::parse_str(PAIR).unwrap())
+            .unwrap();
+        let func = runtime.export_func(None, "pair").unwrap();
+        assert_eq!(
+            runtime.call_func(func, &[Val::U32(3)]).unwrap().results,
+            [Val::Tuple(vec![Val::U32(3), Val::U32(6)])]
+        );
+    }
+
+    #[test]
+    fn snapshots_are_restored_by_calling_again() {
+        let mut runtime = runtime(POST_RETURN, WasiConfig::default());
+        let count = |runtime: &mut Runtime| {
+            let count = parser::ItemIdent::parse_str("count").unwrap();
+            runtime.call(count, &[]).unwrap().results
+        };
+        count(&mut runtime);
+        count(&mut runtime);
+        assert_eq!(runtime.snapshot("two").unwrap(), 2);
+        assert_eq!(count(&mut runtime), [Val::U32(3)]);
+        assert_eq!(runtime.restore("two").unwrap(), 2);
+        assert_eq!(count(&mut runtime), [Val::U32(3)]);
+        // Calls made directly are not recorded
+        let func = runtime.export_func(None, "count").unwrap();
+        runtime.call_func(func, &[]).unwrap();
+        assert_eq!(runtime.snapshot("three").unwrap(), 3);
+        assert_eq!(
+            runtime.snapshots().collect::<Vec<_>>(),
+            [("three", 3), ("two", 2)]
+        );
+        assert!(runtime.restore("none").is_err());
+    }
+
+    /// A component whose `print` writes the digit `n` on a line three times
+    /// and returns twice `n`, trapping for 0, and whose `spin` spins for
+    /// `spins` rounds after printing
+    const PRINT: &str = r#"(component
+        (import "wasi:io/error@0.2.0" (instance $error
+            (export "error" (type (sub resource)))
+        ))
+        (alias export $error "error" (type $error))
+        (import "wasi:io/streams@0.2.0" (instance $streams
+            (alias outer 1 $error (type $e))
+            (export "error" (type $error (eq $e)))
+            (export "output-stream" (type $output-stream (sub resource)))
+            (type $stream-error (variant
+                (case "last-operation-failed" (own $error))
+                (case "closed")
+            ))
+            (export "stream-error" (type $stream-error' (eq $stream-error)))
+            (export "[method]output-stream.blocking-write-and-flush" (func
+                (param "self" (borrow $output-stream))
+                (param "contents" (list u8))
+                (result (result (error $stream-error')))
+            ))
+        ))
+        (alias export $streams "output-stream" (type $output-stream))
+        (import "wasi:cli/stdout@0.2.0" (instance $stdout
+            (alias outer 1 $output-stream (type $t))
+            (export "output-stream" (type $output-stream (eq $t)))
+            (export "get-stdout" (func (result (own $output-stream))))
+        ))
+        (core module $memory (memory (export "memory") 1))
+        (core instance $memory (instantiate $memory))
+        (alias core export $memory "memory" (core memory $memory))
+        (core func $get-stdout (canon lower (func $stdout "get-stdout")))
+        (core func $write (canon lower
+            (func $streams "[method]output-stream.blocking-write-and-flush")
+            (memory $memory)
+        ))
+        (core module $m
+            (import "host" "memory" (memory 1))
+            (import "host" "get-stdout" (func $get-stdout (result i32)))
+            (import "host" "write" (func $write (param i32 i32 i32 i32)))
+            (func $print (export "print") (param $n i32) (result i32)
+                (local $stdout i32)
+                (local $lines i32)
+                (if (i32.eqz (local.get $n)) (then unreachable))
+                (local.set $stdout (call $get-stdout))
+                (i32.store8 (i32.const 16) (i32.add (i32.const 48) (local.get $n)))
+                (i32.store8 (i32.const 17) (i32.const 10))
+                (loop $line
+                    (call $write (local.get $stdout) (i32.const 16) (i32.const 2) (i32.const 32))
+                    (local.set $lines (i32.add (local.get $lines) (i32.const 1)))
+                    (br_if $line (i32.lt_u (local.get $lines) (i32.const 3))))
+                (i32.mul (local.get $n) (i32.const 2)))
+            (func (export "spin") (param $n i32) (param $spins i32) (result i32)
+                (call $print (local.get $n))
+                (loop $spin
+                    (local.set $spins (i32.sub (local.get $spins) (i32.const 1)))
+                    (br_if $spin (i32.gt_s (local.get $spins) (i32.const 0)))))
+        )
+        (core instance $host
+            (export "memory" (memory $memory))
+            (export "get-stdout" (func $get-stdout))
+            (export "write" (func $write))
+        )
+        (core instance $m (instantiate $m (with "host" (instance $host))))
+        (func (export "print") (param "n" u32) (result u32)
+            (canon lift (core func $m "print")))
+        (func (export "spin") (param "n" u32) (param "spins" u32) (result u32)
+            (canon lift (core func $m "spin")))
+    )"#;
+
+    /// The outcomes of mapping `print` over `inputs` on up to `parallel`
+    /// threads along with the guest's output
+    fn map_print(inputs: &[u32], parallel: usize) -> (Vec<Result<u32, String>>, String) {
+        let runtime = runtime(PRINT, WasiConfig::default());
+        runtime.events().collect();
+        let inputs = inputs
+            .iter()
+            .map(|n| vec![Val::U32(*n)])
+            .collect::<Vec<_>>();
+        let ident = ItemIdent::parse_str("print").unwrap();
+        let outcomes = runtime
+            .map(ident, &inputs, parallel)
+            .unwrap()
+            .into_iter()
+            .map(|outcome| match outcome.map_err(|e| e.to_string())?[..] {
+                [Val::U32(n)] => Ok(n),
+                ref results => panic!("expected a u32, got {results:?}"),
+            })
+            .collect();
+        let output = runtime
+            .events()
+            .take()
+            .iter()
+            .filter(|event| matches!(event, Event::Output { .. }))
+            .map(Event::render)
+            .collect();
+        (outcomes, output)
+    }
+
+    #[test]
+    fn mapping() {
+        let (outcomes, output) = map_print(&[1, 2, 3], 1);
+        assert_eq!(outcomes, [Ok(2), Ok(4), Ok(6)]);
+        assert_eq!(output, "1\n1\n1\n2\n2\n2\n3\n3\n3\n");
+    }
+
+    #[test]
+    fn mapping_in_parallel_keeps_the_output_of_calls_together() {
+        let inputs = (1..=9).collect::<Vec<_>>();
+        let (outcomes, output) = map_print(&inputs, 4);
+        let expected = inputs.iter().map(|n| Ok(n * 2)).collect::<Vec<_>>();
+        assert_eq!(outcomes, expected);
+        let expected = inputs
+            .iter()
+            .map(|n| format!("{n}\n").repeat(3))
+            .collect::<String>();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn failed_calls_are_mapped_to_errors() {
+        let (outcomes, output) = map_print(&[1, 0, 2], 2);
+        assert!(
+            matches!(outcomes[..], [Ok(2), Err(_), Ok(4)]),
+            "{outcomes:?}"
+        );
+        assert_eq!(output, "1\n1\n1\n2\n2\n2\n");
+
+        let (outcomes, _) = map_print(&[0], 8);
+        assert!(matches!(outcomes[..], [Err(_)]), "{outcomes:?}");
+        assert_eq!(map_print(&[], 8), (Vec::new(), String::new()));
+    }
+
+    #[test]
+    fn output_is_streamed_while_calls_run() {
+        let options = EngineOptions {
+            stream_output: true,
+            ..Default::default()
+        };
+        let mut runtime = runtime_with(PRINT, WasiConfig::default(), options);
+        let streamed = Arc::new(Mutex::new(Vec::new()));
+        runtime.events().collect_streaming(Arc::new({
+            let streamed = streamed.clone();
+            move |output| streamed.lock().unwrap().extend(output)
+        }));
+        let func = runtime.export_func(None, "spin").unwrap();
+        // Spinning for long enough to be interrupted at least once
+        let args = [Val::U32(1), Val::U32(1_000_000_000)];
+        let outcome = runtime.call_func(func, &args).unwrap();
+        assert_eq!(outcome.results, [Val::U32(2)]);
+        let streamed = streamed.lock().unwrap();
+        let streamed = streamed.iter().map(Event::render).collect::<String>();
+        assert_eq!(streamed, "1\n1\n1\n");
+        assert!(runtime.events().take().is_empty());
+    }
+
+    #[test]
+    fn sandboxed_stderr_is_emitted() {
+        let stderr = STDOUT.replace("stdout", "stderr");
+        let wasi_config = WasiConfig {
+            sandbox: true,
+            ..WasiConfig::default()
+        };
+        let mut runtime = runtime(&stderr, wasi_config);
+        runtime.set_capture(true);
+        runtime.events().collect();
+        let func = runtime.export_func(None, "stderr").unwrap();
+        let results = runtime.call_func(func, &[]).unwrap().results;
+        let [Val::Resource(stream)] = results[..] else {
+            panic!("expected a stream, got {results:?}")
+        };
+        runtime.write_stream(stream, b"oops\n").unwrap();
+        let output = runtime
+            .events()
+            .take()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Output {
+                    source: OutputSource::Guest,
+                    bytes,
+                    ..
+                } => Some(bytes),
+                _ => None,
             })
+            .collect::<Vec<_>>();
+        assert_eq!(output, [bytes::Bytes::from_static(b"oops\n")]);
+        // What the guest writes to stderr can still be captured
+        let (_, stderr) = runtime.take_captured();
+        assert_eq!(stderr, "oops\n");
+    }
+
+    #[test]
+    fn stats_are_shown_per_runtime() {
+        let stats = |runtime: &mut Runtime| {
+            runtime.events().collect();
+            let func = runtime.export_func(None, "stdout").unwrap();
+            runtime.call_func(func, &[]).unwrap();
+            let events = runtime.events().take();
+            events.iter().any(|event| matches!(event, Event::Stats(_)))
+        };
+        let mut shown = runtime(STDOUT, WasiConfig::default());
+        let mut other = runtime(STDOUT, WasiConfig::default());
+        shown.set_stats(true);
+        assert!(stats(&mut shown));
+        assert!(!stats(&mut other));
+    }
+
+    /// A component whose `call` returns what its imported `get` returns
+    const GET: &str = r#"(component
+        (import "get" (func $get (result u32)))
+        (core func $get (canon lower (func $get)))
+        (core module $m
+            (import "host" "get" (func $get (result i32)))
+            (func (export "call") (result i32) (call $get))
+        )
+        (core instance $host (export "get" (func $get)))
+        (core instance $m (instantiate $m (with "host" (instance $host))))
+        (func (export "call") (result u32) (canon lift (core func $m "call")))
+    )"#;
+
+    #[test]
+    fn collected_prompts_are_refused() {
+        let mut runtime = runtime(GET, WasiConfig::default());
+        runtime.set_prompt_imports(true);
+        runtime.events().collect();
+        let func = runtime.export_func(None, "call").unwrap();
+        let error = runtime.call_func(func, &[]).err().unwrap();
+        assert!(
+            format!("{error:?}").contains("can only be prompted for in the terminal"),
+            "{error:?}"
+        );
+    }
+
+    /// A component whose `call` returns what `get` of its imported
+    /// `my:test/store` returns
+    const STORE_USER: &str = r#"(component
+        (import "my:test/store" (instance $store
+            (export "get" (func (result u32)))
+        ))
+        (alias export $store "get" (func $get))
+        (core func $get (canon lower (func $get)))
+        (core module $m
+            (import "host" "get" (func $get (result i32)))
+            (func (export "call") (result i32) (call $get))
+        )
+        (core instance $host (export "get" (func $get)))
+        (core instance $m (instantiate $m (with "host" (instance $host))))
+        (func (export "call") (result u32) (canon lift (core func $m "call")))
+    )"#;
+
+    /// A component exporting a `my:test/store` whose `get` returns 7
+    const STORE: &str = r#"(component
+        (core module $m (func (export "get") (result i32) (i32.const 7)))
+        (core instance $m (instantiate $m))
+        (func $get (result u32) (canon lift (core func $m "get")))
+        (instance $store (export "get" (func $get)))
+        (export "my:test/store" (instance $store))
+    )"#;
+
+    #[test]
+    fn compositions_satisfy_imports() {
+        let definitions = std::env::temp_dir().join(format!(
+            "wepl-store-{}-{:016x}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir(&definitions).unwrap();
+        let definition = definitions.join("store.wasm");
+        std::fs::write(&definition, wat::parse_str(STORE).unwrap()).unwrap();
+        let mut runtime = runtime(STORE_USER, WasiConfig::default());
+        let config = wasm_compose::config::Config {
+            definitions: vec![definition],
+            ..Default::default()
+        };
+        let composed = runtime.compose(config);
+        std::fs::remove_dir_all(&definitions).unwrap();
+        composed.unwrap();
+        // The copy of the component composed from is gone
+        let prefix = format!("wepl-compose-{}-", std::process::id());
+        assert!(!std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .any(|entry| entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&prefix)));
+        let func = runtime.export_func(None, "call").unwrap();
+        assert_eq!(runtime.call_func(func, &[]).unwrap().results, [Val::U32(7)]);
+    }
+
+    /// A component importing the resources `a` and `b`, whose `drop-a` and
+    /// `drop-b` drop a new instance of them
+    const THINGS: &str = r#"(component
+        (import "my:test/things" (instance $things
+            (export "a" (type $a (sub resource)))
+            (export "b" (type $b (sub resource)))
+            (export "make-a" (func (result (own $a))))
+            (export "make-b" (func (result (own $b))))
+        ))
+        (alias export $things "a" (type $a))
+        (alias export $things "b" (type $b))
+        (alias export $things "make-a" (func $make-a))
+        (alias export $things "make-b" (func $make-b))
+        (core func $make-a (canon lower (func $make-a)))
+        (core func $make-b (canon lower (func $make-b)))
+        (core func $drop-a (canon resource.drop $a))
+        (core func $drop-b (canon resource.drop $b))
+        (core module $m
+            (import "host" "make-a" (func $make-a (result i32)))
+            (import "host" "make-b" (func $make-b (result i32)))
+            (import "host" "drop-a" (func $drop-a (param i32)))
+            (import "host" "drop-b" (func $drop-b (param i32)))
+            (func (export "drop-a") (call $drop-a (call $make-a)))
+            (func (export "drop-b") (call $drop-b (call $make-b)))
+        )
+        (core instance $host
+            (export "make-a" (func $make-a))
+            (export "make-b" (func $make-b))
+            (export "drop-a" (func $drop-a))
+            (export "drop-b" (func $drop-b))
+        )
+        (core instance $m (instantiate $m (with "host" (instance $host))))
+        (func (export "drop-a") (canon lift (core func $m "drop-a")))
+        (func (export "drop-b") (canon lift (core func $m "drop-b")))
+    )"#;
+
+    #[test]
+    fn imported_resources_are_told_apart() {
+        let bytes = wat::parse_str(THINGS).unwrap();
+        let resolver = WorldResolver::from_bytes(&bytes).unwrap();
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let mut runtime = Runtime::init(
+            ComponentBytes::Binary(bytes),
+            &resolver,
+            WasiConfig::default(),
+            |_, _| {},
+            {
+                let dropped = dropped.clone();
+                move |resource, _| dropped.lock().unwrap().push(resource.name.clone())
+            },
+            None,
+            EngineOptions::default(),
+        )
+        .unwrap();
+        let things = parser::InterfaceIdent::parse_str("my:test/things").unwrap();
+        assert_eq!(runtime.mock(&resolver, things).unwrap(), 2);
+        // The mocks create instances of the resource they return
+        for name in ["drop-b", "drop-a", "drop-b"] {
+            let func = runtime.export_func(None, name).unwrap();
+            runtime.call_func(func, &[]).unwrap();
         }
-        // TODO: more comparisons
-        _ => false,
+        assert_eq!(*dropped.lock().unwrap(), ["b", "a", "b"]);
+    }
+
+    #[test]
+    fn imported_resources_are_limited() {
+        let component = |count: usize| {
+            let imports = (0..count)
+                .map(|i| {
+                    format!(
+                        r#"(import "my:test/r{i}" (instance (export "r" (type (sub resource)))))"#
+                    )
+                })
+                .collect::<String>();
+            let bytes = wat::parse_str(format!("(component {imports})")).unwrap();
+            let resolver = WorldResolver::from_bytes(&bytes).unwrap();
+            Runtime::init(
+                ComponentBytes::Binary(bytes),
+                &resolver,
+                WasiConfig::default(),
+                |_, _| {},
+                |_, _| {},
+                None,
+                EngineOptions::default(),
+            )
+        };
+        assert!(component(HOST_RESOURCE_KINDS.len()).is_ok());
+        let error = component(HOST_RESOURCE_KINDS.len() + 1).err().unwrap();
+        assert!(
+            format!("{error:#}").contains("the component imports more than 32 resources"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn call_budgets() {
+        let key =
+            |interface: Option<&str>, name: &str| (interface.map(str::to_owned), name.to_owned());
+        let handle = key(Some("wasi:http/outgoing-handler@0.2.0"), "handle");
+        assert!(limits_import("wasi:http/outgoing-handler", &handle));
+        assert!(limits_import("wasi:http/outgoing-handler#handle", &handle));
+        assert!(!limits_import("wasi:http/outgoing-handler#other", &handle));
+        assert!(limits_import("log", &key(None, "log")));
+
+        let budgets = Mutex::new(BTreeMap::from([(
+            "wasi:http/outgoing-handler".to_owned(),
+            CallBudget { max: 2, calls: 0 },
+        )]));
+        assert!(spend_budget(&budgets, &handle).is_ok());
+        assert!(spend_budget(&budgets, &key(Some("my:kv/store"), "get")).is_ok());
+        assert!(spend_budget(&budgets, &handle).is_ok());
+        assert!(spend_budget(&budgets, &handle).is_err());
+    }
+
+    #[test]
+    fn reentrant_calls() {
+        let instance = Arc::new(Mutex::new(0));
+        let mut lock = CallLock::new(&instance).unwrap();
+        assert!(CallLock::new(&instance).is_none());
+        // Other threads wait for the call to finish instead of failing
+        let other = std::thread::spawn({
+            let instance = instance.clone();
+            move || *CallLock::new(&instance).unwrap()
+        });
+        *lock += 1;
+        drop(lock);
+        assert_eq!(other.join().unwrap(), 1);
+        assert!(CallLock::new(&instance).is_some());
+    }
+
+    /// A component whose `is-terminal` tells whether its stdout is a terminal
+    /// through the interfaces of a later 0.2 release of wasi:cli
+    const TERMINAL: &str = r#"(component
+        (import "wasi:cli/terminal-output@0.2.3" (instance $output
+            (export "terminal-output" (type (sub resource)))
+        ))
+        (alias export $output "terminal-output" (type $terminal-output))
+        (import "wasi:cli/terminal-stdout@0.2.3" (instance $stdout
+            (alias outer 1 $terminal-output (type $t))
+            (export "terminal-output" (type $output (eq $t)))
+            (export "get-terminal-stdout" (func (result (option (own $output)))))
+        ))
+        (core module $memory (memory (export "memory") 1))
+        (core instance $memory (instantiate $memory))
+        (alias core export $memory "memory" (core memory $memory))
+        (core func $get (canon lower (func $stdout "get-terminal-stdout") (memory $memory)))
+        (core module $m
+            (import "host" "memory" (memory 1))
+            (import "host" "get-terminal-stdout" (func $get (param i32)))
+            (func (export "is-terminal") (result i32)
+                (call $get (i32.const 0))
+                (i32.load8_u (i32.const 0)))
+        )
+        (core instance $host
+            (export "memory" (memory $memory))
+            (export "get-terminal-stdout" (func $get))
+        )
+        (core instance $m (instantiate $m (with "host" (instance $host))))
+        (func (export "is-terminal") (result bool) (canon lift (core func $m "is-terminal")))
+    )"#;
+
+    /// Call `is-terminal`, returning whether the component saw a terminal
+    fn is_terminal(runtime: &mut Runtime) -> Val {
+        let func = runtime.export_func(None, "is-terminal").unwrap();
+        runtime.call_func(func, &[]).unwrap().results.remove(0)
+    }
+
+    #[test]
+    fn terminal_interfaces_of_later_releases() {
+        let bytes = wat::parse_str(TERMINAL).unwrap();
+        assert!(WorldResolver::from_bytes(&bytes)
+            .unwrap()
+            .imports_wasi_cli());
+        let mut runtime = runtime(TERMINAL, WasiConfig::default());
+        let terminal = std::io::IsTerminal::is_terminal(&std::io::stdout());
+        assert_eq!(is_terminal(&mut runtime), Val::Bool(terminal));
+    }
+
+    #[test]
+    fn collected_output_is_not_a_terminal() {
+        let mut runtime = runtime(TERMINAL, WasiConfig::default());
+        runtime.events().collect();
+        assert_eq!(is_terminal(&mut runtime), Val::Bool(false));
+    }
+
+    #[test]
+    fn seeded_random_is_portable() {
+        assert_eq!(seeded_random(42).next_u64(), 12578764544318200737);
+        assert_ne!(seeded_random(42).next_u64(), seeded_random(43).next_u64());
+    }
+
+    #[test]
+    fn binary_components_are_not_deserialized() {
+        let bytes = wat::parse_str("(component)").unwrap();
+        let resolver = WorldResolver::from_bytes(&bytes).unwrap();
+        let options = EngineOptions::default();
+        let precompiled = precompile(&bytes, &options).unwrap();
+        // Stubs, stdin and registries give binaries, even if they look precompiled
+        let component = ComponentBytes::Binary(precompiled.clone());
+        let init = |component| {
+            let wasi_config = WasiConfig::default();
+            Runtime::init(
+                component,
+                &resolver,
+                wasi_config,
+                |_, _| {},
+                |_, _| {},
+                None,
+                options.clone(),
+            )
+        };
+        assert!(init(component).is_err());
+        assert!(init(ComponentBytes::Precompiled(precompiled)).is_ok());
     }
 }
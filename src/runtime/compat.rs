@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use colored::Colorize;
+use wasmtime::component::{self, Val};
+
+use crate::wit::{Expansion, WorldResolver};
+
+/// Check that the function `f2` can be used in place of `f1`
+///
+/// When `lenient` the types only have to be compatible instead of equal (see
+/// `types_match`).
+pub(super) fn check_function_types(
+    resolver1: &WorldResolver,
+    f1: &wit_parser::Function,
+    resolver2: &WorldResolver,
+    f2: &wit_parser::Function,
+    lenient: bool,
+) -> anyhow::Result<()> {
+    let fun_name = &f1.name;
+    let type_error = |message: String, t1, t2, mismatch| {
+        anyhow::anyhow!(
+            "{message}\n{}",
+            describe_mismatch(resolver1, t1, resolver2, t2, mismatch)
+        )
+    };
+    if f1.params.len() != f2.params.len() {
+        anyhow::bail!("different number of parameters")
+    }
+    for ((arg_name, p1), (_, p2)) in f1.params.iter().zip(&f2.params) {
+        // Arguments flow from the import to the export
+        types_match(resolver1, p1, resolver2, p2, lenient).map_err(|mismatch| {
+            let message = format!("different types for arg '{arg_name}' in function '{fun_name}'");
+            type_error(message, p1, p2, mismatch)
+        })?;
+    }
+    match (&f1.results, &f2.results) {
+        (wit_parser::Results::Named(r1), wit_parser::Results::Named(r2)) => {
+            if r1.len() != r2.len() {
+                anyhow::bail!("different number of return types")
+            }
+            let r2 = r2
+                .iter()
+                .map(|(name, ty)| (name, ty))
+                .collect::<HashMap<&String, &wit_parser::Type>>();
+            for (name, ty) in r1 {
+                let t2 = r2.get(name).with_context(|| {
+                    format!("exported function '{fun_name}' does not have return value '{name}'")
+                })?;
+                // Results flow from the export to the import
+                types_match(resolver2, t2, resolver1, ty, lenient).map_err(|mismatch| {
+                    let message = format!("return value '{name}' has differing types");
+                    type_error(message, ty, t2, mismatch.swapped())
+                })?;
+            }
+        }
+        (wit_parser::Results::Anon(t1), wit_parser::Results::Anon(t2)) => {
+            types_match(resolver2, t2, resolver1, t1, lenient).map_err(|mismatch| {
+                let message = format!("return types did not match for function {fun_name}");
+                type_error(message, t1, t2, mismatch.swapped())
+            })?;
+        }
+        _ => anyhow::bail!("different return type kinds for function '{fun_name}'"),
+    }
+    Ok(())
+}
+
+/// Render the imported type `t1` and the exported type `t2` side by side,
+/// highlighting the lines that differ, followed by where they first differ
+fn describe_mismatch(
+    resolver1: &WorldResolver,
+    t1: &wit_parser::Type,
+    resolver2: &WorldResolver,
+    t2: &wit_parser::Type,
+    mismatch: TypeMismatch,
+) -> String {
+    let t1 = resolver1.display_wit_type(t1, Expansion::Expanded(1));
+    let t2 = resolver2.display_wit_type(t2, Expansion::Expanded(1));
+    let lines1 = std::iter::once("import")
+        .chain(t1.lines())
+        .collect::<Vec<_>>();
+    let lines2 = std::iter::once("export")
+        .chain(t2.lines())
+        .collect::<Vec<_>>();
+    let width = lines1.iter().map(|l| l.len()).max().unwrap_or_default() + 4;
+    let mut output = String::new();
+    for i in 0..lines1.len().max(lines2.len()) {
+        let l1 = lines1.get(i).copied().unwrap_or_default();
+        let l2 = lines2.get(i).copied().unwrap_or_default();
+        let line = format!("  {l1:width$}{l2}");
+        let line = if i == 0 {
+            line.bold()
+        } else if l1.trim_end_matches(',') != l2.trim_end_matches(',') {
+            line.red().bold()
+        } else {
+            line.normal()
+        };
+        output.push_str(&format!("{line}\n"));
+    }
+    output.push_str(&format!("first difference: {mismatch}"));
+    output
+}
+
+/// Where two types first differ
+#[derive(Debug)]
+struct TypeMismatch {
+    /// The path from the outermost types to the difference
+    path: Vec<String>,
+    /// What each type has at the difference
+    found: (String, String),
+}
+
+impl TypeMismatch {
+    fn new(found1: impl Into<String>, found2: impl Into<String>) -> Self {
+        Self {
+            path: Vec::new(),
+            found: (found1.into(), found2.into()),
+        }
+    }
+
+    /// Record that the difference is inside of `segment`
+    fn within(mut self, segment: impl Into<String>) -> Self {
+        self.path.insert(0, segment.into());
+        self
+    }
+
+    /// Swap which type is considered first
+    fn swapped(self) -> Self {
+        let (found1, found2) = self.found;
+        Self {
+            path: self.path,
+            found: (found2, found1),
+        }
+    }
+}
+
+impl std::fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for segment in &self.path {
+            write!(f, "{segment} > ")?;
+        }
+        write!(f, "`{}` vs `{}`", self.found.0, self.found.1)
+    }
+}
+
+/// Check whether values of type `t1` can be used as values of type `t2`
+///
+/// Unless `lenient` the types have to be structurally equal. Otherwise records
+/// may have fields that `t2` does not have and may lack fields that are
+/// optional in `t2`, and every case of variants and enums and every flag of
+/// flags has to exist in `t2`, which may have more.
+fn types_match(
+    resolver1: &WorldResolver,
+    t1: &wit_parser::Type,
+    resolver2: &WorldResolver,
+    t2: &wit_parser::Type,
+    lenient: bool,
+) -> Result<(), TypeMismatch> {
+    let mismatch = || {
+        TypeMismatch::new(
+            resolver1.display_wit_type(t1, Expansion::Collapsed),
+            resolver2.display_wit_type(t2, Expansion::Collapsed),
+        )
+    };
+    match (t1, t2) {
+        (wit_parser::Type::Id(t1), wit_parser::Type::Id(t2)) => {
+            let t1 = resolver1.type_by_id(*t1).unwrap();
+            let t2 = resolver2.type_by_id(*t2).unwrap();
+            type_defs_match(resolver1, t1, resolver2, t2, lenient)
+        }
+        (wit_parser::Type::Id(id), _) => match &resolver1.type_by_id(*id).unwrap().kind {
+            wit_parser::TypeDefKind::Type(t1) => types_match(resolver1, t1, resolver2, t2, lenient),
+            _ => Err(mismatch()),
+        },
+        (_, wit_parser::Type::Id(id)) => match &resolver2.type_by_id(*id).unwrap().kind {
+            wit_parser::TypeDefKind::Type(t2) => types_match(resolver1, t1, resolver2, t2, lenient),
+            _ => Err(mismatch()),
+        },
+        (t1, t2) if t1 == t2 => Ok(()),
+        _ => Err(mismatch()),
+    }
+}
+
+fn type_defs_match(
+    resolver1: &WorldResolver,
+    t1: &wit_parser::TypeDef,
+    resolver2: &WorldResolver,
+    t2: &wit_parser::TypeDef,
+    lenient: bool,
+) -> Result<(), TypeMismatch> {
+    use wit_parser::{Handle, TypeDefKind};
+    let mismatch = || {
+        TypeMismatch::new(
+            resolver1.display_wit_type_def(t1, Expansion::Collapsed),
+            resolver2.display_wit_type_def(t2, Expansion::Collapsed),
+        )
+    };
+    let optional_types_match =
+        |t1: &Option<wit_parser::Type>, t2: &Option<wit_parser::Type>| match (t1, t2) {
+            (None, None) => Ok(()),
+            (Some(t1), Some(t2)) => types_match(resolver1, t1, resolver2, t2, lenient),
+            (t1, t2) => {
+                let display = |resolver: &WorldResolver, t: &Option<wit_parser::Type>| match t {
+                    Some(t) => resolver
+                        .display_wit_type(t, Expansion::Collapsed)
+                        .into_owned(),
+                    None => "nothing".to_owned(),
+                };
+                Err(TypeMismatch::new(
+                    display(resolver1, t1),
+                    display(resolver2, t2),
+                ))
+            }
+        };
+    match (&t1.kind, &t2.kind) {
+        // Aliases are compared by what they alias
+        (TypeDefKind::Type(t1), TypeDefKind::Type(t2)) => {
+            types_match(resolver1, t1, resolver2, t2, lenient)
+        }
+        (TypeDefKind::Type(wit_parser::Type::Id(t1)), _) => {
+            let t1 = resolver1.type_by_id(*t1).unwrap();
+            type_defs_match(resolver1, t1, resolver2, t2, lenient)
+        }
+        (_, TypeDefKind::Type(wit_parser::Type::Id(t2))) => {
+            let t2 = resolver2.type_by_id(*t2).unwrap();
+            type_defs_match(resolver1, t1, resolver2, t2, lenient)
+        }
+        (TypeDefKind::Record(r1), TypeDefKind::Record(r2)) if lenient => {
+            for f2 in &r2.fields {
+                let within = format!("field '{}'", f2.name);
+                match r1.fields.iter().find(|f1| f1.name == f2.name) {
+                    Some(f1) => types_match(resolver1, &f1.ty, resolver2, &f2.ty, lenient)
+                        .map_err(|e| e.within(within))?,
+                    None if is_option(resolver2, &f2.ty) => {}
+                    None => {
+                        let ty = resolver2.display_wit_type(&f2.ty, Expansion::Collapsed);
+                        return Err(TypeMismatch::new("nothing", ty).within(within));
+                    }
+                }
+            }
+            Ok(())
+        }
+        (TypeDefKind::Record(r1), TypeDefKind::Record(r2)) => named_items_match(
+            "field",
+            &r1.fields,
+            &r2.fields,
+            |f| &f.name,
+            |f1, f2| types_match(resolver1, &f1.ty, resolver2, &f2.ty, lenient),
+        ),
+        (TypeDefKind::Resource, TypeDefKind::Resource) => Ok(()),
+        (TypeDefKind::Handle(Handle::Own(r1)), TypeDefKind::Handle(Handle::Own(r2)))
+        | (TypeDefKind::Handle(Handle::Borrow(r1)), TypeDefKind::Handle(Handle::Borrow(r2))) => {
+            // Resources are nominal so their names have to match as well
+            let r1 = resolver1.type_by_id(*r1).unwrap();
+            let r2 = resolver2.type_by_id(*r2).unwrap();
+            if r1.name != r2.name {
+                let name = |r: &wit_parser::TypeDef| r.name.clone().unwrap_or_default();
+                return Err(TypeMismatch::new(name(r1), name(r2)).within("resource"));
+            }
+            type_defs_match(resolver1, r1, resolver2, r2, lenient)
+        }
+        (TypeDefKind::Flags(f1), TypeDefKind::Flags(f2)) if lenient => {
+            named_subset_match("flag", &f1.flags, &f2.flags, |f| &f.name, |_, _| Ok(()))
+        }
+        (TypeDefKind::Enum(e1), TypeDefKind::Enum(e2)) if lenient => {
+            named_subset_match("case", &e1.cases, &e2.cases, |c| &c.name, |_, _| Ok(()))
+        }
+        (TypeDefKind::Variant(v1), TypeDefKind::Variant(v2)) if lenient => named_subset_match(
+            "case",
+            &v1.cases,
+            &v2.cases,
+            |c| &c.name,
+            |c1, c2| optional_types_match(&c1.ty, &c2.ty),
+        ),
+        (TypeDefKind::Flags(f1), TypeDefKind::Flags(f2)) => {
+            named_items_match("flag", &f1.flags, &f2.flags, |f| &f.name, |_, _| Ok(()))
+        }
+        (TypeDefKind::Tuple(t1), TypeDefKind::Tuple(t2)) => {
+            if t1.types.len() != t2.types.len() {
+                let len = |t: &wit_parser::Tuple| format!("{} elements", t.types.len());
+                return Err(TypeMismatch::new(len(t1), len(t2)).within("tuple"));
+            }
+            for (i, (t1, t2)) in t1.types.iter().zip(&t2.types).enumerate() {
+                types_match(resolver1, t1, resolver2, t2, lenient)
+                    .map_err(|e| e.within(format!("tuple element {i}")))?;
+            }
+            Ok(())
+        }
+        (TypeDefKind::Variant(v1), TypeDefKind::Variant(v2)) => named_items_match(
+            "case",
+            &v1.cases,
+            &v2.cases,
+            |c| &c.name,
+            |c1, c2| optional_types_match(&c1.ty, &c2.ty),
+        ),
+        (TypeDefKind::Enum(e1), TypeDefKind::Enum(e2)) => {
+            named_items_match("case", &e1.cases, &e2.cases, |c| &c.name, |_, _| Ok(()))
+        }
+        (TypeDefKind::Option(t1), TypeDefKind::Option(t2)) => {
+            types_match(resolver1, t1, resolver2, t2, lenient).map_err(|e| e.within("option"))
+        }
+        (TypeDefKind::List(t1), TypeDefKind::List(t2)) => {
+            types_match(resolver1, t1, resolver2, t2, lenient).map_err(|e| e.within("list"))
+        }
+        (TypeDefKind::Result(r1), TypeDefKind::Result(r2)) => {
+            optional_types_match(&r1.ok, &r2.ok).map_err(|e| e.within("result ok"))?;
+            optional_types_match(&r1.err, &r2.err).map_err(|e| e.within("result err"))
+        }
+        (TypeDefKind::Future(t1), TypeDefKind::Future(t2)) => {
+            optional_types_match(t1, t2).map_err(|e| e.within("future"))
+        }
+        (TypeDefKind::Stream(s1), TypeDefKind::Stream(s2)) => {
+            optional_types_match(&s1.element, &s2.element)
+                .map_err(|e| e.within("stream element"))?;
+            optional_types_match(&s1.end, &s2.end).map_err(|e| e.within("stream end"))
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+/// Check that two lists of named items (such as fields or cases) have the same
+/// names in the same order and that `items_match` holds for each pair
+fn named_items_match<'a, T>(
+    kind: &str,
+    items1: &'a [T],
+    items2: &'a [T],
+    name: impl Fn(&'a T) -> &'a str,
+    items_match: impl Fn(&'a T, &'a T) -> Result<(), TypeMismatch>,
+) -> Result<(), TypeMismatch> {
+    for i in 0..items1.len().max(items2.len()) {
+        match (items1.get(i), items2.get(i)) {
+            (Some(i1), Some(i2)) if name(i1) == name(i2) => {
+                items_match(i1, i2).map_err(|e| e.within(format!("{kind} '{}'", name(i1))))?
+            }
+            (i1, i2) => {
+                let describe = |item: Option<&'a T>| match item {
+                    Some(item) => format!("{kind} '{}'", name(item)),
+                    None => "nothing".to_owned(),
+                };
+                return Err(TypeMismatch::new(describe(i1), describe(i2)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that every item of `items1` has an item of the same name in `items2`,
+/// in any order, and that `items_match` holds for each pair
+fn named_subset_match<'a, T>(
+    kind: &str,
+    items1: &'a [T],
+    items2: &'a [T],
+    name: impl Fn(&'a T) -> &'a str,
+    items_match: impl Fn(&'a T, &'a T) -> Result<(), TypeMismatch>,
+) -> Result<(), TypeMismatch> {
+    for i1 in items1 {
+        let within = format!("{kind} '{}'", name(i1));
+        match items2.iter().find(|i2| name(i2) == name(i1)) {
+            Some(i2) => items_match(i1, i2).map_err(|e| e.within(within))?,
+            None => return Err(TypeMismatch::new(within, "nothing")),
+        }
+    }
+    Ok(())
+}
+
+/// Whether `ty` is an option, looking through aliases
+fn is_option(resolver: &WorldResolver, ty: &wit_parser::Type) -> bool {
+    let wit_parser::Type::Id(id) = ty else {
+        return false;
+    };
+    match &resolver.type_by_id(*id).unwrap().kind {
+        wit_parser::TypeDefKind::Option(_) => true,
+        wit_parser::TypeDefKind::Type(ty) => is_option(resolver, ty),
+        _ => false,
+    }
+}
+
+/// Convert `val` to a value of the compatible type `ty` (see `types_match`)
+///
+/// Record fields are matched by name, missing optional fields become `none`
+/// and enum cases and variant cases without a payload stand in for each other.
+pub fn coerce_val(val: Val, ty: &component::Type) -> anyhow::Result<Val> {
+    use component::Type;
+    Ok(match (val, ty) {
+        (Val::List(items), Type::List(l)) => Val::List(
+            items
+                .into_iter()
+                .map(|item| coerce_val(item, &l.ty()))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        (Val::Record(mut fields), Type::Record(r)) => Val::Record(
+            r.fields()
+                .map(|field| {
+                    let val = match fields.iter().position(|(name, _)| name == field.name) {
+                        Some(i) => coerce_val(fields.swap_remove(i).1, &field.ty)?,
+                        None if matches!(field.ty, Type::Option(_)) => Val::Option(None),
+                        None => anyhow::bail!("record is missing field '{}'", field.name),
+                    };
+                    Ok((field.name.to_owned(), val))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        (Val::Tuple(items), Type::Tuple(t)) => Val::Tuple(
+            items
+                .into_iter()
+                .zip(t.types())
+                .map(|(item, ty)| coerce_val(item, &ty))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        (Val::Variant(name, payload), Type::Variant(v)) => {
+            let case = v
+                .cases()
+                .find(|c| c.name == name)
+                .with_context(|| format!("variant case '{name}' is not known by the import"))?;
+            let payload = match (payload, case.ty) {
+                (Some(payload), Some(ty)) => Some(Box::new(coerce_val(*payload, &ty)?)),
+                (payload, _) => payload,
+            };
+            Val::Variant(name, payload)
+        }
+        (Val::Enum(name) | Val::Variant(name, None), Type::Enum(e)) => {
+            if !e.names().any(|n| n == name) {
+                anyhow::bail!("enum case '{name}' is not known by the import")
+            }
+            Val::Enum(name)
+        }
+        (Val::Enum(name), Type::Variant(v)) => {
+            if !v.cases().any(|c| c.name == name && c.ty.is_none()) {
+                anyhow::bail!("variant case '{name}' without a payload is not known by the import")
+            }
+            Val::Variant(name, None)
+        }
+        (Val::Flags(flags), Type::Flags(f)) => {
+            if let Some(flag) = flags.iter().find(|flag| !f.names().any(|n| n == *flag)) {
+                anyhow::bail!("flag '{flag}' is not known by the import")
+            }
+            Val::Flags(flags)
+        }
+        (Val::Option(Some(val)), Type::Option(o)) => {
+            Val::Option(Some(Box::new(coerce_val(*val, &o.ty())?)))
+        }
+        (Val::Result(Ok(Some(val))), Type::Result(r)) => match r.ok() {
+            Some(ty) => Val::Result(Ok(Some(Box::new(coerce_val(*val, &ty)?)))),
+            None => Val::Result(Ok(Some(val))),
+        },
+        (Val::Result(Err(Some(val))), Type::Result(r)) => match r.err() {
+            Some(ty) => Val::Result(Err(Some(Box::new(coerce_val(*val, &ty)?)))),
+            None => Val::Result(Err(Some(val))),
+        },
+        (val, _) => val,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The type named `name` in the interface imported by the world
+    fn ty(resolver: &WorldResolver, name: &str) -> wit_parser::Type {
+        let (_, import) = resolver.world().imports.first().unwrap();
+        let wit_parser::WorldItem::Interface { id, .. } = import else {
+            panic!("expected an imported interface")
+        };
+        let interface = resolver.interface_by_id(*id).unwrap();
+        wit_parser::Type::Id(interface.types[name])
+    }
+
+    fn types_equal(
+        resolver1: &WorldResolver,
+        t1: &wit_parser::Type,
+        resolver2: &WorldResolver,
+        t2: &wit_parser::Type,
+    ) -> bool {
+        types_match(resolver1, t1, resolver2, t2, false).is_ok()
+    }
+
+    const TYPES: &str = "
+        package test:types;
+        interface types {
+            record point { x: u32, y: u32 }
+            record named-point { x: u32, name: string }
+            enum color { red, green }
+            flags permissions { read, write }
+            type pair = tuple<u32, string>;
+            type maybe = option<point>;
+            type alias = point;
+            type number = u32;
+            resource file;
+            type file-handle = borrow<file>;
+            type future-point = future<point>;
+            type bytes = stream<u8>;
+        }
+        world w { import types; }
+    ";
+
+    const OTHER_TYPES: &str = "
+        package test:other;
+        interface types {
+            record point { x: u32, y: u32 }
+            record named-point { x: u32, name: u32 }
+            enum color { red, blue }
+            flags permissions { read, write }
+            type pair = tuple<u32, string>;
+            type maybe = option<alias>;
+            type alias = point;
+            type number = u32;
+            resource file;
+            type file-handle = own<file>;
+            type future-point = future<point>;
+            type bytes = stream<u16>;
+        }
+        world w { import types; }
+    ";
+
+    #[test]
+    fn structurally_equal_types() {
+        let r1 = WorldResolver::from_wit_str(TYPES);
+        let r2 = WorldResolver::from_wit_str(OTHER_TYPES);
+        for name in [
+            "point",
+            "permissions",
+            "pair",
+            "maybe",
+            "alias",
+            "number",
+            "future-point",
+        ] {
+            assert!(
+                types_equal(&r1, &ty(&r1, name), &r2, &ty(&r2, name)),
+                "'{name}' should be equal"
+            );
+        }
+        assert!(types_equal(&r1, &ty(&r1, "alias"), &r2, &ty(&r2, "point")));
+        assert!(types_equal(
+            &r1,
+            &ty(&r1, "number"),
+            &r2,
+            &wit_parser::Type::U32
+        ));
+        assert!(types_equal(
+            &r1,
+            &ty(&r1, "file-handle"),
+            &r1,
+            &ty(&r1, "file-handle")
+        ));
+    }
+
+    #[test]
+    fn structurally_different_types() {
+        let r1 = WorldResolver::from_wit_str(TYPES);
+        let r2 = WorldResolver::from_wit_str(OTHER_TYPES);
+        for name in ["named-point", "color", "file-handle", "bytes"] {
+            assert!(
+                !types_equal(&r1, &ty(&r1, name), &r2, &ty(&r2, name)),
+                "'{name}' should not be equal"
+            );
+        }
+        assert!(!types_equal(&r1, &ty(&r1, "point"), &r2, &ty(&r2, "pair")));
+        assert!(!types_equal(
+            &r1,
+            &ty(&r1, "number"),
+            &r2,
+            &wit_parser::Type::U64
+        ));
+    }
+
+    #[test]
+    fn lenient_types() {
+        let r1 = WorldResolver::from_wit_str(
+            "
+            package test:stub;
+            interface types {
+                record config { timeout: u32, retries: u32, verbose: bool }
+                variant error { timeout, other(string) }
+                enum level { low, high }
+                flags perms { read, write }
+            }
+            world w { import types; }
+        ",
+        );
+        let r2 = WorldResolver::from_wit_str(
+            "
+            package test:host;
+            interface types {
+                record config { timeout: u32, retries: u32, name: option<string> }
+                variant error { unknown, other(string), timeout }
+                enum level { low, medium, high }
+                flags perms { read, write, exec }
+            }
+            world w { import types; }
+        ",
+        );
+        for name in ["config", "error", "level", "perms"] {
+            let (t1, t2) = (ty(&r1, name), ty(&r2, name));
+            assert!(types_match(&r1, &t1, &r2, &t2, true).is_ok());
+            assert!(types_match(&r1, &t1, &r2, &t2, false).is_err());
+        }
+        // The import's record has fields the stub's record does not provide
+        let (t1, t2) = (ty(&r1, "config"), ty(&r2, "config"));
+        assert!(types_match(&r2, &t2, &r1, &t1, true).is_err());
+    }
+
+    #[test]
+    fn lenient_types_lack_cases() {
+        let r1 = WorldResolver::from_wit_str(
+            "
+            package test:stub;
+            interface types {
+                variant error { timeout, other(string), unknown }
+                variant reason { other(u32) }
+                enum level { low, medium, high }
+                flags perms { read, write, exec }
+            }
+            world w { import types; }
+        ",
+        );
+        let r2 = WorldResolver::from_wit_str(
+            "
+            package test:host;
+            interface types {
+                variant error { timeout, other(string) }
+                variant reason { other(string) }
+                enum level { low, high }
+                flags perms { read, write }
+            }
+            world w { import types; }
+        ",
+        );
+        let mismatch = |name| {
+            types_match(&r1, &ty(&r1, name), &r2, &ty(&r2, name), true)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(mismatch("error"), "`case 'unknown'` vs `nothing`");
+        assert_eq!(mismatch("reason"), "case 'other' > `u32` vs `string`");
+        assert_eq!(mismatch("level"), "`case 'medium'` vs `nothing`");
+        assert_eq!(mismatch("perms"), "`flag 'exec'` vs `nothing`");
+    }
+
+    #[test]
+    fn first_difference() {
+        let r1 = WorldResolver::from_wit_str(TYPES);
+        let r2 = WorldResolver::from_wit_str(OTHER_TYPES);
+        let mismatch = |name| {
+            types_match(&r1, &ty(&r1, name), &r2, &ty(&r2, name), false)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(mismatch("named-point"), "field 'name' > `string` vs `u32`");
+        assert_eq!(mismatch("color"), "`case 'green'` vs `case 'blue'`");
+        assert_eq!(mismatch("bytes"), "stream element > `u8` vs `u16`");
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+
+use super::{ImportFunc, Runtime};
+use crate::{
+    command::{parser, shell_command},
+    json,
+    wit::{Expansion, WorldResolver},
+};
+
+impl Runtime {
+    /// Stub every function of the imported interface `import_ident` by
+    /// forwarding calls to a subprocess running `command` in the host's shell
+    ///
+    /// Each call is written to the process's stdin as a JSON-RPC request on a
+    /// single line and the process is expected to reply on its stdout with a
+    /// JSON-RPC response on a single line within [`STUB_PROCESS_TIMEOUT`].
+    #[tracing::instrument(name = "stub", level = "debug", skip_all, fields(import = %import_ident, command = %command))]
+    pub fn stub_process(
+        &mut self,
+        resolver: &WorldResolver,
+        import_ident: parser::InterfaceIdent<'_>,
+        command: &str,
+    ) -> anyhow::Result<()> {
+        let import = resolver
+            .imported_interface(import_ident)
+            .with_context(|| format!("no imported interface named '{import_ident}' found"))?;
+        let process = StubProcess::spawn(command, STUB_PROCESS_TIMEOUT)?;
+        let process = Arc::new(Mutex::new(process));
+        for (fun_name, function) in &import.functions {
+            let ident = parser::ItemIdent {
+                interface: Some(import_ident),
+                item: fun_name,
+            };
+            let method = ident.to_string();
+            let process = process.clone();
+            let func = self.json_import(resolver, ident, function, move |params| {
+                process.lock().unwrap().call(&method, params)
+            })?;
+            self.define_import(ident, func);
+        }
+        self.update_imports()
+    }
+
+    /// Stub the imported functions a native plugin implements with it
+    ///
+    /// Returns the names of the functions that were stubbed.
+    pub fn load_plugin(
+        &mut self,
+        resolver: &WorldResolver,
+        path: &std::path::Path,
+    ) -> anyhow::Result<Vec<String>> {
+        let plugin = Arc::new(Plugin::load(path)?);
+        let names = plugin.imports()?;
+        for name in &names {
+            let ident = parser::ItemIdent::parse_str(name)
+                .with_context(|| format!("plugin implements invalid import name '{name}'"))?;
+            let function = resolver
+                .imported_function(ident)
+                .with_context(|| format!("plugin implements unknown import '{name}'"))?;
+            let plugin = plugin.clone();
+            let name = name.clone();
+            let func = self.json_import(resolver, ident, function, move |params| {
+                plugin.call(&name, params)
+            })?;
+            self.define_import(ident, func);
+        }
+        self.update_imports()?;
+        Ok(names)
+    }
+
+    /// An import definition that hands the arguments of each call to `call`
+    /// as JSON and converts the JSON it returns into the results
+    fn json_import(
+        &self,
+        resolver: &WorldResolver,
+        import_ident: parser::ItemIdent<'_>,
+        function: &wit_parser::Function,
+        call: impl Fn(Vec<serde_json::Value>) -> anyhow::Result<serde_json::Value>
+            + Send
+            + Sync
+            + 'static,
+    ) -> anyhow::Result<ImportFunc> {
+        let params = function
+            .params
+            .iter()
+            .map(|(name, ty)| {
+                (
+                    name.clone(),
+                    resolver
+                        .display_wit_type(ty, Expansion::Collapsed)
+                        .into_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let result_types = self.imported_function_results(import_ident)?;
+        let name = import_ident.to_string();
+        Ok(Arc::new(move |_ctx, args, results| {
+            let params = params
+                .iter()
+                .zip(args)
+                .map(|((name, ty), arg)| {
+                    Ok(serde_json::json!({
+                        "name": name,
+                        "type": ty,
+                        "value": json::val_to_json(arg)?,
+                    }))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let values = match (call(params)?, result_types.as_slice()) {
+                (_, []) => Vec::new(),
+                (result, [_]) => vec![result],
+                (serde_json::Value::Array(values), _) => values,
+                (result, _) => {
+                    anyhow::bail!("expected a list of results from '{name}' but got '{result}'")
+                }
+            };
+            if values.len() != results.len() {
+                anyhow::bail!(
+                    "expected {} results from '{name}' but got {}",
+                    results.len(),
+                    values.len()
+                )
+            }
+            for ((result, value), ty) in results.iter_mut().zip(&values).zip(&result_types) {
+                *result = json::json_to_val(value, ty)
+                    .with_context(|| format!("invalid result from '{name}'"))?;
+            }
+            Ok(())
+        }))
+    }
+}
+
+/// How long a stub process has to answer a call before it is stopped and the
+/// call traps
+const STUB_PROCESS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A subprocess that implements imports by answering JSON-RPC requests
+struct StubProcess {
+    command: String,
+    child: std::process::Child,
+    stdin: std::process::ChildStdin,
+    /// The lines the process writes to stdout, which are read on a thread of
+    /// their own so that waiting for an answer can time out
+    lines: mpsc::Receiver<std::io::Result<String>>,
+    /// How long the process has to answer a call
+    timeout: Duration,
+    next_id: u64,
+}
+
+impl StubProcess {
+    fn spawn(command: &str, timeout: Duration) -> anyhow::Result<Self> {
+        if command.trim().is_empty() {
+            anyhow::bail!("no command to run was given");
+        }
+        let mut child = shell_command(command)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .with_context(|| format!("could not start '{command}'"))?;
+        let stdin = child.stdin.take().context("process has no stdin")?;
+        let stdout = child.stdout.take().context("process has no stdout")?;
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            use std::io::BufRead as _;
+            // Ends when the process closes its stdout or the process is dropped
+            for line in std::io::BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            command: command.to_owned(),
+            child,
+            stdin,
+            lines,
+            timeout,
+            next_id: 0,
+        })
+    }
+
+    /// Send a request to the process and wait for the result
+    fn call(
+        &mut self,
+        method: &str,
+        params: Vec<serde_json::Value>,
+    ) -> anyhow::Result<serde_json::Value> {
+        use std::io::Write as _;
+        self.next_id += 1;
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        });
+        writeln!(self.stdin, "{request}")
+            .and_then(|_| self.stdin.flush())
+            .with_context(|| format!("could not send '{method}' call to '{}'", self.command))?;
+        let line = match self.lines.recv_timeout(self.timeout) {
+            Ok(line) => {
+                line.with_context(|| format!("could not read the answer of '{}'", self.command))?
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // Later calls fail right away instead of waiting again
+                let _ = self.child.kill();
+                anyhow::bail!(
+                    "'{}' did not answer '{method}' within {:?} and was stopped",
+                    self.command,
+                    self.timeout
+                )
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                anyhow::bail!("'{}' exited without answering '{method}'", self.command)
+            }
+        };
+        let response: serde_json::Value = serde_json::from_str(&line)
+            .with_context(|| format!("'{}' replied with invalid JSON", self.command))?;
+        if response["id"] != self.next_id {
+            anyhow::bail!(
+                "'{}' replied with id {} but {} was expected",
+                self.command,
+                response["id"],
+                self.next_id
+            )
+        }
+        response_result(response)
+            .with_context(|| format!("'{method}' failed in '{}'", self.command))
+    }
+}
+
+impl Drop for StubProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The result of a JSON-RPC style response or its error message
+fn response_result(mut response: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    if let Some(error) = response.get("error") {
+        anyhow::bail!("{}", error["message"].as_str().unwrap_or("unknown error"))
+    }
+    Ok(response["result"].take())
+}
+
+/// A native library that implements imports
+///
+/// Plugins exchange C strings containing JSON with the host:
+/// * `const char *wepl_plugin_imports(void)` returns a list of the names of
+///   the imported functions the plugin implements (e.g. `["my:pkg/iface#get"]`)
+/// * `char *wepl_plugin_call(const char *name, const char *params)` calls an
+///   import with a list of its parameters and returns an object with either a
+///   `result` or an `error` with a `message`, like a JSON-RPC response
+/// * `void wepl_plugin_free(char *)` optionally frees the strings returned by
+///   `wepl_plugin_call`
+struct Plugin {
+    path: std::path::PathBuf,
+    imports: unsafe extern "C" fn() -> *const std::ffi::c_char,
+    call: unsafe extern "C" fn(
+        *const std::ffi::c_char,
+        *const std::ffi::c_char,
+    ) -> *mut std::ffi::c_char,
+    free: Option<unsafe extern "C" fn(*mut std::ffi::c_char)>,
+    /// Keeps the library loaded while its functions are in use
+    _library: libloading::Library,
+}
+
+impl Plugin {
+    fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let err = || format!("could not load plugin '{}'", path.display());
+        // SAFETY: loading a library runs its initializers, which the user
+        // trusts by asking for it to be loaded
+        unsafe {
+            let library = libloading::Library::new(path).with_context(err)?;
+            let imports = *library.get(b"wepl_plugin_imports\0").with_context(err)?;
+            let call = *library.get(b"wepl_plugin_call\0").with_context(err)?;
+            let free = library.get(b"wepl_plugin_free\0").ok().map(|f| *f);
+            Ok(Self {
+                path: path.to_owned(),
+                imports,
+                call,
+                free,
+                _library: library,
+            })
+        }
+    }
+
+    /// The names of the imported functions the plugin implements
+    fn imports(&self) -> anyhow::Result<Vec<String>> {
+        // SAFETY: the plugin returns a valid C string that it keeps ownership
+        // of, or null
+        let imports = unsafe {
+            let imports = (self.imports)();
+            if imports.is_null() {
+                anyhow::bail!(
+                    "plugin '{}' returned nothing for its imports",
+                    self.path.display()
+                )
+            }
+            std::ffi::CStr::from_ptr(imports)
+        };
+        serde_json::from_slice(imports.to_bytes()).with_context(|| {
+            format!(
+                "plugin '{}' did not return a list of import names",
+                self.path.display()
+            )
+        })
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        params: Vec<serde_json::Value>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let c_name = std::ffi::CString::new(name)?;
+        let c_params = std::ffi::CString::new(serde_json::Value::from(params).to_string())?;
+        // SAFETY: the plugin returns a valid C string that is freed with
+        // `wepl_plugin_free` if it has one
+        let response = unsafe {
+            let response = (self.call)(c_name.as_ptr(), c_params.as_ptr());
+            if response.is_null() {
+                anyhow::bail!(
+                    "plugin '{}' returned nothing for '{name}'",
+                    self.path.display()
+                )
+            }
+            let bytes = std::ffi::CStr::from_ptr(response).to_bytes().to_vec();
+            if let Some(free) = self.free {
+                free(response);
+            }
+            bytes
+        };
+        let response = serde_json::from_slice(&response).with_context(|| {
+            format!(
+                "plugin '{}' replied with invalid JSON for '{name}'",
+                self.path.display()
+            )
+        })?;
+        response_result(response)
+            .with_context(|| format!("'{name}' failed in plugin '{}'", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stub process answering every call with its parameters
+    const ECHO: &str = r#"sed -u 's/^{"id":\([0-9]*\),.*"params":\(.*\)}$/{"id":\1,"result":\2}/'"#;
+
+    #[test]
+    fn stub_processes_answer_calls() {
+        let mut process = StubProcess::spawn(ECHO, Duration::from_secs(10)).unwrap();
+        let params = vec![serde_json::json!("a b"), serde_json::json!(1)];
+        let result = process.call("my:pkg/iface#get", params.clone()).unwrap();
+        assert_eq!(result, serde_json::Value::Array(params));
+        let result = process.call("my:pkg/iface#get", Vec::new()).unwrap();
+        assert_eq!(result, serde_json::json!([]));
+    }
+
+    #[test]
+    fn stub_process_commands_are_run_by_the_shell() {
+        let command = r#"read -r line; echo '{"id": 1, "result": "quoted words"}'"#;
+        let mut process = StubProcess::spawn(command, Duration::from_secs(10)).unwrap();
+        let result = process.call("get", Vec::new()).unwrap();
+        assert_eq!(result, "quoted words");
+    }
+
+    #[test]
+    fn stub_processes_that_do_not_answer_are_stopped() {
+        let timeout = Duration::from_millis(100);
+        let mut process = StubProcess::spawn("sleep 10", timeout).unwrap();
+        let start = std::time::Instant::now();
+        let error = process.call("get", Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("did not answer"), "{error}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(process.call("get", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn stub_processes_that_exit_fail_calls() {
+        let mut process = StubProcess::spawn("true", Duration::from_secs(10)).unwrap();
+        let error = process.call("get", Vec::new()).unwrap_err();
+        assert!(
+            error.to_string().contains("exited without answering"),
+            "{error}"
+        );
+    }
+}
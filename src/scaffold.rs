@@ -0,0 +1,365 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _};
+use wit_parser::{
+    Function, FunctionKind, Handle, InterfaceId, Results, Type, TypeDefKind, TypeId, TypeOwner,
+};
+
+use crate::wit::WorldResolver;
+
+/// Rust keywords, which `wit-bindgen` suffixes with `_` when they are used as
+/// names
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "yield",
+];
+
+/// Write a `cargo component` project to `dir` which implements the imported
+/// interface `name` with functions whose bodies are `todo!()`
+///
+/// Returns the paths of the files written.
+pub fn rust(resolver: &WorldResolver, name: &str, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (full_name, id) = resolver
+        .imported_interface_by_name(name)
+        .with_context(|| format!("no imported interface named '{name}' found"))?;
+    if dir.join("Cargo.toml").exists() {
+        bail!("'{}' already contains a Cargo.toml", dir.display());
+    }
+    // e.g. `wasi`, `keyvalue` and `store` for `wasi:keyvalue/store@0.2.0-draft`
+    let unversioned = full_name.split_once('@').map_or(&*full_name, |(n, _)| n);
+    let (package, interface) = unversioned
+        .split_once('/')
+        .with_context(|| format!("interface '{full_name}' is not part of a package"))?;
+    let (namespace, package) = package
+        .split_once(':')
+        .with_context(|| format!("'{package}' is not a package name"))?;
+    let crate_name = format!("{package}-{interface}-stub");
+
+    let wit = dir.join("wit");
+    let deps = resolver.write_wit_deps(&wit.join("deps"))?;
+    let mut written = deps
+        .iter()
+        .map(|(_, path)| path.clone())
+        .collect::<Vec<_>>();
+    let world = wit.join("world.wit");
+    let source =
+        format!("package component:{crate_name};\n\nworld stub {{\n    export {full_name};\n}}\n");
+    write(&world, &source, &mut written)?;
+
+    let mut manifest = format!(
+        r#"[package]
+name = "{crate_name}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+wit-bindgen-rt = {{ version = "0.26.0", features = ["bitflags"] }}
+
+[lib]
+crate-type = ["cdylib"]
+
+[package.metadata.component]
+package = "component:{crate_name}"
+
+[package.metadata.component.target]
+path = "wit"
+world = "stub"
+
+[package.metadata.component.target.dependencies]
+"#
+    );
+    for (package, path) in &deps {
+        let name = &package.name;
+        let dir = path.parent().unwrap().strip_prefix(dir).unwrap();
+        let dir = dir.to_string_lossy().replace('\\', "/");
+        writeln!(
+            manifest,
+            "\"{}:{}\" = {{ path = \"{dir}\" }}",
+            name.namespace, name.name
+        )?;
+    }
+    write(&dir.join("Cargo.toml"), &manifest, &mut written)?;
+
+    let module = [namespace, package, interface].map(snake_case).join("::");
+    let lib = Generator::new(resolver, id).lib(&module)?;
+    write(&dir.join("src").join("lib.rs"), &lib, &mut written)?;
+    Ok(written)
+}
+
+fn write(path: &Path, contents: &str, written: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("could not create '{}'", dir.display()))?;
+    }
+    std::fs::write(path, contents)
+        .with_context(|| format!("could not write '{}'", path.display()))?;
+    written.push(path.to_owned());
+    Ok(())
+}
+
+/// Generates the Rust source implementing the exports `wit-bindgen` generates
+/// for an interface
+struct Generator<'a> {
+    resolver: &'a WorldResolver,
+    interface: InterfaceId,
+    /// The names used from the interface's bindings module
+    uses: BTreeSet<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(resolver: &'a WorldResolver, interface: InterfaceId) -> Self {
+        Self {
+            resolver,
+            interface,
+            uses: BTreeSet::new(),
+        }
+    }
+
+    /// The `src/lib.rs` of the project, using the bindings in `module` (e.g.
+    /// `wasi::keyvalue::store`)
+    fn lib(mut self, module: &str) -> anyhow::Result<String> {
+        let interface = self
+            .resolver
+            .interface_by_id(self.interface)
+            .context("the interface is not in the component's WIT")?;
+        let resources = interface
+            .types
+            .iter()
+            .filter(|(_, id)| {
+                self.resolver
+                    .type_by_id(**id)
+                    .is_some_and(|t| matches!(t.kind, TypeDefKind::Resource))
+            })
+            .map(|(name, id)| (upper_camel_case(name), *id))
+            .collect::<Vec<_>>();
+
+        let mut guest = String::new();
+        for (name, _) in &resources {
+            writeln!(guest, "    type {name} = {name}Stub;")?;
+        }
+        let mut impls = String::new();
+        for (name, resource) in &resources {
+            self.uses.insert(format!("Guest{name}"));
+            writeln!(impls, "\nstruct {name}Stub;\n")?;
+            let methods = interface.functions.values().filter(|f| match f.kind {
+                FunctionKind::Method(id)
+                | FunctionKind::Static(id)
+                | FunctionKind::Constructor(id) => id == *resource,
+                FunctionKind::Freestanding => false,
+            });
+            let mut body = String::new();
+            for (index, func) in methods.enumerate() {
+                if index > 0 {
+                    body.push('\n');
+                }
+                body.push_str(&self.function(func)?);
+            }
+            match body.is_empty() {
+                true => writeln!(impls, "impl Guest{name} for {name}Stub {{}}")?,
+                false => writeln!(impls, "impl Guest{name} for {name}Stub {{\n{body}}}")?,
+            }
+        }
+        let functions = interface
+            .functions
+            .values()
+            .filter(|f| matches!(f.kind, FunctionKind::Freestanding));
+        for (index, func) in functions.enumerate() {
+            if index > 0 || !resources.is_empty() {
+                guest.push('\n');
+            }
+            guest.push_str(&self.function(func)?);
+        }
+
+        self.uses.insert("Guest".into());
+        let uses = self.uses.iter().cloned().collect::<Vec<_>>().join(", ");
+        Ok(format!(
+            "#[allow(warnings)]\nmod bindings;\n\n\
+             use bindings::exports::{module}::{{{uses}}};\n\n\
+             struct Component;\n\n\
+             impl Guest for Component {{\n{guest}}}\n{impls}\n\
+             bindings::export!(Component with_types_in bindings);\n"
+        ))
+    }
+
+    /// The implementation of `func` with a `todo!()` body
+    fn function(&mut self, func: &Function) -> anyhow::Result<String> {
+        let mut params = Vec::new();
+        let mut skip = 0;
+        if let FunctionKind::Method(_) = func.kind {
+            params.push("&self".to_owned());
+            skip = 1;
+        }
+        for (name, ty) in func.params.iter().skip(skip) {
+            params.push(format!("{}: {}", snake_case(name), self.rust_type(ty)?));
+        }
+        let result = match (&func.kind, &func.results) {
+            (FunctionKind::Constructor(_), _) => Some("Self".to_owned()),
+            (_, Results::Anon(ty)) => Some(self.rust_type(ty)?),
+            (_, Results::Named(results)) if results.is_empty() => None,
+            (_, Results::Named(results)) => {
+                let types = results
+                    .iter()
+                    .map(|(_, ty)| self.rust_type(ty))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Some(tuple(types))
+            }
+        };
+        let result = result.map(|r| format!(" -> {r}")).unwrap_or_default();
+        let name = match func.kind {
+            FunctionKind::Constructor(_) => "new".to_owned(),
+            _ => snake_case(func.item_name()),
+        };
+        Ok(format!(
+            "    fn {}({}){result} {{\n        todo!()\n    }}\n",
+            name,
+            params.join(", ")
+        ))
+    }
+
+    /// The Rust type `wit-bindgen` generates for `ty` in the exports of the
+    /// interface
+    fn rust_type(&mut self, ty: &Type) -> anyhow::Result<String> {
+        Ok(match ty {
+            Type::Bool => "bool".into(),
+            Type::U8 => "u8".into(),
+            Type::U16 => "u16".into(),
+            Type::U32 => "u32".into(),
+            Type::U64 => "u64".into(),
+            Type::S8 => "i8".into(),
+            Type::S16 => "i16".into(),
+            Type::S32 => "i32".into(),
+            Type::S64 => "i64".into(),
+            Type::F32 => "f32".into(),
+            Type::F64 => "f64".into(),
+            Type::Char => "char".into(),
+            Type::String => "String".into(),
+            Type::Id(id) => {
+                let def = self.resolver.type_by_id(*id).context("unknown type")?;
+                if let Some(name) = &def.name {
+                    let name = upper_camel_case(name);
+                    self.uses.insert(name.clone());
+                    return Ok(name);
+                }
+                match &def.kind {
+                    TypeDefKind::Option(ty) => format!("Option<{}>", self.rust_type(ty)?),
+                    TypeDefKind::Result(result) => {
+                        let ok = match &result.ok {
+                            Some(ty) => self.rust_type(ty)?,
+                            None => "()".into(),
+                        };
+                        let err = match &result.err {
+                            Some(ty) => self.rust_type(ty)?,
+                            None => "()".into(),
+                        };
+                        format!("Result<{ok}, {err}>")
+                    }
+                    TypeDefKind::List(ty) => format!("Vec<{}>", self.rust_type(ty)?),
+                    TypeDefKind::Tuple(t) => {
+                        let types = t
+                            .types
+                            .iter()
+                            .map(|ty| self.rust_type(ty))
+                            .collect::<anyhow::Result<Vec<_>>>()?;
+                        tuple(types)
+                    }
+                    TypeDefKind::Handle(Handle::Own(id)) => self.rust_type(&Type::Id(*id))?,
+                    TypeDefKind::Handle(Handle::Borrow(id)) => {
+                        let name = self
+                            .resolver
+                            .type_by_id(*id)
+                            .and_then(|t| t.name.as_deref())
+                            .context("borrowed resources have names")?;
+                        let name = upper_camel_case(name);
+                        // Resources of the interface itself are borrowed as
+                        // `wit-bindgen`'s `$NameBorrow` and imported ones by
+                        // reference
+                        if self.is_own_resource(*id) {
+                            self.uses.insert(format!("{name}Borrow"));
+                            format!("{name}Borrow<'_>")
+                        } else {
+                            self.uses.insert(name.clone());
+                            format!("&{name}")
+                        }
+                    }
+                    TypeDefKind::Type(ty) => self.rust_type(ty)?,
+                    kind => bail!("cannot scaffold functions using {kind:?}"),
+                }
+            }
+        })
+    }
+
+    /// Whether the resource `id` (or the resource it is an alias of) is
+    /// defined in the interface being implemented
+    fn is_own_resource(&self, mut id: TypeId) -> bool {
+        while let Some(def) = self.resolver.type_by_id(id) {
+            match def.kind {
+                TypeDefKind::Type(Type::Id(aliased)) => id = aliased,
+                _ => return matches!(def.owner, TypeOwner::Interface(i) if i == self.interface),
+            }
+        }
+        false
+    }
+}
+
+fn tuple(types: Vec<String>) -> String {
+    match types.as_slice() {
+        [ty] => format!("({ty},)"),
+        types => format!("({})", types.join(", ")),
+    }
+}
+
+/// A WIT name like `get-all` as a Rust function or parameter name
+fn snake_case(name: &str) -> String {
+    let name = name.replace('-', "_").to_lowercase();
+    if RUST_KEYWORDS.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+/// A WIT name like `key-response` as a Rust type name
+fn upper_camel_case(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(snake_case("get-all"), "get_all");
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(snake_case("type"), "type_");
+    }
+
+    #[test]
+    fn upper_camel_case_names() {
+        assert_eq!(upper_camel_case("key-response"), "KeyResponse");
+        assert_eq!(upper_camel_case("HTTP-error"), "HttpError");
+    }
+
+    #[test]
+    fn single_element_tuples() {
+        assert_eq!(tuple(vec!["u8".into()]), "(u8,)");
+    }
+}
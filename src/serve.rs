@@ -0,0 +1,192 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use colored::Colorize;
+use http_body_util::{BodyExt as _, Full};
+use wasmtime::component::ResourceTable;
+use wasmtime_wasi::WasiView;
+use wasmtime_wasi_http::{
+    body::{HyperIncomingBody, HyperOutgoingBody},
+    hyper_request_error,
+    io::TokioIo,
+    proxy::Proxy,
+    WasiHttpCtx, WasiHttpView,
+};
+
+use crate::{
+    http::Fields,
+    runtime::{Context, HttpInstances, Runtime},
+};
+
+/// Serve the `wasi:http/incoming-handler` export of the component on `addr`.
+///
+/// Every request is handled by a fresh instance of the component, set up like
+/// the session's instance. This blocks until the server is interrupted with
+/// Ctrl-C.
+pub fn serve(runtime: &Runtime, addr: SocketAddr) -> anyhow::Result<()> {
+    let handler = Handler::new(runtime)?;
+    let events = runtime.events().clone();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async move {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("could not bind to '{addr}'"))?;
+        events.print(format!(
+            "{} http://{}/ (press Ctrl-C to stop)",
+            "Serving".blue().bold(),
+            listener.local_addr()?
+        ));
+        tokio::select! {
+            result = accept_loop(listener, handler) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        }
+    })
+}
+
+/// The response of the component to a request made with [`handle`]
+pub struct Response {
+    pub status: u16,
+    /// The reason phrase of the status (e.g. `Not Found`), if it is a
+    /// standard one
+    pub reason: Option<&'static str>,
+    pub headers: Fields,
+    pub body: Vec<u8>,
+}
+
+/// Handle a single request with the `wasi:http/incoming-handler` export of
+/// the component, as if it had been received by [`serve`], and wait for the
+/// whole response
+pub fn handle(
+    runtime: &Runtime,
+    method: &str,
+    path: &str,
+    headers: &Fields,
+    body: Vec<u8>,
+) -> anyhow::Result<Response> {
+    let mut request = hyper::Request::builder().method(method).uri(path);
+    // Requests received by a server name their host in a header
+    if !headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("host"))
+    {
+        request = request.header("host", "localhost");
+    }
+    for (name, value) in headers {
+        request = request.header(name, value.as_slice());
+    }
+    let request: hyper::Request<HyperIncomingBody> = request
+        .body(
+            Full::new(Bytes::from(body))
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+        .context("invalid request")?;
+    let handler = Handler::new(runtime)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async move {
+        let (parts, body) = handler.handle(request).await?.into_parts();
+        let body = body
+            .collect()
+            .await
+            .context("could not read the body of the response")?
+            .to_bytes();
+        Ok(Response {
+            status: parts.status.as_u16(),
+            reason: parts.status.canonical_reason(),
+            headers: parts
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_vec()))
+                .collect(),
+            body: body.to_vec(),
+        })
+    })
+}
+
+async fn accept_loop(
+    listener: tokio::net::TcpListener,
+    handler: Arc<Handler>,
+) -> anyhow::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let stream = TokioIo::new(stream);
+        let handler = handler.clone();
+        tokio::task::spawn(async move {
+            let service =
+                hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                    let handler = handler.clone();
+                    let req = req.map(|body| body.map_err(hyper_request_error).boxed());
+                    async move { handler.handle(req).await }
+                });
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .keep_alive(true)
+                .serve_connection(stream, service)
+                .await
+            {
+                tracing::debug!("error serving connection: {e:?}");
+            }
+        });
+    }
+}
+
+/// Handles requests with instances set up like the session's
+struct Handler {
+    instances: HttpInstances,
+}
+
+impl Handler {
+    fn new(runtime: &Runtime) -> anyhow::Result<Arc<Self>> {
+        let instances = runtime.http_instances(|linker| {
+            wasmtime_wasi::add_to_linker_async(linker)?;
+            wasmtime_wasi_http::proxy::add_to_linker(linker)
+        })?;
+        Ok(Arc::new(Handler { instances }))
+    }
+
+    async fn handle(
+        self: Arc<Self>,
+        req: hyper::Request<HyperIncomingBody>,
+    ) -> anyhow::Result<hyper::Response<HyperOutgoingBody>> {
+        tracing::debug!("Handling request: {} {}", req.method(), req.uri());
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let task = tokio::task::spawn(async move {
+            let mut store = self.instances.store();
+            let req = store.data_mut().new_incoming_request(req)?;
+            let out = store.data_mut().new_response_outparam(sender)?;
+            let (proxy, _) = Proxy::instantiate_pre(&mut store, self.instances.pre()).await?;
+            proxy
+                .wasi_http_incoming_handler()
+                .call_handle(&mut store, req, out)
+                .await
+        });
+        match receiver.await {
+            Ok(Ok(resp)) => Ok(resp),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => {
+                let e = match task.await {
+                    Ok(r) => r.err(),
+                    Err(e) => Some(e.into()),
+                };
+                match e {
+                    Some(e) => Err(e.context("guest failed to handle the request")),
+                    None => anyhow::bail!("guest never invoked `response-outparam::set` method"),
+                }
+            }
+        }
+    }
+}
+
+impl WasiHttpView for Context {
+    fn table(&mut self) -> &mut ResourceTable {
+        WasiView::table(self)
+    }
+
+    fn ctx(&mut self) -> &mut WasiHttpCtx {
+        self.http()
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+
+/// How much an instance grew during a call into it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CallStats {
+    /// The bytes the instance's linear memories grew by
+    pub memory: usize,
+    /// The elements the instance's tables grew by
+    pub table_elements: usize,
+    /// The resources wepl implements that the guest created, along with the
+    /// handles the call returned
+    pub resources_created: usize,
+    /// The resources wepl implements that the guest dropped
+    pub resources_dropped: usize,
+    /// The fuel the call consumed, if the engine counts it
+    pub fuel: Option<u64>,
+}
+
+impl fmt::Display for CallStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "memory +{}, table +{}, resources +{} -{}",
+            bytes(self.memory),
+            self.table_elements,
+            self.resources_created,
+            self.resources_dropped
+        )?;
+        match self.fuel {
+            Some(fuel) => write!(f, ", fuel {fuel}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What an instance allocated since its store was built, as its store's
+/// limiter is told and as wepl counts the resources it implements
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Footprint {
+    /// The bytes of all linear memories
+    pub memory: usize,
+    /// The elements of all tables
+    pub table_elements: usize,
+    /// The resources wepl implements that the guest created
+    pub resources_created: usize,
+    /// The resources wepl implements that the guest dropped
+    pub resources_dropped: usize,
+}
+
+impl Footprint {
+    /// How much the instance grew from `before` to this footprint, and how
+    /// much fuel it consumed going from `fuel_before` to `fuel`
+    pub fn growth_since(
+        &self,
+        before: &Footprint,
+        fuel_before: Option<u64>,
+        fuel: Option<u64>,
+    ) -> CallStats {
+        CallStats {
+            memory: self.memory.saturating_sub(before.memory),
+            table_elements: self.table_elements.saturating_sub(before.table_elements),
+            resources_created: self.resources_created - before.resources_created,
+            resources_dropped: self.resources_dropped - before.resources_dropped,
+            fuel: fuel_before.zip(fuel).map(|(before, after)| before - after),
+        }
+    }
+}
+
+impl wasmtime::ResourceLimiter for Footprint {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        self.memory += desired - current;
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: u32,
+        desired: u32,
+        _maximum: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        self.table_elements += (desired - current) as usize;
+        Ok(true)
+    }
+}
+
+/// `n` bytes in the largest unit that shows them exactly, since memories grow
+/// by pages of 64 KiB
+fn bytes(n: usize) -> String {
+    match n {
+        0 => "0 B".to_owned(),
+        n if n % (1024 * 1024) == 0 => format!("{} MiB", n / (1024 * 1024)),
+        n if n % 1024 == 0 => format!("{} KiB", n / 1024),
+        n => format!("{n} B"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let stats = CallStats {
+            memory: 128 * 1024,
+            table_elements: 2,
+            resources_created: 1,
+            resources_dropped: 0,
+            fuel: Some(4521),
+        };
+        assert_eq!(
+            stats.to_string(),
+            "memory +128 KiB, table +2, resources +1 -0, fuel 4521"
+        );
+    }
+
+    #[test]
+    fn display_without_fuel() {
+        assert_eq!(
+            CallStats::default().to_string(),
+            "memory +0 B, table +0, resources +0 -0"
+        );
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(bytes(2 * 1024 * 1024), "2 MiB");
+        assert_eq!(bytes(100), "100 B");
+    }
+}
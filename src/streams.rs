@@ -0,0 +1,111 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::bail;
+use wasmtime::component::Val;
+
+/// The host-side queues standing in for the `stream`s (and `future`s, which
+/// are streams of one value) a component reads, by the handle chunks are
+/// pushed to with `.stream push`
+///
+/// Queues are created when a chunk is first pushed to them. The runtime
+/// cannot pass streams or futures to components yet, so the queues only stage
+/// the chunks a call will read.
+#[derive(Clone, Default)]
+pub struct HostStreams(Arc<Mutex<BTreeMap<String, Queue>>>);
+
+/// The chunks pushed to a stream that haven't been read yet
+#[derive(Clone, Default)]
+pub struct Queue {
+    pub chunks: VecDeque<Val>,
+    /// Whether the writing end is closed, so no more chunks can be pushed
+    pub closed: bool,
+}
+
+/// What reading from a queue gives
+#[derive(Debug, PartialEq)]
+pub enum Read {
+    Chunk(Val),
+    /// No chunk is ready but more may be pushed
+    Pending,
+    /// No chunk is left and none will be pushed
+    Closed,
+}
+
+impl HostStreams {
+    pub fn push(&self, handle: &str, chunk: Val) -> anyhow::Result<()> {
+        let mut queues = self.0.lock().unwrap();
+        let queue = queues.entry(handle.to_owned()).or_default();
+        if queue.closed {
+            bail!("the stream '{handle}' is closed");
+        }
+        queue.chunks.push_back(chunk);
+        Ok(())
+    }
+
+    /// Close the writing end of the stream `handle`, creating it if no chunk
+    /// was pushed to it (e.g. for an empty stream)
+    pub fn close(&self, handle: &str) {
+        let mut queues = self.0.lock().unwrap();
+        queues.entry(handle.to_owned()).or_default().closed = true;
+    }
+
+    pub fn read(&self, handle: &str) -> anyhow::Result<Read> {
+        let mut queues = self.0.lock().unwrap();
+        let Some(queue) = queues.get_mut(handle) else {
+            bail!("no stream named '{handle}'");
+        };
+        Ok(match queue.chunks.pop_front() {
+            Some(chunk) => Read::Chunk(chunk),
+            None if queue.closed => Read::Closed,
+            None => Read::Pending,
+        })
+    }
+
+    /// The contents of every queue
+    pub fn dump(&self) -> BTreeMap<String, Queue> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_streams() {
+        let streams = HostStreams::default();
+        assert!(streams.read("h").is_err());
+    }
+
+    #[test]
+    fn queues() {
+        let streams = HostStreams::default();
+        streams.push("h", Val::U32(1)).unwrap();
+        streams.push("h", Val::U32(2)).unwrap();
+        assert_eq!(streams.read("h").unwrap(), Read::Chunk(Val::U32(1)));
+        assert_eq!(streams.read("h").unwrap(), Read::Chunk(Val::U32(2)));
+    }
+
+    #[test]
+    fn closed_streams() {
+        let streams = HostStreams::default();
+        streams.push("h", Val::U32(1)).unwrap();
+        streams.close("h");
+        assert!(streams.push("h", Val::U32(2)).is_err());
+        assert_eq!(streams.read("h").unwrap(), Read::Chunk(Val::U32(1)));
+        assert_eq!(streams.read("h").unwrap(), Read::Closed);
+        streams.close("empty");
+        assert_eq!(streams.read("empty").unwrap(), Read::Closed);
+    }
+
+    #[test]
+    fn pending_streams() {
+        let streams = HostStreams::default();
+        streams.push("open", Val::Bool(true)).unwrap();
+        streams.read("open").unwrap();
+        assert_eq!(streams.read("open").unwrap(), Read::Pending);
+    }
+}
@@ -0,0 +1,98 @@
+use wasmtime::component::Val;
+
+use crate::command::format_val;
+
+/// The most characters a cell shows before it is cut off with `…`
+const MAX_CELL_WIDTH: usize = 40;
+
+/// `val` as a table if `enabled` and it is a non-empty list of records
+pub fn format(val: &Val, enabled: bool) -> Option<String> {
+    enabled.then(|| table(val)).flatten()
+}
+
+/// The list of records `val` as a table with a column for each field and a
+/// row for each record, or `None` if it is not a non-empty list of records
+fn table(val: &Val) -> Option<String> {
+    let Val::List(records) = val else {
+        return None;
+    };
+    let Some(Val::Record(first)) = records.first() else {
+        return None;
+    };
+    let header = first.iter().map(|(name, _)| name.clone()).collect();
+    let mut rows = vec![header];
+    for record in records {
+        let Val::Record(fields) = record else {
+            return None;
+        };
+        rows.push(fields.iter().map(|(_, val)| cell(val)).collect());
+    }
+    let widths = (0..first.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row: &Vec<String>| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let rule = widths.iter().map(|width| "-".repeat(*width)).collect();
+    rows.insert(1, rule);
+    let lines = rows.iter().map(|row| {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>();
+        cells.join("  ").trim_end().to_owned()
+    });
+    Some(lines.collect::<Vec<_>>().join("\n"))
+}
+
+/// `val` formatted for a cell, cut off if it is too wide
+fn cell(val: &Val) -> String {
+    let text = format_val(val);
+    if text.chars().count() <= MAX_CELL_WIDTH {
+        return text;
+    }
+    let mut cut = text.chars().take(MAX_CELL_WIDTH - 1).collect::<String>();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, age: u32) -> Val {
+        Val::Record(vec![
+            ("name".to_owned(), Val::String(name.to_owned())),
+            ("age".to_owned(), Val::U32(age)),
+        ])
+    }
+
+    #[test]
+    fn tables() {
+        let people = Val::List(vec![record("alice", 30), record(&"x".repeat(50), 7)]);
+        assert_eq!(
+            table(&people).unwrap(),
+            format!(
+                "name{}  age\n{}  ---\n\"alice\"{}  30\n\"{}…  7",
+                " ".repeat(36),
+                "-".repeat(40),
+                " ".repeat(33),
+                "x".repeat(38)
+            )
+        );
+    }
+
+    #[test]
+    fn empty_lists() {
+        assert_eq!(table(&Val::List(Vec::new())), None);
+    }
+
+    #[test]
+    fn lists_of_other_values() {
+        assert_eq!(table(&Val::List(vec![Val::U32(1)])), None);
+        assert_eq!(table(&record("bob", 1)), None);
+    }
+}
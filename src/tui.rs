@@ -0,0 +1,607 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write as _},
+    os::fd::AsFd as _,
+    sync::{Arc, Mutex},
+};
+
+use ratatui::{
+    backend::CrosstermBackend,
+    buffer::Buffer,
+    crossterm::{
+        event::{self, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Widget as _},
+    Frame, Terminal,
+};
+use wit_parser::WorldItem;
+
+use crate::{
+    capture::Capture,
+    command::{self, format_function, format_val, val_as_type},
+    events::OutputHandler,
+    print_error_of_line, print_uncallable_exports, Event, Session,
+};
+
+/// How many lines Page Up and Page Down scroll the session pane by
+const PAGE: usize = 10;
+
+/// Run `session` in a full-screen terminal UI until the user quits
+///
+/// Lines are typed into the command pane, what they print goes to the session
+/// pane above it and what the component writes to stdout to the guest output
+/// pane. The export tree and the variables pane fill in the command pane.
+pub fn run(session: &mut Session) -> anyhow::Result<()> {
+    let output = Capture::new()?;
+    // The panes show plain text, which escape codes would garble
+    colored::control::set_override(false);
+    let mut tui = Tui::new(session)?;
+    // The guest output pane is drawn while calls run, when stdout is captured
+    let stdout = BufWriter::new(File::from(std::io::stdout().as_fd().try_clone_to_owned()?));
+    enable_raw_mode()?;
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+    let result = Terminal::new(CrosstermBackend::new(stdout))
+        .map_err(anyhow::Error::from)
+        .and_then(|terminal| {
+            tui.run(
+                Arc::new(Mutex::new(Screen {
+                    terminal,
+                    last: Buffer::empty(Rect::default()),
+                    guest: Rect::default(),
+                })),
+                &output,
+            )
+        });
+    let _ = disable_raw_mode();
+    let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+    result
+}
+
+/// The pane keys go to
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Command,
+    Exports,
+    Variables,
+    Session,
+}
+
+impl Pane {
+    const ALL: [Pane; 4] = [Pane::Command, Pane::Exports, Pane::Variables, Pane::Session];
+
+    /// The pane `offset` panes after this one, going around
+    fn cycle(self, offset: isize) -> Self {
+        let i = Self::ALL.iter().position(|pane| *pane == self).unwrap() as isize;
+        Self::ALL[(i + offset).rem_euclid(Self::ALL.len() as isize) as usize]
+    }
+}
+
+/// A row of the export tree
+enum ExportRow {
+    /// An exported interface, which is expanded or collapsed
+    Interface { name: String, expanded: bool },
+    /// An exported function, at the top level or in an expanded interface
+    Function {
+        /// What calls the function, e.g. `my:pkg/users#get`
+        call: String,
+        text: String,
+    },
+}
+
+/// The terminal along with what was last drawn on it, which the guest output
+/// pane is drawn over while calls run
+struct Screen {
+    terminal: Terminal<CrosstermBackend<BufWriter<File>>>,
+    last: Buffer,
+    /// Where the guest output pane is
+    guest: Rect,
+}
+
+impl Screen {
+    /// Draw the guest output pane showing `guest_output` over what was last
+    /// drawn, leaving the other panes as they are
+    fn show_guest_output(&mut self, guest_output: &str) -> std::io::Result<()> {
+        let Screen {
+            terminal,
+            last,
+            guest,
+        } = self;
+        *terminal.current_buffer_mut() = last.clone();
+        guest_pane(guest_output, guest.height).render(*guest, terminal.current_buffer_mut());
+        terminal.hide_cursor()?;
+        terminal.flush()?;
+        *last = terminal.current_buffer_mut().clone();
+        terminal.swap_buffers();
+        terminal.backend_mut().flush()
+    }
+}
+
+struct Tui<'a> {
+    session: &'a mut Session,
+    focus: Pane,
+    input: Input,
+    /// The lines typed and what they printed
+    transcript: Vec<String>,
+    /// How many lines the session pane is scrolled up from its end
+    scrolled: usize,
+    /// What the component and its stubs wrote to stdout, which is added to
+    /// while calls run
+    guest_output: Arc<Mutex<String>>,
+    /// Where the guest output pane was last drawn
+    guest_area: Rect,
+    /// The interfaces expanded in the export tree
+    expanded: HashSet<String>,
+    exports: ListState,
+    variables: ListState,
+    quit: bool,
+}
+
+impl<'a> Tui<'a> {
+    fn new(session: &'a mut Session) -> anyhow::Result<Self> {
+        let mut transcript = vec![format!("World: {}", session.instance.resolver.world_name())];
+        if session.instance.runtime.lock().unwrap().dry_run() {
+            transcript
+                .push("Only WIT is loaded, so calls are type-checked but not made".to_owned());
+        }
+        let events = session.events();
+        events.collect();
+        print_uncallable_exports(&session.instance.resolver, &events);
+        for event in events.take() {
+            transcript.extend(event.render().lines().map(str::to_owned));
+        }
+        Ok(Self {
+            session,
+            focus: Pane::Command,
+            input: Input::default(),
+            transcript,
+            scrolled: 0,
+            guest_output: Arc::default(),
+            guest_area: Rect::default(),
+            expanded: HashSet::new(),
+            exports: ListState::default().with_selected(Some(0)),
+            variables: ListState::default().with_selected(Some(0)),
+            quit: false,
+        })
+    }
+
+    fn run(&mut self, screen: Arc<Mutex<Screen>>, output: &Capture) -> anyhow::Result<()> {
+        while !self.quit {
+            {
+                let mut screen = screen.lock().unwrap();
+                let last = screen
+                    .terminal
+                    .draw(|frame| self.draw(frame))?
+                    .buffer
+                    .clone();
+                screen.last = last;
+                screen.guest = self.guest_area;
+            }
+            if let event::Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key, &screen, output)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        screen: &Arc<Mutex<Screen>>,
+        output: &Capture,
+    ) -> anyhow::Result<()> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c' | 'd') if ctrl => self.quit = true,
+            KeyCode::Tab => self.focus = self.focus.cycle(1),
+            KeyCode::BackTab => self.focus = self.focus.cycle(-1),
+            KeyCode::Esc => self.focus = Pane::Command,
+            KeyCode::PageUp => self.scroll(PAGE as isize),
+            KeyCode::PageDown => self.scroll(-(PAGE as isize)),
+            _ => match self.focus {
+                Pane::Command => match key.code {
+                    KeyCode::Enter => {
+                        let line = self.input.submit();
+                        self.run_line(&line, screen, output)?;
+                    }
+                    KeyCode::Char('l') if ctrl => self.transcript.clear(),
+                    code => self.input.edit(code),
+                },
+                Pane::Exports => {
+                    let rows = self.export_rows();
+                    match key.code {
+                        KeyCode::Up => select(&mut self.exports, rows.len(), -1),
+                        KeyCode::Down => select(&mut self.exports, rows.len(), 1),
+                        KeyCode::Enter | KeyCode::Right | KeyCode::Left => {
+                            match self.exports.selected().and_then(|i| rows.get(i)) {
+                                Some(ExportRow::Interface {
+                                    name,
+                                    expanded: true,
+                                }) => {
+                                    self.expanded.remove(name);
+                                }
+                                Some(ExportRow::Interface {
+                                    name,
+                                    expanded: false,
+                                }) => {
+                                    self.expanded.insert(name.clone());
+                                }
+                                Some(ExportRow::Function { call, .. })
+                                    if key.code == KeyCode::Enter =>
+                                {
+                                    self.input.insert(&format!("{call}("));
+                                    self.focus = Pane::Command;
+                                }
+                                _ => {}
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Pane::Variables => {
+                    let names = self.variable_names();
+                    match key.code {
+                        KeyCode::Up => select(&mut self.variables, names.len(), -1),
+                        KeyCode::Down => select(&mut self.variables, names.len(), 1),
+                        KeyCode::Enter => {
+                            if let Some(name) = self.variables.selected().and_then(|i| names.get(i))
+                            {
+                                self.input.insert(name);
+                                self.focus = Pane::Command;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Pane::Session => match key.code {
+                    KeyCode::Up => self.scroll(1),
+                    KeyCode::Down => self.scroll(-1),
+                    KeyCode::Home => self.scrolled = self.transcript.len(),
+                    KeyCode::End => self.scrolled = 0,
+                    _ => {}
+                },
+            },
+        }
+        Ok(())
+    }
+
+    /// Run `line` as if it were typed into the repl, adding what it printed
+    /// to the session pane and what the component wrote to stdout to the
+    /// guest output pane, which shows it while calls run
+    fn run_line(
+        &mut self,
+        line: &str,
+        screen: &Arc<Mutex<Screen>>,
+        output: &Capture,
+    ) -> anyhow::Result<()> {
+        self.transcript.push(format!("> {line}"));
+        self.scrolled = 0;
+        let streamed: OutputHandler = Arc::new({
+            let (screen, guest_output) = (screen.clone(), self.guest_output.clone());
+            move |output: Vec<Event>| {
+                let mut screen = screen.lock().unwrap();
+                let mut guest_output = guest_output.lock().unwrap();
+                guest_output.extend(output.iter().map(Event::render));
+                let _ = screen.show_guest_output(&guest_output);
+            }
+        });
+        let session = &mut *self.session;
+        session.add_history(line);
+        let ((result, events), printed) = output.run(|| {
+            let (result, events) = session.run_line_collecting(line, Some(streamed));
+            if let Err(e) = &result {
+                print_error_of_line(e, Some(line));
+            }
+            (result, events)
+        })?;
+        match result {
+            Ok(command::Outcome::ClearScreen) => self.transcript.clear(),
+            Ok(command::Outcome::Edit(line)) => self.input.insert(&line),
+            Ok(command::Outcome::Exit(_)) => self.quit = true,
+            _ => {}
+        }
+        self.transcript.extend(printed.lines().map(str::to_owned));
+        for event in events {
+            match event {
+                Event::Output { .. } => self.guest_output.lock().unwrap().push_str(&event.render()),
+                event => self
+                    .transcript
+                    .extend(event.render().lines().map(str::to_owned)),
+            }
+        }
+        Ok(())
+    }
+
+    fn scroll(&mut self, lines: isize) {
+        self.scrolled = self
+            .scrolled
+            .saturating_add_signed(lines)
+            .min(self.transcript.len());
+    }
+
+    /// The rows of the export tree of the current instance
+    fn export_rows(&self) -> Vec<ExportRow> {
+        let resolver = &self.session.instance.resolver;
+        let mut rows = Vec::new();
+        for (key, item) in &resolver.world().exports {
+            let name = resolver.world_item_name(key);
+            match item {
+                WorldItem::Function(f) => rows.push(ExportRow::Function {
+                    text: format!("{name}: {}", format_function(f, resolver)),
+                    call: name,
+                }),
+                WorldItem::Interface { id, .. } => {
+                    let expanded = self.expanded.contains(&name);
+                    if expanded {
+                        if let Some(interface) = resolver.interface_by_id(*id) {
+                            rows.push(ExportRow::Interface {
+                                name: name.clone(),
+                                expanded,
+                            });
+                            for (function, f) in &interface.functions {
+                                rows.push(ExportRow::Function {
+                                    call: format!("{name}#{function}"),
+                                    text: format!("  {function}: {}", format_function(f, resolver)),
+                                });
+                            }
+                            continue;
+                        }
+                    }
+                    rows.push(ExportRow::Interface { name, expanded });
+                }
+                WorldItem::Type(_) => {}
+            }
+        }
+        rows
+    }
+
+    /// The names of the variables in the order the variables pane lists them
+    fn variable_names(&self) -> Vec<String> {
+        let mut names = self.session.scope.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [left, right] = split(frame.size(), Direction::Horizontal, [35, 65]);
+        let [exports, variables] = split(left, Direction::Vertical, [60, 40]);
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(5),
+                Constraint::Percentage(30),
+                Constraint::Length(3),
+            ])
+            .split(right);
+        let (transcript, guest, command) = (right[0], right[1], right[2]);
+
+        let rows = self
+            .export_rows()
+            .into_iter()
+            .map(|row| match row {
+                ExportRow::Interface { name, expanded } => {
+                    let marker = if expanded { "▾" } else { "▸" };
+                    ListItem::new(format!("{marker} {name}"))
+                        .style(Style::default().add_modifier(Modifier::BOLD))
+                }
+                ExportRow::Function { text, .. } => ListItem::new(text),
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(rows)
+            .block(self.block("Exports", Pane::Exports))
+            .highlight_style(self.highlight(Pane::Exports));
+        frame.render_stateful_widget(list, exports, &mut self.exports);
+
+        let rows = self
+            .variable_names()
+            .into_iter()
+            .map(|name| {
+                let val = &self.session.scope[&name];
+                ListItem::new(format!(
+                    "{name}: {} = {}",
+                    val_as_type(val),
+                    format_val(val)
+                ))
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(rows)
+            .block(self.block("Variables", Pane::Variables))
+            .highlight_style(self.highlight(Pane::Variables));
+        frame.render_stateful_widget(list, variables, &mut self.variables);
+
+        let lines = self.transcript.iter().map(|line| Line::raw(line.as_str()));
+        let (offset, _) = visible_lines(self.transcript.len(), transcript.height, self.scrolled);
+        let paragraph = Paragraph::new(lines.collect::<Vec<_>>())
+            .block(self.block("Session (PgUp/PgDn scroll)", Pane::Session))
+            .scroll((offset, 0));
+        frame.render_widget(paragraph, transcript);
+
+        let guest_output = self.guest_output.lock().unwrap();
+        frame.render_widget(guest_pane(&guest_output, guest.height), guest);
+        self.guest_area = guest;
+
+        let paragraph = Paragraph::new(format!("> {}", self.input.text)).block(self.block(
+            "Command (Tab: next pane, Enter: run or insert, Ctrl-D: quit)",
+            Pane::Command,
+        ));
+        frame.render_widget(paragraph, command);
+        if self.focus == Pane::Command {
+            let column = self.input.text[..self.input.cursor].chars().count() as u16;
+            frame.set_cursor(command.x + 3 + column, command.y + 1);
+        }
+    }
+
+    /// The bordered block around `pane`, highlighted when it has the focus
+    fn block<'t>(&self, title: &'t str, pane: Pane) -> Block<'t> {
+        let style = match self.focus == pane {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        };
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(style)
+            .title(title)
+    }
+
+    /// The style of the selected row of `pane`, which only stands out when
+    /// the pane has the focus
+    fn highlight(&self, pane: Pane) -> Style {
+        match self.focus == pane {
+            true => Style::default().add_modifier(Modifier::REVERSED),
+            false => Style::default(),
+        }
+    }
+}
+
+/// Split `area` in the direction `direction` by the percentages `percentages`
+fn split<const N: usize>(area: Rect, direction: Direction, percentages: [u16; N]) -> [Rect; N] {
+    let areas = Layout::default()
+        .direction(direction)
+        .constraints(percentages.map(Constraint::Percentage))
+        .split(area);
+    std::array::from_fn(|i| areas[i])
+}
+
+/// The guest output pane showing the end of `guest_output` that fits in its
+/// `height` rows
+fn guest_pane(guest_output: &str, height: u16) -> Paragraph<'_> {
+    let lines = guest_output.lines().collect::<Vec<_>>();
+    let (offset, _) = visible_lines(lines.len(), height, 0);
+    Paragraph::new(lines.into_iter().map(Line::raw).collect::<Vec<_>>())
+        .block(Block::default().borders(Borders::ALL).title("Guest output"))
+        .scroll((offset, 0))
+}
+
+/// Move the selection of a list of `len` rows by `offset` rows
+fn select(state: &mut ListState, len: usize, offset: isize) {
+    if len == 0 {
+        return;
+    }
+    let selected = state.selected().unwrap_or(0).saturating_add_signed(offset);
+    state.select(Some(selected.min(len - 1)));
+}
+
+/// The first of `total` lines a bordered pane of `height` rows shows when it
+/// is scrolled `scrolled` lines up from the end, along with how many it shows
+fn visible_lines(total: usize, height: u16, scrolled: usize) -> (u16, usize) {
+    let rows = height.saturating_sub(2) as usize;
+    let end = total.saturating_sub(scrolled).max(rows.min(total));
+    let start = end.saturating_sub(rows);
+    (start as u16, end - start)
+}
+
+/// The line being typed into the command pane along with the lines typed
+/// before it
+#[derive(Default)]
+struct Input {
+    text: String,
+    /// The byte offset of the cursor in `text`
+    cursor: usize,
+    history: Vec<String>,
+    /// The line of the history being shown, if one is
+    recalled: Option<usize>,
+}
+
+impl Input {
+    fn insert(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+    }
+
+    fn edit(&mut self, code: KeyCode) {
+        let previous = self.text[..self.cursor].chars().next_back();
+        let next = self.text[self.cursor..].chars().next();
+        match code {
+            KeyCode::Char(c) => self.insert(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Backspace if previous.is_some() => {
+                self.cursor -= previous.map_or(0, char::len_utf8);
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Delete if next.is_some() => {
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Left => self.cursor -= previous.map_or(0, char::len_utf8),
+            KeyCode::Right => self.cursor += next.map_or(0, char::len_utf8),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Up => self.recall(-1),
+            KeyCode::Down => self.recall(1),
+            _ => {}
+        }
+    }
+
+    /// Show the line `offset` lines later in the history than the one shown,
+    /// with an empty line after the last one
+    fn recall(&mut self, offset: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let current = self.recalled.unwrap_or(self.history.len());
+        let recalled = current
+            .saturating_add_signed(offset)
+            .min(self.history.len());
+        self.recalled = (recalled < self.history.len()).then_some(recalled);
+        self.text = self
+            .recalled
+            .map(|i| self.history[i].clone())
+            .unwrap_or_default();
+        self.cursor = self.text.len();
+    }
+
+    /// Take the line typed to run it, adding it to the history
+    fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        self.recalled = None;
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editing() {
+        let mut input = Input::default();
+        for c in "aé".chars() {
+            input.edit(KeyCode::Char(c));
+        }
+        input.edit(KeyCode::Left);
+        input.edit(KeyCode::Char('b'));
+        assert_eq!((input.text.as_str(), input.cursor), ("abé", 2));
+        input.edit(KeyCode::Delete);
+        input.edit(KeyCode::Backspace);
+        assert_eq!((input.text.as_str(), input.cursor), ("a", 1));
+        assert_eq!(input.submit(), "a");
+        input.insert("b");
+        input.submit();
+        input.edit(KeyCode::Up);
+        input.edit(KeyCode::Up);
+        input.edit(KeyCode::Up);
+        assert_eq!(input.text, "a");
+        input.edit(KeyCode::Down);
+        assert_eq!(input.text, "b");
+        input.edit(KeyCode::Down);
+        assert_eq!((input.text.as_str(), input.recalled), ("", None));
+    }
+
+    #[test]
+    fn scrolling() {
+        // Ten rows of a pane of twelve are left inside its borders
+        assert_eq!(visible_lines(4, 12, 0), (0, 4));
+        assert_eq!(visible_lines(25, 12, 0), (15, 10));
+        assert_eq!(visible_lines(25, 12, 5), (10, 10));
+        assert_eq!(visible_lines(25, 12, 25), (0, 10));
+    }
+}
@@ -0,0 +1,236 @@
+use wasmparser::{BinaryReaderError, Parser, Payload, Validator, WasmFeatures};
+
+use crate::info::section_name;
+
+/// Custom sections written by the common toolchains, by their exact name
+const KNOWN_CUSTOM_SECTIONS: &[&str] = &[
+    "name",
+    "component-name",
+    "producers",
+    "target_features",
+    "registry-metadata",
+    "sourceMappingURL",
+    "external_debug_info",
+    "build_id",
+    "linking",
+    "dylink.0",
+    "version",
+    "authors",
+    "description",
+    "licenses",
+    "homepage",
+    "source",
+    "revision",
+    "core",
+    "corestack",
+    "coreinstances",
+    "coremodules",
+];
+
+/// Custom sections written by the common toolchains, by the start of their name
+const KNOWN_CUSTOM_SECTION_PREFIXES: &[&str] =
+    &[".debug_", "component-type", "reloc.", "metadata.code."];
+
+/// Validate `bytes` with every WebAssembly feature enabled, returning
+/// warnings about things that are valid but unusual
+///
+/// The warnings are about custom sections no common toolchain writes,
+/// components and modules without a `producers` section and imports and
+/// exports whose names are neither lowercase kebab-case nor interface names.
+pub fn validate(bytes: &[u8]) -> anyhow::Result<Vec<String>> {
+    Validator::new_with_features(WasmFeatures::all())
+        .validate_all(bytes)
+        .map_err(|e| located(bytes, &e))?;
+    let mut warnings = Vec::new();
+    // The label of each binary being parsed, whether it has producers and the
+    // number of modules and components nested in it
+    let mut stack = vec![("component".to_owned(), false, 0, 0)];
+    for payload in Parser::new(0).parse_all(bytes) {
+        let (label, has_producers, modules, components) = stack.last_mut().unwrap();
+        match payload? {
+            Payload::CustomSection(custom) => {
+                let name = custom.name();
+                if name == "producers" {
+                    *has_producers = true;
+                } else if !KNOWN_CUSTOM_SECTIONS.contains(&name)
+                    && !KNOWN_CUSTOM_SECTION_PREFIXES
+                        .iter()
+                        .any(|prefix| name.starts_with(prefix))
+                {
+                    warnings.push(format!("unknown custom section \"{name}\" in the {label}"));
+                }
+            }
+            Payload::ComponentImportSection(imports) => {
+                for import in imports {
+                    let name = import?.name.0;
+                    if !is_canonical_name(name) {
+                        warnings.push(non_canonical("import", name, label));
+                    }
+                }
+            }
+            Payload::ComponentExportSection(exports) => {
+                for export in exports {
+                    let name = export?.name.0;
+                    if !is_canonical_name(name) {
+                        warnings.push(non_canonical("export", name, label));
+                    }
+                }
+            }
+            Payload::ModuleSection { .. } => {
+                let nested = format!("{label}/module {modules}");
+                *modules += 1;
+                stack.push((nested, false, 0, 0));
+            }
+            Payload::ComponentSection { .. } => {
+                let nested = format!("{label}/component {components}");
+                *components += 1;
+                stack.push((nested, false, 0, 0));
+            }
+            Payload::End(_) => {
+                let (label, has_producers, _, _) = stack.pop().unwrap();
+                if !has_producers {
+                    warnings.push(format!("the {label} has no producers section"));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(warnings)
+}
+
+fn non_canonical(kind: &str, name: &str, label: &str) -> String {
+    format!("the {kind} \"{name}\" of the {label} is neither a lowercase kebab-case name nor an interface name")
+}
+
+/// Whether `name` is a lowercase kebab-case name (optionally of a resource's
+/// function like `[method]file.read`) or an interface name like
+/// `wasi:http/types@0.2.0`
+fn is_canonical_name(name: &str) -> bool {
+    let is_kebab = |name: &str| {
+        !name.is_empty()
+            && name.split('-').all(|word| {
+                word.starts_with(|c: char| c.is_ascii_lowercase())
+                    && word
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            })
+    };
+    if let Some((package, interface)) = name.split_once('/') {
+        let interface = interface.split_once('@').map_or(interface, |(i, _)| i);
+        return package.split(':').count() == 2
+            && package.split(':').all(is_kebab)
+            && is_kebab(interface);
+    }
+    let name = ["[constructor]", "[method]", "[static]"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    name.split('.').all(is_kebab)
+}
+
+/// `error` with the section of `bytes` it happened in
+pub fn located(bytes: &[u8], error: &BinaryReaderError) -> anyhow::Error {
+    let offset = error.offset();
+    match locate(bytes, offset) {
+        Some(section) => anyhow::anyhow!(
+            "{} (at offset {offset:#x} in the {section})",
+            error.message()
+        ),
+        None => anyhow::anyhow!("{} (at offset {offset:#x})", error.message()),
+    }
+}
+
+/// The innermost section of `bytes` containing `offset` along with the
+/// component or module it is in (e.g. `code section of component/module 1`)
+fn locate(bytes: &[u8], offset: usize) -> Option<String> {
+    let mut stack = vec![("component".to_owned(), "component", 0, 0)];
+    let mut found = None;
+    // Invalid bytes may fail to parse after the section is found
+    for payload in Parser::new(0).parse_all(bytes) {
+        let Ok(payload) = payload else {
+            break;
+        };
+        let (label, kind, modules, components) = stack.last_mut().unwrap();
+        if let Some((id, range)) = payload.as_section() {
+            if range.contains(&offset) {
+                let section = match &payload {
+                    Payload::CustomSection(custom) => format!("custom \"{}\"", custom.name()),
+                    _ => section_name(kind, id).to_owned(),
+                };
+                found = Some(format!("{section} section of the {label}"));
+            }
+        }
+        match payload {
+            Payload::ModuleSection { .. } => {
+                let nested = format!("{label}/module {modules}");
+                *modules += 1;
+                stack.push((nested, "module", 0, 0));
+            }
+            Payload::ComponentSection { .. } => {
+                let nested = format!("{label}/component {components}");
+                *components += 1;
+                stack.push((nested, "component", 0, 0));
+            }
+            Payload::Version {
+                encoding: wasmparser::Encoding::Module,
+                ..
+            } => {
+                *kind = "module";
+                if stack.len() == 1 {
+                    stack[0].0 = "module".into();
+                }
+            }
+            Payload::End(_) => {
+                stack.pop();
+                if stack.is_empty() {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_names() {
+        assert!(is_canonical_name("get-user"));
+        assert!(is_canonical_name("[method]blob.read-all"));
+        assert!(is_canonical_name("wasi:http/types@0.2.0"));
+        assert!(!is_canonical_name("HTTP-get"));
+        assert!(!is_canonical_name("url=<https://example.com>"));
+    }
+
+    #[test]
+    fn warnings() {
+        let bytes = wat::parse_str(
+            r#"(component
+                (core module (func (export "f") nop))
+                (@custom "mystery" "data")
+            )"#,
+        )
+        .unwrap();
+        let warnings = validate(&bytes).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                "the component/module 0 has no producers section",
+                "unknown custom section \"mystery\" in the component",
+                "the component has no producers section"
+            ]
+        );
+    }
+
+    #[test]
+    fn error_locations() {
+        let code = wat::parse_str(r#"(module (func (export "f") nop))"#).unwrap();
+        // Cut the module short in the middle of its code section
+        let truncated = &code[..code.len() - 2];
+        let error = validate(truncated).unwrap_err().to_string();
+        assert!(error.contains("code section of the module"), "{error}");
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// How often the watched file is checked for changes
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches a file for changes by polling its modification time
+pub struct Watcher {
+    changed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    /// Start watching the file at `path` on a background thread
+    pub fn new(path: PathBuf) -> Self {
+        let changed = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let flag = changed.clone();
+        let stop = stopped.clone();
+        std::thread::spawn(move || {
+            let mut last = modified(&path);
+            while !stop.load(Ordering::SeqCst) {
+                std::thread::sleep(POLL_INTERVAL);
+                let current = modified(&path);
+                // A missing file is likely being rewritten so wait for it to reappear
+                if current.is_some() && current != last {
+                    flag.store(true, Ordering::SeqCst);
+                }
+                if current.is_some() {
+                    last = current;
+                }
+            }
+        });
+        Self { changed, stopped }
+    }
+
+    /// Whether the file changed since the last time this was called
+    pub fn changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// When the file at `path` was last modified, if it exists
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -1,15 +1,18 @@
 use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use wit_component::DecodedWasm;
 use wit_parser::{
-    Function, Interface, InterfaceId, Package, Resolve, TypeDef, TypeId, World, WorldId, WorldItem,
-    WorldKey,
+    Function, Interface, InterfaceId, Package, PackageId, Resolve, TypeDef, TypeId, World, WorldId,
+    WorldItem, WorldKey,
 };
 
 use crate::command::parser;
+use crate::fuzzy;
 
 /// A resolver for a wit world.
+#[derive(Clone)]
 pub struct WorldResolver {
     resolve: Resolve,
     world_id: WorldId,
@@ -26,6 +29,18 @@ impl WorldResolver {
         this
     }
 
+    /// Create a new instance bound to the only world of the WIT source `wit`,
+    /// which tests describe the types they need with
+    ///
+    /// Panics if the WIT is invalid or does not have exactly one world.
+    #[cfg(test)]
+    pub fn from_wit_str(wit: &str) -> Self {
+        let mut resolve = Resolve::default();
+        let packages = resolve.push_str("test.wit", wit).unwrap();
+        let world = resolve.select_world(&packages, None).unwrap();
+        Self::new(resolve, world)
+    }
+
     /// Create a new instance from the given bytes.
     pub fn from_bytes(component_bytes: &[u8]) -> anyhow::Result<Self> {
         let (resolve, world) = match wit_component::decode(component_bytes)
@@ -37,6 +52,206 @@ impl WorldResolver {
         Ok(Self::new(resolve, world))
     }
 
+    /// Create a new instance from the WIT file or directory at `path`, bound
+    /// to the world named `world` or to the only world of its packages.
+    ///
+    /// A file or directory can define several packages (e.g. with nested
+    /// `package` blocks), whose worlds are all candidates, while the worlds of
+    /// the packages in `deps` are not.
+    pub fn from_wit(path: &Path, world: Option<&str>) -> anyhow::Result<Self> {
+        let mut resolve = Resolve::default();
+        let (packages, _) = resolve
+            .push_path(path)
+            .with_context(|| format!("could not read the WIT at '{}'", path.display()))?;
+        let worlds = packages
+            .iter()
+            .flat_map(|id| resolve.packages[*id].worlds.values().copied())
+            .collect::<Vec<_>>();
+        let world_id = *worlds
+            .first()
+            .with_context(|| format!("the WIT at '{}' defines no world", path.display()))?;
+        let mut this = Self::new(resolve, world_id);
+        match world {
+            Some(world) => this.select_world_among(world, &worlds)?,
+            None if worlds.len() > 1 => {
+                let names = worlds.iter().map(|id| this.world_name_of(*id));
+                anyhow::bail!(
+                    "the WIT at '{}' defines several worlds, pick one with `--world` ({})",
+                    path.display(),
+                    names.collect::<Vec<_>>().join(", ")
+                );
+            }
+            None => {}
+        }
+        Ok(this)
+    }
+
+    /// A component implementing the world with functions that trap, which
+    /// stands in for a component when only WIT is explored.
+    ///
+    /// Functions using `future` or `stream` are left out since components
+    /// cannot be built with them yet.
+    pub fn dummy_component(&self) -> anyhow::Result<Vec<u8>> {
+        let mut resolve = self.resolve.clone();
+        for (_, interface) in resolve.interfaces.iter_mut() {
+            interface.functions.retain(|_, f| !self.uses_async_types(f));
+            interface
+                .types
+                .retain(|_, id| !self.is_async_type(&wit_parser::Type::Id(*id)));
+        }
+        let world = &mut resolve.worlds[self.world_id];
+        for items in [&mut world.imports, &mut world.exports] {
+            items.retain(|_, item| match item {
+                WorldItem::Function(f) => !self.uses_async_types(f),
+                WorldItem::Type(id) => !self.is_async_type(&wit_parser::Type::Id(*id)),
+                WorldItem::Interface { .. } => true,
+            });
+        }
+        let mut module = wit_component::dummy_module(&resolve, self.world_id);
+        wit_component::embed_component_metadata(
+            &mut module,
+            &resolve,
+            self.world_id,
+            wit_component::StringEncoding::UTF8,
+        )?;
+        wit_component::ComponentEncoder::default()
+            .module(&module)?
+            .validate(true)
+            .encode()
+            .context("could not build a component implementing the world")
+    }
+
+    /// Whether `func` takes or returns a `future` or `stream`, which the
+    /// runtime cannot pass to or from components yet
+    pub fn uses_async_types(&self, func: &Function) -> bool {
+        func.params.iter().any(|(_, ty)| self.is_async_type(ty))
+            || func.results.iter_types().any(|ty| self.is_async_type(ty))
+    }
+
+    /// Whether `ty` is or contains a `future` or `stream`
+    fn is_async_type(&self, ty: &wit_parser::Type) -> bool {
+        self.type_contains(ty, &|def| {
+            matches!(
+                def.kind,
+                wit_parser::TypeDefKind::Future(_) | wit_parser::TypeDefKind::Stream(_)
+            )
+        })
+    }
+
+    /// Whether the repl can call `func`, which needs every argument to be
+    /// made of values it can write, so not of the resources of imports (like
+    /// the request of `wasi:http/incoming-handler#handle`) or of streams
+    ///
+    /// The `outgoing-request` of `wasi:http/types` is the exception since
+    /// `.http-request` builds one.
+    pub fn is_callable(&self, func: &Function) -> bool {
+        let is_imported_handle = |def: &TypeDef| match def.kind {
+            wit_parser::TypeDefKind::Handle(
+                wit_parser::Handle::Own(id) | wit_parser::Handle::Borrow(id),
+            ) => self.exported_resource_name(id).is_none() && !self.is_http_request(id),
+            _ => false,
+        };
+        !self.uses_async_types(func)
+            && !func
+                .params
+                .iter()
+                .any(|(_, ty)| self.type_contains(ty, &is_imported_handle))
+    }
+
+    /// Whether any exported function can be called from the repl, not
+    /// counting `wasi:cli/run` which is meant to be run with `.run`
+    pub fn has_callable_exports(&self) -> bool {
+        let run = self.exported_wasi_cli_run();
+        self.world().exports.iter().any(|(key, item)| match item {
+            WorldItem::Function(f) => self.is_callable(f),
+            WorldItem::Interface { .. } if run == Some(self.world_item_name(key)) => false,
+            WorldItem::Interface { id, .. } => self
+                .interface_by_id(*id)
+                .is_some_and(|i| i.functions.values().any(|f| self.is_callable(f))),
+            WorldItem::Type(_) => false,
+        })
+    }
+
+    /// Whether `ty` is or contains a type whose definition is `matching`
+    fn type_contains(&self, ty: &wit_parser::Type, matching: &dyn Fn(&TypeDef) -> bool) -> bool {
+        let wit_parser::Type::Id(id) = ty else {
+            return false;
+        };
+        let Some(def) = self.type_by_id(*id) else {
+            return false;
+        };
+        if matching(def) {
+            return true;
+        }
+        let contained: Vec<&wit_parser::Type> = match &def.kind {
+            wit_parser::TypeDefKind::Type(ty)
+            | wit_parser::TypeDefKind::Option(ty)
+            | wit_parser::TypeDefKind::List(ty) => vec![ty],
+            wit_parser::TypeDefKind::Result(r) => r.ok.iter().chain(&r.err).collect(),
+            wit_parser::TypeDefKind::Tuple(t) => t.types.iter().collect(),
+            wit_parser::TypeDefKind::Record(r) => r.fields.iter().map(|f| &f.ty).collect(),
+            wit_parser::TypeDefKind::Variant(v) => {
+                v.cases.iter().filter_map(|c| c.ty.as_ref()).collect()
+            }
+            wit_parser::TypeDefKind::Future(ty) => ty.iter().collect(),
+            wit_parser::TypeDefKind::Stream(s) => s.element.iter().chain(&s.end).collect(),
+            wit_parser::TypeDefKind::Resource
+            | wit_parser::TypeDefKind::Handle(_)
+            | wit_parser::TypeDefKind::Flags(_)
+            | wit_parser::TypeDefKind::Enum(_)
+            | wit_parser::TypeDefKind::Unknown => vec![],
+        };
+        contained
+            .into_iter()
+            .any(|ty| self.type_contains(ty, matching))
+    }
+
+    /// The names of every world in the WIT, including their package (e.g.
+    /// `my:pkg/my-world`).
+    pub fn world_names(&self) -> Vec<String> {
+        self.resolve
+            .worlds
+            .iter()
+            .map(|(id, _)| self.world_name_of(id))
+            .collect()
+    }
+
+    /// Bind to the world `name`, which can leave out the package (e.g.
+    /// `my-world` for `my:pkg/my-world`).
+    pub fn select_world(&mut self, name: &str) -> anyhow::Result<()> {
+        let worlds = self
+            .resolve
+            .worlds
+            .iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        self.select_world_among(name, &worlds)
+    }
+
+    /// Bind to the world `name` of `worlds` like [`WorldResolver::select_world`]
+    fn select_world_among(&mut self, name: &str, worlds: &[WorldId]) -> anyhow::Result<()> {
+        let found = worlds
+            .iter()
+            .copied()
+            .find(|id| self.resolve.worlds[*id].name == name || self.world_name_of(*id) == name);
+        let Some(id) = found else {
+            let names = worlds
+                .iter()
+                .map(|id| self.world_name_of(*id))
+                .collect::<Vec<_>>();
+            let hint = match fuzzy::suggest(name, names.iter().map(String::as_str)) {
+                Some(suggestion) => format!(", did you mean `{suggestion}`?"),
+                None => String::new(),
+            };
+            anyhow::bail!(
+                "no world named '{name}'{hint} (the worlds are: {})",
+                names.join(", ")
+            );
+        };
+        self.world_id = id;
+        Ok(())
+    }
+
     /// Get the exported function by the given `FunctionIdent`.
     pub fn exported_function(&self, ident: parser::ItemIdent) -> Option<&Function> {
         match ident.interface {
@@ -54,6 +269,121 @@ impl WorldResolver {
         }
     }
 
+    /// Get the exported function `ident`, failing with an error that suggests
+    /// the closest exported function if there is none.
+    pub fn find_exported_function(&self, ident: parser::ItemIdent) -> anyhow::Result<&Function> {
+        self.exported_function(ident).with_context(|| {
+            let names = self.exported_function_names();
+            // Without an interface the name may be meant for a function in one
+            let item = |name: &'_ str| match ident.interface {
+                Some(_) => name.to_owned(),
+                None => name.rsplit('#').next().unwrap_or(name).to_owned(),
+            };
+            let items = names.iter().map(|name| item(name)).collect::<Vec<_>>();
+            let typed = ident.to_string();
+            let hint = match fuzzy::suggest(&typed, items.iter().map(String::as_str)) {
+                Some(suggestion) => {
+                    let index = items.iter().position(|item| item == suggestion).unwrap();
+                    format!(" (did you mean `{}`?)", names[index])
+                }
+                None => String::new(),
+            };
+            format!("no function with name '{ident}'{hint}")
+        })
+    }
+
+    /// The names of the exported functions, prefixed with the name of the
+    /// interface exporting them if any (e.g. `my:pkg/users#get-user`).
+    pub fn exported_function_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for (key, item) in &self.world().exports {
+            match item {
+                WorldItem::Function(f) => names.push(f.name.clone()),
+                WorldItem::Interface { id, .. } => {
+                    let interface = self.resolve.name_world_key(key);
+                    let Some(i) = self.interface_by_id(*id) else {
+                        continue;
+                    };
+                    names.extend(i.functions.keys().map(|f| format!("{interface}#{f}")));
+                }
+                WorldItem::Type(_) => {}
+            }
+        }
+        names
+    }
+
+    /// Find the exported resource `ident` and return the name of the interface
+    /// exporting it.
+    ///
+    /// All exported interfaces are searched if `ident` has no interface.
+    pub fn exported_resource(&self, ident: parser::ItemIdent) -> Option<String> {
+        self.world().exports.iter().find_map(|(key, item)| {
+            let WorldItem::Interface { id, .. } = item else {
+                return None;
+            };
+            let name = self.resolve.name_world_key(key);
+            if ident.interface.is_some_and(|i| i.to_string() != name) {
+                return None;
+            }
+            let ty = self.interface_by_id(*id)?.types.get(ident.item)?;
+            matches!(
+                self.type_by_id(*ty)?.kind,
+                wit_parser::TypeDefKind::Resource
+            )
+            .then_some(name)
+        })
+    }
+
+    /// The name of the exported interface and the name of the resource that
+    /// the type `id` is or refers to, if it is an exported resource
+    pub fn exported_resource_name(&self, id: TypeId) -> Option<(String, String)> {
+        let ty = self.type_by_id(id)?;
+        match &ty.kind {
+            wit_parser::TypeDefKind::Type(wit_parser::Type::Id(id))
+            | wit_parser::TypeDefKind::Handle(
+                wit_parser::Handle::Own(id) | wit_parser::Handle::Borrow(id),
+            ) => self.exported_resource_name(*id),
+            wit_parser::TypeDefKind::Resource => {
+                let wit_parser::TypeOwner::Interface(owner) = ty.owner else {
+                    return None;
+                };
+                let interface = self
+                    .world()
+                    .exports
+                    .iter()
+                    .find_map(|(key, item)| match item {
+                        WorldItem::Interface { id, .. } if *id == owner => {
+                            Some(self.resolve.name_world_key(key))
+                        }
+                        _ => None,
+                    })?;
+                Some((interface, ty.name.clone()?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the type `id` is or refers to the `outgoing-request` resource
+    /// of `wasi:http/types`
+    fn is_http_request(&self, id: TypeId) -> bool {
+        let Some(ty) = self.type_by_id(id) else {
+            return false;
+        };
+        match &ty.kind {
+            wit_parser::TypeDefKind::Type(wit_parser::Type::Id(id)) => self.is_http_request(*id),
+            wit_parser::TypeDefKind::Resource => {
+                let wit_parser::TypeOwner::Interface(owner) = ty.owner else {
+                    return false;
+                };
+                ty.name.as_deref() == Some("outgoing-request")
+                    && self
+                        .interface_name(&owner)
+                        .is_some_and(|name| crate::http::is_types_interface(&name))
+            }
+            _ => false,
+        }
+    }
+
     /// Get the imported function by the given `FunctionIdent`.
     pub fn imported_function(&self, ident: parser::ItemIdent) -> Option<&Function> {
         match ident.interface {
@@ -71,6 +401,35 @@ impl WorldResolver {
         }
     }
 
+    /// A copy of the resolve with a world that exports the imported interface
+    /// `ident` for building components which satisfy that import.
+    pub fn stub_world(&self, ident: parser::InterfaceIdent) -> anyhow::Result<(Resolve, WorldId)> {
+        let id = self
+            .world()
+            .imports
+            .iter()
+            .find_map(|(key, item)| match item {
+                WorldItem::Interface { id, .. }
+                    if self.resolve.name_world_key(key) == ident.to_string() =>
+                {
+                    Some(*id)
+                }
+                _ => None,
+            })
+            .with_context(|| format!("no imported interface named '{ident}' found"))?;
+        let name = self
+            .resolve
+            .id_of(id)
+            .with_context(|| format!("interface '{ident}' is not part of a package"))?;
+        let mut resolve = self.resolve.clone();
+        let packages = resolve.push_str(
+            "stub.wit",
+            &format!("package wepl:stub;\n\nworld stub {{\n    export {name};\n}}\n"),
+        )?;
+        let world = resolve.select_world(&packages, Some("stub"))?;
+        Ok((resolve, world))
+    }
+
     /// Get the exported interface by the given `InterfaceIdent`.
     pub fn exported_interface(&self, ident: parser::InterfaceIdent) -> Option<&Interface> {
         self.interface_in_items(ident, self.world().exports.iter())
@@ -112,6 +471,18 @@ impl WorldResolver {
         types
     }
 
+    /// Every named type with the name of the interface defining it if any
+    pub(crate) fn named_types(&self) -> impl Iterator<Item = (Option<String>, &TypeDef)> {
+        self.resolve.types.iter().filter_map(|(_, t)| {
+            t.name.as_ref()?;
+            let interface = match &t.owner {
+                wit_parser::TypeOwner::Interface(i) => self.interface_name(i),
+                _ => None,
+            };
+            Some((interface, t))
+        })
+    }
+
     pub fn display_wit_type<'a>(
         &'a self,
         param_type: &wit_parser::Type,
@@ -243,17 +614,127 @@ impl WorldResolver {
             wit_parser::TypeDefKind::Resource => "resource<...>".into(),
             wit_parser::TypeDefKind::Handle(_) => "handle<...>".into(),
             wit_parser::TypeDefKind::Flags(_) => "flags<...>".into(),
-            wit_parser::TypeDefKind::Future(_) => "future<...>".into(),
-            wit_parser::TypeDefKind::Stream(_) => "stream<...>".into(),
+            wit_parser::TypeDefKind::Future(t) => match t {
+                Some(t) => format!("future<{}>", self.display_wit_type(t, Expansion::Collapsed)),
+                None => "future".into(),
+            },
+            wit_parser::TypeDefKind::Stream(s) => {
+                let element = s
+                    .element
+                    .as_ref()
+                    .map(|t| self.display_wit_type(t, Expansion::Collapsed));
+                let end = s
+                    .end
+                    .as_ref()
+                    .map(|t| self.display_wit_type(t, Expansion::Collapsed));
+                match (element, end) {
+                    (Some(element), Some(end)) => format!("stream<{element}, {end}>"),
+                    (Some(t), _) => format!("stream<{t}>"),
+                    (_, Some(t)) => format!("stream<_, {t}>"),
+                    _ => "stream".into(),
+                }
+            }
             wit_parser::TypeDefKind::Unknown => unreachable!(),
         };
         Cow::Owned(display)
     }
 
-    /// Whether the wasi cli (0.2.0) package is imported.
+    /// A placeholder value of type `typ` written as repl input (e.g.
+    /// `{name: "…", retries: 0}` for a record) for the user to fill in
+    ///
+    /// Handles are written as the name of their resource, standing for a
+    /// variable holding one.
+    pub fn example_value(&self, typ: &wit_parser::Type) -> String {
+        let str = match typ {
+            wit_parser::Type::Bool => "false",
+            wit_parser::Type::U8
+            | wit_parser::Type::U16
+            | wit_parser::Type::U32
+            | wit_parser::Type::U64
+            | wit_parser::Type::S8
+            | wit_parser::Type::S16
+            | wit_parser::Type::S32
+            | wit_parser::Type::S64
+            | wit_parser::Type::F32
+            | wit_parser::Type::F64 => "0",
+            wit_parser::Type::String | wit_parser::Type::Char => "\"…\"",
+            wit_parser::Type::Id(id) => {
+                let typ = self
+                    .resolve
+                    .types
+                    .get(*id)
+                    .expect("found type id for type not present in resolver");
+                return self.example_type_def_value(typ);
+            }
+        };
+        str.to_owned()
+    }
+
+    fn example_type_def_value(&self, typ: &TypeDef) -> String {
+        let join = |types: &mut dyn Iterator<Item = &wit_parser::Type>| {
+            types
+                .map(|t| self.example_value(t))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let case = |name: &str, ty: Option<&wit_parser::Type>| match ty {
+            Some(ty) => format!("{name}({})", self.example_value(ty)),
+            None => name.to_owned(),
+        };
+        match &typ.kind {
+            wit_parser::TypeDefKind::Type(t) => self.example_value(t),
+            wit_parser::TypeDefKind::Record(r) => {
+                let fields = r
+                    .fields
+                    .iter()
+                    .map(|f| format!("{}: {}", f.name, self.example_value(&f.ty)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{{{fields}}}")
+            }
+            wit_parser::TypeDefKind::List(t) => format!("[{}]", self.example_value(t)),
+            wit_parser::TypeDefKind::Tuple(t) => format!("({})", join(&mut t.types.iter())),
+            wit_parser::TypeDefKind::Option(t) => case("some", Some(t)),
+            wit_parser::TypeDefKind::Result(r) => match (&r.ok, &r.err) {
+                (None, Some(err)) => case("err", Some(err)),
+                (ok, _) => case("ok", ok.as_ref()),
+            },
+            wit_parser::TypeDefKind::Variant(v) => match v.cases.first() {
+                Some(c) => case(&c.name, c.ty.as_ref()),
+                None => "_".into(),
+            },
+            wit_parser::TypeDefKind::Enum(e) => match e.cases.first() {
+                Some(c) => c.name.clone(),
+                None => "_".into(),
+            },
+            wit_parser::TypeDefKind::Flags(f) => {
+                let flags = f
+                    .flags
+                    .iter()
+                    .map(|f| f.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{{{flags}}}")
+            }
+            wit_parser::TypeDefKind::Handle(
+                wit_parser::Handle::Own(id) | wit_parser::Handle::Borrow(id),
+            ) => self
+                .type_by_id(*id)
+                .and_then(|t| t.name.clone())
+                .unwrap_or_else(|| "_".into()),
+            wit_parser::TypeDefKind::Resource
+            | wit_parser::TypeDefKind::Future(_)
+            | wit_parser::TypeDefKind::Stream(_)
+            | wit_parser::TypeDefKind::Unknown => "_".into(),
+        }
+    }
+
+    /// Whether the wasi cli (0.2.x) package is imported.
     ///
     /// Note that this is being used as a heuristic to determine whether to
-    /// link wasi command.
+    /// link wasi command. The linker serves any 0.2 patch release with the
+    /// 0.2.0 definitions, so e.g. `wasi:cli/terminal-stdout@0.2.3` is linked
+    /// rather than stubbed.
     pub fn imports_wasi_cli(&self) -> bool {
         for package in self.package_dependencies() {
             if package.name.namespace == "wasi"
@@ -262,7 +743,7 @@ impl WorldResolver {
                     .name
                     .version
                     .as_ref()
-                    .map(|v| v.major == 0 && v.minor == 2 && v.patch == 0)
+                    .map(|v| v.major == 0 && v.minor == 2)
                     .unwrap_or(false)
             {
                 return true;
@@ -271,6 +752,107 @@ impl WorldResolver {
         false
     }
 
+    /// Whether the wasi http (0.2.0) incoming handler is exported.
+    pub fn exports_wasi_http_handler(&self) -> bool {
+        self.world().exports.keys().any(|export_name| {
+            let WorldKey::Interface(interface_id) = export_name else {
+                return false;
+            };
+            let interface = self.resolve.interfaces.get(*interface_id).unwrap();
+            let Some(package) = interface.package.and_then(|p| self.resolve.packages.get(p)) else {
+                return false;
+            };
+            package.name.namespace == "wasi"
+                && package.name.name == "http"
+                && interface.name.as_deref() == Some("incoming-handler")
+        })
+    }
+
+    /// The name of the exported wasi cli `run` interface, if any.
+    pub fn exported_wasi_cli_run(&self) -> Option<String> {
+        self.world().exports.keys().find_map(|export_name| {
+            let name = self.world_item_name(export_name);
+            name.starts_with("wasi:cli/run@").then_some(name)
+        })
+    }
+
+    /// Every WIT package the component's types come from.
+    pub fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.resolve.packages.iter().map(|(_, package)| package)
+    }
+
+    /// The WIT source of the package the world is in.
+    pub fn world_wit(&self) -> anyhow::Result<String> {
+        let package = self.world().package.context("the world has no package")?;
+        wit_component::WitPrinter::default().print(&self.resolve, &[package])
+    }
+
+    /// Write the WIT packages of the world to `dir` in the layout `wit-bindgen`
+    /// and `cargo component` read: the world's package in `dir` and the
+    /// packages it depends on in `dir/deps`.
+    ///
+    /// Returns the paths of the files written.
+    pub fn write_wit(&self, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let mut written = self
+            .write_wit_deps(&dir.join("deps"))?
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect::<Vec<_>>();
+        if let Some(id) = self.world().package {
+            let path = dir.join(format!("{}.wit", self.resolve.packages[id].name.name));
+            self.write_package(id, &path)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Write every WIT package except the world's own to a directory of its
+    /// own in `deps` (e.g. `deps/wasi-cli@0.2.0/package.wit`).
+    ///
+    /// Returns each package with the path of the file written for it.
+    pub fn write_wit_deps(&self, deps: &Path) -> anyhow::Result<Vec<(&Package, PathBuf)>> {
+        let mut written = Vec::new();
+        for (id, package) in self.resolve.packages.iter() {
+            if Some(id) == self.world().package {
+                continue;
+            }
+            let name = &package.name;
+            let dir = match &name.version {
+                Some(version) => format!("{}-{}@{version}", name.namespace, name.name),
+                None => format!("{}-{}", name.namespace, name.name),
+            };
+            let path = deps.join(dir).join("package.wit");
+            self.write_package(id, &path)?;
+            written.push((package, path));
+        }
+        Ok(written)
+    }
+
+    fn write_package(&self, id: PackageId, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("could not create '{}'", dir.display()))?;
+        }
+        // Each package is printed on its own rather than nested in the others
+        let source = wit_component::WitPrinter::default().print(&self.resolve, &[id])?;
+        std::fs::write(path, source)
+            .with_context(|| format!("could not write '{}'", path.display()))
+    }
+
+    /// The full name and id of the imported interface `name`, which can leave
+    /// out the version (e.g. `wasi:keyvalue/store` for
+    /// `wasi:keyvalue/store@0.2.0-draft`).
+    pub fn imported_interface_by_name(&self, name: &str) -> Option<(String, InterfaceId)> {
+        self.world().imports.iter().find_map(|(key, item)| {
+            let WorldItem::Interface { id, .. } = item else {
+                return None;
+            };
+            let full_name = self.resolve.name_world_key(key);
+            let unversioned = full_name.split_once('@').map_or(&*full_name, |(n, _)| n);
+            (full_name == name || unversioned == name).then(|| (full_name.clone(), *id))
+        })
+    }
+
     /// All packages that are imported dependencies of the current world.
     pub fn package_dependencies(&self) -> impl Iterator<Item = &Package> {
         self.world()
@@ -322,14 +904,18 @@ impl WorldResolver {
                         // We're assuming that all interfaces in these packages are handled by `wasmtime-wasi` 
                         // command implementation. This should be true for many components so we'll leave
                         // the hack for now.
-                        && ["cli", "io", "filesystem", "random"].contains(&package.name.name.as_str()))
+                        && ["cli", "io", "clocks", "filesystem", "random", "sockets"].contains(&package.name.name.as_str()))
                 }
                 _ => true,
             })
     }
 
     pub(crate) fn world_name(&self) -> String {
-        let world = self.world();
+        self.world_name_of(self.world_id)
+    }
+
+    fn world_name_of(&self, id: WorldId) -> String {
+        let world = &self.resolve.worlds[id];
         let world_package = if let Some(world_package) = world.package {
             let world_package = self.resolve.packages.get(world_package).unwrap();
             format!("{}/", world_package.name)